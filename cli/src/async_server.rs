@@ -0,0 +1,754 @@
+//! The tokio based HTTP server enabled with `--async-server`.
+//!
+//! The oxhttp server dedicates a thread to each connection so slow clients may exhaust the threads.
+//! Here the connections are tokio tasks and the requests are given to the same handler as the oxhttp server,
+//! run by a bounded pool of blocking workers.
+//! The requests waiting for a worker are counted and the server answers `503 Service Unavailable`
+//! when the queue is full.
+//!
+//! A worker never waits for a slow client upload: the request body is fully received by the connection task
+//! before the request is given to a worker, in memory or in a temporary file if it is large.
+//! The request is only counted in the queue once its body is received so that slow uploads do not fill it,
+//! and the bodies larger than `max_decoded_body_size` are rejected with `413 Payload Too Large`.
+//! The response body is produced by the worker and streamed through a bounded channel.
+//! It is aborted if the client does not read it during the timeout.
+//!
+//! This server also terminates the TLS connections, the verified client certificate subject is given to the handler.
+//!
+//! On SIGINT or SIGTERM the server stops accepting connections and waits for the in-flight requests to finish.
+
+use crate::config::ServerConfig;
+use crate::tls::{client_certificate_subject, server_config};
+use crate::Peer;
+use anyhow::Context;
+use bytes::Bytes;
+use http_body::{Body as HttpBody, Frame};
+use http_body_util::BodyExt;
+use hyper::header::{HeaderValue as HyperHeaderValue, CONTENT_TYPE, RETRY_AFTER, SERVER};
+use hyper::http::request::Parts;
+use hyper::http::uri::Authority;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::StatusCode;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::server::graceful::GracefulShutdown;
+use oxhttp::model::{Body, HeaderName, Method, Request, Response, Status};
+use rand::random;
+use std::convert::Infallible;
+use std::env::temp_dir;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, SeekFrom};
+use std::mem::replace;
+use std::net::{SocketAddr, TcpListener as StdTcpListener, ToSocketAddrs};
+use std::path::PathBuf;
+use std::pin::{pin, Pin};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncRead, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::runtime::{Builder, Handle};
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tokio::task::spawn_blocking;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsAcceptor;
+
+/// Number of body chunks buffered between the network and a worker
+const CHANNEL_CAPACITY: usize = 16;
+/// Size of the response body chunks
+const CHUNK_SIZE: usize = 16 * 1024;
+/// Value of the `Retry-After` header of the 503 responses in seconds
+const RETRY_AFTER_SECONDS: &str = "1";
+/// Request bodies larger than this size are written to a temporary file while being received
+const MAX_IN_MEMORY_REQUEST_BODY_SIZE: usize = 1024 * 1024;
+/// Pause of the accept loop after an error like too many open files, so that it does not spin
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+type Handler = dyn Fn(&mut Request, &Peer) -> Response + Send + Sync;
+type Chunk = io::Result<Bytes>;
+
+/// A plain TCP or a TLS connection
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+pub fn serve_async(
+    handler: impl Fn(&mut Request, &Peer) -> Response + Send + Sync + 'static,
+    config: &ServerConfig,
+) -> anyhow::Result<()> {
+    let workers = config.workers()?;
+    let state = Arc::new(ServerState {
+        handler: Arc::new(handler),
+        admitted: AtomicUsize::new(0),
+        max_admitted: workers + config.max_queued_requests(),
+        timeout: config.timeout(),
+        max_body_size: config.max_decoded_body_size(),
+    });
+    let shutdown_timeout = config.shutdown_timeout();
+    // The blocking pool is the worker pool: tokio queues the tasks when all its threads are busy
+    let runtime = Builder::new_multi_thread()
+        .enable_all()
+        .max_blocking_threads(workers)
+        .thread_name("oxigraph-server")
+        .build()
+        .context("Failed to start the tokio runtime")?;
+    let result = runtime.block_on(run(state, config));
+    runtime.shutdown_timeout(shutdown_timeout);
+    result
+}
+
+async fn run(state: Arc<ServerState>, config: &ServerConfig) -> anyhow::Result<()> {
+    let bind = config.bind();
+    let timeout_duration = config.timeout();
+    let shutdown_timeout = config.shutdown_timeout();
+    let tls = if let Some(tls) = &config.tls {
+        Some(TlsAcceptor::from(server_config(tls)?))
+    } else {
+        None
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let listeners = bind
+        .to_socket_addrs()?
+        .map(StdTcpListener::bind)
+        .collect::<io::Result<Vec<_>>>()?;
+    // A permit is taken before accepting a connection and released when it is closed
+    let connections = Arc::new(Semaphore::new(config.max_concurrent_connections()?));
+
+    // All listeners feed the same accept loop
+    let (stream_sender, mut streams) = mpsc::channel(CHANNEL_CAPACITY);
+    for listener in listeners {
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let stream_sender = stream_sender.clone();
+        let tls = tls.clone();
+        let connections = Arc::clone(&connections);
+        tokio::spawn(async move {
+            loop {
+                let Ok(permit) = Arc::clone(&connections).acquire_owned().await else {
+                    return;
+                };
+                let (stream, address) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        eprintln!("Failed to accept a connection: {e}");
+                        sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+                let Some(tls) = &tls else {
+                    if stream_sender
+                        .send((
+                            Box::new(stream) as Box<dyn Stream>,
+                            Peer {
+                                address: Some(address),
+                                certificate_subject: None,
+                            },
+                            permit,
+                        ))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                    continue;
+                };
+                // The handshake gets its own task so that a slow client does not block the other ones
+                let tls = tls.clone();
+                let stream_sender = stream_sender.clone();
+                tokio::spawn(async move {
+                    match timeout(timeout_duration, tls.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let peer = Peer {
+                                address: Some(address),
+                                certificate_subject: client_certificate_subject(stream.get_ref().1),
+                            };
+                            stream_sender
+                                .send((Box::new(stream) as Box<dyn Stream>, peer, permit))
+                                .await
+                                .ok();
+                        }
+                        Ok(Err(e)) => {
+                            if !matches!(
+                                e.kind(),
+                                io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset
+                            ) {
+                                eprintln!("TLS handshake error with {address}: {e}");
+                            }
+                        }
+                        Err(_) => (), // The handshake timed out
+                    }
+                });
+            }
+        });
+    }
+    drop(stream_sender);
+    #[cfg(target_os = "linux")]
+    crate::systemd_notify_ready()?;
+    eprintln!("Listening for requests at {scheme}://{bind}");
+
+    let graceful = GracefulShutdown::new();
+    let mut shutdown = pin!(shutdown_signal());
+    loop {
+        tokio::select! {
+            stream = streams.recv() => {
+                let Some((stream, peer, permit)) = stream else {
+                    break;
+                };
+                let state = Arc::clone(&state);
+                let peer = Arc::new(peer);
+                let connection = http1::Builder::new()
+                    .timer(TokioTimer::new())
+                    .header_read_timeout(timeout_duration)
+                    .serve_connection(
+                        TokioIo::new(stream),
+                        service_fn(move |request| {
+                            handle(request, Arc::clone(&state), Arc::clone(&peer))
+                        }),
+                    );
+                let connection = graceful.watch(connection);
+                tokio::spawn(async move {
+                    let _permit: OwnedSemaphorePermit = permit;
+                    if let Err(e) = connection.await {
+                        if !e.is_incomplete_message() {
+                            eprintln!("HTTP connection error: {e}");
+                        }
+                    }
+                });
+            }
+            result = &mut shutdown => {
+                result?;
+                break;
+            }
+        }
+    }
+    drop(streams);
+    eprintln!("Shutting down, waiting for the in-flight requests to finish");
+    if timeout(shutdown_timeout, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        eprintln!(
+            "Some requests were still running after {}s, aborting them",
+            shutdown_timeout.as_secs()
+        );
+    }
+    Ok(())
+}
+
+/// Resolves when the process gets SIGINT or SIGTERM
+async fn shutdown_signal() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await
+    }
+}
+
+struct ServerState {
+    handler: Arc<Handler>,
+    /// Number of requests running or waiting for a worker
+    admitted: AtomicUsize,
+    max_admitted: usize,
+    /// Maximal duration without progress while receiving a request body or sending a response body
+    timeout: Duration,
+    /// Maximal size in bytes of a received request body
+    max_body_size: u64,
+}
+
+impl ServerState {
+    fn admit(self: &Arc<Self>) -> Option<AdmissionPermit> {
+        if self.admitted.fetch_add(1, Ordering::SeqCst) >= self.max_admitted {
+            self.admitted.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(AdmissionPermit(Arc::clone(self)))
+    }
+}
+
+/// Frees the request slot once the response has been fully written
+struct AdmissionPermit(Arc<ServerState>);
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.0.admitted.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+async fn handle<B>(
+    request: hyper::Request<B>,
+    state: Arc<ServerState>,
+    peer: Arc<Peer>,
+) -> Result<hyper::Response<ResponseBody>, Infallible>
+where
+    B: HttpBody<Data = Bytes> + Send + Unpin + 'static,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let (parts, body) = request.into_parts();
+    let body = match receive_request_body(body, state.timeout, state.max_body_size).await {
+        Ok(body) => body,
+        Err((status, message)) => return Ok(simple_response(status, &message)),
+    };
+    // Only taken now so that the slow uploads do not hold a slot
+    let Some(permit) = state.admit() else {
+        let mut response = simple_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The server is overloaded, please retry later",
+        );
+        response.headers_mut().insert(
+            RETRY_AFTER,
+            HyperHeaderValue::from_static(RETRY_AFTER_SECONDS),
+        );
+        return Ok(response);
+    };
+    let (head_sender, head_receiver) = oneshot::channel();
+    let (chunk_sender, chunk_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let handler = Arc::clone(&state.handler);
+    let send_timeout = state.timeout;
+    spawn_blocking(move || {
+        let _permit = permit;
+        let mut response = match build_request(&parts, body) {
+            Ok(mut request) => handler(&mut request, &peer),
+            Err(message) => crate::error(Status::BAD_REQUEST, message),
+        };
+        let body = replace(response.body_mut(), Body::from(Vec::new()));
+        let head = response_head(&response);
+        let is_valid = head.is_ok();
+        if head_sender.send(head).is_ok() && is_valid {
+            send_response_body(body, &chunk_sender, send_timeout);
+        }
+    });
+    Ok(match head_receiver.await {
+        Ok(Ok(head)) => head.map(|()| ResponseBody::Channel(chunk_receiver)),
+        Ok(Err(message)) => simple_response(StatusCode::INTERNAL_SERVER_ERROR, &message),
+        Err(_) => simple_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "The request handler failed unexpectedly",
+        ),
+    })
+}
+
+/// Converts the hyper request into an oxhttp one with the already received body
+fn build_request(parts: &Parts, body: ReceivedBody) -> Result<Request, String> {
+    let method = Method::from_str(parts.method.as_str()).map_err(|e| e.to_string())?;
+    let host = request_host(parts)?;
+    let path = parts
+        .uri
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    let url = format!("http://{host}{path}")
+        .parse()
+        .map_err(|e| format!("Invalid request URL: {e}"))?;
+    let mut request = Request::builder(method, url);
+    for (name, value) in &parts.headers {
+        let value = value
+            .to_str()
+            .map_err(|_| format!("The {name} header value is not valid ASCII"))?;
+        request = request
+            .with_header(
+                HeaderName::from_str(name.as_str()).map_err(|e| e.to_string())?,
+                value,
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(request.with_body(match body {
+        ReceivedBody::Memory(body) => Body::from(body),
+        ReceivedBody::File(body) => Body::from_read(body),
+    }))
+}
+
+/// The authority of the request, from its `Host` header
+///
+/// It is validated so that it can't change the path of the request URL.
+fn request_host(parts: &Parts) -> Result<Authority, String> {
+    let Some(host) = parts.headers.get(hyper::header::HOST) else {
+        return Ok(parts
+            .uri
+            .authority()
+            .cloned()
+            .unwrap_or_else(|| Authority::from_static("localhost")));
+    };
+    let authority = host
+        .to_str()
+        .ok()
+        .and_then(|host| Authority::from_str(host).ok())
+        .ok_or_else(|| "Invalid Host header".to_owned())?;
+    if authority.as_str().contains('@') {
+        return Err("The Host header must not contain user information".into());
+    }
+    Ok(authority)
+}
+
+/// Converts the oxhttp response status and headers, the body is streamed separately
+fn response_head(response: &Response) -> Result<hyper::Response<()>, String> {
+    let mut head = hyper::Response::new(());
+    *head.status_mut() =
+        StatusCode::from_u16(u16::from(response.status())).map_err(|e| e.to_string())?;
+    let headers = head.headers_mut();
+    headers.insert(
+        SERVER,
+        HyperHeaderValue::from_static(concat!("Oxigraph/", env!("CARGO_PKG_VERSION"))),
+    );
+    for (name, value) in response.headers().iter() {
+        headers.append(
+            hyper::header::HeaderName::from_str(name).map_err(|e| e.to_string())?,
+            HyperHeaderValue::from_bytes(value).map_err(|e| e.to_string())?,
+        );
+    }
+    Ok(head)
+}
+
+/// A request body fully received from the client
+enum ReceivedBody {
+    Memory(Vec<u8>),
+    File(TemporaryFile),
+}
+
+/// Receives the request body, writing it to a temporary file if it is larger than [`MAX_IN_MEMORY_REQUEST_BODY_SIZE`]
+///
+/// Fails with `408 Request Timeout` if the client does not send anything during `idle_timeout`
+/// and with `413 Payload Too Large` if the body is larger than `max_size` bytes.
+async fn receive_request_body<B>(
+    body: B,
+    idle_timeout: Duration,
+    max_size: u64,
+) -> Result<ReceivedBody, (StatusCode, String)>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let too_large = || {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("The request body must not be larger than {max_size} bytes"),
+        )
+    };
+    let mut body = body;
+    // The Content-Length header gives the exact size
+    if body.size_hint().lower() > max_size {
+        return Err(too_large());
+    }
+    let mut size = 0;
+    let mut buffer = Vec::new();
+    let mut file = None;
+    loop {
+        let frame = match timeout(idle_timeout, body.frame()).await {
+            Ok(Some(Ok(frame))) => frame,
+            Ok(Some(Err(e))) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Failed to read the request body: {}", e.into()),
+                ))
+            }
+            Ok(None) => break,
+            Err(_) => {
+                return Err((
+                    StatusCode::REQUEST_TIMEOUT,
+                    "The request body has not been received in time".into(),
+                ))
+            }
+        };
+        let Ok(data) = frame.into_data() else {
+            continue; // Trailers
+        };
+        size += u64::try_from(data.len()).map_err(|_| too_large())?;
+        if size > max_size {
+            return Err(too_large());
+        }
+        if let Some((file, _)) = &mut file {
+            write_to_temporary_file(file, &data).await?;
+        } else {
+            buffer.extend_from_slice(&data);
+            if buffer.len() > MAX_IN_MEMORY_REQUEST_BODY_SIZE {
+                let path = TemporaryPath(
+                    temp_dir().join(format!("oxigraph-request-{:032x}", random::<u128>())),
+                );
+                let mut new_file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create_new(true)
+                    .open(&path.0)
+                    .await
+                    .map_err(temporary_file_error)?;
+                write_to_temporary_file(&mut new_file, &buffer).await?;
+                buffer = Vec::new();
+                file = Some((new_file, path));
+            }
+        }
+    }
+    let Some((mut file, path)) = file else {
+        return Ok(ReceivedBody::Memory(buffer));
+    };
+    file.flush().await.map_err(temporary_file_error)?;
+    file.seek(SeekFrom::Start(0))
+        .await
+        .map_err(temporary_file_error)?;
+    Ok(ReceivedBody::File(TemporaryFile {
+        file: file.into_std().await,
+        _path: path,
+    }))
+}
+
+async fn write_to_temporary_file(file: &mut File, data: &[u8]) -> Result<(), (StatusCode, String)> {
+    file.write_all(data).await.map_err(temporary_file_error)
+}
+
+fn temporary_file_error(error: io::Error) -> (StatusCode, String) {
+    eprintln!("Failed to write a request body to a temporary file: {error}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Failed to store the request body".into(),
+    )
+}
+
+/// A temporary file removed when dropped
+struct TemporaryFile {
+    // Declared before the path so that the file is closed before being removed
+    file: fs::File,
+    _path: TemporaryPath,
+}
+
+impl Read for TemporaryFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+struct TemporaryPath(PathBuf);
+
+impl Drop for TemporaryPath {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            eprintln!(
+                "Failed to remove the temporary file {}: {e}",
+                self.0.display()
+            );
+        }
+    }
+}
+
+/// Reads the response body on the worker thread and sends it to the connection task
+///
+/// Lazily serialized results are evaluated while being read so they can't be moved to another thread.
+/// The body is aborted if the client does not read a chunk during `send_timeout` so that the worker is freed.
+/// An empty chunk marks the end of the body.
+fn send_response_body(mut body: Body, sender: &mpsc::Sender<Chunk>, send_timeout: Duration) {
+    let runtime = Handle::current();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let chunk = match body.read(&mut buffer) {
+            Ok(len) => Ok(Bytes::copy_from_slice(&buffer[..len])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+        let is_last = chunk.as_ref().map_or(true, Bytes::is_empty);
+        if !matches!(
+            runtime.block_on(timeout(send_timeout, sender.send(chunk))),
+            Ok(Ok(()))
+        ) || is_last
+        {
+            return; // The client is gone, too slow or the body is complete
+        }
+    }
+}
+
+pub enum ResponseBody {
+    Full(Option<Bytes>),
+    /// Ends with an empty chunk, the body has been aborted if the channel is closed before
+    Channel(mpsc::Receiver<Chunk>),
+}
+
+impl HttpBody for ResponseBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<io::Result<Frame<Bytes>>>> {
+        match self.get_mut() {
+            Self::Full(data) => Poll::Ready(data.take().map(|data| Ok(Frame::data(data)))),
+            Self::Channel(receiver) => receiver.poll_recv(cx).map(|chunk| match chunk {
+                Some(Ok(chunk)) if chunk.is_empty() => None,
+                Some(chunk) => Some(chunk.map(Frame::data)),
+                None => Some(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "The response body has been aborted",
+                ))),
+            }),
+        }
+    }
+}
+
+fn simple_response(status: StatusCode, message: &str) -> hyper::Response<ResponseBody> {
+    let mut response =
+        hyper::Response::new(ResponseBody::Full(Some(Bytes::from(message.to_owned()))));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HyperHeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    response
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+    use std::net::Ipv4Addr;
+
+    fn state(max_admitted: usize) -> Arc<ServerState> {
+        Arc::new(ServerState {
+            handler: Arc::new(|request: &mut Request, _: &Peer| {
+                let mut body = String::new();
+                if let Err(e) = request.body_mut().read_to_string(&mut body) {
+                    return crate::error(Status::BAD_REQUEST, e);
+                }
+                Response::builder(Status::OK)
+                    .with_header(HeaderName::CONTENT_TYPE, "text/plain")
+                    .unwrap()
+                    .with_body(format!("{} {body}", request.url().path()))
+            }),
+            admitted: AtomicUsize::new(0),
+            max_admitted,
+            timeout: Duration::from_secs(10),
+            max_body_size: 16,
+        })
+    }
+
+    async fn call(
+        state: Arc<ServerState>,
+        body: &'static str,
+    ) -> anyhow::Result<(StatusCode, String)> {
+        let request = hyper::Request::post("/foo")
+            .header("Host", "example.com")
+            .body(Full::new(Bytes::from_static(body.as_bytes())))?;
+        let response = handle(
+            request,
+            state,
+            Arc::new(Peer {
+                address: Some(SocketAddr::from((Ipv4Addr::LOCALHOST, 1234))),
+                certificate_subject: None,
+            }),
+        )
+        .await
+        .unwrap_or_else(|e| match e {});
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        Ok((status, String::from_utf8(body.to_vec())?))
+    }
+
+    /// A body of an unknown size
+    struct ChunkedBody(Vec<&'static str>);
+
+    impl HttpBody for ChunkedBody {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            _: &mut TaskContext<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            let chunks = &mut self.get_mut().0;
+            Poll::Ready(
+                (!chunks.is_empty())
+                    .then(|| Ok(Frame::data(Bytes::from_static(chunks.remove(0).as_bytes())))),
+            )
+        }
+    }
+
+    #[test]
+    fn test_handle() -> anyhow::Result<()> {
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let state = state(1);
+                assert_eq!(
+                    call(Arc::clone(&state), "bar").await?,
+                    (StatusCode::OK, "/foo bar".into())
+                );
+                assert_eq!(state.admitted.load(Ordering::SeqCst), 0);
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn test_overloaded() -> anyhow::Result<()> {
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let state = state(1);
+                let permit = state.admit();
+                assert!(permit.is_some());
+                assert_eq!(
+                    call(Arc::clone(&state), "").await?.0,
+                    StatusCode::SERVICE_UNAVAILABLE
+                );
+                drop(permit);
+                assert_eq!(call(state, "").await?.0, StatusCode::OK);
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn test_body_too_large() -> anyhow::Result<()> {
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let state = state(1);
+                assert_eq!(
+                    call(Arc::clone(&state), "01234567890123456789").await?.0,
+                    StatusCode::PAYLOAD_TOO_LARGE
+                );
+                assert_eq!(state.admitted.load(Ordering::SeqCst), 0);
+
+                // Without Content-Length, the body is checked while being received
+                let body = ChunkedBody(vec!["0123456789", "0123456789"]);
+                assert!(matches!(
+                    receive_request_body(body, Duration::from_secs(10), 16).await,
+                    Err((StatusCode::PAYLOAD_TOO_LARGE, _))
+                ));
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn test_request_host() -> anyhow::Result<()> {
+        for (host, expected) in [
+            ("example.com", Some("example.com")),
+            ("example.com:8080", Some("example.com:8080")),
+            ("[::1]:7878", Some("[::1]:7878")),
+            ("example.com/admin", None),
+            ("user@example.com", None),
+            ("example.com?", None),
+            ("", None),
+        ] {
+            let (parts, ()) = hyper::Request::get("/query")
+                .header("Host", host)
+                .body(())?
+                .into_parts();
+            assert_eq!(
+                request_host(&parts).ok().as_ref().map(Authority::as_str),
+                expected,
+                "{host}"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,476 @@
+//! The audit log of the SPARQL queries and updates.
+//!
+//! The Graph Store writes, the saved query calls, the fragments and the admin requests are logged too.
+//! Each operation is written as a JSON object on its own line:
+//! ```json
+//! {"timestamp":"2024-01-01T00:00:00Z","client":"192.0.2.1","principal":"alice","operation":"update","text":"INSERT DATA { ... }","duration_ms":3,"inserted":1,"deleted":0}
+//! ```
+//! Query entries have a `results` key with the number of returned solutions or triples.
+//! The entries of the other requests have the request method and target as text.
+//! If the evaluation failed, the `error` key contains the error message.
+
+use crate::config::AuditConfig;
+use crate::{HttpError, Peer};
+use json_event_parser::{JsonEvent, ToWriteJsonWriter};
+use oxhttp::model::{HeaderName, Request};
+use oxigraph::sparql::UpdateSummary;
+use oxsdatatypes::DateTime;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{rename, File, OpenOptions};
+use std::io::{self, stdout, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+pub struct AuditLog {
+    sink: Mutex<AuditSink>,
+    hash_text: bool,
+    /// Maximal number of inserted and of deleted quads written per update, `None` if they are not written
+    max_recorded_changes: Option<usize>,
+    trusted_proxies: Vec<IpAddr>,
+}
+
+impl AuditLog {
+    pub fn open(config: &AuditConfig) -> io::Result<Self> {
+        let sink = if config.is_stdout() {
+            AuditSink::Stdout
+        } else {
+            let file = open_append(&config.path)?;
+            AuditSink::File {
+                size: file.metadata()?.len(),
+                file,
+                path: config.path.clone(),
+                max_size: config.max_file_size.map(|size| size * 1024 * 1024),
+                max_files: config.max_files,
+            }
+        };
+        Ok(Self {
+            sink: Mutex::new(sink),
+            hash_text: config.hash_text,
+            max_recorded_changes: config.record_changes.then_some(config.max_recorded_changes),
+            trusted_proxies: config.trusted_proxies.clone(),
+        })
+    }
+
+    fn write_line(&self, line: &[u8]) {
+        let mut sink = self.sink.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = sink.write_line(line) {
+            eprintln!("Failed to write to the audit log: {e}");
+        }
+    }
+}
+
+enum AuditSink {
+    Stdout,
+    File {
+        path: PathBuf,
+        file: File,
+        size: u64,
+        max_size: Option<u64>,
+        max_files: usize,
+    },
+}
+
+impl AuditSink {
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Stdout => {
+                let mut stdout = stdout().lock();
+                stdout.write_all(line)?;
+                stdout.flush()
+            }
+            Self::File {
+                path,
+                file,
+                size,
+                max_size,
+                max_files,
+            } => {
+                let len = u64::try_from(line.len()).unwrap_or(u64::MAX);
+                if max_size.is_some_and(|max_size| *size > 0 && *size + len > max_size) {
+                    *file = rotate(path, *max_files)?;
+                    *size = 0;
+                }
+                file.write_all(line)?;
+                *size += len;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Renames `path` to `path.1`, `path.1` to `path.2`... and opens a new empty file at `path`
+fn rotate(path: &Path, max_files: usize) -> io::Result<File> {
+    let rotated = |i: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{i}"));
+        PathBuf::from(name)
+    };
+    if max_files == 0 {
+        return File::create(path);
+    }
+    for i in (1..max_files).rev() {
+        let from = rotated(i);
+        if from.exists() {
+            rename(from, rotated(i + 1))?;
+        }
+    }
+    rename(path, rotated(1))?;
+    open_append(path)
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Creates the audit records of the operations done by a request
+#[derive(Clone, Default)]
+pub struct RequestAuditor {
+    log: Option<Arc<AuditLog>>,
+    client: Option<String>,
+    principal: Option<String>,
+}
+
+impl RequestAuditor {
+    pub fn new(
+        log: Option<Arc<AuditLog>>,
+        request: &Request,
+        peer: &Peer,
+        principal: Option<&str>,
+    ) -> Self {
+        let Some(log) = log else {
+            return Self::default();
+        };
+        Self {
+            client: peer
+                .address
+                .map(|address| client_address(request, address.ip(), &log.trusted_proxies))
+                .map(|address| address.to_string()),
+            log: Some(log),
+            principal: principal.map(Into::into),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.log.is_some()
+    }
+
+    /// The maximal number of inserted and of deleted quads to record, `None` if they are not recorded
+    pub fn max_recorded_changes(&self) -> Option<usize> {
+        self.log.as_ref()?.max_recorded_changes
+    }
+
+    /// Starts the record of a query or update, it is written when dropped
+    pub fn start(&self, operation: AuditOperation, text: &dyn fmt::Display) -> AuditRecord {
+        AuditRecord {
+            inner: self.log.as_ref().map(|log| {
+                Box::new(AuditRecordData {
+                    log: Arc::clone(log),
+                    client: self.client.clone(),
+                    principal: self.principal.clone(),
+                    operation,
+                    text: text.to_string(),
+                    start: Instant::now(),
+                    results: None,
+                    summary: None,
+                    error: None,
+                })
+            }),
+        }
+    }
+}
+
+/// The address of the client
+///
+/// It is the peer address, except if the peer is a trusted proxy:
+/// then the `X-Forwarded-For` hops are read from the last one until one is not a trusted proxy.
+fn client_address(request: &Request, peer: IpAddr, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    if !trusted_proxies.contains(&client) {
+        return client;
+    }
+    let Some(forwarded_for) = HeaderName::from_str("X-Forwarded-For")
+        .ok()
+        .and_then(|name| request.header(&name))
+        .and_then(|value| value.to_str().ok())
+    else {
+        return client;
+    };
+    for hop in forwarded_for.rsplit(',') {
+        let Ok(hop) = IpAddr::from_str(hop.trim()) else {
+            break; // Invalid value set by the proxy, we keep its address
+        };
+        client = hop;
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+    }
+    client
+}
+
+/// Displays the method and the target of a request like `PUT /store?graph=http%3A%2F%2Fexample.com%2Fg`
+pub struct RequestTarget<'a>(pub &'a Request);
+
+impl fmt::Display for RequestTarget<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.0.method(), self.0.url().path())?;
+        if let Some(query) = self.0.url().query() {
+            write!(f, "?{query}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum AuditOperation {
+    Query,
+    Update,
+    /// A write to the Graph Store Protocol endpoint
+    GraphStore,
+    /// A saved query call
+    Api,
+    Fragments,
+    Admin,
+}
+
+impl AuditOperation {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Query => "query",
+            Self::Update => "update",
+            Self::GraphStore => "graph_store",
+            Self::Api => "api",
+            Self::Fragments => "fragments",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+/// A query or update being evaluated, the log entry is written when the record is dropped
+///
+/// Query records are kept in the streamed response so that they count the returned results.
+pub struct AuditRecord {
+    inner: Option<Box<AuditRecordData>>,
+}
+
+struct AuditRecordData {
+    log: Arc<AuditLog>,
+    client: Option<String>,
+    principal: Option<String>,
+    operation: AuditOperation,
+    text: String,
+    start: Instant,
+    results: Option<u64>,
+    summary: Option<UpdateSummary>,
+    error: Option<String>,
+}
+
+impl AuditRecord {
+    /// Records the error if there is one
+    pub fn check<T>(&mut self, result: Result<T, HttpError>) -> Result<T, HttpError> {
+        if let (Err((_, message)), Some(inner)) = (&result, &mut self.inner) {
+            inner.error = Some(message.clone());
+        }
+        result
+    }
+
+    /// Counts the result or records its error
+    pub fn count_result<T, E: fmt::Display>(&mut self, result: Result<T, E>) -> Result<T, E> {
+        if let Some(inner) = &mut self.inner {
+            match &result {
+                Ok(_) => *inner.results.get_or_insert(0) += 1,
+                Err(e) => inner.error = Some(e.to_string()),
+            }
+        }
+        result
+    }
+
+    /// Counts `count` successfully returned results
+    pub fn count_results(&mut self, count: usize) {
+        if let Some(inner) = &mut self.inner {
+            *inner.results.get_or_insert(0) += u64::try_from(count).unwrap_or(u64::MAX);
+        }
+    }
+
+    /// Sets the result count to zero for queries returning no results
+    pub fn start_results(&mut self) {
+        if let Some(inner) = &mut self.inner {
+            inner.results.get_or_insert(0);
+        }
+    }
+
+    pub fn set_update_summary(&mut self, summary: UpdateSummary) {
+        if let Some(inner) = &mut self.inner {
+            inner.summary = Some(summary);
+        }
+    }
+}
+
+impl Drop for AuditRecord {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            match inner.to_json_line() {
+                Ok(line) => inner.log.write_line(&line),
+                Err(e) => eprintln!("Failed to serialize an audit log entry: {e}"),
+            }
+        }
+    }
+}
+
+impl AuditRecordData {
+    fn to_json_line(&self) -> io::Result<Vec<u8>> {
+        let duration = self.start.elapsed().as_millis().to_string();
+        let mut writer = ToWriteJsonWriter::new(Vec::new());
+        writer.write_event(JsonEvent::StartObject)?;
+        writer.write_event(JsonEvent::ObjectKey("timestamp".into()))?;
+        writer.write_event(JsonEvent::String(DateTime::now().to_string().into()))?;
+        for (key, value) in [("client", &self.client), ("principal", &self.principal)] {
+            writer.write_event(JsonEvent::ObjectKey(key.into()))?;
+            writer.write_event(if let Some(value) = value {
+                JsonEvent::String(value.into())
+            } else {
+                JsonEvent::Null
+            })?;
+        }
+        writer.write_event(JsonEvent::ObjectKey("operation".into()))?;
+        writer.write_event(JsonEvent::String(self.operation.as_str().into()))?;
+        if self.log.hash_text {
+            writer.write_event(JsonEvent::ObjectKey("text_sha256".into()))?;
+            writer.write_event(JsonEvent::String(
+                hex::encode(Sha256::digest(&self.text)).into(),
+            ))?;
+        } else {
+            writer.write_event(JsonEvent::ObjectKey("text".into()))?;
+            writer.write_event(JsonEvent::String(self.text.as_str().into()))?;
+        }
+        writer.write_event(JsonEvent::ObjectKey("duration_ms".into()))?;
+        writer.write_event(JsonEvent::Number(duration.into()))?;
+        if let Some(results) = self.results {
+            writer.write_event(JsonEvent::ObjectKey("results".into()))?;
+            writer.write_event(JsonEvent::Number(results.to_string().into()))?;
+        }
+        if let Some(summary) = &self.summary {
+            writer.write_event(JsonEvent::ObjectKey("inserted".into()))?;
+            writer.write_event(JsonEvent::Number(
+                summary.inserted_count().to_string().into(),
+            ))?;
+            writer.write_event(JsonEvent::ObjectKey("deleted".into()))?;
+            writer.write_event(JsonEvent::Number(
+                summary.deleted_count().to_string().into(),
+            ))?;
+            for (key, quads) in [
+                ("inserted_quads", summary.inserted_quads()),
+                ("deleted_quads", summary.deleted_quads()),
+            ] {
+                let Some(quads) = quads else {
+                    continue;
+                };
+                writer.write_event(JsonEvent::ObjectKey(key.into()))?;
+                writer.write_event(JsonEvent::StartArray)?;
+                for quad in quads {
+                    writer.write_event(JsonEvent::String(format!("{quad} .").into()))?;
+                }
+                writer.write_event(JsonEvent::EndArray)?;
+            }
+        }
+        if let Some(error) = &self.error {
+            writer.write_event(JsonEvent::ObjectKey("error".into()))?;
+            writer.write_event(JsonEvent::String(error.into()))?;
+        }
+        writer.write_event(JsonEvent::EndObject)?;
+        let mut line = writer.finish()?;
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use oxhttp::model::Method;
+    use std::env::temp_dir;
+    use std::fs::{read_to_string, remove_file};
+
+    #[test]
+    fn test_record() -> anyhow::Result<()> {
+        let path = temp_dir().join(format!("oxigraph-audit-{}.log", rand::random::<u128>()));
+        let log = Arc::new(AuditLog::open(&AuditConfig::new(path.clone()))?);
+        let request = Request::builder(Method::GET, "http://localhost/query".parse()?).build();
+        let peer = Peer {
+            address: Some("192.0.2.2:1234".parse()?),
+            certificate_subject: None,
+        };
+        let auditor = RequestAuditor::new(Some(log), &request, &peer, Some("alice"));
+        let mut record = auditor.start(AuditOperation::Query, &"ASK {}");
+        record.count_result(Ok::<_, io::Error>(())).ok();
+        drop(record);
+        let mut record = auditor.start(AuditOperation::Update, &"CLEAR ALL");
+        record
+            .check(Err::<(), _>(crate::bad_request("Invalid update")))
+            .ok();
+        drop(record);
+        let content = read_to_string(&path)?;
+        remove_file(&path)?;
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(
+            r#""client":"192.0.2.2","principal":"alice","operation":"query","text":"ASK {}""#
+        ));
+        assert!(lines[0].contains(r#""results":1"#));
+        assert!(lines[1].contains(r#""operation":"update""#));
+        assert!(lines[1].contains(r#""error":"Invalid update""#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_address() -> anyhow::Result<()> {
+        let proxies = ["10.0.0.1".parse()?, "10.0.0.2".parse()?];
+        let request = |forwarded_for: &str| {
+            Request::builder(Method::GET, "http://localhost/query".parse()?)
+                .with_header(HeaderName::from_str("X-Forwarded-For")?, forwarded_for)
+                .map(|builder| builder.build())
+                .map_err(anyhow::Error::from)
+        };
+        let spoofed = request("192.0.2.1")?;
+        assert_eq!(
+            client_address(&spoofed, "192.0.2.9".parse()?, &proxies),
+            "192.0.2.9".parse::<IpAddr>()?
+        );
+        let forwarded = request("192.0.2.1, 192.0.2.2, 10.0.0.2")?;
+        assert_eq!(
+            client_address(&forwarded, "10.0.0.1".parse()?, &proxies),
+            "192.0.2.2".parse::<IpAddr>()?
+        );
+        let invalid = request("foo")?;
+        assert_eq!(
+            client_address(&invalid, "10.0.0.1".parse()?, &proxies),
+            "10.0.0.1".parse::<IpAddr>()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotate() -> anyhow::Result<()> {
+        let path = temp_dir().join(format!("oxigraph-audit-{}.log", rand::random::<u128>()));
+        let mut sink = AuditSink::File {
+            file: open_append(&path)?,
+            path: path.clone(),
+            size: 0,
+            max_size: Some(4),
+            max_files: 1,
+        };
+        sink.write_line(b"foo\n")?;
+        sink.write_line(b"bar\n")?;
+        sink.write_line(b"baz\n")?;
+        let rotated = PathBuf::from(format!("{}.1", path.display()));
+        assert_eq!(read_to_string(&path)?, "baz\n");
+        assert_eq!(read_to_string(&rotated)?, "bar\n");
+        remove_file(path)?;
+        remove_file(rotated)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,669 @@
+//! Authentication and per-graph authorization for the HTTP server.
+//!
+//! The access control file is a line-based format where `#` starts a comment:
+//! ```text
+//! # Static API token: the SHA-256 of the token, hex encoded
+//! token <principal> <sha256-of-token>
+//! # HTTP Basic user: a PBKDF2-HMAC-SHA256 password hash
+//! user <principal> pbkdf2-sha256:<iterations>:<salt-hex>:<hash-hex>
+//! # Key to verify HS256 JWT bearer tokens, the principal is the "sub" claim
+//! jwt-hs256-key <hex-key>
+//! # Requests without Authorization header but with a TLS client certificate verified against
+//! # the client certificate authorities get the certificate subject common name as principal.
+//! # Access rule: <principal> is a principal name, "*" for every authenticated principal
+//! # or "anonymous" for requests without credentials. These two names can't be used by the token,
+//! # user and JWT principals.
+//! # <graph> is a graph IRI between <>, "default" for the default graph or "*" for all graphs.
+//! # <dataset> restricts the rule to a dataset when serving several datasets, the rule applies to
+//! # all of them if it is omitted or "*".
+//! grant <principal> read|write <graph> [<dataset>]
+//! ```
+
+use crate::HttpError;
+use anyhow::{bail, ensure, Context};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use oxhttp::model::{HeaderName, Request, Status};
+use oxigraph::model::{GraphName, NamedNode};
+use oxigraph::sparql::{QueryDataset, Update};
+use pbkdf2::pbkdf2_hmac;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use subtle::ConstantTimeEq;
+
+/// The principal names with a special meaning in the grants
+const RESERVED_PRINCIPALS: [&str; 2] = ["*", "anonymous"];
+
+/// The authentication methods and access rules of the server.
+pub struct AccessControl {
+    tokens: HashMap<[u8; 32], String>,
+    users: HashMap<String, PasswordHash>,
+    jwt_key: Option<Vec<u8>>,
+    grants: Vec<Grant>,
+    /// SHA-256 of the already verified `user:password` pairs to avoid running PBKDF2 on each request
+    verified_passwords: Mutex<HashSet<[u8; 32]>>,
+}
+
+struct PasswordHash {
+    iterations: u32,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+struct Grant {
+    principal: String,
+    write: bool,
+    /// `None` if all graphs
+    graph: Option<GraphName>,
+    /// `None` if all datasets
+    dataset: Option<String>,
+}
+
+impl AccessControl {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .parse()
+            .with_context(|| format!("Invalid access control file {}", path.display()))
+    }
+
+    /// Authenticates the request using its `Authorization` header and returns its permissions.
+    ///
+    /// If there is no `Authorization` header, the principal is the common name of the verified TLS client certificate, if any.
+    /// Requests without credentials get the permissions of the `anonymous` principal.
+    pub fn authenticate(
+        &self,
+        request: &Request,
+        client_certificate_subject: Option<&str>,
+    ) -> Result<Permissions, HttpError> {
+        let Some(authorization) = request.header(&HeaderName::AUTHORIZATION) else {
+            let Some(subject) = client_certificate_subject else {
+                return Ok(self.permissions(None));
+            };
+            if RESERVED_PRINCIPALS.contains(&subject) {
+                return Err(unauthorized(format!(
+                    "{subject} is a reserved principal name"
+                )));
+            }
+            return Ok(self.permissions(Some(subject.into())));
+        };
+        let authorization = authorization
+            .to_str()
+            .map_err(|_| unauthorized("Invalid Authorization header"))?;
+        let (scheme, credentials) = authorization
+            .split_once(' ')
+            .ok_or_else(|| unauthorized("Invalid Authorization header"))?;
+        let credentials = credentials.trim();
+        let principal = if scheme.eq_ignore_ascii_case("Bearer") {
+            let token_hash: [u8; 32] = Sha256::digest(credentials).into();
+            if let Some(principal) = self.tokens.get(&token_hash) {
+                principal.clone()
+            } else if let Some(key) = &self.jwt_key {
+                verify_jwt(key, credentials).ok_or_else(|| unauthorized("Invalid bearer token"))?
+            } else {
+                return Err(unauthorized("Invalid bearer token"));
+            }
+        } else if scheme.eq_ignore_ascii_case("Basic") {
+            let credentials = STANDARD
+                .decode(credentials)
+                .ok()
+                .and_then(|c| String::from_utf8(c).ok())
+                .ok_or_else(|| unauthorized("Invalid Basic credentials encoding"))?;
+            let (user, password) = credentials
+                .split_once(':')
+                .ok_or_else(|| unauthorized("Invalid Basic credentials encoding"))?;
+            if !self.verify_password(user, password) {
+                return Err(unauthorized("Invalid user or password"));
+            }
+            user.to_owned()
+        } else {
+            return Err(unauthorized(format!(
+                "The {scheme} authentication scheme is not supported"
+            )));
+        };
+        Ok(self.permissions(Some(principal)))
+    }
+
+    fn verify_password(&self, user: &str, password: &str) -> bool {
+        let Some(expected) = self.users.get(user) else {
+            return false;
+        };
+        let key: [u8; 32] = Sha256::new()
+            .chain_update(user)
+            .chain_update(":")
+            .chain_update(password)
+            .finalize()
+            .into();
+        if self
+            .verified_passwords
+            .lock()
+            .map_or(false, |verified| verified.contains(&key))
+        {
+            return true;
+        }
+        let mut hash = vec![0; expected.hash.len()];
+        pbkdf2_hmac::<Sha256>(
+            password.as_bytes(),
+            &expected.salt,
+            expected.iterations,
+            &mut hash,
+        );
+        if !bool::from(hash.ct_eq(&expected.hash)) {
+            return false;
+        }
+        if let Ok(mut verified) = self.verified_passwords.lock() {
+            verified.insert(key);
+        }
+        true
+    }
+
+    fn parse_line(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        match parts {
+            [] => (),
+            ["token", principal, hash] => {
+                check_principal_name(principal)?;
+                let hash = <[u8; 32]>::try_from(hex::decode(hash)?.as_slice())
+                    .context("The token hash must be a SHA-256 hash")?;
+                self.tokens.insert(hash, (*principal).into());
+            }
+            ["user", principal, hash] => {
+                check_principal_name(principal)?;
+                let [algorithm, iterations, salt, hash] = hash.split(':').collect::<Vec<_>>()[..]
+                else {
+                    bail!("The password hash must be of the form pbkdf2-sha256:<iterations>:<salt-hex>:<hash-hex>")
+                };
+                if algorithm != "pbkdf2-sha256" {
+                    bail!("The password hash algorithm {algorithm} is not supported, only pbkdf2-sha256 is")
+                }
+                let iterations = iterations.parse()?;
+                ensure!(
+                    iterations > 0,
+                    "The PBKDF2 iteration count must be positive"
+                );
+                let hash = hex::decode(hash)?;
+                ensure!(!hash.is_empty(), "The password hash must not be empty");
+                self.users.insert(
+                    (*principal).into(),
+                    PasswordHash {
+                        iterations,
+                        salt: hex::decode(salt)?,
+                        hash,
+                    },
+                );
+            }
+            ["jwt-hs256-key", key] => self.jwt_key = Some(hex::decode(key)?),
+            ["grant", principal, access, graph, dataset @ ..] if dataset.len() <= 1 => {
+                let write = match *access {
+                    "read" => false,
+                    "write" => true,
+                    _ => bail!("The access mode must be read or write, found {access}"),
+                };
+                let graph = match *graph {
+                    "*" => None,
+                    "default" => Some(GraphName::DefaultGraph),
+                    graph => Some(
+                        NamedNode::new(
+                            graph
+                                .strip_prefix('<')
+                                .and_then(|g| g.strip_suffix('>'))
+                                .unwrap_or(graph),
+                        )?
+                        .into(),
+                    ),
+                };
+                let dataset = match dataset.first() {
+                    None | Some(&"*") => None,
+                    Some(dataset) => Some((*dataset).into()),
+                };
+                self.grants.push(Grant {
+                    principal: (*principal).into(),
+                    write,
+                    graph,
+                    dataset,
+                });
+            }
+            _ => bail!("Unexpected line {}", parts.join(" ")),
+        }
+        Ok(())
+    }
+
+    fn permissions(&self, principal: Option<String>) -> Permissions {
+        let mut read = GraphSet::default();
+        let mut write = GraphSet::default();
+        let mut dataset_grants = Vec::new();
+        for grant in &self.grants {
+            let applies = match &principal {
+                Some(principal) => grant.principal == "*" || grant.principal == *principal,
+                None => grant.principal == "anonymous",
+            };
+            if !applies {
+                continue;
+            }
+            if let Some(dataset) = &grant.dataset {
+                dataset_grants.push(DatasetGrant {
+                    dataset: dataset.clone(),
+                    write: grant.write,
+                    graph: grant.graph.clone(),
+                });
+            } else {
+                read.add(grant.graph.as_ref());
+                if grant.write {
+                    write.add(grant.graph.as_ref());
+                }
+            }
+        }
+        Permissions {
+            principal,
+            read,
+            write,
+            dataset_grants,
+        }
+    }
+}
+
+impl FromStr for AccessControl {
+    type Err = anyhow::Error;
+
+    fn from_str(config: &str) -> anyhow::Result<Self> {
+        let mut access_control = Self {
+            tokens: HashMap::new(),
+            users: HashMap::new(),
+            jwt_key: None,
+            grants: Vec::new(),
+            verified_passwords: Mutex::new(HashSet::new()),
+        };
+        for (i, line) in config.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(l, _)| l);
+            access_control
+                .parse_line(&line.split_whitespace().collect::<Vec<_>>())
+                .with_context(|| format!("Error on line {}", i + 1))?;
+        }
+        Ok(access_control)
+    }
+}
+
+/// The graphs a request is allowed to read and write.
+#[derive(Clone)]
+pub struct Permissions {
+    /// `None` if the request is not authenticated
+    principal: Option<String>,
+    read: GraphSet,
+    write: GraphSet,
+    /// The grants restricted to a dataset, not included in `read` and `write`
+    dataset_grants: Vec<DatasetGrant>,
+}
+
+#[derive(Clone)]
+struct DatasetGrant {
+    dataset: String,
+    write: bool,
+    /// `None` if all graphs
+    graph: Option<GraphName>,
+}
+
+impl Permissions {
+    /// Permissions when no access control is configured: everything is allowed.
+    pub fn all() -> Self {
+        Self {
+            principal: None,
+            read: GraphSet::all(),
+            write: GraphSet::all(),
+            dataset_grants: Vec::new(),
+        }
+    }
+
+    /// The permissions on the dataset `name`: the ones on all datasets and the ones restricted to it
+    pub fn for_dataset(&self, name: &str) -> Self {
+        let mut read = self.read.clone();
+        let mut write = self.write.clone();
+        for grant in &self.dataset_grants {
+            if grant.dataset == name {
+                read.add(grant.graph.as_ref());
+                if grant.write {
+                    write.add(grant.graph.as_ref());
+                }
+            }
+        }
+        Self {
+            principal: self.principal.clone(),
+            read,
+            write,
+            dataset_grants: Vec::new(),
+        }
+    }
+
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+
+    pub fn can_read(&self, graph: &GraphName) -> bool {
+        self.read.contains(graph)
+    }
+
+    pub fn check_read(&self, graph: &GraphName) -> Result<(), HttpError> {
+        if self.can_read(graph) {
+            Ok(())
+        } else {
+            Err(self.forbidden(format!("read the graph {graph}")))
+        }
+    }
+
+    pub fn check_read_any(&self) -> Result<(), HttpError> {
+        if self.read.is_empty() {
+            Err(self.forbidden("read any graph"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that all graphs are readable, e.g. to expose statistics about the whole store
+    pub fn check_read_all(&self) -> Result<(), HttpError> {
+        if self.read.all {
+            Ok(())
+        } else {
+            Err(self.forbidden("read all graphs"))
+        }
+    }
+
+    pub fn check_write(&self, graph: &GraphName) -> Result<(), HttpError> {
+        if self.write.contains(graph) {
+            Ok(())
+        } else {
+            Err(self.forbidden(format!("write the graph {graph}")))
+        }
+    }
+
+    pub fn check_write_all(&self) -> Result<(), HttpError> {
+        if self.write.all {
+            Ok(())
+        } else {
+            Err(self.forbidden("write all graphs"))
+        }
+    }
+
+    /// Checks that the request is authenticated and allowed to write all graphs
+    pub fn check_admin(&self) -> Result<(), HttpError> {
+        if self.principal.is_some() && self.write.all {
+            Ok(())
+        } else {
+            Err(self.forbidden("maintain the database"))
+        }
+    }
+
+    /// Restricts the query dataset to the readable graphs
+    pub fn restrict_dataset(&self, dataset: &mut QueryDataset) -> Result<(), HttpError> {
+        self.check_read_any()?;
+        if !self.read.all {
+            dataset.restrict_to_graphs(&self.read.graphs);
+        }
+        Ok(())
+    }
+
+    /// Checks that all the graphs modified by the update are writable
+    /// and restricts the graphs the update is reading from to the readable ones
+    pub fn restrict_update(&self, update: &mut Update) -> Result<(), HttpError> {
+        if !self.write.all {
+            let Some(modified_graphs) = update.modified_graphs() else {
+                return Err(self.forbidden("write all graphs"));
+            };
+            for graph in &modified_graphs {
+                self.check_write(graph)?;
+            }
+        }
+        if !self.read.all {
+            for dataset in update.using_datasets_mut() {
+                dataset.restrict_to_graphs(&self.read.graphs);
+            }
+        }
+        Ok(())
+    }
+
+    fn forbidden(&self, action: impl Into<String>) -> HttpError {
+        if let Some(principal) = &self.principal {
+            (
+                Status::FORBIDDEN,
+                format!("{principal} is not allowed to {}", action.into()),
+            )
+        } else {
+            unauthorized(format!("Authentication is required to {}", action.into()))
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct GraphSet {
+    all: bool,
+    graphs: Vec<GraphName>,
+}
+
+impl GraphSet {
+    fn all() -> Self {
+        Self {
+            all: true,
+            graphs: Vec::new(),
+        }
+    }
+
+    /// Adds a graph, `None` meaning all graphs
+    fn add(&mut self, graph: Option<&GraphName>) {
+        if let Some(graph) = graph {
+            if !self.graphs.contains(graph) {
+                self.graphs.push(graph.clone());
+            }
+        } else {
+            self.all = true;
+        }
+    }
+
+    fn contains(&self, graph: &GraphName) -> bool {
+        self.all || self.graphs.contains(graph)
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.all && self.graphs.is_empty()
+    }
+}
+
+fn unauthorized(message: impl Into<String>) -> HttpError {
+    (Status::UNAUTHORIZED, message.into())
+}
+
+fn check_principal_name(principal: &str) -> anyhow::Result<()> {
+    ensure!(
+        !RESERVED_PRINCIPALS.contains(&principal),
+        "{principal} is a reserved principal name"
+    );
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    sub: String,
+}
+
+/// Verifies a HS256 JWT and returns its subject
+///
+/// The `exp` and `nbf` claims are checked if present.
+fn verify_jwt(key: &[u8], token: &str) -> Option<String> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = 0;
+    validation.validate_nbf = true;
+    validation.validate_aud = false;
+    validation.required_spec_claims = HashSet::from(["sub".into()]);
+    let sub = jsonwebtoken::decode::<JwtClaims>(token, &DecodingKey::from_secret(key), &validation)
+        .ok()?
+        .claims
+        .sub;
+    (!RESERVED_PRINCIPALS.contains(&sub.as_str())).then_some(sub)
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use jsonwebtoken::EncodingKey;
+
+    #[test]
+    fn test_basic() -> anyhow::Result<()> {
+        // bob uses the "password" password
+        let access_control = AccessControl::from_str(
+            "user bob pbkdf2-sha256:1000:73616c74:632c2812e46d4604102ba7618e9d6d7d2f8128f6266b4a03264d2a0460b7dcb3
+            grant bob read default",
+        )?;
+        let request = |credentials: &str| -> anyhow::Result<Request> {
+            Ok(Request::builder(
+                oxhttp::model::Method::GET,
+                "http://localhost/query".parse()?,
+            )
+            .with_header(HeaderName::AUTHORIZATION, format!("Basic {credentials}"))?
+            .build())
+        };
+        let permissions = access_control
+            .authenticate(&request(&STANDARD.encode("bob:password"))?, None)
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        assert_eq!(permissions.principal(), Some("bob"));
+        assert!(permissions.can_read(&GraphName::DefaultGraph));
+        assert!(access_control
+            .authenticate(&request(&STANDARD.encode("bob:wrong"))?, None)
+            .is_err());
+        assert!(access_control
+            .authenticate(&request("not base64")?, None)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_certificate() -> anyhow::Result<()> {
+        let access_control = AccessControl::from_str("grant alice read default")?;
+        let request = Request::builder(
+            oxhttp::model::Method::GET,
+            "http://localhost/query".parse()?,
+        )
+        .build();
+        let permissions = access_control
+            .authenticate(&request, Some("alice"))
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        assert_eq!(permissions.principal(), Some("alice"));
+        assert!(permissions.can_read(&GraphName::DefaultGraph));
+        let permissions = access_control
+            .authenticate(&request, None)
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        assert!(!permissions.can_read(&GraphName::DefaultGraph));
+        assert!(access_control
+            .authenticate(&request, Some("anonymous"))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt() -> anyhow::Result<()> {
+        fn sign(key: &[u8], header: &str, payload: &str) -> anyhow::Result<String> {
+            let signed = format!(
+                "{}.{}",
+                URL_SAFE_NO_PAD.encode(header),
+                URL_SAFE_NO_PAD.encode(payload)
+            );
+            let signature = jsonwebtoken::crypto::sign(
+                signed.as_bytes(),
+                &EncodingKey::from_secret(key),
+                Algorithm::HS256,
+            )?;
+            Ok(format!("{signed}.{signature}"))
+        }
+
+        let access_control = AccessControl::from_str(
+            "jwt-hs256-key 6b6579\ngrant alice read <http://example.com/g>",
+        )?;
+        let header = r#"{"alg":"HS256","typ":"JWT"}"#;
+        let valid = sign(b"key", header, r#"{"sub":"alice","exp":99999999999}"#)?;
+        assert_eq!(verify_jwt(b"key", &valid).as_deref(), Some("alice"));
+        let request = Request::builder(
+            oxhttp::model::Method::GET,
+            "http://localhost/query".parse()?,
+        )
+        .with_header(HeaderName::AUTHORIZATION, format!("Bearer {valid}"))?
+        .build();
+        let permissions = access_control
+            .authenticate(&request, None)
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        assert!(permissions.can_read(&NamedNode::new("http://example.com/g")?.into()));
+        assert!(!permissions.can_read(&GraphName::DefaultGraph));
+
+        assert_eq!(
+            verify_jwt(b"other", &valid),
+            None,
+            "wrong key must be rejected"
+        );
+        let expired = sign(b"key", header, r#"{"sub":"alice","exp":1}"#)?;
+        assert_eq!(verify_jwt(b"key", &expired), None);
+        let not_yet_valid = sign(b"key", header, r#"{"sub":"alice","nbf":99999999999}"#)?;
+        assert_eq!(verify_jwt(b"key", &not_yet_valid), None);
+        let without_expiration = sign(b"key", header, r#"{"sub":"alice"}"#)?;
+        assert_eq!(
+            verify_jwt(b"key", &without_expiration).as_deref(),
+            Some("alice")
+        );
+        let none = sign(b"key", r#"{"alg":"none"}"#, r#"{"sub":"alice"}"#)?;
+        assert_eq!(verify_jwt(b"key", &none), None);
+        let anonymous = sign(b"key", header, r#"{"sub":"anonymous"}"#)?;
+        assert_eq!(verify_jwt(b"key", &anonymous), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dataset_grants() -> anyhow::Result<()> {
+        let access_control = AccessControl::from_str(
+            "grant alice read * foo\ngrant alice write default bar\ngrant * read <http://example.com/g> *",
+        )?;
+        let request = Request::builder(
+            oxhttp::model::Method::GET,
+            "http://localhost/query".parse()?,
+        )
+        .build();
+        let permissions = access_control
+            .authenticate(&request, Some("alice"))
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        let graph = NamedNode::new("http://example.com/g")?.into();
+        assert!(permissions.can_read(&graph));
+        assert!(!permissions.can_read(&GraphName::DefaultGraph));
+
+        let foo = permissions.for_dataset("foo");
+        assert!(foo.can_read(&GraphName::DefaultGraph));
+        assert!(foo.check_read_all().is_ok());
+        assert!(foo.check_write(&GraphName::DefaultGraph).is_err());
+
+        let bar = permissions.for_dataset("bar");
+        assert!(bar.can_read(&graph));
+        assert!(bar.check_write(&GraphName::DefaultGraph).is_ok());
+        assert!(bar.check_read_all().is_err());
+
+        let baz = permissions.for_dataset("baz");
+        assert!(baz.can_read(&graph));
+        assert!(!baz.can_read(&GraphName::DefaultGraph));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(AccessControl::from_str("grant alice admin *").is_err());
+        assert!(AccessControl::from_str("grant alice read * foo bar").is_err());
+        assert!(AccessControl::from_str("token alice foo").is_err());
+        assert!(AccessControl::from_str("user alice sha1:foo").is_err());
+        assert!(AccessControl::from_str("foo").is_err());
+        assert!(AccessControl::from_str(
+            "token anonymous 2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+        )
+        .is_err());
+        assert!(AccessControl::from_str(
+            "user * pbkdf2-sha256:1000:73616c74:632c2812e46d4604102ba7618e9d6d7d2f8128f6266b4a03264d2a0460b7dcb3"
+        )
+        .is_err());
+    }
+}
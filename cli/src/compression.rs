@@ -0,0 +1,382 @@
+//! HTTP content encoding negotiation and compression of the request and response bodies.
+
+use crate::{error, Peer};
+use flate2::read::{GzEncoder, MultiGzDecoder};
+use oxhttp::model::{Body, HeaderName, HeaderValue, Method, Request, Response, Status};
+use std::io::{self, Read};
+use std::mem::replace;
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW_SIZE: u32 = 22;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ContentEncoding {
+    Gzip,
+    Zstd,
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// The supported encodings ordered by preference
+    const ALL: [Self; 3] = [Self::Zstd, Self::Brotli, Self::Gzip];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Brotli => "br",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "zstd" => Some(Self::Zstd),
+            "br" => Some(Self::Brotli),
+            _ => None,
+        }
+    }
+
+    fn encode(self, body: Body) -> Body {
+        match self {
+            Self::Gzip => Body::from_read(GzEncoder::new(body, flate2::Compression::fast())),
+            Self::Zstd => match zstd::stream::read::Encoder::new(body, ZSTD_LEVEL) {
+                Ok(encoder) => Body::from_read(encoder),
+                Err(e) => Body::from_read(ErrorReader(Some(e))),
+            },
+            Self::Brotli => Body::from_read(brotli::CompressorReader::new(
+                body,
+                BROTLI_BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW_SIZE,
+            )),
+        }
+    }
+
+    fn decode(self, body: Body) -> io::Result<Body> {
+        Ok(match self {
+            Self::Gzip => Body::from_read(MultiGzDecoder::new(body)),
+            Self::Zstd => Body::from_read(zstd::stream::read::Decoder::new(body)?),
+            Self::Brotli => Body::from_read(brotli::Decompressor::new(body, BROTLI_BUFFER_SIZE)),
+        })
+    }
+}
+
+/// Decodes the request bodies according to their `Content-Encoding`
+/// and compresses the successful responses according to the `Accept-Encoding` request header.
+///
+/// The decoded request bodies are limited to `max_decoded_body_size` bytes.
+/// The ETags of the compressed responses get the encoding name as suffix so that each representation has its own strong ETag,
+/// the suffix is removed from the `If-Match` and `If-None-Match` request headers.
+pub fn compression_middleware(
+    on_request: impl Fn(&mut Request, &Peer) -> Response + Send + Sync + 'static,
+    max_decoded_body_size: u64,
+) -> impl Fn(&mut Request, &Peer) -> Response + Send + Sync + 'static {
+    let accept_encoding = HeaderValue::from_str("Accept-Encoding").unwrap();
+    move |request, peer| {
+        if let Err(response) = decode_request_body(request, max_decoded_body_size) {
+            return response;
+        }
+        remove_etag_encodings(request, &HeaderName::IF_MATCH);
+        remove_etag_encodings(request, &HeaderName::IF_NONE_MATCH);
+        let encoding = request
+            .header(&HeaderName::ACCEPT_ENCODING)
+            .and_then(|h| h.to_str().ok())
+            .and_then(negotiate_encoding);
+        let mut response = on_request(request, peer);
+        if response.status() != Status::OK && response.status() != Status::NOT_MODIFIED {
+            return response;
+        }
+        response
+            .headers_mut()
+            .append(HeaderName::VARY, accept_encoding.clone());
+        let Some(encoding) = encoding else {
+            return response;
+        };
+        if *request.method() == Method::HEAD
+            || response.header(&HeaderName::CONTENT_ENCODING).is_some()
+        {
+            return response;
+        }
+        if let Some(etag) = response
+            .header(&HeaderName::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .and_then(|etag| etag.strip_suffix('"'))
+            .and_then(|etag| HeaderValue::from_str(&format!("{etag}-{}\"", encoding.name())).ok())
+        {
+            response.headers_mut().set(HeaderName::ETAG, etag);
+        }
+        if response.status() == Status::NOT_MODIFIED {
+            return response;
+        }
+        let body = replace(response.body_mut(), Body::from(Vec::new()));
+        *response.body_mut() = encoding.encode(body);
+        response.headers_mut().append(
+            HeaderName::CONTENT_ENCODING,
+            HeaderValue::from_str(encoding.name()).unwrap(),
+        );
+        response
+    }
+}
+
+/// Removes the encoding suffixes added by [`compression_middleware`] from the entity tags of a request header
+fn remove_etag_encodings(request: &mut Request, header: &HeaderName) {
+    let Some(value) = request.header(header).and_then(|value| value.to_str().ok()) else {
+        return;
+    };
+    let mut changed = false;
+    let tags = value
+        .split(',')
+        .map(|tag| {
+            let tag = tag.trim();
+            for encoding in ContentEncoding::ALL {
+                if let Some(tag) = tag.strip_suffix(&format!("-{}\"", encoding.name())) {
+                    changed = true;
+                    return format!("{tag}\"");
+                }
+            }
+            tag.to_owned()
+        })
+        .collect::<Vec<_>>();
+    if !changed {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&tags.join(", ")) {
+        request.headers_mut().set(header.clone(), value);
+    }
+}
+
+fn decode_request_body(request: &mut Request, max_decoded_size: u64) -> Result<(), Response> {
+    let Some(encoding) = request.header(&HeaderName::CONTENT_ENCODING) else {
+        return Ok(());
+    };
+    let encoding = encoding
+        .to_str()
+        .map_err(|e| error(Status::BAD_REQUEST, e))?
+        .trim()
+        .to_ascii_lowercase();
+    if encoding == "identity" {
+        return Ok(());
+    }
+    let encoding = ContentEncoding::from_name(&encoding).ok_or_else(|| {
+        error(
+            Status::UNSUPPORTED_MEDIA_TYPE,
+            format!("The content encoding {encoding} is not supported, only gzip, zstd and br are"),
+        )
+    })?;
+    let body = replace(request.body_mut(), Body::from(Vec::new()));
+    *request.body_mut() = Body::from_read(SizeLimitedReader {
+        inner: encoding
+            .decode(body)
+            .map_err(|e| error(Status::BAD_REQUEST, e))?,
+        remaining: max_decoded_size,
+        limit: max_decoded_size,
+    });
+    Ok(())
+}
+
+/// Fails when more than `limit` bytes are read to protect against decompression bombs
+struct SizeLimitedReader<R: Read> {
+    inner: R,
+    remaining: u64,
+    limit: u64,
+}
+
+impl<R: Read> Read for SizeLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // We read one more byte than allowed to detect bodies that are too large
+        let max_len = usize::try_from(self.remaining.saturating_add(1))
+            .unwrap_or(usize::MAX)
+            .min(buf.len());
+        let len = self.inner.read(&mut buf[..max_len])?;
+        self.remaining = self
+            .remaining
+            .checked_sub(u64::try_from(len).map_err(io::Error::other)?)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "The decoded request body is larger than the limit of {} bytes",
+                        self.limit
+                    ),
+                )
+            })?;
+        Ok(len)
+    }
+}
+
+/// Returns the preferred supported encoding allowed by an `Accept-Encoding` header value
+pub fn negotiate_encoding(accept_encoding: &str) -> Option<ContentEncoding> {
+    let qualities = accept_encoding
+        .split(',')
+        .map(|item| {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|p| p.split_once('='))
+                .find(|(k, _)| k.trim().eq_ignore_ascii_case("q"))
+                .map_or(1., |(_, v)| v.trim().parse::<f32>().unwrap_or(0.));
+            (name, quality)
+        })
+        .collect::<Vec<_>>();
+    let quality = |name: &str| {
+        qualities
+            .iter()
+            .find(|(n, _)| n == name)
+            .or_else(|| qualities.iter().find(|(n, _)| n == "*"))
+            .map_or(0., |(_, q)| *q)
+    };
+    let mut best: Option<(ContentEncoding, f32)> = None;
+    for encoding in ContentEncoding::ALL {
+        let quality = quality(encoding.name());
+        if quality > 0. && best.map_or(true, |(_, q)| quality > q) {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Returns the given error on the first read
+struct ErrorReader(Option<io::Error>);
+
+impl Read for ErrorReader {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        if let Some(e) = self.0.take() {
+            Err(e)
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use std::io::read_to_string;
+
+    #[test]
+    fn test_negotiate_encoding() {
+        assert_eq!(negotiate_encoding("gzip"), Some(ContentEncoding::Gzip));
+        assert_eq!(
+            negotiate_encoding("gzip, deflate, br"),
+            Some(ContentEncoding::Brotli)
+        );
+        assert_eq!(
+            negotiate_encoding("gzip, br;q=0.5"),
+            Some(ContentEncoding::Gzip)
+        );
+        assert_eq!(negotiate_encoding("*"), Some(ContentEncoding::Zstd));
+        assert_eq!(
+            negotiate_encoding("*, zstd;q=0"),
+            Some(ContentEncoding::Brotli)
+        );
+        assert_eq!(negotiate_encoding("identity"), None);
+        assert_eq!(negotiate_encoding(""), None);
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let handler = compression_middleware(
+            |request, _| {
+                let body = read_to_string(request.body_mut()).unwrap_or_default();
+                Response::builder(Status::OK).with_body(body)
+            },
+            u64::MAX,
+        );
+        for encoding in ContentEncoding::ALL {
+            let mut compressed = Vec::new();
+            encoding
+                .encode(Body::from("foo"))
+                .read_to_end(&mut compressed)?;
+            let mut request = Request::builder(Method::POST, "http://localhost/".parse()?)
+                .with_header(HeaderName::CONTENT_ENCODING, encoding.name())?
+                .with_header(HeaderName::ACCEPT_ENCODING, encoding.name())?
+                .with_body(compressed);
+            let mut response = handler(&mut request, &Peer::default());
+            assert_eq!(response.status(), Status::OK);
+            assert_eq!(
+                response
+                    .header(&HeaderName::CONTENT_ENCODING)
+                    .map(|h| h.to_str())
+                    .transpose()?,
+                Some(encoding.name())
+            );
+            let body = replace(response.body_mut(), Body::from(Vec::new()));
+            assert_eq!(read_to_string(encoding.decode(body)?)?, "foo");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decoded_body_size_limit() -> anyhow::Result<()> {
+        let handler = compression_middleware(
+            |request, _| match read_to_string(request.body_mut()) {
+                Ok(body) => Response::builder(Status::OK).with_body(body),
+                Err(e) => error(Status::BAD_REQUEST, e),
+            },
+            3,
+        );
+        for (body, status) in [("foo", Status::OK), ("foobar", Status::BAD_REQUEST)] {
+            let mut compressed = Vec::new();
+            ContentEncoding::Gzip
+                .encode(Body::from(body))
+                .read_to_end(&mut compressed)?;
+            let mut request = Request::builder(Method::POST, "http://localhost/".parse()?)
+                .with_header(HeaderName::CONTENT_ENCODING, "gzip")?
+                .with_body(compressed);
+            assert_eq!(handler(&mut request, &Peer::default()).status(), status);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_etag() -> anyhow::Result<()> {
+        let handler = compression_middleware(
+            |request, _| {
+                let status = if request
+                    .header(&HeaderName::IF_NONE_MATCH)
+                    .and_then(|tag| tag.to_str().ok())
+                    .is_some_and(|tag| tag == "\"1\"" || tag == "\"0\", \"1\"")
+                {
+                    Status::NOT_MODIFIED
+                } else {
+                    Status::OK
+                };
+                Response::builder(status)
+                    .with_header(HeaderName::ETAG, "\"1\"")
+                    .unwrap()
+                    .build()
+            },
+            u64::MAX,
+        );
+        let etag = |accept_encoding: &str, if_none_match: Option<&str>| -> anyhow::Result<_> {
+            let mut request = Request::builder(Method::GET, "http://localhost/".parse()?)
+                .with_header(HeaderName::ACCEPT_ENCODING, accept_encoding)?;
+            if let Some(if_none_match) = if_none_match {
+                request = request.with_header(HeaderName::IF_NONE_MATCH, if_none_match)?;
+            }
+            let response = handler(&mut request.build(), &Peer::default());
+            Ok((
+                response.status(),
+                response
+                    .header(&HeaderName::ETAG)
+                    .map(|h| h.to_str().map(ToOwned::to_owned))
+                    .transpose()?,
+            ))
+        };
+        assert_eq!(etag("identity", None)?, (Status::OK, Some("\"1\"".into())));
+        assert_eq!(etag("gzip", None)?, (Status::OK, Some("\"1-gzip\"".into())));
+        assert_eq!(
+            etag("gzip", Some("\"1-gzip\""))?,
+            (Status::NOT_MODIFIED, Some("\"1-gzip\"".into()))
+        );
+        assert_eq!(
+            etag("br", Some("\"0-br\", \"1-gzip\""))?,
+            (Status::NOT_MODIFIED, Some("\"1-br\"".into()))
+        );
+        Ok(())
+    }
+}
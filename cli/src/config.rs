@@ -0,0 +1,556 @@
+//! The server configuration file and its reloading.
+
+use crate::auth::AccessControl;
+use crate::tls::TlsOptions;
+use anyhow::{bail, ensure, Context};
+use oxiri::Iri;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::thread::available_parallelism;
+use std::time::Duration;
+use url::Url;
+
+const DEFAULT_BIND: &str = "localhost:7878";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_QUEUED_REQUESTS: usize = 128;
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_PLAN_CACHE_SIZE: usize = 1024;
+const DEFAULT_MAX_CURSORS: usize = 64;
+const DEFAULT_MAX_CURSORS_PER_PRINCIPAL: usize = 8;
+const DEFAULT_CURSOR_TIMEOUT: Duration = Duration::from_secs(300);
+const DEFAULT_MAX_DECODED_BODY_SIZE: u64 = 1 << 30;
+/// How often the configuration file modification time is checked
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Content of the TOML configuration file given with `--config`
+///
+/// Relative paths are resolved against the directory of the configuration file.
+#[derive(Deserialize, Default, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Host and port to listen to
+    pub bind: Option<String>,
+    /// File defining the accepted credentials and the per-graph access rules
+    pub auth_file: Option<PathBuf>,
+    /// Directory containing the saved queries exposed at /api/{name}
+    pub queries_dir: Option<PathBuf>,
+    /// Uses the tokio based server instead of the thread per connection one, always done if TLS is enabled
+    pub async_server: bool,
+    /// Namespace prefixes used when serializing RDF, e.g. `schema = "http://schema.org/"`
+    pub prefixes: BTreeMap<String, String>,
+    pub cors: CorsConfig,
+    pub tls: Option<TlsOptions>,
+    pub limits: LimitsConfig,
+    pub log: LogConfig,
+    pub audit: Option<AuditConfig>,
+    pub admin: AdminConfig,
+}
+
+#[derive(Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to do cross-origin requests like "https://example.com", "*" allows all origins
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Maximal duration of an HTTP request in seconds
+    pub timeout: Option<u64>,
+    /// Maximal number of connections handled at the same time
+    pub max_concurrent_connections: Option<usize>,
+    /// Number of requests evaluated at the same time by the async server
+    pub workers: Option<usize>,
+    /// Number of requests waiting for a worker before the async server answers 503
+    pub max_queued_requests: Option<usize>,
+    /// Maximal duration in seconds the async server waits for the in-flight requests on shutdown
+    pub shutdown_timeout: Option<u64>,
+    /// Maximal number of prepared queries kept in the query plan cache, 0 disables the cache
+    pub plan_cache_size: Option<usize>,
+    /// Maximal number of solutions or triples returned per query response, the next ones are returned through a cursor
+    ///
+    /// The results of the saved queries of the `/api` endpoints are truncated to it.
+    pub max_result_rows: Option<usize>,
+    /// Maximal number of cursors open at the same time
+    pub max_cursors: Option<usize>,
+    /// Maximal number of cursors open at the same time by the same principal, the anonymous clients sharing this limit
+    pub max_cursors_per_principal: Option<usize>,
+    /// Duration in seconds after which a cursor that has not been used is closed
+    pub cursor_timeout: Option<u64>,
+    /// Maximal size in bytes of a request body once its content encoding has been decoded
+    ///
+    /// The async server also applies it to the received bodies before their decoding.
+    pub max_decoded_body_size: Option<u64>,
+}
+
+#[derive(Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Writes a line to stderr for each handled request
+    pub requests: bool,
+}
+
+/// The /admin/dump, /admin/backup and /admin/optimize maintenance endpoints
+#[derive(Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Enables the endpoints, they require an authentication file
+    pub enabled: bool,
+    /// Directory the backups are created in, backups are disabled if not set
+    pub backup_dir: Option<PathBuf>,
+}
+
+/// The audit log of the SPARQL queries and updates
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    /// File the JSON lines are appended to, "-" for the standard output
+    pub path: PathBuf,
+    /// Size in MiB after which the file is rotated
+    pub max_file_size: Option<u64>,
+    /// Number of rotated files kept
+    #[serde(default = "default_audit_max_files")]
+    pub max_files: usize,
+    /// Writes the SHA-256 hash of the queries and updates instead of their text
+    #[serde(default)]
+    pub hash_text: bool,
+    /// Writes the quads inserted and deleted by the updates
+    #[serde(default)]
+    pub record_changes: bool,
+    /// Maximal number of inserted quads and of deleted quads written per update
+    #[serde(default = "default_audit_max_recorded_changes")]
+    pub max_recorded_changes: usize,
+    /// Addresses of the reverse proxies whose `X-Forwarded-For` header is used to get the client address
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl AuditConfig {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            max_file_size: None,
+            max_files: default_audit_max_files(),
+            hash_text: false,
+            record_changes: false,
+            max_recorded_changes: default_audit_max_recorded_changes(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+
+    pub fn is_stdout(&self) -> bool {
+        self.path.as_os_str() == "-"
+    }
+}
+
+fn default_audit_max_files() -> usize {
+    5
+}
+
+fn default_audit_max_recorded_changes() -> usize {
+    1000
+}
+
+impl ServerConfig {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut config = Self::from_toml(&content)
+            .with_context(|| format!("Invalid configuration file {}", path.display()))?;
+        if let Some(base) = path.parent() {
+            config.resolve_paths(base);
+        }
+        Ok(config)
+    }
+
+    fn from_toml(content: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = base.join(&*path);
+            }
+        };
+        if let Some(auth_file) = &mut self.auth_file {
+            resolve(auth_file);
+        }
+        if let Some(queries_dir) = &mut self.queries_dir {
+            resolve(queries_dir);
+        }
+        if let Some(backup_dir) = &mut self.admin.backup_dir {
+            resolve(backup_dir);
+        }
+        if let Some(audit) = &mut self.audit {
+            if !audit.is_stdout() {
+                resolve(&mut audit.path);
+            }
+        }
+        if let Some(tls) = &mut self.tls {
+            resolve(&mut tls.certificate);
+            resolve(&mut tls.private_key);
+            if let Some(client_ca) = &mut tls.client_ca {
+                resolve(client_ca);
+            }
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for origin in &self.cors.allowed_origins {
+            if origin == "*" {
+                continue;
+            }
+            let url = Url::parse(origin)
+                .with_context(|| format!("Invalid CORS allowed origin {origin}"))?;
+            if url.origin().ascii_serialization() != *origin {
+                bail!("Invalid CORS allowed origin {origin}, it must be an origin like https://example.com without path and trailing slash")
+            }
+        }
+        for (name, iri) in &self.prefixes {
+            ensure!(
+                is_valid_prefix_name(name),
+                "Invalid prefix name '{name}', it must be made of ASCII letters, digits, '_', '-' and '.', start with a letter and not end with '.'"
+            );
+            Iri::parse(iri.as_str())
+                .with_context(|| format!("Invalid IRI for prefix {name}: {iri}"))?;
+        }
+        if self.limits.timeout == Some(0) {
+            bail!("The timeout must be at least 1 second")
+        }
+        if self.limits.max_concurrent_connections == Some(0) {
+            bail!("The maximal number of concurrent connections must be at least 1")
+        }
+        if self
+            .audit
+            .as_ref()
+            .is_some_and(|audit| audit.max_file_size == Some(0))
+        {
+            bail!("The audit log maximal file size must be at least 1 MiB")
+        }
+        if self.limits.workers == Some(0) {
+            bail!("The number of workers must be at least 1")
+        }
+        if self.limits.max_result_rows == Some(0) {
+            bail!("The maximal number of result rows must be at least 1")
+        }
+        if self.limits.max_cursors_per_principal == Some(0) {
+            bail!("The maximal number of cursors per principal must be at least 1")
+        }
+        Ok(())
+    }
+
+    pub fn bind(&self) -> &str {
+        self.bind.as_deref().unwrap_or(DEFAULT_BIND)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.limits
+            .timeout
+            .map_or(DEFAULT_TIMEOUT, Duration::from_secs)
+    }
+
+    pub fn max_concurrent_connections(&self) -> io::Result<usize> {
+        if let Some(max) = self.limits.max_concurrent_connections {
+            Ok(max)
+        } else {
+            Ok(available_parallelism()?.get() * 128)
+        }
+    }
+
+    pub fn workers(&self) -> io::Result<usize> {
+        if let Some(workers) = self.limits.workers {
+            Ok(workers)
+        } else {
+            Ok(available_parallelism()?.get())
+        }
+    }
+
+    pub fn max_queued_requests(&self) -> usize {
+        self.limits
+            .max_queued_requests
+            .unwrap_or(DEFAULT_MAX_QUEUED_REQUESTS)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.limits
+            .shutdown_timeout
+            .map_or(DEFAULT_SHUTDOWN_TIMEOUT, Duration::from_secs)
+    }
+
+    pub fn plan_cache_size(&self) -> usize {
+        self.limits
+            .plan_cache_size
+            .unwrap_or(DEFAULT_PLAN_CACHE_SIZE)
+    }
+
+    pub fn max_cursors(&self) -> usize {
+        self.limits.max_cursors.unwrap_or(DEFAULT_MAX_CURSORS)
+    }
+
+    pub fn max_cursors_per_principal(&self) -> usize {
+        self.limits
+            .max_cursors_per_principal
+            .unwrap_or(DEFAULT_MAX_CURSORS_PER_PRINCIPAL)
+    }
+
+    pub fn cursor_timeout(&self) -> Duration {
+        self.limits
+            .cursor_timeout
+            .map_or(DEFAULT_CURSOR_TIMEOUT, Duration::from_secs)
+    }
+
+    pub fn max_decoded_body_size(&self) -> u64 {
+        self.limits
+            .max_decoded_body_size
+            .unwrap_or(DEFAULT_MAX_DECODED_BODY_SIZE)
+    }
+
+    /// The namespace prefixes to use when serializing RDF
+    pub fn prefixes(&self) -> Vec<(String, String)> {
+        self.prefixes
+            .iter()
+            .map(|(name, iri)| (name.clone(), iri.clone()))
+            .collect()
+    }
+
+    /// Builds the options that are applied without a restart
+    pub fn runtime(&self) -> anyhow::Result<RuntimeConfig> {
+        Ok(RuntimeConfig {
+            access_control: self
+                .auth_file
+                .as_deref()
+                .map(AccessControl::from_file)
+                .transpose()?,
+            cors_allowed_origins: self.cors.allowed_origins.clone(),
+            log_requests: self.log.requests,
+        })
+    }
+
+    /// Checks that the options only applied on startup are the same
+    fn has_same_startup_options(&self, other: &Self) -> bool {
+        self.bind == other.bind
+            && self.queries_dir == other.queries_dir
+            && self.async_server == other.async_server
+            && self.prefixes == other.prefixes
+            && self.audit == other.audit
+            && self.tls == other.tls
+            && self.limits == other.limits
+            && self.admin == other.admin
+    }
+}
+
+/// The options that are reloaded without a restart
+pub struct RuntimeConfig {
+    pub access_control: Option<AccessControl>,
+    pub cors_allowed_origins: Vec<String>,
+    pub log_requests: bool,
+}
+
+impl RuntimeConfig {
+    /// If all origins are allowed, i.e. the CORS responses do not depend on the request origin
+    pub fn cors_allows_all_origins(&self) -> bool {
+        self.cors_allowed_origins.iter().any(|o| o == "*")
+    }
+
+    /// The value of the `Access-Control-Allow-Origin` header for a request from the given origin
+    pub fn cors_allowed_origin<'a>(&self, origin: &'a str) -> Option<&'a str> {
+        if self.cors_allows_all_origins() {
+            Some("*")
+        } else if self.cors_allowed_origins.iter().any(|o| o == origin) {
+            Some(origin)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reloads the runtime options each time the configuration file is modified
+///
+/// `load` builds the full configuration, including the command line overrides.
+pub fn watch_config(
+    path: PathBuf,
+    initial: ServerConfig,
+    load: impl Fn() -> anyhow::Result<ServerConfig> + Send + 'static,
+    runtime: Arc<RwLock<Arc<RuntimeConfig>>>,
+) -> anyhow::Result<()> {
+    let mut last_modified = fs::metadata(&path)?.modified()?;
+    thread::Builder::new()
+        .name("Configuration reloader".into())
+        .spawn(move || loop {
+            thread::sleep(RELOAD_CHECK_INTERVAL);
+            let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+            let result = load().and_then(|config| {
+                if !config.has_same_startup_options(&initial) {
+                    eprintln!(
+                        "The bind, queries_dir, async_server, prefixes, tls, limits, audit and admin options of {} are only applied on restart",
+                        path.display()
+                    );
+                }
+                let new_runtime = Arc::new(config.runtime()?);
+                *runtime
+                    .write()
+                    .map_err(|_| anyhow::anyhow!("The configuration lock is poisoned"))? =
+                    new_runtime;
+                Ok(())
+            });
+            match result {
+                Ok(()) => eprintln!("Configuration reloaded from {}", path.display()),
+                Err(e) => {
+                    eprintln!("Failed to reload the configuration, keeping the previous one: {e:#}")
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// A subset of the SPARQL and Turtle PN_PREFIX grammar rule restricted to ASCII, the empty name being allowed
+fn is_valid_prefix_name(name: &str) -> bool {
+    name.is_empty()
+        || (name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && !name.ends_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let config = ServerConfig::from_toml(
+            r#"
+            bind = "0.0.0.0:7878"
+            auth_file = "auth.txt"
+            queries_dir = "queries"
+            async_server = true
+
+            [prefixes]
+            schema = "http://schema.org/"
+            ex = "http://example.com/"
+
+            [cors]
+            allowed_origins = ["https://example.com", "http://localhost:8080"]
+
+            [tls]
+            certificate = "cert.pem"
+            private_key = "key.pem"
+
+            [limits]
+            timeout = 10
+            workers = 4
+            max_queued_requests = 16
+            plan_cache_size = 0
+            max_result_rows = 1000
+            max_cursors_per_principal = 2
+            cursor_timeout = 10
+            max_decoded_body_size = 1024
+
+            [log]
+            requests = true
+
+            [audit]
+            path = "-"
+            record_changes = true
+            trusted_proxies = ["127.0.0.1", "::1"]
+
+            [admin]
+            enabled = true
+            backup_dir = "backups"
+            "#,
+        )?;
+        assert_eq!(config.bind(), "0.0.0.0:7878");
+        assert_eq!(config.timeout(), Duration::from_secs(10));
+        assert!(config.log.requests);
+        assert!(config.async_server);
+        assert_eq!(
+            config.prefixes(),
+            [
+                ("ex".into(), "http://example.com/".into()),
+                ("schema".into(), "http://schema.org/".into())
+            ]
+        );
+        assert!(config.admin.enabled);
+        assert_eq!(config.admin.backup_dir, Some(PathBuf::from("backups")));
+        assert_eq!(
+            config
+                .audit
+                .as_ref()
+                .map(|audit| (audit.is_stdout(), audit.max_files)),
+            Some((true, 5))
+        );
+        assert_eq!(config.workers()?, 4);
+        assert_eq!(config.max_queued_requests(), 16);
+        assert_eq!(config.shutdown_timeout(), DEFAULT_SHUTDOWN_TIMEOUT);
+        assert_eq!(config.plan_cache_size(), 0);
+        assert_eq!(config.limits.max_result_rows, Some(1000));
+        assert_eq!(config.max_cursors(), DEFAULT_MAX_CURSORS);
+        assert_eq!(config.max_cursors_per_principal(), 2);
+        assert_eq!(config.cursor_timeout(), Duration::from_secs(10));
+        assert_eq!(config.max_decoded_body_size(), 1024);
+        assert_eq!(
+            ServerConfig::default().plan_cache_size(),
+            DEFAULT_PLAN_CACHE_SIZE
+        );
+        assert_eq!(
+            config.tls.as_ref().map(|tls| tls.client_ca.is_none()),
+            Some(true)
+        );
+        assert_eq!(ServerConfig::from_toml("")?, ServerConfig::default());
+        assert_eq!(ServerConfig::default().bind(), DEFAULT_BIND);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(ServerConfig::from_toml("foo = 1").is_err());
+        assert!(
+            ServerConfig::from_toml("[cors]\nallowed_origins = [\"https://example.com/\"]")
+                .is_err()
+        );
+        assert!(ServerConfig::from_toml("[cors]\nallowed_origins = [\"example.com\"]").is_err());
+        assert!(ServerConfig::from_toml("[limits]\ntimeout = 0").is_err());
+        assert!(ServerConfig::from_toml("[prefixes]\nex = \"example.com\"").is_err());
+        assert!(ServerConfig::from_toml("[prefixes]\n\"1ex\" = \"http://example.com/\"").is_err());
+        assert!(ServerConfig::from_toml("[prefixes]\n\"ex.\" = \"http://example.com/\"").is_err());
+        assert!(ServerConfig::from_toml("[limits]\nworkers = 0").is_err());
+        assert!(ServerConfig::from_toml("[limits]\nmax_result_rows = 0").is_err());
+        assert!(ServerConfig::from_toml("[limits]\nmax_cursors_per_principal = 0").is_err());
+        assert!(ServerConfig::from_toml("[audit]\nrecord_changes = true").is_err());
+        assert!(ServerConfig::from_toml("[tls]\ncertificate = \"cert.pem\"").is_err());
+        assert!(ServerConfig::from_toml("[admin]\nbackup = true").is_err());
+    }
+
+    #[test]
+    fn test_cors_allowed_origin() {
+        let runtime = RuntimeConfig {
+            access_control: None,
+            cors_allowed_origins: vec!["https://example.com".into()],
+            log_requests: false,
+        };
+        assert_eq!(
+            runtime.cors_allowed_origin("https://example.com"),
+            Some("https://example.com")
+        );
+        assert_eq!(runtime.cors_allowed_origin("https://example.org"), None);
+        assert!(!runtime.cors_allows_all_origins());
+    }
+}
@@ -0,0 +1,432 @@
+//! Server-side cursors paging through the results of SPARQL queries.
+
+use crate::datasets::Dataset;
+use crate::{internal_server_error, HttpError};
+use oxhttp::model::Status;
+use oxigraph::model::Triple;
+use oxigraph::sparql::{
+    EvaluationError, PreparedQuery, QueryOptions, QueryResults, QuerySolution, Variable,
+};
+use oxigraph::store::Store;
+use rand::random;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A page of query results
+pub enum Page {
+    Solutions {
+        variables: Vec<Variable>,
+        solutions: Vec<QuerySolution>,
+    },
+    Boolean(bool),
+    Graph(Vec<Triple>),
+}
+
+type PageResult = Result<(Page, bool), String>;
+
+/// The open cursors, identified by random tokens
+///
+/// Each cursor is a thread owning the results iterator of its query:
+/// the next page resumes the evaluation where the previous one stopped, on the same store snapshot,
+/// instead of evaluating the query again with an `OFFSET`.
+/// A cursor is closed when its last page is returned or if it has not been used during `timeout`.
+/// The thread stops as soon as the first page is the last one and no cursor is opened.
+pub struct Cursors {
+    max_page_size: Option<usize>,
+    capacity: usize,
+    capacity_per_principal: usize,
+    timeout: Duration,
+    cursors: Mutex<HashMap<String, Cursor>>,
+    /// The running cursor threads, including the ones of the closed cursors that are not stopped yet
+    threads: Arc<Mutex<RunningThreads>>,
+}
+
+/// The cursors available to the requests to a store
+#[derive(Clone, Copy)]
+pub struct CursorScope<'a> {
+    pub cursors: &'a Cursors,
+    /// The dataset of the store if the server serves several datasets
+    pub dataset: Option<&'a Dataset>,
+}
+
+struct Cursor {
+    /// The dataset the query has been evaluated on, the cursor is not available from the other ones
+    dataset: Option<Dataset>,
+    principal: Option<String>,
+    query: String,
+    page_size: usize,
+    requests: SyncSender<(usize, Sender<PageResult>)>,
+    last_used: Instant,
+}
+
+impl Cursors {
+    /// Builds the cursors registry
+    ///
+    /// If `max_page_size` is set, all query results are paged.
+    /// At most `capacity` cursors are open at the same time and at most `capacity_per_principal` by the same principal,
+    /// the anonymous clients sharing the same limit.
+    pub fn new(
+        max_page_size: Option<usize>,
+        capacity: usize,
+        capacity_per_principal: usize,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            max_page_size,
+            capacity,
+            capacity_per_principal,
+            timeout,
+            cursors: Mutex::default(),
+            threads: Arc::default(),
+        }
+    }
+
+    /// The size of the pages given the one requested by the client, `None` if the results should not be paged
+    pub fn page_size(&self, requested: Option<usize>) -> Option<usize> {
+        match (requested, self.max_page_size) {
+            (Some(requested), Some(max)) => Some(requested.min(max)),
+            (requested, max) => requested.or(max),
+        }
+    }
+
+    /// Evaluates the query and returns its first page and, if there are more results, the token of the cursor to get them
+    ///
+    /// The query is evaluated once, by the cursor thread, that sends back the first page
+    /// and stops, releasing its slot, if it is the last one.
+    /// The `dataset` handle is kept by the cursor so that the store directory is not removed while it is open.
+    pub fn open(
+        &self,
+        store: Store,
+        dataset: Option<Dataset>,
+        query: PreparedQuery,
+        query_text: &str,
+        principal: Option<&str>,
+        page_size: usize,
+    ) -> Result<(Page, Option<String>), HttpError> {
+        let slot = self.reserve_thread(principal)?;
+        let (requests, receiver) = sync_channel::<(usize, Sender<PageResult>)>(1);
+        let (first_reply, first_page) = channel();
+        let timeout = self.timeout;
+        let thread_dataset = dataset.clone();
+        thread::Builder::new()
+            .name("cursor".into())
+            .spawn(move || {
+                let mut slot = Some(slot);
+                // Declared before the store so that the store is dropped first
+                let _dataset = thread_dataset;
+                let store = store;
+                let results = match store.query_prepared(&query, QueryOptions::default()) {
+                    Ok(results) => results,
+                    Err(e) => {
+                        drop(slot.take());
+                        let _ = first_reply.send(Err(e.to_string()));
+                        return;
+                    }
+                };
+                let mut pages = PageReader::new(results);
+                let mut reply = first_reply;
+                let mut page_size = page_size;
+                loop {
+                    let page = pages.next_page(page_size).map_err(|e| e.to_string());
+                    let is_done = page.as_ref().map_or(true, |(_, is_done)| *is_done);
+                    if is_done {
+                        // Released before replying so that the slot is free when the last page is returned
+                        drop(slot.take());
+                    }
+                    if reply.send(page).is_err() || is_done {
+                        return;
+                    }
+                    let Ok((next_page_size, next_reply)) = receiver.recv_timeout(timeout) else {
+                        return;
+                    };
+                    page_size = next_page_size;
+                    reply = next_reply;
+                }
+            })
+            .map_err(internal_server_error)?;
+        let (page, is_done) = receive(&first_page)?;
+        if is_done {
+            return Ok((page, None));
+        }
+        let token = format!("{:032x}", random::<u128>());
+        self.cursors().insert(
+            token.clone(),
+            Cursor {
+                dataset,
+                principal: principal.map(ToOwned::to_owned),
+                query: query_text.to_owned(),
+                page_size,
+                requests,
+                last_used: Instant::now(),
+            },
+        );
+        Ok((page, Some(token)))
+    }
+
+    /// Returns the next page of the cursor `token`, the query it comes from and if there are more results
+    ///
+    /// The pages have the size of the first one if `requested_page_size` is not set.
+    /// The cursor must have been opened on the same `dataset` by the same `principal`.
+    pub fn next(
+        &self,
+        token: &str,
+        dataset: Option<&Dataset>,
+        principal: Option<&str>,
+        requested_page_size: Option<usize>,
+    ) -> Result<(Page, String, bool), HttpError> {
+        let (requests, query, page_size) = {
+            let mut cursors = self.cursors();
+            let cursor = cursors
+                .get_mut(token)
+                .filter(|cursor| {
+                    cursor.principal.as_deref() == principal
+                        && match (&cursor.dataset, dataset) {
+                            (Some(cursor_dataset), Some(dataset)) => {
+                                cursor_dataset.is_same(dataset)
+                            }
+                            (None, None) => true,
+                            _ => false,
+                        }
+                })
+                .ok_or_else(unknown_cursor)?;
+            cursor.last_used = Instant::now();
+            (
+                cursor.requests.clone(),
+                cursor.query.clone(),
+                requested_page_size.map_or(cursor.page_size, |size| {
+                    self.max_page_size.map_or(size, |max| size.min(max))
+                }),
+            )
+        };
+        let (reply, page) = channel();
+        if requests.send((page_size, reply)).is_err() {
+            // The cursor thread has already stopped
+            self.cursors().remove(token);
+            return Err(unknown_cursor());
+        }
+        let result = receive(&page);
+        if result.as_ref().map_or(true, |(_, is_done)| *is_done) {
+            self.cursors().remove(token);
+        }
+        let (page, is_done) = result?;
+        Ok((page, query, !is_done))
+    }
+
+    /// Reserves the slot of a new cursor thread of `principal`, the check and the reservation are done under the cursors lock
+    fn reserve_thread(&self, principal: Option<&str>) -> Result<ThreadSlot, HttpError> {
+        let timeout = self.timeout;
+        let mut cursors = self.cursors();
+        // The threads of the removed cursors stop as soon as they see that their requests channel is closed
+        cursors.retain(|_, cursor| cursor.last_used.elapsed() < timeout);
+        let mut threads = lock(&self.threads);
+        if threads.total >= self.capacity {
+            return Err((
+                Status::SERVICE_UNAVAILABLE,
+                "Too many open cursors, please retry later".into(),
+            ));
+        }
+        let principal = principal.map(ToOwned::to_owned);
+        let of_principal = threads.by_principal.entry(principal.clone()).or_default();
+        if *of_principal >= self.capacity_per_principal {
+            return Err((
+                Status::SERVICE_UNAVAILABLE,
+                "Too many cursors open by the same client, please read their last pages or retry later"
+                    .into(),
+            ));
+        }
+        *of_principal += 1;
+        threads.total += 1;
+        Ok(ThreadSlot {
+            threads: Arc::clone(&self.threads),
+            principal,
+        })
+    }
+
+    fn cursors(&self) -> MutexGuard<'_, HashMap<String, Cursor>> {
+        lock(&self.cursors)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The number of running cursor threads, in total and by principal
+#[derive(Default)]
+struct RunningThreads {
+    total: usize,
+    by_principal: HashMap<Option<String>, usize>,
+}
+
+/// A reserved cursor thread, released when dropped by the thread when it stops
+struct ThreadSlot {
+    threads: Arc<Mutex<RunningThreads>>,
+    principal: Option<String>,
+}
+
+impl Drop for ThreadSlot {
+    fn drop(&mut self) {
+        let mut threads = lock(&self.threads);
+        threads.total -= 1;
+        if let Some(of_principal) = threads.by_principal.get_mut(&self.principal) {
+            *of_principal -= 1;
+            if *of_principal == 0 {
+                threads.by_principal.remove(&self.principal);
+            }
+        }
+    }
+}
+
+fn receive(receiver: &Receiver<PageResult>) -> Result<(Page, bool), HttpError> {
+    receiver
+        .recv()
+        .map_err(|_| internal_server_error("The cursor evaluation has stopped unexpectedly"))?
+        .map_err(internal_server_error)
+}
+
+fn unknown_cursor() -> HttpError {
+    (
+        Status::NOT_FOUND,
+        "The cursor does not exist or has expired".into(),
+    )
+}
+
+/// Reads the results page by page, keeping the first result of the next page to know if there is one
+struct PageReader {
+    results: QueryResults,
+    pending_solution: Option<QuerySolution>,
+    pending_triple: Option<Triple>,
+}
+
+impl PageReader {
+    fn new(results: QueryResults) -> Self {
+        Self {
+            results,
+            pending_solution: None,
+            pending_triple: None,
+        }
+    }
+
+    /// Returns the next page and if it is the last one
+    fn next_page(&mut self, page_size: usize) -> Result<(Page, bool), EvaluationError> {
+        match &mut self.results {
+            QueryResults::Solutions(solutions) => {
+                let variables = solutions.variables().to_vec();
+                let (solutions, is_done) =
+                    read_page(solutions, &mut self.pending_solution, page_size)?;
+                Ok((
+                    Page::Solutions {
+                        variables,
+                        solutions,
+                    },
+                    is_done,
+                ))
+            }
+            QueryResults::Boolean(value) => Ok((Page::Boolean(*value), true)),
+            QueryResults::Graph(triples) => {
+                let (triples, is_done) = read_page(triples, &mut self.pending_triple, page_size)?;
+                Ok((Page::Graph(triples), is_done))
+            }
+        }
+    }
+}
+
+fn read_page<T>(
+    iter: &mut impl Iterator<Item = Result<T, EvaluationError>>,
+    pending: &mut Option<T>,
+    page_size: usize,
+) -> Result<(Vec<T>, bool), EvaluationError> {
+    let mut page = Vec::new();
+    page.extend(pending.take());
+    while page.len() < page_size {
+        let Some(result) = iter.next() else {
+            return Ok((page, true));
+        };
+        page.push(result?);
+    }
+    Ok(if let Some(next) = iter.next() {
+        *pending = Some(next?);
+        (page, false)
+    } else {
+        (page, true)
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paging() -> anyhow::Result<()> {
+        let store = Store::new()?;
+        let cursors = Cursors::new(Some(2), 2, 1, Duration::from_secs(60));
+        assert_eq!(cursors.page_size(None), Some(2));
+        assert_eq!(cursors.page_size(Some(1)), Some(1));
+        assert_eq!(cursors.page_size(Some(10)), Some(2));
+
+        let query = "SELECT ?i WHERE { VALUES ?i { 1 2 3 } }";
+        let (page, token) = cursors
+            .open(
+                store.clone(),
+                None,
+                store.prepare(query)?,
+                query,
+                Some("alice"),
+                2,
+            )
+            .map_err(|(_, message)| anyhow::anyhow!(message))?;
+        assert!(matches!(page, Page::Solutions { solutions, .. } if solutions.len() == 2));
+        let Some(token) = token else {
+            anyhow::bail!("The results should be paged");
+        };
+        // Only one cursor is allowed per principal
+        assert!(cursors
+            .open(
+                store.clone(),
+                None,
+                store.prepare(query)?,
+                query,
+                Some("alice"),
+                2
+            )
+            .is_err());
+        // The results that fit in a page do not keep a cursor open
+        for _ in 0..2 {
+            let (page, token_of_small) = cursors
+                .open(store.clone(), None, store.prepare(query)?, query, None, 3)
+                .map_err(|(_, message)| anyhow::anyhow!(message))?;
+            assert!(matches!(page, Page::Solutions { solutions, .. } if solutions.len() == 3));
+            assert!(token_of_small.is_none());
+        }
+        // Only two cursors are allowed in total
+        let (_, other_token) = cursors
+            .open(store.clone(), None, store.prepare(query)?, query, None, 2)
+            .map_err(|(_, message)| anyhow::anyhow!(message))?;
+        assert!(other_token.is_some());
+        assert!(cursors
+            .open(
+                store.clone(),
+                None,
+                store.prepare(query)?,
+                query,
+                Some("bob"),
+                2
+            )
+            .is_err());
+        // The cursor is bound to its principal
+        assert!(cursors.next(&token, None, None, None).is_err());
+
+        let (page, text, has_more) = cursors
+            .next(&token, None, Some("alice"), None)
+            .map_err(|(_, message)| anyhow::anyhow!(message))?;
+        assert!(matches!(page, Page::Solutions { solutions, .. } if solutions.len() == 1));
+        assert_eq!(text, query);
+        assert!(!has_more);
+        assert!(cursors.next(&token, None, Some("alice"), Some(2)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+//! Management of the datasets served by a single server process.
+
+use crate::saved_queries::SavedQueries;
+use crate::{bad_request, internal_server_error, HttpError};
+use anyhow::{bail, Context};
+use oxhttp::model::Status;
+use oxigraph::store::{Store, StoreOptions};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Dataset names that would conflict with the server own endpoints
+const RESERVED_NAMES: [&str; 1] = ["admin"];
+
+/// A set of named stores with their own saved queries
+///
+/// Each dataset is persisted in a subdirectory of the root directory named after the dataset.
+/// Its saved queries are persisted in the subdirectory of the saved queries directory with the same name.
+pub struct Datasets {
+    /// If `None`, the datasets are kept in memory
+    root: Option<PathBuf>,
+    /// If `None`, the saved queries are kept in memory
+    queries_root: Option<PathBuf>,
+    options: StoreOptions,
+    datasets: RwLock<BTreeMap<String, Dataset>>,
+}
+
+/// A handle to a dataset
+///
+/// The directory of a deleted dataset is removed when its last handle is dropped,
+/// so the handle must be kept as long as the dataset store is used.
+#[derive(Clone)]
+pub struct Dataset(Arc<DatasetInner>);
+
+struct DatasetInner {
+    name: String,
+    // Declared before the removal so that the store is closed before its directory is removed
+    store: Store,
+    queries: SavedQueries,
+    removal: DirectoryRemoval,
+}
+
+impl Dataset {
+    fn new(name: String, store: Store, queries: SavedQueries, directories: Vec<PathBuf>) -> Self {
+        Self(Arc::new(DatasetInner {
+            name,
+            store,
+            queries,
+            removal: DirectoryRemoval {
+                directories,
+                deleted: AtomicBool::new(false),
+            },
+        }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Checks if both handles are to the same dataset, a deleted dataset is not the same as a new one with the same name
+    pub fn is_same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.0.store
+    }
+
+    pub fn queries(&self) -> &SavedQueries {
+        &self.0.queries
+    }
+}
+
+/// Removes the directories of a deleted dataset on drop
+struct DirectoryRemoval {
+    directories: Vec<PathBuf>,
+    deleted: AtomicBool,
+}
+
+impl Drop for DirectoryRemoval {
+    fn drop(&mut self) {
+        if !self.deleted.load(Ordering::Acquire) {
+            return;
+        }
+        for directory in &self.directories {
+            if let Err(e) = fs::remove_dir_all(directory) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!(
+                        "Failed to remove the directory {} of a deleted dataset: {e}",
+                        directory.display()
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Datasets {
+    /// Opens all the datasets stored in the subdirectories of `root`
+    pub fn open(root: PathBuf, options: StoreOptions) -> anyhow::Result<Self> {
+        fs::create_dir_all(&root)
+            .with_context(|| format!("Failed to create {}", root.display()))?;
+        let mut datasets = BTreeMap::new();
+        for entry in fs::read_dir(&root)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let Some(name) = entry.file_name().to_str().map(ToOwned::to_owned) else {
+                continue;
+            };
+            if validate_dataset_name(&name).is_err() {
+                eprintln!(
+                    "Ignoring the directory {} that is not a valid dataset name",
+                    entry.path().display()
+                );
+                continue;
+            }
+            let store = Store::open_with_options(entry.path(), &options)
+                .with_context(|| format!("Failed to open the dataset {name}"))?;
+            datasets.insert(
+                name.clone(),
+                Dataset::new(name, store, SavedQueries::default(), vec![entry.path()]),
+            );
+        }
+        Ok(Self {
+            root: Some(root),
+            queries_root: None,
+            options,
+            datasets: RwLock::new(datasets),
+        })
+    }
+
+    /// Creates a set of datasets kept in memory
+    pub fn in_memory() -> Self {
+        Self {
+            root: None,
+            queries_root: None,
+            options: StoreOptions::default(),
+            datasets: RwLock::default(),
+        }
+    }
+
+    /// Loads the saved queries of each dataset from the subdirectory of `queries_root` named after it
+    ///
+    /// The saved queries of datasets kept in memory are also kept in memory.
+    pub fn open_saved_queries(&mut self, queries_root: PathBuf) -> anyhow::Result<()> {
+        if self.root.is_none() {
+            bail!("The saved queries directory can only be set if the datasets are persisted")
+        }
+        let datasets = self
+            .datasets
+            .get_mut()
+            .map_err(|_| anyhow::anyhow!("The dataset list lock is poisoned"))?;
+        for (name, dataset) in datasets.iter_mut() {
+            let queries = SavedQueries::open(queries_root.join(name))?;
+            let mut directories = dataset.0.removal.directories.clone();
+            directories.push(queries_root.join(name));
+            *dataset = Dataset::new(name.clone(), dataset.store().clone(), queries, directories);
+        }
+        self.queries_root = Some(queries_root);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Dataset>, HttpError> {
+        Ok(self.read()?.get(name).cloned())
+    }
+
+    pub fn names(&self) -> Result<Vec<String>, HttpError> {
+        Ok(self.read()?.keys().cloned().collect())
+    }
+
+    /// Creates a new empty dataset
+    ///
+    /// Returns `false` if the dataset already exists
+    /// and fails with `409 Conflict` if a dataset with the same name is still being deleted.
+    pub fn create(&self, name: &str) -> Result<bool, HttpError> {
+        validate_dataset_name(name)?;
+        let mut datasets = self.write()?;
+        if datasets.contains_key(name) {
+            return Ok(false);
+        }
+        let directory = self.root.as_ref().map(|root| root.join(name));
+        let queries_directory = self.queries_root.as_ref().map(|root| root.join(name));
+        if directory
+            .iter()
+            .chain(&queries_directory)
+            .any(|directory| directory.exists())
+        {
+            return Err((
+                Status::CONFLICT,
+                format!("The previous dataset {name} is still being deleted, please retry later"),
+            ));
+        }
+        let store = if let Some(directory) = &directory {
+            Store::open_with_options(directory, &self.options)
+        } else {
+            Store::new()
+        }
+        .map_err(internal_server_error)?;
+        let queries = if let Some(queries_directory) = &queries_directory {
+            SavedQueries::open(queries_directory.clone()).map_err(internal_server_error)?
+        } else {
+            SavedQueries::default()
+        };
+        datasets.insert(
+            name.into(),
+            Dataset::new(
+                name.into(),
+                store,
+                queries,
+                directory.into_iter().chain(queries_directory).collect(),
+            ),
+        );
+        Ok(true)
+    }
+
+    /// Deletes a dataset, all its content and its saved queries
+    ///
+    /// The requests already using the dataset are allowed to finish,
+    /// its directory is removed once they are done.
+    /// Returns `false` if the dataset does not exist.
+    pub fn delete(&self, name: &str) -> Result<bool, HttpError> {
+        let Some(dataset) = self.write()?.remove(name) else {
+            return Ok(false);
+        };
+        dataset.0.removal.deleted.store(true, Ordering::Release);
+        Ok(true)
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, BTreeMap<String, Dataset>>, HttpError> {
+        self.datasets
+            .read()
+            .map_err(|_| internal_server_error("The dataset list lock is poisoned"))
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, BTreeMap<String, Dataset>>, HttpError> {
+        self.datasets
+            .write()
+            .map_err(|_| internal_server_error("The dataset list lock is poisoned"))
+    }
+}
+
+/// Dataset names are used both as URL path segments and as directory names
+fn validate_dataset_name(name: &str) -> Result<(), HttpError> {
+    if name.is_empty()
+        || name.len() > 64
+        || !name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(bad_request(format!(
+            "'{name}' is not a valid dataset name, only up to 64 ASCII letters, digits, '-' and '_' are allowed"
+        )));
+    }
+    if RESERVED_NAMES.contains(&name) {
+        return Err((
+            Status::CONFLICT,
+            format!("The dataset name '{name}' is reserved"),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_and_delete() -> Result<(), HttpError> {
+        let datasets = Datasets::in_memory();
+        assert!(datasets.create("foo")?);
+        assert!(!datasets.create("foo")?);
+        assert!(datasets.create("bar_2")?);
+        assert_eq!(datasets.names()?, ["bar_2", "foo"]);
+        assert!(datasets.get("foo")?.is_some());
+        assert!(datasets.delete("foo")?);
+        assert!(!datasets.delete("foo")?);
+        assert!(datasets.get("foo")?.is_none());
+        assert!(datasets.create("admin").is_err());
+        assert!(datasets.create("../foo").is_err());
+        assert!(datasets.create("").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_while_used() -> anyhow::Result<()> {
+        let root =
+            std::env::temp_dir().join(format!("oxigraph-datasets-{}", rand::random::<u128>()));
+        let mut datasets = Datasets::open(root.join("data"), StoreOptions::default())?;
+        datasets.open_saved_queries(root.join("queries"))?;
+        let create = |name| datasets.create(name).map_err(|(_, e)| anyhow::anyhow!(e));
+        let delete = |name| datasets.delete(name).map_err(|(_, e)| anyhow::anyhow!(e));
+        assert!(create("foo")?);
+        let dataset = datasets
+            .get("foo")
+            .map_err(|(_, e)| anyhow::anyhow!(e))?
+            .context("foo should exist")?;
+        assert!(delete("foo")?);
+        // The dataset is still used: its directories are kept and can't be reused
+        assert!(root.join("data/foo").exists());
+        assert!(root.join("queries/foo").exists());
+        dataset.store().len()?;
+        assert!(datasets.create("foo").is_err());
+        drop(dataset);
+        assert!(!root.join("data/foo").exists());
+        assert!(!root.join("queries/foo").exists());
+        assert!(create("foo")?);
+        drop(datasets);
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+//! [Triple Pattern Fragments](https://linkeddatafragments.org/specification/triple-pattern-fragments/)
+//! and [Quad Pattern Fragments](https://linkeddatafragments.org/specification/quad-pattern-fragments/) interface.
+//!
+//! Without a `graph` parameter the fragments are the triples of the default graph.
+
+use crate::auth::Permissions;
+use crate::{bad_request, base_url, internal_server_error, rdf_content_negotiation, HttpError};
+use oxhttp::model::{HeaderName, Request, Response, Status};
+use oxigraph::io::RdfSerializer;
+use oxigraph::model::vocab::{rdf, xsd};
+use oxigraph::model::{
+    BlankNode, GraphName, Literal, NamedNode, NamedNodeRef, Quad, Subject, Term,
+};
+use oxigraph::store::Store;
+use url::form_urlencoded;
+
+const PAGE_SIZE: usize = 100;
+/// Above this number of matching quads, the count is an estimate
+const MAX_EXACT_COUNT: usize = 100_000;
+
+const HYDRA_COLLECTION: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#Collection");
+const HYDRA_EXPLICIT_REPRESENTATION: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#ExplicitRepresentation");
+const HYDRA_FIRST: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#first");
+const HYDRA_ITEMS_PER_PAGE: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#itemsPerPage");
+const HYDRA_MAPPING: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#mapping");
+const HYDRA_NEXT: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#next");
+const HYDRA_PARTIAL_COLLECTION_VIEW: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#PartialCollectionView");
+const HYDRA_PREVIOUS: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#previous");
+const HYDRA_PROPERTY: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#property");
+const HYDRA_SEARCH: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#search");
+const HYDRA_TEMPLATE: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#template");
+const HYDRA_TOTAL_ITEMS: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#totalItems");
+const HYDRA_VARIABLE: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#variable");
+const HYDRA_VARIABLE_REPRESENTATION: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/hydra/core#variableRepresentation");
+const VOID_DATASET: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#Dataset");
+const VOID_SUBSET: NamedNodeRef<'_> = NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#subset");
+const VOID_TRIPLES: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://rdfs.org/ns/void#triples");
+const FOAF_PRIMARY_TOPIC: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://xmlns.com/foaf/0.1/primaryTopic");
+const SD_GRAPH: NamedNodeRef<'_> =
+    NamedNodeRef::new_unchecked("http://www.w3.org/ns/sparql-service-description#graph");
+
+/// Returns a page of the fragment, the pages have at most `max_page_size` quads if it is set
+pub fn handle_fragments_request(
+    request: &Request,
+    store: &Store,
+    prefixes: &[(String, String)],
+    max_page_size: Option<usize>,
+    permissions: &Permissions,
+) -> Result<Response, HttpError> {
+    let page_size = max_page_size.map_or(PAGE_SIZE, |max| max.min(PAGE_SIZE));
+    let mut subject = None;
+    let mut predicate = None;
+    let mut object = None;
+    let mut graph = None;
+    let mut page = 1;
+    // The pattern parameters, kept to build the links to the other pages
+    let mut pattern_parameters = Vec::new();
+    for (k, v) in request.url().query_pairs() {
+        match k.as_ref() {
+            "subject" => subject = parse_term(&v)?,
+            "predicate" => predicate = parse_term(&v)?,
+            "object" => object = parse_term(&v)?,
+            "graph" => graph = Some(parse_term(&v)?),
+            "page" => {
+                page = v
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|page| *page > 0)
+                    .ok_or_else(|| bad_request(format!("Invalid page number {v}")))?;
+                continue;
+            }
+            _ => continue,
+        }
+        pattern_parameters.push((k.into_owned(), v.into_owned()));
+    }
+    let subject = subject
+        .map(|subject| match subject {
+            Term::NamedNode(subject) => Ok(Subject::from(subject)),
+            _ => Err(bad_request("The subject must be an IRI")),
+        })
+        .transpose()?;
+    let predicate = predicate
+        .map(|predicate| match predicate {
+            Term::NamedNode(predicate) => Ok(predicate),
+            _ => Err(bad_request("The predicate must be an IRI")),
+        })
+        .transpose()?;
+    // None if any graph matches
+    let graph_name = match graph {
+        None => Some(GraphName::DefaultGraph),
+        Some(None) => None,
+        Some(Some(Term::NamedNode(graph))) => Some(graph.into()),
+        Some(Some(_)) => return Err(bad_request("The graph must be an IRI")),
+    };
+    if let Some(graph_name) = &graph_name {
+        permissions.check_read(graph_name)?;
+    } else {
+        permissions.check_read_any()?;
+    }
+    let format = rdf_content_negotiation(request)?;
+    if graph_name != Some(GraphName::DefaultGraph) && !format.supports_datasets() {
+        return Err(bad_request(format!(
+            "Quad pattern fragments can't be serialized using {format} that does not support named graphs"
+        )));
+    }
+
+    let offset = (page - 1)
+        .checked_mul(page_size)
+        .ok_or_else(|| bad_request("The page number is too big"))?;
+    let mut count = 0;
+    let mut quads = Vec::with_capacity(page_size);
+    for quad in store.quads_for_pattern(
+        subject.as_ref().map(Into::into),
+        predicate.as_ref().map(Into::into),
+        object.as_ref().map(Into::into),
+        graph_name.as_ref().map(Into::into),
+    ) {
+        let quad = quad.map_err(internal_server_error)?;
+        if graph_name.is_none() && !permissions.can_read(&quad.graph_name) {
+            continue;
+        }
+        if count >= offset && quads.len() < page_size {
+            quads.push(quad);
+        }
+        count += 1;
+        if count >= MAX_EXACT_COUNT && count > offset + page_size {
+            break;
+        }
+    }
+
+    let base = base_url(request);
+    let page_url = |page: usize| {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        for (k, v) in &pattern_parameters {
+            query.append_pair(k, v);
+        }
+        if page > 1 {
+            query.append_pair("page", &page.to_string());
+        }
+        let query = query.finish();
+        NamedNode::new_unchecked(if query.is_empty() {
+            base.clone()
+        } else {
+            format!("{base}?{query}")
+        })
+    };
+    let current_page = page_url(page);
+    // The metadata are in a separated graph if the format allows it
+    let metadata_graph = if format.supports_datasets() {
+        GraphName::from(NamedNode::new_unchecked(format!(
+            "{}#metadata",
+            current_page.as_str()
+        )))
+    } else {
+        GraphName::DefaultGraph
+    };
+    let mut metadata = Vec::new();
+    let mut add = |subject: Subject, predicate: NamedNodeRef<'_>, object: Term| {
+        metadata.push(Quad::new(
+            subject,
+            predicate,
+            object,
+            metadata_graph.clone(),
+        ));
+    };
+    if let GraphName::NamedNode(metadata_graph) = &metadata_graph {
+        add(
+            metadata_graph.clone().into(),
+            FOAF_PRIMARY_TOPIC,
+            current_page.clone().into(),
+        );
+    }
+    let dataset = NamedNode::new_unchecked(format!("{base}#dataset"));
+    add(dataset.clone().into(), rdf::TYPE, VOID_DATASET.into());
+    add(dataset.clone().into(), rdf::TYPE, HYDRA_COLLECTION.into());
+    add(
+        dataset.clone().into(),
+        VOID_SUBSET,
+        current_page.clone().into(),
+    );
+    let search = BlankNode::default();
+    add(dataset.into(), HYDRA_SEARCH, search.clone().into());
+    add(
+        search.clone().into(),
+        HYDRA_TEMPLATE,
+        Literal::new_simple_literal(format!("{base}{{?subject,predicate,object,graph}}")).into(),
+    );
+    add(
+        search.clone().into(),
+        HYDRA_VARIABLE_REPRESENTATION,
+        HYDRA_EXPLICIT_REPRESENTATION.into(),
+    );
+    for (variable, property) in [
+        ("subject", rdf::SUBJECT),
+        ("predicate", rdf::PREDICATE),
+        ("object", rdf::OBJECT),
+        ("graph", SD_GRAPH),
+    ] {
+        let mapping = BlankNode::default();
+        add(search.clone().into(), HYDRA_MAPPING, mapping.clone().into());
+        add(
+            mapping.clone().into(),
+            HYDRA_VARIABLE,
+            Literal::new_simple_literal(variable).into(),
+        );
+        add(mapping.into(), HYDRA_PROPERTY, property.into());
+    }
+    let count_literal = Term::from(Literal::new_typed_literal(count.to_string(), xsd::INTEGER));
+    add(
+        current_page.clone().into(),
+        rdf::TYPE,
+        HYDRA_PARTIAL_COLLECTION_VIEW.into(),
+    );
+    add(
+        current_page.clone().into(),
+        HYDRA_TOTAL_ITEMS,
+        count_literal.clone(),
+    );
+    add(current_page.clone().into(), VOID_TRIPLES, count_literal);
+    add(
+        current_page.clone().into(),
+        HYDRA_ITEMS_PER_PAGE,
+        Literal::new_typed_literal(page_size.to_string(), xsd::INTEGER).into(),
+    );
+    add(current_page.clone().into(), HYDRA_FIRST, page_url(1).into());
+    if page > 1 {
+        add(
+            current_page.clone().into(),
+            HYDRA_PREVIOUS,
+            page_url(page - 1).into(),
+        );
+    }
+    if count > offset + page_size {
+        add(current_page.into(), HYDRA_NEXT, page_url(page + 1).into());
+    }
+
+    let mut writer = RdfSerializer::from_format(format)
+        .with_prefix("hydra", "http://www.w3.org/ns/hydra/core#")
+        .and_then(|s| s.with_prefix("void", "http://rdfs.org/ns/void#"))
+        .and_then(|s| {
+            prefixes
+                .iter()
+                .try_fold(s, |s, (name, iri)| s.with_prefix(name, iri))
+        })
+        .map_err(internal_server_error)?
+        .serialize_to_write(Vec::new());
+    for quad in quads.iter().chain(&metadata) {
+        writer.write_quad(quad).map_err(internal_server_error)?;
+    }
+    Ok(Response::builder(Status::OK)
+        .with_header(HeaderName::CONTENT_TYPE, format.media_type())
+        .unwrap()
+        .with_body(writer.finish().map_err(internal_server_error)?))
+}
+
+/// Parses a term in the Hydra [explicit representation](https://www.hydra-cg.com/spec/latest/core/#hydra:ExplicitRepresentation)
+///
+/// Returns `None` for variables.
+fn parse_term(value: &str) -> Result<Option<Term>, HttpError> {
+    if value.is_empty() || value.starts_with('?') {
+        return Ok(None);
+    }
+    if let Some(literal) = value.strip_prefix('"') {
+        let end = literal
+            .rfind('"')
+            .ok_or_else(|| bad_request(format!("Invalid literal {value}")))?;
+        let (lexical_form, suffix) = (&literal[..end], &literal[end + 1..]);
+        let literal = if suffix.is_empty() {
+            Literal::new_simple_literal(lexical_form)
+        } else if let Some(language) = suffix.strip_prefix('@') {
+            Literal::new_language_tagged_literal(lexical_form, language).map_err(bad_request)?
+        } else if let Some(datatype) = suffix.strip_prefix("^^") {
+            let datatype = datatype
+                .strip_prefix('<')
+                .and_then(|d| d.strip_suffix('>'))
+                .unwrap_or(datatype);
+            Literal::new_typed_literal(lexical_form, NamedNode::new(datatype).map_err(bad_request)?)
+        } else {
+            return Err(bad_request(format!("Invalid literal {value}")));
+        };
+        return Ok(Some(literal.into()));
+    }
+    if value.starts_with("_:") {
+        return Err(bad_request(
+            "Blank nodes are not allowed in the fragment patterns",
+        ));
+    }
+    Ok(Some(NamedNode::new(value).map_err(bad_request)?.into()))
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_term() -> Result<(), HttpError> {
+        assert_eq!(parse_term("")?, None);
+        assert_eq!(parse_term("?s")?, None);
+        assert_eq!(
+            parse_term("http://example.com/s")?,
+            Some(NamedNode::new_unchecked("http://example.com/s").into())
+        );
+        assert_eq!(
+            parse_term("\"foo \"bar\"\"")?,
+            Some(Literal::new_simple_literal("foo \"bar\"").into())
+        );
+        assert_eq!(
+            parse_term("\"foo\"@en")?,
+            Some(Literal::new_language_tagged_literal_unchecked("foo", "en").into())
+        );
+        assert_eq!(
+            parse_term("\"1\"^^http://www.w3.org/2001/XMLSchema#integer")?,
+            Some(Literal::new_typed_literal("1", xsd::INTEGER).into())
+        );
+        assert!(parse_term("_:b").is_err());
+        assert!(parse_term("\"foo").is_err());
+        assert!(parse_term("\"foo\"bar").is_err());
+        Ok(())
+    }
+}
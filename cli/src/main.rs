@@ -1,6 +1,20 @@
 #![allow(clippy::print_stderr, clippy::cast_precision_loss, clippy::use_debug)]
+use crate::async_server::serve_async;
+use crate::audit::{AuditLog, AuditOperation, AuditRecord, RequestAuditor, RequestTarget};
+use crate::auth::{AccessControl, Permissions};
+use crate::compression::compression_middleware;
+use crate::config::{watch_config, AuditConfig, RuntimeConfig, ServerConfig};
+use crate::cursors::{CursorScope, Cursors, Page};
+use crate::datasets::{Dataset, Datasets};
+use crate::fragments::handle_fragments_request;
+use crate::plan_cache::PlanCache;
+use crate::saved_queries::{
+    handle_saved_queries_admin_request, write_simple_json_boolean, SavedQueries,
+    SimpleJsonSolutionsWriter,
+};
+use crate::tls::TlsOptions;
 use anyhow::{bail, ensure, Context};
-use clap::{Parser, Subcommand, ValueHint};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueHint};
 use flate2::read::MultiGzDecoder;
 use oxhttp::model::{Body, HeaderName, HeaderValue, Method, Request, Response, Status};
 use oxhttp::Server;
@@ -9,8 +23,11 @@ use oxigraph::model::{
     GraphName, GraphNameRef, IriParseError, NamedNode, NamedNodeRef, NamedOrBlankNode,
 };
 use oxigraph::sparql::results::{QueryResultsFormat, QueryResultsSerializer};
-use oxigraph::sparql::{Query, QueryOptions, QueryResults, Update};
-use oxigraph::store::{BulkLoader, LoaderError, Store};
+use oxigraph::sparql::{
+    EvaluationError, PreparedQuery, Query, QueryOptions, QueryResults, RowLimitPolicy, Update,
+    UpdateOptions,
+};
+use oxigraph::store::{BulkLoader, Compression, LoaderError, Store, StoreOptions};
 use oxiri::Iri;
 use rand::random;
 use rayon_core::ThreadPoolBuilder;
@@ -21,20 +38,33 @@ use std::cmp::{max, min};
 use std::env;
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{self, stdin, stdout, BufWriter, Read, Write};
-use std::net::ToSocketAddrs;
+use std::io::{self, stderr, stdin, stdout, BufWriter, Read, Write};
+use std::mem::replace;
+use std::net::{SocketAddr, ToSocketAddrs};
 #[cfg(target_os = "linux")]
 use std::os::unix::net::UnixDatagram;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::str::FromStr;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::thread::available_parallelism;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use std::{fmt, fs, str};
 use url::form_urlencoded;
 
+mod async_server;
+mod audit;
+mod auth;
+mod compression;
+mod config;
+mod cursors;
+mod datasets;
+mod fragments;
+mod plan_cache;
+mod saved_queries;
+mod tls;
+
 const MAX_SPARQL_BODY_SIZE: u64 = 0x0010_0000;
-const HTTP_TIMEOUT: Duration = Duration::from_secs(60);
 const HTML_ROOT_PAGE: &str = include_str!("../templates/query.html");
 #[allow(clippy::large_include_file)]
 const YASGUI_JS: &str = include_str!("../templates/yasgui/yasgui.min.js");
@@ -58,12 +88,16 @@ enum Command {
         /// If not present. An in-memory storage will be used.
         #[arg(short, long, value_hint = ValueHint::DirPath)]
         location: Option<PathBuf>,
-        /// Host and port to listen to.
-        #[arg(short, long, default_value = "localhost:7878", value_hint = ValueHint::Hostname)]
-        bind: String,
-        /// Allows cross-origin requests
+        /// Serves several datasets, each one at /{dataset}/query, /{dataset}/update and /{dataset}/store.
+        ///
+        /// Each subdirectory of the location is a dataset named after it.
+        /// Datasets are created and deleted with PUT and DELETE requests to /admin/datasets/{dataset} if --admin is set.
         #[arg(long)]
-        cors: bool,
+        datasets: bool,
+        #[command(flatten)]
+        server: ServerArgs,
+        #[command(flatten)]
+        storage: StorageArgs,
     },
     /// Start Oxigraph HTTP server in read-only mode.
     ///
@@ -74,12 +108,11 @@ enum Command {
         /// Directory in which Oxigraph data are persisted.
         #[arg(short, long, value_hint = ValueHint::DirPath)]
         location: PathBuf,
-        /// Host and port to listen to.
-        #[arg(short, long, default_value = "localhost:7878")]
-        bind: String,
-        /// Allows cross-origin requests
-        #[arg(long)]
-        cors: bool,
+        #[command(flatten)]
+        server: ServerArgs,
+        /// Only the block cache, bloom filter, open files and statistics options are used.
+        #[command(flatten)]
+        storage: StorageArgs,
     },
     /// Start Oxigraph HTTP server in secondary mode.
     ///
@@ -97,12 +130,11 @@ enum Command {
         /// By default, temporary storage is used.
         #[arg(long, value_hint = ValueHint::DirPath)]
         secondary_location: Option<PathBuf>,
-        /// Host and port to listen to.
-        #[arg(short, long, default_value = "localhost:7878")]
-        bind: String,
-        /// Allows cross-origin requests
-        #[arg(long)]
-        cors: bool,
+        #[command(flatten)]
+        server: ServerArgs,
+        /// Only the block cache, bloom filter and statistics options are used.
+        #[command(flatten)]
+        storage: StorageArgs,
     },
     /// Creates database backup into a target directory.
     ///
@@ -159,6 +191,8 @@ enum Command {
         /// Only available when loading a graph file (N-Triples, Turtle...) and not a dataset file (N-Quads, TriG...).
         #[arg(long, value_hint = ValueHint::Url)]
         graph: Option<String>,
+        #[command(flatten)]
+        storage: StorageArgs,
     },
     /// Dump the store content into a file.
     Dump {
@@ -223,7 +257,8 @@ enum Command {
         explain: bool,
         /// Write to the given file an explanation of the query evaluation.
         ///
-        /// If the file extension is .json the JSON format is used, if .txt a human readable format is used.
+        /// If the file extension is .json the JSON format is used, if .txt a human readable format is used
+        /// and if .dot or .gv the Graphviz DOT format is used.
         ///
         /// Use the stats option to print also query evaluation statistics.
         #[arg(long, conflicts_with = "explain", value_hint = ValueHint::FilePath)]
@@ -252,6 +287,17 @@ enum Command {
         /// Base IRI of the update.
         #[arg(long, value_hint = ValueHint::Url)]
         update_base: Option<String>,
+        /// Prints to stderr a human-readable explanation of the update evaluation
+        /// with the plans of its operations and the number of quads they inserted and deleted.
+        ///
+        /// Use the stats option to print also evaluation statistics.
+        #[arg(long)]
+        explain: bool,
+        /// Computes some evaluation statistics to print as part of the update explanation.
+        ///
+        /// Beware, computing the statistics adds some overhead to the evaluation runtime.
+        #[arg(long)]
+        stats: bool,
     },
     /// Optimizes the database storage.
     ///
@@ -310,42 +356,264 @@ enum Command {
     },
 }
 
+/// HTTPS support of the server.
+#[derive(ClapArgs, Clone)]
+struct TlsArgs {
+    /// PEM file containing the TLS certificate chain. Enables HTTPS.
+    ///
+    /// The certificate and its private key are reloaded on SIGHUP.
+    /// The TLS connections are served by the async server.
+    #[arg(long, requires = "tls_key", value_hint = ValueHint::FilePath)]
+    tls_cert: Option<PathBuf>,
+    /// PEM file containing the private key of the TLS certificate.
+    #[arg(long, requires = "tls_cert", value_hint = ValueHint::FilePath)]
+    tls_key: Option<PathBuf>,
+    /// PEM file containing the certificate authorities the client certificates must be signed by.
+    ///
+    /// If set, clients must authenticate with a TLS client certificate whose subject common name is the principal.
+    #[arg(long, requires = "tls_cert", value_hint = ValueHint::FilePath)]
+    tls_client_ca: Option<PathBuf>,
+}
+
+impl TlsArgs {
+    fn tls_options(self) -> Option<TlsOptions> {
+        Some(TlsOptions {
+            certificate: self.tls_cert?,
+            private_key: self.tls_key?,
+            client_ca: self.tls_client_ca,
+        })
+    }
+}
+
+/// Options of the HTTP server.
+#[derive(ClapArgs, Clone)]
+struct ServerArgs {
+    /// Host and port to listen to [default: localhost:7878].
+    #[arg(short, long, value_hint = ValueHint::Hostname)]
+    bind: Option<String>,
+    /// Allows cross-origin requests from any origin.
+    #[arg(long)]
+    cors: bool,
+    /// File defining the accepted API tokens, users and JWT key and the per-graph access rules.
+    ///
+    /// If not set, no authentication is done and all graphs are readable and writable by everyone.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    auth_file: Option<PathBuf>,
+    /// Directory containing the saved queries exposed at /api/{name}, one {name}.toml file per query.
+    ///
+    /// Saved queries are also managed with PUT and DELETE requests to /admin/queries/{name}.
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    queries_dir: Option<PathBuf>,
+    /// Uses the tokio based server.
+    ///
+    /// Connections are handled asynchronously and requests are evaluated by a bounded pool of workers.
+    /// When the pool and its queue are full, the server answers 503 Service Unavailable.
+    #[arg(long)]
+    async_server: bool,
+    /// File the audit log of the SPARQL queries and updates is appended to, "-" for the standard output.
+    ///
+    /// Each entry is a JSON object on its own line.
+    /// It requires --async-server or TLS: the default server does not give the client addresses.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    audit_log: Option<PathBuf>,
+    /// TOML configuration file of the server.
+    ///
+    /// The options given on the command line override the ones of the file.
+    /// The access control, CORS and logging options are reloaded when the file is modified.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
+    /// Enables the /admin/dump, /admin/backup and /admin/optimize maintenance endpoints
+    /// and the creation and deletion of datasets with --datasets.
+    ///
+    /// They require an --auth-file and are only allowed to the principals that can write all graphs.
+    #[arg(long)]
+    admin: bool,
+    /// Directory the backups created with /admin/backup are written to.
+    ///
+    /// If not set, /admin/backup is disabled.
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    backup_dir: Option<PathBuf>,
+    #[command(flatten)]
+    tls: TlsArgs,
+}
+
+impl ServerArgs {
+    /// The configuration file content with the command line options applied
+    fn server_config(&self) -> anyhow::Result<ServerConfig> {
+        let mut config = if let Some(config) = &self.config {
+            ServerConfig::from_file(config)?
+        } else {
+            ServerConfig::default()
+        };
+        if let Some(bind) = &self.bind {
+            config.bind = Some(bind.clone());
+        }
+        if self.cors {
+            config.cors.allowed_origins = vec!["*".into()];
+        }
+        if let Some(auth_file) = &self.auth_file {
+            config.auth_file = Some(auth_file.clone());
+        }
+        if let Some(queries_dir) = &self.queries_dir {
+            config.queries_dir = Some(queries_dir.clone());
+        }
+        if self.async_server {
+            config.async_server = true;
+        }
+        if self.admin {
+            config.admin.enabled = true;
+        }
+        if let Some(backup_dir) = &self.backup_dir {
+            config.admin.backup_dir = Some(backup_dir.clone());
+        }
+        if let Some(audit_log) = &self.audit_log {
+            if let Some(audit) = &mut config.audit {
+                audit.path = audit_log.clone();
+            } else {
+                config.audit = Some(AuditConfig::new(audit_log.clone()));
+            }
+        }
+        if let Some(tls) = self.tls.clone().tls_options() {
+            config.tls = Some(tls);
+        }
+        Ok(config)
+    }
+}
+
+/// Tuning of the on-disk storage.
+#[derive(ClapArgs)]
+struct StorageArgs {
+    /// Preset of storage options to start from.
+    ///
+    /// "small" for machines with little memory, "read-heavy" for stores that are mostly queried
+    /// and "bulk-import" for stores in which large amounts of data are written.
+    /// The other storage options override the preset values.
+    #[arg(long)]
+    storage_preset: Option<String>,
+    /// Size of the block cache in bytes.
+    #[arg(long)]
+    block_cache_size: Option<usize>,
+    /// Number of bits per key of the bloom filters. Bloom filters are disabled by default.
+    #[arg(long)]
+    bloom_filter_bits_per_key: Option<f64>,
+    /// Compression of the data files: "none", "lz4" or "lz4hc".
+    ///
+    /// A comma separated list sets the compression of each level, the last value being used for the remaining levels.
+    #[arg(long, value_delimiter = ',')]
+    compression: Vec<String>,
+    /// Maximal number of files kept open.
+    #[arg(long)]
+    max_open_files: Option<usize>,
+    /// Size of each write buffer in bytes.
+    #[arg(long)]
+    write_buffer_size: Option<usize>,
+    /// Maximal number of write buffers per index.
+    #[arg(long)]
+    max_write_buffer_number: Option<usize>,
+    /// Collects RocksDB statistics like the block cache hits and misses, exposed at /metrics.
+    #[arg(long)]
+    storage_statistics: bool,
+}
+
+impl StorageArgs {
+    fn store_options(&self) -> anyhow::Result<StoreOptions> {
+        let mut options = if let Some(preset) = &self.storage_preset {
+            StoreOptions::from_preset_name(preset).with_context(|| {
+                format!(
+                    "The storage preset {preset} is not supported, only small, read-heavy and bulk-import are"
+                )
+            })?
+        } else {
+            StoreOptions::default()
+        };
+        if let Some(block_cache_size) = self.block_cache_size {
+            options = options.with_block_cache_size(block_cache_size);
+        }
+        if let Some(bits_per_key) = self.bloom_filter_bits_per_key {
+            options = options.with_bloom_filter(bits_per_key);
+        }
+        if !self.compression.is_empty() {
+            options = options.with_compression_per_level(
+                self.compression
+                    .iter()
+                    .map(|name| {
+                        Compression::from_name(name).with_context(|| {
+                            format!("The compression {name} is not supported, only none, lz4 and lz4hc are")
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            );
+        }
+        if let Some(max_open_files) = self.max_open_files {
+            options = options.with_max_open_files(max_open_files);
+        }
+        if let Some(write_buffer_size) = self.write_buffer_size {
+            options = options.with_write_buffer_size(write_buffer_size);
+        }
+        if let Some(max_write_buffer_number) = self.max_write_buffer_number {
+            options = options.with_max_write_buffer_number(max_write_buffer_number);
+        }
+        if self.storage_statistics {
+            options = options.with_statistics();
+        }
+        Ok(options)
+    }
+}
+
 pub fn main() -> anyhow::Result<()> {
     let matches = Args::parse();
     match matches.command {
         Command::Serve {
             location,
-            bind,
-            cors,
+            datasets,
+            server,
+            storage,
         } => serve(
-            if let Some(location) = location {
-                Store::open(location)
+            if datasets {
+                Service::Datasets(if let Some(location) = location {
+                    Datasets::open(location, storage.store_options()?)?
+                } else {
+                    Datasets::in_memory()
+                })
             } else {
-                Store::new()
-            }?,
-            &bind,
+                Service::Store(if let Some(location) = location {
+                    Store::open_with_options(location, &storage.store_options()?)
+                } else {
+                    Store::new()
+                }?)
+            },
             false,
-            cors,
+            server,
         ),
         Command::ServeReadOnly {
             location,
-            bind,
-            cors,
-        } => serve(Store::open_read_only(location)?, &bind, true, cors),
+            server,
+            storage,
+        } => serve(
+            Service::Store(Store::open_read_only_with_options(
+                location,
+                &storage.store_options()?,
+            )?),
+            true,
+            server,
+        ),
         Command::ServeSecondary {
             primary_location,
             secondary_location,
-            bind,
-            cors,
+            server,
+            storage,
         } => serve(
-            if let Some(secondary_location) = secondary_location {
-                Store::open_persistent_secondary(primary_location, secondary_location)
+            Service::Store(if let Some(secondary_location) = secondary_location {
+                Store::open_persistent_secondary_with_options(
+                    primary_location,
+                    secondary_location,
+                    &storage.store_options()?,
+                )
             } else {
-                Store::open_secondary(primary_location)
-            }?,
-            &bind,
+                Store::open_secondary_with_options(primary_location, &storage.store_options()?)
+            }?),
             true,
-            cors,
+            server,
         ),
         Command::Backup {
             location,
@@ -362,8 +630,9 @@ pub fn main() -> anyhow::Result<()> {
             format,
             base,
             graph,
+            storage,
         } => {
-            let store = Store::open(location)?;
+            let store = Store::open_with_options(location, &storage.store_options()?)?;
             let format = if let Some(format) = format {
                 Some(rdf_format_from_name(&format)?)
             } else {
@@ -657,13 +926,16 @@ pub fn main() -> anyhow::Result<()> {
                         explanation.write_in_json(&mut file)?;
                     },
                     Some("txt") => {
-                        write!(file, "{explanation:?}")?;
+                        explanation.write_in_text(&mut file)?;
                     },
-                    _ => bail!("The given explanation file {} must have an extension that is .json or .txt", explain_file.display())
+                    Some("dot" | "gv") => {
+                        explanation.write_in_dot(&mut file)?;
+                    },
+                    _ => bail!("The given explanation file {} must have an extension that is .json, .txt, .dot or .gv", explain_file.display())
                 }
                 close_file_writer(file)?;
             } else if explain || stats {
-                eprintln!("{explanation:#?}");
+                explanation.write_in_text(stderr().lock())?;
             }
             print_result
         }
@@ -672,6 +944,8 @@ pub fn main() -> anyhow::Result<()> {
             update,
             update_file,
             update_base,
+            explain,
+            stats,
         } => {
             let update = if let Some(update) = update {
                 update
@@ -684,7 +958,13 @@ pub fn main() -> anyhow::Result<()> {
             };
             let update = Update::parse(&update, update_base.as_deref())?;
             let store = Store::open(location)?;
-            store.update(update)?;
+            if explain || stats {
+                let explanation =
+                    store.explain_update_opt(update, UpdateOptions::default(), stats)?;
+                explanation.write_in_text(stderr().lock())?;
+            } else {
+                store.update(update)?;
+            }
             store.flush()?;
             Ok(())
         }
@@ -911,21 +1191,149 @@ fn rdf_format_from_name(name: &str) -> anyhow::Result<RdfFormat> {
     bail!("The file format '{name}' is unknown")
 }
 
-fn serve(store: Store, bind: &str, read_only: bool, cors: bool) -> anyhow::Result<()> {
-    let mut server = if cors {
-        Server::new(cors_middleware(move |request| {
-            handle_request(request, store.clone(), read_only)
-                .unwrap_or_else(|(status, message)| error(status, message))
-        }))
-    } else {
-        Server::new(move |request| {
-            handle_request(request, store.clone(), read_only)
-                .unwrap_or_else(|(status, message)| error(status, message))
+/// The content exposed by the HTTP server
+enum Service {
+    Store(Store),
+    Datasets(Datasets),
+}
+
+fn serve(mut service: Service, read_only: bool, args: ServerArgs) -> anyhow::Result<()> {
+    let config = args.server_config()?;
+    let runtime = Arc::new(RwLock::new(Arc::new(config.runtime()?)));
+    let queries = match (&mut service, config.queries_dir.clone()) {
+        (Service::Store(_), Some(queries_dir)) => SavedQueries::open(queries_dir)?,
+        (Service::Datasets(datasets), Some(queries_dir)) => {
+            // Each dataset has its own saved queries
+            datasets.open_saved_queries(queries_dir)?;
+            SavedQueries::default()
+        }
+        (_, None) => SavedQueries::default(),
+    };
+    let admin = if config.admin.enabled {
+        if config.auth_file.is_none() {
+            bail!("The admin endpoints can only be enabled if an authentication file is given")
+        }
+        if let Some(backup_dir) = &config.admin.backup_dir {
+            fs::create_dir_all(backup_dir).with_context(|| {
+                format!(
+                    "Failed to create the backup directory {}",
+                    backup_dir.display()
+                )
+            })?;
+        }
+        Some(AdminOptions {
+            backup_dir: config.admin.backup_dir.clone(),
         })
+    } else {
+        if config.admin.backup_dir.is_some() {
+            bail!("The backup directory can only be set if the admin endpoints are enabled")
+        }
+        None
+    };
+    let context = ServerContext {
+        queries,
+        prefixes: config.prefixes(),
+        plans: PlanCache::new(config.plan_cache_size()),
+        max_result_rows: config.limits.max_result_rows,
+        cursors: Cursors::new(
+            config.limits.max_result_rows,
+            config.max_cursors(),
+            config.max_cursors_per_principal(),
+            config.cursor_timeout(),
+        ),
+        admin,
+    };
+    let audit_log = if let Some(audit) = &config.audit {
+        if !config.async_server && config.tls.is_none() {
+            // The oxhttp server does not give the peer address and the audit entries must have it
+            bail!("The audit log can only be enabled with the async server")
+        }
+        Some(Arc::new(AuditLog::open(audit).with_context(|| {
+            format!("Failed to open the audit log {}", audit.path.display())
+        })?))
+    } else {
+        None
+    };
+    if let Some(path) = args.config.clone() {
+        watch_config(
+            path,
+            config.clone(),
+            move || args.server_config(),
+            Arc::clone(&runtime),
+        )?;
+    }
+    let www_authenticate =
+        HeaderValue::from_str(r#"Basic realm="Oxigraph", Bearer realm="Oxigraph""#)?;
+    let handler = {
+        let runtime = Arc::clone(&runtime);
+        move |request: &mut Request, peer: &Peer| {
+            let start = Instant::now();
+            let runtime = current_runtime_config(&runtime);
+            let mut response = runtime
+                .access_control
+                .as_ref()
+                .map_or_else(
+                    || Ok(Permissions::all()),
+                    |a| a.authenticate(request, peer.certificate_subject.as_deref()),
+                )
+                .and_then(|permissions| {
+                    let auditor = RequestAuditor::new(
+                        audit_log.clone(),
+                        request,
+                        peer,
+                        permissions.principal(),
+                    );
+                    match &service {
+                        Service::Store(store) => handle_request(
+                            request,
+                            store.clone(),
+                            &context,
+                            read_only,
+                            &permissions,
+                            &auditor,
+                        ),
+                        Service::Datasets(datasets) => handle_datasets_request(
+                            request,
+                            datasets,
+                            &context,
+                            read_only,
+                            &permissions,
+                            &auditor,
+                        ),
+                    }
+                })
+                .unwrap_or_else(|(status, message)| error(status, message));
+            if response.status() == Status::UNAUTHORIZED {
+                response
+                    .headers_mut()
+                    .append(HeaderName::WWW_AUTHENTICATE, www_authenticate.clone());
+            }
+            if runtime.log_requests {
+                eprintln!(
+                    "{} {} {} {}ms",
+                    request.method(),
+                    request.url().path(),
+                    response.status(),
+                    start.elapsed().as_millis()
+                );
+            }
+            response
+        }
+    };
+    let handler = cors_middleware(
+        compression_middleware(handler, config.max_decoded_body_size()),
+        runtime,
+    );
+    if config.async_server || config.tls.is_some() {
+        // oxhttp does not support TLS, the async server terminates the TLS connections itself
+        return serve_async(handler, &config);
     }
-    .with_global_timeout(HTTP_TIMEOUT)
-    .with_server_name(concat!("Oxigraph/", env!("CARGO_PKG_VERSION")))?
-    .with_max_concurrent_connections(available_parallelism()?.get() * 128);
+    let bind = config.bind();
+    let timeout = config.timeout();
+    let mut server = Server::new(move |request| handler(request, &Peer::default()))
+        .with_global_timeout(timeout)
+        .with_server_name(concat!("Oxigraph/", env!("CARGO_PKG_VERSION")))?
+        .with_max_concurrent_connections(config.max_concurrent_connections()?);
     for socket in bind.to_socket_addrs()? {
         server = server.bind(socket);
     }
@@ -937,9 +1345,15 @@ fn serve(store: Store, bind: &str, read_only: bool, cors: bool) -> anyhow::Resul
     Ok(())
 }
 
+/// The current runtime options, even if a reload panicked
+fn current_runtime_config(runtime: &RwLock<Arc<RuntimeConfig>>) -> Arc<RuntimeConfig> {
+    Arc::clone(&runtime.read().unwrap_or_else(PoisonError::into_inner))
+}
+
 fn cors_middleware(
-    on_request: impl Fn(&mut Request) -> Response + Send + Sync + 'static,
-) -> impl Fn(&mut Request) -> Response + Send + Sync + 'static {
+    on_request: impl Fn(&mut Request, &Peer) -> Response + Send + Sync + 'static,
+    runtime: Arc<RwLock<Arc<RuntimeConfig>>>,
+) -> impl Fn(&mut Request, &Peer) -> Response + Send + Sync + 'static {
     let origin = HeaderName::from_str("Origin").unwrap();
     let access_control_allow_origin = HeaderName::from_str("Access-Control-Allow-Origin").unwrap();
     let access_control_request_method =
@@ -949,46 +1363,354 @@ fn cors_middleware(
         HeaderName::from_str("Access-Control-Request-Headers").unwrap();
     let access_control_allow_headers =
         HeaderName::from_str("Access-Control-Allow-Headers").unwrap();
-    let star = HeaderValue::from_str("*").unwrap();
-    move |request| {
-        if *request.method() == Method::OPTIONS {
+    let origin_value = HeaderValue::from_str("Origin").unwrap();
+    move |request, peer| {
+        let runtime = current_runtime_config(&runtime);
+        if runtime.cors_allowed_origins.is_empty() {
+            return on_request(request, peer);
+        }
+        let allowed_origin = request
+            .header(&origin)
+            .and_then(|origin| origin.to_str().ok())
+            .and_then(|origin| runtime.cors_allowed_origin(origin))
+            .map(ToOwned::to_owned);
+        let mut response = if *request.method() == Method::OPTIONS {
             let mut response = Response::builder(Status::NO_CONTENT);
-            if request.header(&origin).is_some() {
-                response
-                    .headers_mut()
-                    .append(access_control_allow_origin.clone(), star.clone());
-            }
-            if let Some(method) = request.header(&access_control_request_method) {
-                response
-                    .headers_mut()
-                    .append(access_control_allow_method.clone(), method.clone());
-            }
-            if let Some(headers) = request.header(&access_control_request_headers) {
-                response
-                    .headers_mut()
-                    .append(access_control_allow_headers.clone(), headers.clone());
+            if allowed_origin.is_some() {
+                if let Some(method) = request.header(&access_control_request_method) {
+                    response
+                        .headers_mut()
+                        .append(access_control_allow_method.clone(), method.clone());
+                }
+                if let Some(headers) = request.header(&access_control_request_headers) {
+                    response
+                        .headers_mut()
+                        .append(access_control_allow_headers.clone(), headers.clone());
+                }
             }
             response.build()
         } else {
-            let mut response = on_request(request);
-            if request.header(&origin).is_some() {
+            on_request(request, peer)
+        };
+        if !runtime.cors_allows_all_origins() {
+            // The response depends on the request origin, even if it is not allowed
+            response
+                .headers_mut()
+                .append(HeaderName::VARY, origin_value.clone());
+        }
+        if let Some(allowed_origin) = allowed_origin {
+            if let Ok(allowed_origin) = HeaderValue::from_str(&allowed_origin) {
                 response
                     .headers_mut()
-                    .append(access_control_allow_origin.clone(), star.clone());
+                    .append(access_control_allow_origin.clone(), allowed_origin);
             }
-            response
         }
+        response
     }
 }
 
 type HttpError = (Status, String);
 
+/// The client a request has been received from
+#[derive(Default)]
+struct Peer {
+    /// Address of the TCP peer, `None` for the thread per connection server that does not provide it
+    address: Option<SocketAddr>,
+    /// Common name of the client TLS certificate, already verified during the TLS handshake
+    certificate_subject: Option<String>,
+}
+
+/// The state shared by the requests to all the datasets of a server
+struct ServerContext {
+    /// The saved queries of the store, each dataset has its own ones
+    queries: SavedQueries,
+    /// Namespace prefixes used when serializing RDF
+    prefixes: Vec<(String, String)>,
+    plans: PlanCache,
+    /// Maximal number of solutions or triples returned per response
+    max_result_rows: Option<usize>,
+    cursors: Cursors,
+    /// `None` if the /admin/dump, /admin/backup and /admin/optimize endpoints are disabled
+    admin: Option<AdminOptions>,
+}
+
+impl ServerContext {
+    /// Truncates to `max_result_rows` the results of the queries that are not paged
+    fn limit_result_rows(&self, options: QueryOptions) -> QueryOptions {
+        if let Some(max_result_rows) = self.max_result_rows {
+            options.with_max_result_rows(max_result_rows, RowLimitPolicy::Truncate)
+        } else {
+            options
+        }
+    }
+}
+
+/// Options of the maintenance endpoints
+struct AdminOptions {
+    /// Directory the backups are created in, `None` if backups are disabled
+    backup_dir: Option<PathBuf>,
+}
+
 fn handle_request(
     request: &mut Request,
     store: Store,
+    context: &ServerContext,
+    read_only: bool,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
+) -> Result<Response, HttpError> {
+    let path = request.url().path().to_owned();
+    handle_store_request(
+        request,
+        &path,
+        store,
+        None,
+        context,
+        read_only,
+        permissions,
+        auditor,
+    )
+}
+
+/// Handles the requests when several datasets are served
+fn handle_datasets_request(
+    request: &mut Request,
+    datasets: &Datasets,
+    context: &ServerContext,
+    read_only: bool,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
+) -> Result<Response, HttpError> {
+    let path = request.url().path().to_owned();
+    if let Some(response) = static_file(&path, request.method()) {
+        return Ok(response);
+    }
+    if path == "/admin/datasets" || path.starts_with("/admin/datasets/") {
+        let mut record = auditor.start(AuditOperation::Admin, &RequestTarget(request));
+        return record.check(handle_datasets_admin_request(
+            request,
+            &path,
+            datasets,
+            context.admin.is_some(),
+            read_only,
+            permissions,
+        ));
+    }
+    // The first path segment is the dataset name
+    let (name, path) = match path[1..].find('/') {
+        Some(end) => (&path[1..=end], &path[end + 1..]),
+        None => (&path[1..], "/"),
+    };
+    let dataset = datasets.get(name)?.ok_or_else(|| {
+        (
+            Status::NOT_FOUND,
+            format!("The dataset {name} does not exist"),
+        )
+    })?;
+    let response = handle_store_request(
+        request,
+        path,
+        dataset.store().clone(),
+        Some(&dataset),
+        context,
+        read_only,
+        &permissions.for_dataset(name),
+        auditor,
+    )?;
+    Ok(keep_dataset_until_sent(response, request, dataset))
+}
+
+/// Handles the requests to the /admin/datasets endpoint
+///
+/// The datasets can only be created and deleted if the admin endpoints are enabled.
+fn handle_datasets_admin_request(
+    request: &Request,
+    path: &str,
+    datasets: &Datasets,
+    admin: bool,
+    read_only: bool,
+    permissions: &Permissions,
+) -> Result<Response, HttpError> {
+    match (path, request.method().as_ref()) {
+        ("/admin/datasets", "GET") => {
+            // Only the datasets the principal is allowed to read are listed
+            let mut body = String::from("[");
+            for (i, name) in datasets
+                .names()?
+                .into_iter()
+                .filter(|name| permissions.for_dataset(name).check_read_any().is_ok())
+                .enumerate()
+            {
+                if i > 0 {
+                    body.push(',');
+                }
+                // Dataset names do not need any escaping
+                body.push_str(&format!("\"{name}\""));
+            }
+            body.push(']');
+            Ok(Response::builder(Status::OK)
+                .with_header(HeaderName::CONTENT_TYPE, "application/json")
+                .unwrap()
+                .with_body(body))
+        }
+        (path, "PUT") if admin && path.starts_with("/admin/datasets/") => {
+            if read_only {
+                return Err(the_server_is_read_only());
+            }
+            permissions.check_admin()?;
+            Ok(
+                Response::builder(if datasets.create(&path["/admin/datasets/".len()..])? {
+                    Status::CREATED
+                } else {
+                    Status::NO_CONTENT
+                })
+                .build(),
+            )
+        }
+        (path, "DELETE") if admin && path.starts_with("/admin/datasets/") => {
+            if read_only {
+                return Err(the_server_is_read_only());
+            }
+            permissions.check_admin()?;
+            let name = &path["/admin/datasets/".len()..];
+            if datasets.delete(name)? {
+                Ok(Response::builder(Status::NO_CONTENT).build())
+            } else {
+                Err((
+                    Status::NOT_FOUND,
+                    format!("The dataset {name} does not exist"),
+                ))
+            }
+        }
+        _ => Err((
+            Status::NOT_FOUND,
+            format!(
+                "{} {path} is not supported by this server",
+                request.method()
+            ),
+        )),
+    }
+}
+
+/// Keeps the dataset until the response body has been sent because it might be lazily read from the store
+fn keep_dataset_until_sent(
+    mut response: Response,
+    request: &Request,
+    dataset: Dataset,
+) -> Response {
+    if *request.method() == Method::HEAD || response.status() != Status::OK {
+        return response;
+    }
+    let body = replace(response.body_mut(), Body::from(Vec::new()));
+    *response.body_mut() = Body::from_read(DatasetBody {
+        body,
+        _dataset: dataset,
+    });
+    response
+}
+
+struct DatasetBody {
+    // Declared before the dataset so that the store is not used anymore when the dataset is dropped
+    body: Body,
+    _dataset: Dataset,
+}
+
+impl Read for DatasetBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+/// The HTML UI assets that are served whatever the requested dataset
+fn static_file(path: &str, method: &Method) -> Option<Response> {
+    let (content_type, content) = match path {
+        "/yasgui.min.css" => ("text/css", YASGUI_CSS),
+        "/yasgui.min.js" => ("application/javascript", YASGUI_JS),
+        "/logo.svg" => ("image/svg+xml", LOGO),
+        _ => return None,
+    };
+    let response = Response::builder(Status::OK)
+        .with_header(HeaderName::CONTENT_TYPE, content_type)
+        .unwrap();
+    match method.as_ref() {
+        "HEAD" => Some(response.build()),
+        "GET" => Some(response.with_body(content)),
+        _ => None,
+    }
+}
+
+/// Handles the requests to a store, `path` being relative to the store root
+///
+/// `dataset` is the dataset of the store if the server serves several datasets.
+fn handle_store_request(
+    request: &mut Request,
+    path: &str,
+    store: Store,
+    dataset: Option<&Dataset>,
+    context: &ServerContext,
+    read_only: bool,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
+) -> Result<Response, HttpError> {
+    let Some(operation) = audited_request_operation(path, request.method()) else {
+        return route_store_request(
+            request,
+            path,
+            store,
+            dataset,
+            context,
+            read_only,
+            permissions,
+            auditor,
+        );
+    };
+    let mut record = auditor.start(operation, &RequestTarget(request));
+    record.check(route_store_request(
+        request,
+        path,
+        store,
+        dataset,
+        context,
+        read_only,
+        permissions,
+        auditor,
+    ))
+}
+
+/// The audit log operation of the requests that are not SPARQL queries or updates, `None` if they are not logged
+///
+/// The saved query calls are logged by [`evaluate_saved_query`] to count the results.
+fn audited_request_operation(path: &str, method: &Method) -> Option<AuditOperation> {
+    if path.starts_with("/admin/") {
+        Some(AuditOperation::Admin)
+    } else if path == "/fragments" {
+        Some(AuditOperation::Fragments)
+    } else if path.starts_with("/store") && matches!(method.as_ref(), "PUT" | "POST" | "DELETE") {
+        Some(AuditOperation::GraphStore)
+    } else {
+        None
+    }
+}
+
+fn route_store_request(
+    request: &mut Request,
+    path: &str,
+    store: Store,
+    dataset: Option<&Dataset>,
+    context: &ServerContext,
     read_only: bool,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
 ) -> Result<Response, HttpError> {
-    match (request.url().path(), request.method().as_ref()) {
+    if let Some(response) = static_file(path, request.method()) {
+        return Ok(response);
+    }
+    let cursors = CursorScope {
+        cursors: &context.cursors,
+        dataset,
+    };
+    let queries = dataset.map_or(&context.queries, Dataset::queries);
+    match (path, request.method().as_ref()) {
         ("/", "HEAD") => Ok(Response::builder(Status::OK)
             .with_header(HeaderName::CONTENT_TYPE, "text/html")
             .unwrap()
@@ -997,33 +1719,16 @@ fn handle_request(
             .with_header(HeaderName::CONTENT_TYPE, "text/html")
             .unwrap()
             .with_body(HTML_ROOT_PAGE)),
-        ("/yasgui.min.css", "HEAD") => Ok(Response::builder(Status::OK)
-            .with_header(HeaderName::CONTENT_TYPE, "text/css")
-            .unwrap()
-            .build()),
-        ("/yasgui.min.css", "GET") => Ok(Response::builder(Status::OK)
-            .with_header(HeaderName::CONTENT_TYPE, "text/css")
-            .unwrap()
-            .with_body(YASGUI_CSS)),
-        ("/yasgui.min.js", "HEAD") => Ok(Response::builder(Status::OK)
-            .with_header(HeaderName::CONTENT_TYPE, "application/javascript")
-            .unwrap()
-            .build()),
-        ("/yasgui.min.js", "GET") => Ok(Response::builder(Status::OK)
-            .with_header(HeaderName::CONTENT_TYPE, "application/javascript")
-            .unwrap()
-            .with_body(YASGUI_JS)),
-        ("/logo.svg", "HEAD") => Ok(Response::builder(Status::OK)
-            .with_header(HeaderName::CONTENT_TYPE, "image/svg+xml")
-            .unwrap()
-            .build()),
-        ("/logo.svg", "GET") => Ok(Response::builder(Status::OK)
-            .with_header(HeaderName::CONTENT_TYPE, "image/svg+xml")
-            .unwrap()
-            .with_body(LOGO)),
-        ("/query", "GET") => {
-            configure_and_evaluate_sparql_query(&store, &[url_query(request)], None, request)
-        }
+        ("/query", "GET") => configure_and_evaluate_sparql_query(
+            &store,
+            context,
+            cursors,
+            &[url_query(request)],
+            None,
+            request,
+            permissions,
+            auditor,
+        ),
         ("/query", "POST") => {
             let content_type =
                 content_type(request).ok_or_else(|| bad_request("No Content-Type given"))?;
@@ -1032,9 +1737,13 @@ fn handle_request(
                     .map_err(bad_request)?;
                 configure_and_evaluate_sparql_query(
                     &store,
+                    context,
+                    cursors,
                     &[url_query(request)],
                     Some(query),
                     request,
+                    permissions,
+                    auditor,
                 )
             } else if content_type == "application/x-www-form-urlencoded" {
                 let mut buffer = Vec::new();
@@ -1045,9 +1754,13 @@ fn handle_request(
                     .map_err(bad_request)?;
                 configure_and_evaluate_sparql_query(
                     &store,
+                    context,
+                    cursors,
                     &[url_query(request), &buffer],
                     None,
                     request,
+                    permissions,
+                    auditor,
                 )
             } else {
                 Err(unsupported_media_type(&content_type))
@@ -1057,6 +1770,7 @@ fn handle_request(
             if read_only {
                 return Err(the_server_is_read_only());
             }
+            let _guard = write_guard()?;
             let content_type =
                 content_type(request).ok_or_else(|| bad_request("No Content-Type given"))?;
             if content_type == "application/sparql-update" {
@@ -1067,6 +1781,8 @@ fn handle_request(
                     &[url_query(request)],
                     Some(update),
                     request,
+                    permissions,
+                    auditor,
                 )
             } else if content_type == "application/x-www-form-urlencoded" {
                 let mut buffer = Vec::new();
@@ -1080,29 +1796,39 @@ fn handle_request(
                     &[url_query(request), &buffer],
                     None,
                     request,
+                    permissions,
+                    auditor,
                 )
             } else {
                 Err(unsupported_media_type(&content_type))
             }
         }
+        ("/fragments", "GET") => handle_fragments_request(
+            request,
+            &store,
+            &context.prefixes,
+            context.max_result_rows,
+            permissions,
+        ),
         (path, "GET") if path.starts_with("/store") => {
-            if let Some(target) = store_target(request)? {
+            if let Some(target) = store_target(request, path)? {
+                permissions.check_read(&target.clone().into())?;
                 assert_that_graph_exists(&store, &target)?;
                 let format = rdf_content_negotiation(request)?;
-
+                // The version is read before the snapshot so that the ETag is never newer than the content
+                let etag = graph_etag(&store, &target);
+                if let Some(response) = not_modified(request, etag.as_deref())? {
+                    return Ok(response);
+                }
                 let quads = store.quads_for_pattern(
                     None,
                     None,
                     None,
                     Some(GraphName::from(target).as_ref()),
                 );
-                ReadForWrite::build_response(
-                    move |w| {
-                        Ok((
-                            RdfSerializer::from_format(format).serialize_to_write(w),
-                            quads,
-                        ))
-                    },
+                let serializer = rdf_serializer(format, &context.prefixes)?;
+                let response = ReadForWrite::build_response(
+                    move |w| Ok((serializer.serialize_to_write(w), quads)),
                     |(mut writer, mut quads)| {
                         Ok(if let Some(q) = quads.next() {
                             writer.write_triple(&q?.into())?;
@@ -1113,7 +1839,8 @@ fn handle_request(
                         })
                     },
                     format.media_type(),
-                )
+                )?;
+                with_etag(response, etag.as_deref())
             } else {
                 let format = rdf_content_negotiation(request)?;
                 if !format.supports_datasets() {
@@ -1121,13 +1848,19 @@ fn handle_request(
                         "It is not possible to serialize the full RDF dataset using {format} that does not support named graphs"
                     )));
                 }
-                ReadForWrite::build_response(
-                    move |w| {
-                        Ok((
-                            RdfSerializer::from_format(format).serialize_to_write(w),
-                            store.iter(),
-                        ))
-                    },
+                permissions.check_read_any()?;
+                let etag = dataset_etag(&store);
+                if let Some(response) = not_modified(request, etag.as_deref())? {
+                    return Ok(response);
+                }
+                let permissions = permissions.clone();
+                let quads = store.iter().filter(move |q| {
+                    q.as_ref()
+                        .map_or(true, |q| permissions.can_read(&q.graph_name))
+                });
+                let serializer = rdf_serializer(format, &context.prefixes)?;
+                let response = ReadForWrite::build_response(
+                    move |w| Ok((serializer.serialize_to_write(w), quads)),
                     |(mut writer, mut quads)| {
                         Ok(if let Some(q) = quads.next() {
                             writer.write_quad(&q?)?;
@@ -1138,7 +1871,8 @@ fn handle_request(
                         })
                     },
                     format.media_type(),
-                )
+                )?;
+                with_etag(response, etag.as_deref())
             }
         }
         (path, "PUT") if path.starts_with("/store") => {
@@ -1147,9 +1881,11 @@ fn handle_request(
             }
             let content_type =
                 content_type(request).ok_or_else(|| bad_request("No Content-Type given"))?;
-            if let Some(target) = store_target(request)? {
+            if let Some(target) = store_target(request, path)? {
+                permissions.check_write(&target.clone().into())?;
                 let format = RdfFormat::from_media_type(&content_type)
                     .ok_or_else(|| unsupported_media_type(&content_type))?;
+                let _guard = check_if_match(request, || existing_graph_etag(&store, &target))?;
                 let new = !match &target {
                     NamedGraphName::NamedNode(target) => {
                         if store
@@ -1180,8 +1916,10 @@ fn handle_request(
                 })
                 .build())
             } else {
+                permissions.check_write_all()?;
                 let format = RdfFormat::from_media_type(&content_type)
                     .ok_or_else(|| unsupported_media_type(&content_type))?;
+                let _guard = check_if_match(request, || Ok(dataset_etag(&store)))?;
                 store.clear().map_err(internal_server_error)?;
                 web_load_dataset(&store, request, format)?;
                 Ok(Response::builder(Status::NO_CONTENT).build())
@@ -1191,7 +1929,9 @@ fn handle_request(
             if read_only {
                 return Err(the_server_is_read_only());
             }
-            if let Some(target) = store_target(request)? {
+            if let Some(target) = store_target(request, path)? {
+                permissions.check_write(&target.clone().into())?;
+                let _guard = check_if_match(request, || existing_graph_etag(&store, &target))?;
                 match target {
                     NamedGraphName::DefaultGraph => store
                         .clear_graph(GraphNameRef::DefaultGraph)
@@ -1213,6 +1953,8 @@ fn handle_request(
                     }
                 }
             } else {
+                permissions.check_write_all()?;
+                let _guard = check_if_match(request, || Ok(dataset_etag(&store)))?;
                 store.clear().map_err(internal_server_error)?;
             }
             Ok(Response::builder(Status::NO_CONTENT).build())
@@ -1221,9 +1963,11 @@ fn handle_request(
             if read_only {
                 return Err(the_server_is_read_only());
             }
+            let _guard = write_guard()?;
             let content_type =
                 content_type(request).ok_or_else(|| bad_request("No Content-Type given"))?;
-            if let Some(target) = store_target(request)? {
+            if let Some(target) = store_target(request, path)? {
+                permissions.check_write(&target.clone().into())?;
                 let format = RdfFormat::from_media_type(&content_type)
                     .ok_or_else(|| unsupported_media_type(&content_type))?;
                 let new = assert_that_graph_exists(&store, &target).is_ok();
@@ -1238,11 +1982,13 @@ fn handle_request(
                 let format = RdfFormat::from_media_type(&content_type)
                     .ok_or_else(|| unsupported_media_type(&content_type))?;
                 if format.supports_datasets() {
+                    permissions.check_write_all()?;
                     web_load_dataset(&store, request, format)?;
                     Ok(Response::builder(Status::NO_CONTENT).build())
                 } else {
                     let graph =
                         resolve_with_base(request, &format!("/store/{:x}", random::<u128>()))?;
+                    permissions.check_write(&graph.clone().into())?;
                     web_load_graph(&store, request, format, &graph.clone().into())?;
                     Ok(Response::builder(Status::CREATED)
                         .with_header(HeaderName::LOCATION, graph.into_string())
@@ -1252,13 +1998,134 @@ fn handle_request(
             }
         }
         (path, "HEAD") if path.starts_with("/store") => {
-            if let Some(target) = store_target(request)? {
+            let etag = if let Some(target) = store_target(request, path)? {
+                permissions.check_read(&target.clone().into())?;
                 assert_that_graph_exists(&store, &target)?;
+                graph_etag(&store, &target)
+            } else {
+                permissions.check_read_any()?;
+                dataset_etag(&store)
+            };
+            if let Some(response) = not_modified(request, etag.as_deref())? {
+                return Ok(response);
             }
-            Ok(Response::builder(Status::OK).build())
+            with_etag(Response::builder(Status::OK).build(), etag.as_deref())
         }
-        _ => Err((
-            Status::NOT_FOUND,
+        ("/admin/dump", "GET") if context.admin.is_some() => {
+            permissions.check_admin()?;
+            let format = if let Some(format) = url_query_parameter(request, "format") {
+                rdf_format_from_name(&format).map_err(bad_request)?
+            } else {
+                rdf_content_negotiation(request)?
+            };
+            if let Some(target) = admin_dump_target(request)? {
+                assert_that_graph_exists(&store, &target)?;
+                // quads_for_pattern reads from a single snapshot
+                let quads = store.quads_for_pattern(
+                    None,
+                    None,
+                    None,
+                    Some(GraphName::from(target).as_ref()),
+                );
+                let serializer = rdf_serializer(format, &context.prefixes)?;
+                ReadForWrite::build_response(
+                    move |w| Ok((serializer.serialize_to_write(w), quads)),
+                    |(mut writer, mut quads)| {
+                        Ok(if let Some(q) = quads.next() {
+                            writer.write_triple(&q?.into())?;
+                            Some((writer, quads))
+                        } else {
+                            writer.finish()?;
+                            None
+                        })
+                    },
+                    format.media_type(),
+                )
+            } else {
+                if !format.supports_datasets() {
+                    return Err(bad_request(format!(
+                        "It is not possible to dump the full RDF dataset using {format} that does not support named graphs, use the graph or default parameter to select a graph"
+                    )));
+                }
+                // The iterator is built here to get the snapshot at request time
+                let quads = store.iter();
+                let serializer = rdf_serializer(format, &context.prefixes)?;
+                ReadForWrite::build_response(
+                    move |w| Ok((serializer.serialize_to_write(w), quads)),
+                    |(mut writer, mut quads)| {
+                        Ok(if let Some(q) = quads.next() {
+                            writer.write_quad(&q?)?;
+                            Some((writer, quads))
+                        } else {
+                            writer.finish()?;
+                            None
+                        })
+                    },
+                    format.media_type(),
+                )
+            }
+        }
+        (path, "GET") if path.starts_with("/api/") => evaluate_saved_query(
+            &store,
+            queries,
+            context,
+            &path["/api/".len()..],
+            request,
+            permissions,
+            auditor,
+        ),
+        (path, _) if path == "/admin/queries" || path.starts_with("/admin/queries/") => {
+            handle_saved_queries_admin_request(request, path, queries, read_only, permissions)
+        }
+        ("/admin/backup", "POST") if context.admin.is_some() => {
+            if read_only {
+                return Err(the_server_is_read_only());
+            }
+            permissions.check_admin()?;
+            let Some(backup_dir) = context
+                .admin
+                .as_ref()
+                .and_then(|admin| admin.backup_dir.as_ref())
+            else {
+                return Err((
+                    Status::FORBIDDEN,
+                    "Backups are disabled, no backup directory is configured".into(),
+                ));
+            };
+            let destination = url_query_parameter(request, "destination")
+                .ok_or_else(|| bad_request("The destination parameter is required"))?;
+            let destination = backup_destination(backup_dir, &destination)?;
+            if destination.exists() {
+                return Err(bad_request(format!(
+                    "The backup destination {} already exists",
+                    destination.display()
+                )));
+            }
+            store.backup(&destination).map_err(internal_server_error)?;
+            Ok(Response::builder(Status::NO_CONTENT).build())
+        }
+        ("/admin/optimize", "POST") if context.admin.is_some() => {
+            if read_only {
+                return Err(the_server_is_read_only());
+            }
+            permissions.check_admin()?;
+            store.optimize().map_err(internal_server_error)?;
+            Ok(Response::builder(Status::NO_CONTENT).build())
+        }
+        ("/metrics", "GET") => {
+            permissions.check_read_all()?;
+            let mut body = Vec::new();
+            store
+                .metrics()
+                .write_in_prometheus(&mut body)
+                .map_err(internal_server_error)?;
+            Ok(Response::builder(Status::OK)
+                .with_header(HeaderName::CONTENT_TYPE, "text/plain; version=0.0.4")
+                .unwrap()
+                .with_body(body))
+        }
+        _ => Err((
+            Status::NOT_FOUND,
             format!(
                 "{} {} is not supported by this server",
                 request.method(),
@@ -1268,6 +2135,22 @@ fn handle_request(
     }
 }
 
+/// The directory of the backup named `name` inside of the backup directory
+fn backup_destination(backup_dir: &Path, name: &str) -> Result<PathBuf, HttpError> {
+    if name.is_empty()
+        || name.len() > 255
+        || name.starts_with('.')
+        || !name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+    {
+        return Err(bad_request(format!(
+            "'{name}' is not a valid backup name, only ASCII letters, digits, '-', '_' and '.' are allowed and it must not start with '.'"
+        )));
+    }
+    Ok(backup_dir.join(name))
+}
+
 fn base_url(request: &Request) -> String {
     let mut url = request.url().clone();
     url.set_query(None);
@@ -1297,13 +2180,19 @@ fn url_query_parameter<'a>(request: &'a Request, param: &str) -> Option<Cow<'a,
 
 fn configure_and_evaluate_sparql_query(
     store: &Store,
+    context: &ServerContext,
+    cursors: CursorScope<'_>,
     encoded: &[&[u8]],
     mut query: Option<String>,
     request: &Request,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
 ) -> Result<Response, HttpError> {
     let mut default_graph_uris = Vec::new();
     let mut named_graph_uris = Vec::new();
     let mut use_default_graph_as_union = false;
+    let mut cursor = None;
+    let mut page_size = None;
     for encoded in encoded {
         for (k, v) in form_urlencoded::parse(encoded) {
             match k.as_ref() {
@@ -1316,30 +2205,125 @@ fn configure_and_evaluate_sparql_query(
                 "default-graph-uri" => default_graph_uris.push(v.into_owned()),
                 "union-default-graph" => use_default_graph_as_union = true,
                 "named-graph-uri" => named_graph_uris.push(v.into_owned()),
+                "cursor" => cursor = Some(v.into_owned()),
+                "page-size" => {
+                    page_size = Some(
+                        v.parse::<usize>()
+                            .ok()
+                            .filter(|size| *size > 0)
+                            .ok_or_else(|| bad_request(format!("Invalid page-size value: {v}")))?,
+                    )
+                }
                 _ => (),
             }
         }
     }
+    if let Some(cursor) = cursor {
+        if query.is_some() {
+            return Err(bad_request(
+                "The 'query' and 'cursor' parameters should not be set at the same time",
+            ));
+        }
+        let (page, query, has_more) =
+            cursors
+                .cursors
+                .next(&cursor, cursors.dataset, permissions.principal(), page_size)?;
+        let record = auditor.start(AuditOperation::Query, &query);
+        return query_results_page_response(
+            page,
+            has_more.then_some(cursor.as_str()),
+            &context.prefixes,
+            request,
+            record,
+        );
+    }
     let query = query.ok_or_else(|| bad_request("You should set the 'query' parameter"))?;
     evaluate_sparql_query(
         store,
+        context,
+        cursors
+            .cursors
+            .page_size(page_size)
+            .map(|size| (cursors, size)),
         &query,
         use_default_graph_as_union,
         default_graph_uris,
         named_graph_uris,
         request,
+        permissions,
+        auditor,
     )
 }
 
+/// Evaluates the query, returning only a page of the results if `paging` is set
 fn evaluate_sparql_query(
     store: &Store,
+    context: &ServerContext,
+    paging: Option<(CursorScope<'_>, usize)>,
     query: &str,
     use_default_graph_as_union: bool,
     default_graph_uris: Vec<String>,
     named_graph_uris: Vec<String>,
     request: &Request,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
 ) -> Result<Response, HttpError> {
-    let mut query = Query::parse(query, Some(&base_url(request))).map_err(bad_request)?;
+    let mut record = auditor.start(AuditOperation::Query, &query);
+    let prepared = record.check(prepare_sparql_query(
+        store,
+        &context.plans,
+        query,
+        use_default_graph_as_union,
+        default_graph_uris,
+        named_graph_uris,
+        request,
+        permissions,
+    ))?;
+    if let Some((cursors, page_size)) = paging {
+        let (page, cursor) = record.check(cursors.cursors.open(
+            store.clone(),
+            cursors.dataset.cloned(),
+            prepared,
+            query,
+            permissions.principal(),
+            page_size,
+        ))?;
+        return query_results_page_response(
+            page,
+            cursor.as_deref(),
+            &context.prefixes,
+            request,
+            record,
+        );
+    }
+    let results = record.check(
+        store
+            .query_prepared(
+                &prepared,
+                context.limit_result_rows(QueryOptions::default()),
+            )
+            .map_err(internal_server_error),
+    )?;
+    query_results_response(results, &context.prefixes, request, record)
+}
+
+/// Prepares the query, reusing the plan cache, and sets its dataset from the protocol parameters and the permissions
+fn prepare_sparql_query(
+    store: &Store,
+    plans: &PlanCache,
+    query: &str,
+    use_default_graph_as_union: bool,
+    default_graph_uris: Vec<String>,
+    named_graph_uris: Vec<String>,
+    request: &Request,
+    permissions: &Permissions,
+) -> Result<PreparedQuery, HttpError> {
+    let base_iri = base_url(request);
+    let mut query = plans.get_or_prepare(query, &base_iri, || {
+        store
+            .prepare(Query::parse(query, Some(&base_iri)).map_err(bad_request)?)
+            .map_err(internal_server_error)
+    })?;
 
     if use_default_graph_as_union {
         if !default_graph_uris.is_empty() || !named_graph_uris.is_empty() {
@@ -1364,23 +2348,36 @@ fn evaluate_sparql_query(
                 .map_err(bad_request)?,
         );
     }
+    permissions.restrict_dataset(query.dataset_mut())?;
+    Ok(query)
+}
 
-    let results = store.query(query).map_err(internal_server_error)?;
+/// Serializes the query results using the request content negotiation
+///
+/// The audit record is kept until the results are fully serialized.
+fn query_results_response(
+    results: QueryResults,
+    prefixes: &[(String, String)],
+    request: &Request,
+    mut record: AuditRecord,
+) -> Result<Response, HttpError> {
     match results {
         QueryResults::Solutions(solutions) => {
-            let format = query_results_content_negotiation(request)?;
+            let format = record.check(query_results_content_negotiation(request))?;
+            record.start_results();
             ReadForWrite::build_response(
                 move |w| {
                     Ok((
                         QueryResultsSerializer::from_format(format)
                             .serialize_solutions_to_write(w, solutions.variables().to_vec())?,
                         solutions,
+                        record,
                     ))
                 },
-                |(mut writer, mut solutions)| {
+                |(mut writer, mut solutions, mut record)| {
                     Ok(if let Some(solution) = solutions.next() {
-                        writer.write(&solution?)?;
-                        Some((writer, solutions))
+                        writer.write(&record.count_result(solution)?)?;
+                        Some((writer, solutions, record))
                     } else {
                         writer.finish()?;
                         None
@@ -1390,7 +2387,7 @@ fn evaluate_sparql_query(
             )
         }
         QueryResults::Boolean(result) => {
-            let format = query_results_content_negotiation(request)?;
+            let format = record.check(query_results_content_negotiation(request))?;
             let mut body = Vec::new();
             QueryResultsSerializer::from_format(format)
                 .serialize_boolean_to_write(&mut body, result)
@@ -1401,18 +2398,15 @@ fn evaluate_sparql_query(
                 .with_body(body))
         }
         QueryResults::Graph(triples) => {
-            let format = rdf_content_negotiation(request)?;
+            let format = record.check(rdf_content_negotiation(request))?;
+            let serializer = record.check(rdf_serializer(format, prefixes))?;
+            record.start_results();
             ReadForWrite::build_response(
-                move |w| {
-                    Ok((
-                        RdfSerializer::from_format(format).serialize_to_write(w),
-                        triples,
-                    ))
-                },
-                |(mut writer, mut triples)| {
+                move |w| Ok((serializer.serialize_to_write(w), triples, record)),
+                |(mut writer, mut triples, mut record)| {
                     Ok(if let Some(t) = triples.next() {
-                        writer.write_triple(&t?)?;
-                        Some((writer, triples))
+                        writer.write_triple(&record.count_result(t)?)?;
+                        Some((writer, triples, record))
                     } else {
                         writer.finish()?;
                         None
@@ -1424,11 +2418,138 @@ fn evaluate_sparql_query(
     }
 }
 
+/// Serializes a page of query results with a link to the next page if there is a cursor
+fn query_results_page_response(
+    page: Page,
+    cursor: Option<&str>,
+    prefixes: &[(String, String)],
+    request: &Request,
+    mut record: AuditRecord,
+) -> Result<Response, HttpError> {
+    let mut body = Vec::new();
+    let content_type = match page {
+        Page::Solutions {
+            variables,
+            solutions,
+        } => {
+            let format = record.check(query_results_content_negotiation(request))?;
+            let mut writer = QueryResultsSerializer::from_format(format)
+                .serialize_solutions_to_write(&mut body, variables)
+                .map_err(internal_server_error)?;
+            for solution in &solutions {
+                writer.write(solution).map_err(internal_server_error)?;
+            }
+            writer.finish().map_err(internal_server_error)?;
+            record.count_results(solutions.len());
+            format.media_type()
+        }
+        Page::Boolean(result) => {
+            let format = record.check(query_results_content_negotiation(request))?;
+            QueryResultsSerializer::from_format(format)
+                .serialize_boolean_to_write(&mut body, result)
+                .map_err(internal_server_error)?;
+            format.media_type()
+        }
+        Page::Graph(triples) => {
+            let format = record.check(rdf_content_negotiation(request))?;
+            let mut writer = record
+                .check(rdf_serializer(format, prefixes))?
+                .serialize_to_write(&mut body);
+            for triple in &triples {
+                writer.write_triple(triple).map_err(internal_server_error)?;
+            }
+            writer.finish().map_err(internal_server_error)?;
+            record.count_results(triples.len());
+            format.media_type()
+        }
+    };
+    let mut response = Response::builder(Status::OK)
+        .with_header(HeaderName::CONTENT_TYPE, content_type)
+        .map_err(internal_server_error)?;
+    if let Some(cursor) = cursor {
+        let mut next = request.url().clone();
+        next.query_pairs_mut().clear().append_pair("cursor", cursor);
+        let link = format!("<{next}>; rel=\"next\"");
+        response = response
+            .with_header(
+                HeaderName::from_str("Link").map_err(internal_server_error)?,
+                link.as_str(),
+            )
+            .map_err(internal_server_error)?;
+    }
+    Ok(response.with_body(body))
+}
+
+/// Evaluates the saved query `name` with the parameters given in the URL query
+fn evaluate_saved_query(
+    store: &Store,
+    queries: &SavedQueries,
+    context: &ServerContext,
+    name: &str,
+    request: &Request,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
+) -> Result<Response, HttpError> {
+    let mut record = auditor.start(AuditOperation::Api, &RequestTarget(request));
+    let saved = record.check(queries.get(name).and_then(|saved| {
+        saved.ok_or_else(|| {
+            (
+                Status::NOT_FOUND,
+                format!("The saved query {name} does not exist"),
+            )
+        })
+    }))?;
+    let (mut query, options) = record.check(saved.bind(request.url().query_pairs()))?;
+    record.check(permissions.restrict_dataset(query.dataset_mut()))?;
+    let standard_format = saved.returns_graph()
+        || record.check(api_results_content_negotiation(request))? == ApiResultsFormat::Standard;
+    let results = record.check(
+        store
+            .query_opt(query, context.limit_result_rows(options))
+            .map_err(|e| match e {
+                EvaluationError::InvalidBinding(_) => bad_request(e),
+                e => internal_server_error(e),
+            }),
+    )?;
+    if standard_format {
+        return query_results_response(results, &context.prefixes, request, record);
+    }
+    match results {
+        QueryResults::Solutions(solutions) => {
+            record.start_results();
+            ReadForWrite::build_response(
+                move |w| Ok((SimpleJsonSolutionsWriter::start(w)?, solutions, record)),
+                |(mut writer, mut solutions, mut record)| {
+                    Ok(if let Some(solution) = solutions.next() {
+                        writer.write(&record.count_result(solution)?)?;
+                        Some((writer, solutions, record))
+                    } else {
+                        writer.finish()?;
+                        None
+                    })
+                },
+                "application/json",
+            )
+        }
+        QueryResults::Boolean(result) => Ok(Response::builder(Status::OK)
+            .with_header(HeaderName::CONTENT_TYPE, "application/json")
+            .unwrap()
+            .with_body(
+                write_simple_json_boolean(Vec::new(), result).map_err(internal_server_error)?,
+            )),
+        QueryResults::Graph(_) => Err(internal_server_error(
+            "The saved query unexpectedly returned triples",
+        )),
+    }
+}
+
 fn configure_and_evaluate_sparql_update(
     store: &Store,
     encoded: &[&[u8]],
     mut update: Option<String>,
     request: &Request,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
 ) -> Result<Response, HttpError> {
     let mut use_default_graph_as_union = false;
     let mut default_graph_uris = Vec::new();
@@ -1457,6 +2578,8 @@ fn configure_and_evaluate_sparql_update(
         default_graph_uris,
         named_graph_uris,
         request,
+        permissions,
+        auditor,
     )
 }
 
@@ -1467,7 +2590,44 @@ fn evaluate_sparql_update(
     default_graph_uris: Vec<String>,
     named_graph_uris: Vec<String>,
     request: &Request,
+    permissions: &Permissions,
+    auditor: &RequestAuditor,
 ) -> Result<Response, HttpError> {
+    let mut record = auditor.start(AuditOperation::Update, &update);
+    let update = record.check(parse_sparql_update(
+        update,
+        use_default_graph_as_union,
+        default_graph_uris,
+        named_graph_uris,
+        request,
+        permissions,
+    ))?;
+    if auditor.is_enabled() {
+        let mut options = UpdateOptions::default();
+        if let Some(max_recorded_changes) = auditor.max_recorded_changes() {
+            options = options.with_recorded_changes(max_recorded_changes);
+        }
+        let summary = record.check(
+            store
+                .update_with_summary(update, options)
+                .map_err(internal_server_error),
+        )?;
+        record.set_update_summary(summary);
+    } else {
+        store.update(update).map_err(internal_server_error)?;
+    }
+    Ok(Response::builder(Status::NO_CONTENT).build())
+}
+
+/// Parses the update and sets its dataset from the protocol parameters and the permissions
+fn parse_sparql_update(
+    update: &str,
+    use_default_graph_as_union: bool,
+    default_graph_uris: Vec<String>,
+    named_graph_uris: Vec<String>,
+    request: &Request,
+    permissions: &Permissions,
+) -> Result<Update, HttpError> {
     let mut update =
         Update::parse(update, Some(base_url(request).as_str())).map_err(bad_request)?;
 
@@ -1506,12 +2666,12 @@ fn evaluate_sparql_update(
             using.set_available_named_graphs(named_graph_uris.clone());
         }
     }
-    store.update(update).map_err(internal_server_error)?;
-    Ok(Response::builder(Status::NO_CONTENT).build())
+    permissions.restrict_update(&mut update)?;
+    Ok(update)
 }
 
-fn store_target(request: &Request) -> Result<Option<NamedGraphName>, HttpError> {
-    if request.url().path() == "/store" {
+fn store_target(request: &Request, path: &str) -> Result<Option<NamedGraphName>, HttpError> {
+    if path == "/store" {
         let mut graph = None;
         let mut default = false;
         for (k, v) in request.url().query_pairs() {
@@ -1543,6 +2703,28 @@ fn store_target(request: &Request) -> Result<Option<NamedGraphName>, HttpError>
     }
 }
 
+fn admin_dump_target(request: &Request) -> Result<Option<NamedGraphName>, HttpError> {
+    let mut graph = None;
+    let mut default = false;
+    for (k, v) in request.url().query_pairs() {
+        match k.as_ref() {
+            "graph" => graph = Some(v.into_owned()),
+            "default" => default = true,
+            _ => continue,
+        }
+    }
+    match (graph, default) {
+        (Some(_), true) => Err(bad_request(
+            "Both graph and default parameters should not be set at the same time",
+        )),
+        (Some(graph), false) => Ok(Some(NamedGraphName::NamedNode(
+            NamedNode::new(graph).map_err(bad_request)?,
+        ))),
+        (None, true) => Ok(Some(NamedGraphName::DefaultGraph)),
+        (None, false) => Ok(None),
+    }
+}
+
 fn assert_that_graph_exists(store: &Store, target: &NamedGraphName) -> Result<(), HttpError> {
     if match target {
         NamedGraphName::DefaultGraph => true,
@@ -1562,6 +2744,131 @@ fn assert_that_graph_exists(store: &Store, target: &NamedGraphName) -> Result<()
     }
 }
 
+/// Serializes the conditional writes with all the other writes so that the If-Match check and the write are atomic
+///
+/// The unconditional writes only share the lock and are still run concurrently.
+static WRITE_LOCK: RwLock<()> = RwLock::new(());
+
+/// Must be kept until the end of a write
+struct WriteGuard {
+    _shared: Option<RwLockReadGuard<'static, ()>>,
+    _exclusive: Option<RwLockWriteGuard<'static, ()>>,
+}
+
+/// Guard of an unconditional write
+fn write_guard() -> Result<WriteGuard, HttpError> {
+    Ok(WriteGuard {
+        _shared: Some(
+            WRITE_LOCK
+                .read()
+                .map_err(|_| internal_server_error("The write lock is poisoned"))?,
+        ),
+        _exclusive: None,
+    })
+}
+
+/// Random value distinguishing the ETags of the different server processes
+/// because the store versions are not persisted
+fn etag_epoch() -> u64 {
+    static EPOCH: OnceLock<u64> = OnceLock::new();
+    *EPOCH.get_or_init(random)
+}
+
+fn etag(version: Option<u64>) -> Option<String> {
+    Some(format!("\"{:x}-{}\"", etag_epoch(), version?))
+}
+
+fn graph_etag(store: &Store, target: &NamedGraphName) -> Option<String> {
+    etag(match target {
+        NamedGraphName::NamedNode(target) => store.graph_version(target.as_ref()),
+        NamedGraphName::DefaultGraph => store.graph_version(GraphNameRef::DefaultGraph),
+    })
+}
+
+/// The ETag of the graph or `None` if it does not exist
+fn existing_graph_etag(
+    store: &Store,
+    target: &NamedGraphName,
+) -> Result<Option<String>, HttpError> {
+    if let NamedGraphName::NamedNode(target) = target {
+        if !store
+            .contains_named_graph(target)
+            .map_err(internal_server_error)?
+        {
+            return Ok(None);
+        }
+    }
+    Ok(graph_etag(store, target))
+}
+
+fn dataset_etag(store: &Store) -> Option<String> {
+    etag(store.version())
+}
+
+fn with_etag(mut response: Response, etag: Option<&str>) -> Result<Response, HttpError> {
+    if let Some(etag) = etag {
+        response.headers_mut().append(
+            HeaderName::ETAG,
+            HeaderValue::from_str(etag).map_err(internal_server_error)?,
+        );
+    }
+    Ok(response)
+}
+
+/// Returns a 304 response if the If-None-Match header matches the current ETag
+fn not_modified(request: &Request, etag: Option<&str>) -> Result<Option<Response>, HttpError> {
+    let (Some(etag), Some(if_none_match)) = (etag, request.header(&HeaderName::IF_NONE_MATCH))
+    else {
+        return Ok(None);
+    };
+    let if_none_match = if_none_match.to_str().map_err(bad_request)?;
+    // If-None-Match uses the weak comparison
+    let matches = if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|tag| tag.trim().trim_start_matches("W/") == etag);
+    Ok(if matches {
+        Some(with_etag(
+            Response::builder(Status::NOT_MODIFIED).build(),
+            Some(etag),
+        )?)
+    } else {
+        None
+    })
+}
+
+/// Fails with 412 if the If-Match header does not match the current ETag of the target
+///
+/// The returned guard must be kept until the write is done.
+/// If there is an If-Match header, no other write can happen between the check and the end of the write.
+fn check_if_match(
+    request: &Request,
+    current_etag: impl FnOnce() -> Result<Option<String>, HttpError>,
+) -> Result<WriteGuard, HttpError> {
+    let Some(if_match) = request.header(&HeaderName::IF_MATCH) else {
+        return write_guard();
+    };
+    let if_match = if_match.to_str().map_err(bad_request)?.to_owned();
+    let guard = WRITE_LOCK
+        .write()
+        .map_err(|_| internal_server_error("The write lock is poisoned"))?;
+    let current_etag = current_etag()?;
+    // If-Match uses the strong comparison: weak tags never match
+    let matches = current_etag.is_some_and(|etag| {
+        if_match.trim() == "*" || if_match.split(',').any(|tag| tag.trim() == etag)
+    });
+    if !matches {
+        return Err((
+            Status::PRECONDITION_FAILED,
+            "The If-Match precondition does not match the current state of the target".into(),
+        ));
+    }
+    Ok(WriteGuard {
+        _shared: None,
+        _exclusive: Some(guard),
+    })
+}
+
 #[derive(Eq, PartialEq, Debug, Clone, Hash)]
 enum NamedGraphName {
     NamedNode(NamedNode),
@@ -1577,6 +2884,20 @@ impl From<NamedGraphName> for GraphName {
     }
 }
 
+/// Builds a serializer to `format` using the configured namespace prefixes
+fn rdf_serializer(
+    format: RdfFormat,
+    prefixes: &[(String, String)],
+) -> Result<RdfSerializer, HttpError> {
+    prefixes
+        .iter()
+        .try_fold(
+            RdfSerializer::from_format(format),
+            |serializer, (name, iri)| serializer.with_prefix(name, iri),
+        )
+        .map_err(internal_server_error)
+}
+
 fn rdf_content_negotiation(request: &Request) -> Result<RdfFormat, HttpError> {
     content_negotiation(
         request,
@@ -1603,6 +2924,34 @@ fn query_results_content_negotiation(request: &Request) -> Result<QueryResultsFo
     )
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ApiResultsFormat {
+    /// A plain JSON array of objects for solutions and a JSON boolean for ASK queries
+    SimpleJson,
+    /// One of the formats supported by the /query endpoint
+    Standard,
+}
+
+/// `application/json` selects the simplified JSON, the SPARQL results media types the standard formats
+fn api_results_content_negotiation(request: &Request) -> Result<ApiResultsFormat, HttpError> {
+    content_negotiation(
+        request,
+        |media_type| {
+            if media_type.split(';').next().map(str::trim) == Some("application/json") {
+                Some(ApiResultsFormat::SimpleJson)
+            } else {
+                QueryResultsFormat::from_media_type(media_type).map(|_| ApiResultsFormat::Standard)
+            }
+        },
+        ApiResultsFormat::SimpleJson,
+        &[
+            ("application", ApiResultsFormat::SimpleJson),
+            ("text", ApiResultsFormat::Standard),
+        ],
+        "application/json or application/sparql-results+json",
+    )
+}
+
 fn content_negotiation<F: Copy>(
     request: &Request,
     parse: impl Fn(&str) -> Option<F>,
@@ -1887,9 +3236,11 @@ mod tests {
     use assert_fs::{NamedTempFile, TempDir};
     use flate2::write::GzEncoder;
     use flate2::Compression;
+    use oxigraph::model::QuadRef;
     use predicates::prelude::*;
     use std::fs::remove_dir_all;
     use std::io::read_to_string;
+    use std::time::Duration;
 
     fn cli_command() -> Command {
         let mut command = Command::new(env!("CARGO"));
@@ -2271,6 +3622,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn cli_update_explain() -> Result<()> {
+        let store_dir = TempDir::new()?;
+        cli_command()
+            .arg("update")
+            .arg("--location")
+            .arg(store_dir.path())
+            .arg("--explain")
+            .arg("--update")
+            .arg("INSERT { ?s ?p \"o\" } WHERE { VALUES (?s ?p) { (<http://example.com/s> <http://example.com/p>) } }")
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(
+                "DELETE/INSERT: 1 inserted quads, 0 deleted quads",
+            ));
+        assert_cli_state(
+            &store_dir,
+            "<http://example.com/s> <http://example.com/p> \"o\" .\n",
+        );
+        Ok(())
+    }
+
     #[test]
     fn cli_convert_file() -> Result<()> {
         let input_file = NamedTempFile::new("input.ttl")?;
@@ -2377,6 +3750,44 @@ mod tests {
         )
     }
 
+    #[test]
+    fn get_query_paged() -> Result<()> {
+        let server = ServerTest::new()?;
+        let request = Request::builder(
+            Method::GET,
+            "http://localhost/query?query=SELECT%20?i%20WHERE%20{%20VALUES%20?i%20{%201%202%203%20}%20}&page-size=2"
+                .parse()?,
+        )
+        .with_header(HeaderName::ACCEPT, "text/csv")?
+        .build();
+        let mut response = server.exec(request);
+        assert_eq!(response.status(), Status::OK);
+        assert_eq!(read_to_string(response.body_mut())?, "i\r\n1\r\n2\r\n");
+        let link = response
+            .header(&HeaderName::from_str("Link")?)
+            .map(|h| h.to_str())
+            .transpose()?
+            .unwrap_or_default()
+            .to_owned();
+        let next = link
+            .strip_prefix('<')
+            .and_then(|link| link.strip_suffix(">; rel=\"next\""))
+            .unwrap_or_default();
+        assert!(next.contains("cursor="), "Unexpected link: {link}");
+
+        let request = Request::builder(Method::GET, next.parse()?)
+            .with_header(HeaderName::ACCEPT, "text/csv")?
+            .build();
+        let mut response = server.exec(request);
+        assert_eq!(response.status(), Status::OK);
+        assert!(response.header(&HeaderName::from_str("Link")?).is_none());
+        assert_eq!(read_to_string(response.body_mut())?, "i\r\n3\r\n");
+
+        // The cursor is closed after its last page
+        let request = Request::builder(Method::GET, next.parse()?).build();
+        server.test_status(request, Status::NOT_FOUND)
+    }
+
     #[test]
     fn get_query_accept_star() -> Result<()> {
         let request = Request::builder(
@@ -3051,25 +4462,1154 @@ mod tests {
         server.test_body(request, "<s> <p> \"\"@abcdefghijklmn .\n<http://example.com/ s> <http://example.com/ p> \"\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}\u{200d}\u{1f467}\" <http://example.com> .\n")
     }
 
-    struct ServerTest {
-        store: Store,
+    /// alice can write all graphs and uses the "secret" token, bob uses the "password" password
+    fn admin_access_control() -> Result<AccessControl> {
+        AccessControl::from_str(
+            "token alice 2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b
+            user bob pbkdf2-sha256:1000:73616c74:632c2812e46d4604102ba7618e9d6d7d2f8128f6266b4a03264d2a0460b7dcb3
+            grant alice write *
+            grant bob write <http://example.com/g>
+            grant anonymous write *",
+        )
     }
 
-    impl ServerTest {
-        fn new() -> Result<Self> {
-            Ok(Self {
-                store: Store::new()?,
-            })
+    #[test]
+    fn admin_dump() -> Result<()> {
+        let server = ServerTest::with_admin(Store::new()?, AdminOptions { backup_dir: None });
+        let access_control = admin_access_control()?;
+        let request = Request::builder(
+            Method::POST,
+            "http://localhost/store?graph=http://example.com/g".parse()?,
+        )
+        .with_header(HeaderName::CONTENT_TYPE, "application/n-triples")?
+        .with_body("<http://example.com/s> <http://example.com/p> <http://example.com/o> .");
+        server.test_status(request, Status::CREATED)?;
+
+        let request = Request::builder(Method::GET, "http://localhost/admin/dump".parse()?)
+            .with_header(HeaderName::AUTHORIZATION, "Bearer secret")?
+            .with_header(HeaderName::ACCEPT, "application/n-quads")?
+            .build();
+        let mut response = server.exec_with_access_control(request, &access_control);
+        assert_eq!(response.status(), Status::OK);
+        assert_eq!(
+            read_to_string(response.body_mut())?,
+            "<http://example.com/s> <http://example.com/p> <http://example.com/o> <http://example.com/g> .\n"
+        );
+
+        let request = Request::builder(
+            Method::GET,
+            "http://localhost/admin/dump?format=nt&graph=http://example.com/g".parse()?,
+        )
+        .with_header(HeaderName::AUTHORIZATION, "Bearer secret")?
+        .build();
+        let mut response = server.exec_with_access_control(request, &access_control);
+        assert_eq!(response.status(), Status::OK);
+        assert_eq!(
+            read_to_string(response.body_mut())?,
+            "<http://example.com/s> <http://example.com/p> <http://example.com/o> .\n"
+        );
+
+        let request = Request::builder(
+            Method::GET,
+            "http://localhost/admin/dump?format=nt".parse()?,
+        )
+        .with_header(HeaderName::AUTHORIZATION, "Bearer secret")?
+        .build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::BAD_REQUEST,
+        )?;
+
+        let request = Request::builder(
+            Method::GET,
+            "http://localhost/admin/dump?graph=http://example.com/foo".parse()?,
+        )
+        .with_header(HeaderName::AUTHORIZATION, "Bearer secret")?
+        .build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::NOT_FOUND,
+        )?;
+
+        // Only the authenticated principals allowed to write all graphs can dump
+        let request = Request::builder(Method::GET, "http://localhost/admin/dump".parse()?)
+            .with_header(HeaderName::AUTHORIZATION, "Basic Ym9iOnBhc3N3b3Jk")?
+            .build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::FORBIDDEN,
+        )?;
+        let request = Request::builder(Method::GET, "http://localhost/admin/dump".parse()?).build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::UNAUTHORIZED,
+        )?;
+
+        // The endpoint is disabled by default
+        let request = Request::builder(Method::GET, "http://localhost/admin/dump".parse()?).build();
+        ServerTest::new()?.test_status(request, Status::NOT_FOUND)
+    }
+
+    #[test]
+    fn admin_backup() -> Result<()> {
+        let store_dir = TempDir::new()?;
+        let backup_dir = TempDir::new()?;
+        let store = Store::open(store_dir.path())?;
+        store.insert(QuadRef::new(
+            NamedNodeRef::new("http://example.com/s")?,
+            NamedNodeRef::new("http://example.com/p")?,
+            NamedNodeRef::new("http://example.com/o")?,
+            GraphNameRef::DefaultGraph,
+        ))?;
+        let server = ServerTest::with_admin(
+            store,
+            AdminOptions {
+                backup_dir: Some(backup_dir.path().to_owned()),
+            },
+        );
+        let access_control = admin_access_control()?;
+        let backup = |destination: &str, authorization: Option<&str>| -> Result<Response> {
+            let mut request = Request::builder(
+                Method::POST,
+                format!("http://localhost/admin/backup?destination={destination}").parse()?,
+            );
+            if let Some(authorization) = authorization {
+                request = request.with_header(HeaderName::AUTHORIZATION, authorization)?;
+            }
+            Ok(server.exec_with_access_control(request.build(), &access_control))
+        };
+
+        ServerTest::check_status(backup("b1", Some("Bearer secret"))?, Status::NO_CONTENT)?;
+        let backup_store = Store::open_read_only(backup_dir.path().join("b1"))?;
+        assert_eq!(backup_store.len()?, 1);
+
+        // The backup must not exist yet and must be in the backup directory
+        ServerTest::check_status(backup("b1", Some("Bearer secret"))?, Status::BAD_REQUEST)?;
+        ServerTest::check_status(
+            backup("..%2Fb2", Some("Bearer secret"))?,
+            Status::BAD_REQUEST,
+        )?;
+        ServerTest::check_status(
+            backup("%2Ftmp%2Fb2", Some("Bearer secret"))?,
+            Status::BAD_REQUEST,
+        )?;
+        ServerTest::check_status(backup("", Some("Bearer secret"))?, Status::BAD_REQUEST)?;
+        let request = Request::builder(Method::POST, "http://localhost/admin/backup".parse()?)
+            .with_header(HeaderName::AUTHORIZATION, "Bearer secret")?
+            .build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::BAD_REQUEST,
+        )?;
+
+        // Authentication is required
+        ServerTest::check_status(backup("b3", None)?, Status::UNAUTHORIZED)?;
+        ServerTest::check_status(
+            backup("b3", Some("Basic Ym9iOnBhc3N3b3Jk"))?,
+            Status::FORBIDDEN,
+        )?;
+        assert!(!backup_dir.path().join("b3").exists());
+
+        // Backups are refused by read-only servers
+        let request = Request::builder(
+            Method::POST,
+            "http://localhost/admin/backup?destination=b4".parse()?,
+        )
+        .build();
+        ServerTest::check_status(server.exec_read_only(request), Status::FORBIDDEN)?;
+
+        // Backups are disabled without backup directory
+        let server = ServerTest::with_admin(Store::new()?, AdminOptions { backup_dir: None });
+        let request = Request::builder(
+            Method::POST,
+            "http://localhost/admin/backup?destination=b5".parse()?,
+        )
+        .with_header(HeaderName::AUTHORIZATION, "Bearer secret")?
+        .build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::FORBIDDEN,
+        )?;
+
+        // The endpoint is disabled by default
+        let request = Request::builder(
+            Method::POST,
+            "http://localhost/admin/backup?destination=b6".parse()?,
+        )
+        .build();
+        ServerTest::new()?.test_status(request, Status::NOT_FOUND)
+    }
+
+    #[test]
+    fn admin_optimize() -> Result<()> {
+        let server = ServerTest::with_admin(Store::new()?, AdminOptions { backup_dir: None });
+        let access_control = admin_access_control()?;
+        let request = Request::builder(Method::POST, "http://localhost/admin/optimize".parse()?)
+            .with_header(HeaderName::AUTHORIZATION, "Bearer secret")?
+            .build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::NO_CONTENT,
+        )?;
+
+        let request =
+            Request::builder(Method::POST, "http://localhost/admin/optimize".parse()?).build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::UNAUTHORIZED,
+        )?;
+
+        let request =
+            Request::builder(Method::POST, "http://localhost/admin/optimize".parse()?).build();
+        ServerTest::check_status(server.exec_read_only(request), Status::FORBIDDEN)?;
+
+        let request =
+            Request::builder(Method::POST, "http://localhost/admin/optimize".parse()?).build();
+        ServerTest::new()?.test_status(request, Status::NOT_FOUND)
+    }
+
+    #[test]
+    fn metrics() -> Result<()> {
+        let server = ServerTest::new()?;
+        let request = Request::builder(Method::POST, "http://localhost/update".parse()?)
+            .with_header(HeaderName::CONTENT_TYPE, "application/sparql-update")?
+            .with_body("INSERT DATA { <http://example.com/s> <http://example.com/p> 1 }");
+        server.test_status(request, Status::NO_CONTENT)?;
+        let request = Request::builder(
+            Method::GET,
+            "http://localhost/query?query=ASK%20%7B%20%3Fs%20%3Fp%20%3Fo%20%7D".parse()?,
+        )
+        .build();
+        server.test_status(request, Status::OK)?;
+
+        let request = Request::builder(Method::GET, "http://localhost/metrics".parse()?).build();
+        let mut response = server.exec(request);
+        let body = read_to_string(response.body_mut())?;
+        assert_eq!(response.status(), Status::OK, "Error message: {body}");
+        assert!(body.contains("oxigraph_query_total 1\n"));
+        assert!(body.contains("oxigraph_update_total 1\n"));
+        assert!(body.contains("# TYPE oxigraph_query_duration_seconds histogram\n"));
+
+        let access_control = AccessControl::from_str(
+            "token alice 2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b
+            grant alice read <http://example.com/g>",
+        )?;
+        let request = Request::builder(Method::GET, "http://localhost/metrics".parse()?).build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::UNAUTHORIZED,
+        )?;
+        let request = Request::builder(Method::GET, "http://localhost/metrics".parse()?)
+            .with_header(HeaderName::AUTHORIZATION, "Bearer secret")?
+            .build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::FORBIDDEN,
+        )
+    }
+
+    #[test]
+    fn access_control() -> Result<()> {
+        let server = ServerTest::new()?;
+        server.store.insert(QuadRef::new(
+            NamedNodeRef::new("http://example.com/s")?,
+            NamedNodeRef::new("http://example.com/p")?,
+            NamedNodeRef::new("http://example.com/o")?,
+            NamedNodeRef::new("http://example.com/h")?,
+        ))?;
+        // alice uses the "secret" token and bob the "password" password
+        let access_control = AccessControl::from_str(
+            "token alice 2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b
+            user bob pbkdf2-sha256:1000:73616c74:632c2812e46d4604102ba7618e9d6d7d2f8128f6266b4a03264d2a0460b7dcb3
+            grant alice write <http://example.com/g>
+            grant bob read <http://example.com/g>
+            grant * read default",
+        )?;
+        let update = |authorization: &str, update: &'static str| -> Result<Request> {
+            Ok(
+                Request::builder(Method::POST, "http://localhost/update".parse()?)
+                    .with_header(HeaderName::AUTHORIZATION, authorization)?
+                    .with_header(HeaderName::CONTENT_TYPE, "application/sparql-update")?
+                    .with_body(update),
+            )
+        };
+        let count_query = |authorization: &str| -> Result<Request> {
+            Ok(Request::builder(
+                Method::GET,
+                "http://localhost/query?query=SELECT%20(COUNT(*)%20AS%20%3Fc)%20WHERE%20%7B%20GRAPH%20%3Fg%20%7B%20%3Fs%20%3Fp%20%3Fo%20%7D%20%7D"
+                    .parse()?,
+            )
+            .with_header(HeaderName::AUTHORIZATION, authorization)?
+            .with_header(HeaderName::ACCEPT, "text/csv")?
+            .build())
+        };
+        let store = |method: Method, authorization: &str, graph: &str| -> Result<Request> {
+            Ok(Request::builder(
+                method,
+                format!("http://localhost/store?graph={graph}").parse()?,
+            )
+            .with_header(HeaderName::AUTHORIZATION, authorization)?
+            .with_header(HeaderName::CONTENT_TYPE, "application/n-triples")?
+            .with_body("<http://example.com/s> <http://example.com/p> <http://example.com/o> ."))
+        };
+        let alice = "Bearer secret";
+        let bob = "Basic Ym9iOnBhc3N3b3Jk";
+
+        // Authentication
+        let request =
+            Request::builder(Method::GET, "http://localhost/query?query=ASK{}".parse()?).build();
+        ServerTest::check_status(
+            server.exec_with_access_control(request, &access_control),
+            Status::UNAUTHORIZED,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(count_query("Bearer wrong")?, &access_control),
+            Status::UNAUTHORIZED,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(count_query("Basic Ym9iOndyb25n")?, &access_control),
+            Status::UNAUTHORIZED,
+        )?;
+
+        // SPARQL
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                update(alice, "INSERT DATA { GRAPH <http://example.com/g> { <http://example.com/s> <http://example.com/p> <http://example.com/o> } }")?,
+                &access_control,
+            ),
+            Status::NO_CONTENT,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                update(alice, "INSERT DATA { <http://example.com/s> <http://example.com/p> <http://example.com/o> }")?,
+                &access_control,
+            ),
+            Status::FORBIDDEN,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                update(
+                    alice,
+                    "INSERT { GRAPH ?g { ?s ?p ?o } } WHERE { GRAPH ?g { ?s ?p ?o } }",
+                )?,
+                &access_control,
+            ),
+            Status::FORBIDDEN,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                update(bob, "CLEAR GRAPH <http://example.com/g>")?,
+                &access_control,
+            ),
+            Status::FORBIDDEN,
+        )?;
+        let mut response = server.exec_with_access_control(count_query(bob)?, &access_control);
+        assert_eq!(read_to_string(response.body_mut())?, "c\r\n1\r\n");
+        let mut response = server.exec(count_query(bob)?);
+        assert_eq!(read_to_string(response.body_mut())?, "c\r\n2\r\n");
+
+        // Graph Store Protocol
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                store(Method::GET, bob, "http%3A%2F%2Fexample.com%2Fh")?,
+                &access_control,
+            ),
+            Status::FORBIDDEN,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                store(Method::GET, bob, "http%3A%2F%2Fexample.com%2Fg")?,
+                &access_control,
+            ),
+            Status::OK,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                store(Method::PUT, bob, "http%3A%2F%2Fexample.com%2Fg")?,
+                &access_control,
+            ),
+            Status::FORBIDDEN,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                store(Method::PUT, alice, "http%3A%2F%2Fexample.com%2Fg")?,
+                &access_control,
+            ),
+            Status::NO_CONTENT,
+        )?;
+        ServerTest::check_status(
+            server.exec_with_access_control(
+                store(Method::DELETE, alice, "http%3A%2F%2Fexample.com%2Fh")?,
+                &access_control,
+            ),
+            Status::FORBIDDEN,
+        )
+    }
+
+    #[test]
+    fn conditional_requests() -> Result<()> {
+        let server = ServerTest::new()?;
+        let put = |if_match: Option<&str>| -> Result<Request> {
+            let mut request =
+                Request::builder(Method::PUT, "http://localhost/store/person/1.ttl".parse()?)
+                    .with_header(HeaderName::CONTENT_TYPE, "text/turtle")?;
+            if let Some(if_match) = if_match {
+                request = request.with_header(HeaderName::IF_MATCH, if_match)?;
+            }
+            Ok(request.with_body("<http://example.com/s> <http://example.com/p> 1 ."))
+        };
+        let get = |if_none_match: Option<&str>| -> Result<Request> {
+            let mut request =
+                Request::builder(Method::GET, "http://localhost/store/person/1.ttl".parse()?)
+                    .with_header(HeaderName::ACCEPT, "text/turtle")?;
+            if let Some(if_none_match) = if_none_match {
+                request = request.with_header(HeaderName::IF_NONE_MATCH, if_none_match)?;
+            }
+            Ok(request.build())
+        };
+        let etag = |response: &Response| -> Result<String> {
+            Ok(response
+                .header(&HeaderName::ETAG)
+                .context("No ETag")?
+                .to_str()?
+                .to_owned())
+        };
+
+        // The graph does not exist yet
+        server.test_status(put(Some("*"))?, Status::PRECONDITION_FAILED)?;
+        server.test_status(put(None)?, Status::CREATED)?;
+
+        let response = server.exec(get(None)?);
+        assert_eq!(response.status(), Status::OK);
+        let first_etag = etag(&response)?;
+        let response = server.exec(get(Some(&first_etag))?);
+        assert_eq!(response.status(), Status::NOT_MODIFIED);
+        assert_eq!(etag(&response)?, first_etag);
+        server.test_status(get(Some(&format!("W/{first_etag}")))?, Status::NOT_MODIFIED)?;
+        server.test_status(get(Some("\"foo\""))?, Status::OK)?;
+
+        // Writes to other graphs do not change the ETag
+        server.test_status(
+            Request::builder(Method::PUT, "http://localhost/store/person/2.ttl".parse()?)
+                .with_header(HeaderName::CONTENT_TYPE, "text/turtle")?
+                .with_body("<http://example.com/s> <http://example.com/p> 2 ."),
+            Status::CREATED,
+        )?;
+        server.test_status(get(Some(&first_etag))?, Status::NOT_MODIFIED)?;
+
+        server.test_status(put(Some(&first_etag))?, Status::NO_CONTENT)?;
+        server.test_status(put(Some(&first_etag))?, Status::PRECONDITION_FAILED)?;
+        let response = server.exec(get(Some(&first_etag))?);
+        assert_eq!(response.status(), Status::OK);
+        let second_etag = etag(&response)?;
+        assert_ne!(second_etag, first_etag);
+
+        server.test_status(
+            Request::builder(
+                Method::DELETE,
+                "http://localhost/store/person/1.ttl".parse()?,
+            )
+            .with_header(HeaderName::IF_MATCH, first_etag.as_str())?
+            .build(),
+            Status::PRECONDITION_FAILED,
+        )?;
+        server.test_status(
+            Request::builder(
+                Method::DELETE,
+                "http://localhost/store/person/1.ttl".parse()?,
+            )
+            .with_header(HeaderName::IF_MATCH, second_etag.as_str())?
+            .build(),
+            Status::NO_CONTENT,
+        )
+    }
+
+    #[test]
+    fn datasets() -> Result<()> {
+        let datasets = Datasets::in_memory();
+        let context = ServerTest::context(Some(AdminOptions { backup_dir: None }));
+        let permissions = AccessControl::from_str("grant alice write *")?
+            .authenticate(
+                &Request::builder(Method::GET, "http://localhost/".parse()?).build(),
+                Some("alice"),
+            )
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        let exec = |method: Method, url: &str, body: &'static str| -> Result<Response> {
+            let mut request = Request::builder(method, url.parse()?)
+                .with_header(HeaderName::CONTENT_TYPE, "application/sparql-update")?
+                .with_header(HeaderName::ACCEPT, "application/n-triples")?
+                .with_body(body);
+            Ok(handle_datasets_request(
+                &mut request,
+                &datasets,
+                &context,
+                false,
+                &permissions,
+                &RequestAuditor::default(),
+            )
+            .unwrap_or_else(|(status, message)| error(status, message)))
+        };
+
+        ServerTest::check_status(
+            exec(Method::PUT, "http://localhost/admin/datasets/foo", "")?,
+            Status::CREATED,
+        )?;
+        ServerTest::check_status(
+            exec(Method::PUT, "http://localhost/admin/datasets/foo", "")?,
+            Status::NO_CONTENT,
+        )?;
+        ServerTest::check_status(
+            exec(Method::PUT, "http://localhost/admin/datasets/bar", "")?,
+            Status::CREATED,
+        )?;
+        ServerTest::check_status(
+            exec(Method::PUT, "http://localhost/admin/datasets/admin", "")?,
+            Status::CONFLICT,
+        )?;
+        let mut response = exec(Method::GET, "http://localhost/admin/datasets", "")?;
+        assert_eq!(read_to_string(response.body_mut())?, r#"["bar","foo"]"#);
+
+        // Each dataset has its own content
+        ServerTest::check_status(
+            exec(
+                Method::POST,
+                "http://localhost/foo/update",
+                "INSERT DATA { <http://example.com/s> <http://example.com/p> <http://example.com/o> }",
+            )?,
+            Status::NO_CONTENT,
+        )?;
+        let mut response = exec(Method::GET, "http://localhost/foo/store?default", "")?;
+        assert_eq!(
+            read_to_string(response.body_mut())?,
+            "<http://example.com/s> <http://example.com/p> <http://example.com/o> .\n"
+        );
+        let mut response = exec(Method::GET, "http://localhost/bar/store?default", "")?;
+        assert_eq!(read_to_string(response.body_mut())?, "");
+        ServerTest::check_status(
+            exec(Method::GET, "http://localhost/baz/store?default", "")?,
+            Status::NOT_FOUND,
+        )?;
+        ServerTest::check_status(exec(Method::GET, "http://localhost/foo", "")?, Status::OK)?;
+        ServerTest::check_status(
+            exec(Method::GET, "http://localhost/logo.svg", "")?,
+            Status::OK,
+        )?;
+
+        // Each dataset has its own saved queries
+        ServerTest::check_status(
+            exec(
+                Method::PUT,
+                "http://localhost/foo/admin/queries/all",
+                "query = \"CONSTRUCT WHERE { ?s ?p ?o }\"",
+            )?,
+            Status::CREATED,
+        )?;
+        ServerTest::check_status(
+            exec(Method::GET, "http://localhost/foo/api/all", "")?,
+            Status::OK,
+        )?;
+        ServerTest::check_status(
+            exec(Method::GET, "http://localhost/bar/api/all", "")?,
+            Status::NOT_FOUND,
+        )?;
+
+        ServerTest::check_status(
+            exec(Method::DELETE, "http://localhost/admin/datasets/foo", "")?,
+            Status::NO_CONTENT,
+        )?;
+        ServerTest::check_status(
+            exec(Method::DELETE, "http://localhost/admin/datasets/foo", "")?,
+            Status::NOT_FOUND,
+        )?;
+        ServerTest::check_status(
+            exec(Method::GET, "http://localhost/foo/store?default", "")?,
+            Status::NOT_FOUND,
+        )?;
+
+        // The anonymous users can't manage the datasets even if they are allowed to write everything
+        let mut request =
+            Request::builder(Method::PUT, "http://localhost/admin/datasets/baz".parse()?).build();
+        ServerTest::check_status(
+            handle_datasets_request(
+                &mut request,
+                &datasets,
+                &context,
+                false,
+                &Permissions::all(),
+                &RequestAuditor::default(),
+            )
+            .unwrap_or_else(|(status, message)| error(status, message)),
+            Status::UNAUTHORIZED,
+        )?;
+
+        // The datasets can't be managed if the admin endpoints are disabled
+        let mut request =
+            Request::builder(Method::PUT, "http://localhost/admin/datasets/baz".parse()?).build();
+        ServerTest::check_status(
+            handle_datasets_request(
+                &mut request,
+                &datasets,
+                &ServerTest::context(None),
+                false,
+                &permissions,
+                &RequestAuditor::default(),
+            )
+            .unwrap_or_else(|(status, message)| error(status, message)),
+            Status::NOT_FOUND,
+        )
+    }
+
+    #[test]
+    fn datasets_access_control() -> Result<()> {
+        let datasets = Datasets::in_memory();
+        datasets
+            .create("foo")
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        datasets
+            .create("bar")
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        let context = ServerTest::context(Some(AdminOptions { backup_dir: None }));
+        let permissions = AccessControl::from_str("grant alice write * foo")?
+            .authenticate(
+                &Request::builder(Method::GET, "http://localhost/".parse()?).build(),
+                Some("alice"),
+            )
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        let exec = |method: Method, url: &str| -> Result<Response> {
+            let mut request = Request::builder(method, url.parse()?)
+                .with_header(HeaderName::ACCEPT, "application/n-triples")?
+                .build();
+            Ok(handle_datasets_request(
+                &mut request,
+                &datasets,
+                &context,
+                false,
+                &permissions,
+                &RequestAuditor::default(),
+            )
+            .unwrap_or_else(|(status, message)| error(status, message)))
+        };
+
+        let mut response = exec(Method::GET, "http://localhost/admin/datasets")?;
+        assert_eq!(read_to_string(response.body_mut())?, "[\"foo\"]");
+        ServerTest::check_status(
+            exec(Method::GET, "http://localhost/foo/store?default")?,
+            Status::OK,
+        )?;
+        ServerTest::check_status(
+            exec(Method::GET, "http://localhost/bar/store?default")?,
+            Status::FORBIDDEN,
+        )?;
+        ServerTest::check_status(
+            exec(Method::DELETE, "http://localhost/admin/datasets/foo")?,
+            Status::FORBIDDEN,
+        )
+    }
+
+    #[test]
+    fn audit_log() -> Result<()> {
+        let file = NamedTempFile::new("audit.log")?;
+        let mut config = AuditConfig::new(file.path().to_owned());
+        config.record_changes = true;
+        config.trusted_proxies = vec!["127.0.0.1".parse()?];
+        let log = Arc::new(AuditLog::open(&config)?);
+        let store = Store::new()?;
+        let context = ServerTest::context(None);
+        let peer = Peer {
+            address: Some("127.0.0.1:1234".parse()?),
+            certificate_subject: None,
+        };
+        let exec = |request: &mut Request| {
+            let auditor =
+                RequestAuditor::new(Some(Arc::clone(&log)), request, &peer, Some("alice"));
+            let mut response = handle_request(
+                request,
+                store.clone(),
+                &context,
+                false,
+                &Permissions::all(),
+                &auditor,
+            )
+            .unwrap_or_else(|(status, message)| error(status, message));
+            // The query records are written once the results are fully serialized
+            read_to_string(response.body_mut())?;
+            Ok::<_, anyhow::Error>(response.status())
+        };
+
+        let mut request = Request::builder(Method::POST, "http://localhost/update".parse()?)
+            .with_header(HeaderName::CONTENT_TYPE, "application/sparql-update")?
+            .with_header(HeaderName::from_str("X-Forwarded-For")?, "192.0.2.1")?
+            .with_body("INSERT DATA { <http://example.com/s> <http://example.com/p> 1, 2 }");
+        assert_eq!(exec(&mut request)?, Status::NO_CONTENT);
+        let mut request = Request::builder(
+            Method::GET,
+            "http://localhost/query?query=SELECT%20*%20WHERE%20%7B%20%3Fs%20%3Fp%20%3Fo%20%7D"
+                .parse()?,
+        )
+        .build();
+        assert_eq!(exec(&mut request)?, Status::OK);
+        let mut request = Request::builder(Method::POST, "http://localhost/update".parse()?)
+            .with_header(HeaderName::CONTENT_TYPE, "application/sparql-update")?
+            .with_body("INSERT DATA {");
+        assert_eq!(exec(&mut request)?, Status::BAD_REQUEST);
+        let mut request =
+            Request::builder(Method::DELETE, "http://localhost/store?default".parse()?).build();
+        assert_eq!(exec(&mut request)?, Status::NO_CONTENT);
+        let mut request =
+            Request::builder(Method::GET, "http://localhost/store?default".parse()?).build();
+        assert_eq!(exec(&mut request)?, Status::OK);
+
+        let content = fs::read_to_string(file.path())?;
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4, "{content}");
+        assert!(
+            lines[0].contains(r#""client":"192.0.2.1","principal":"alice","operation":"update""#)
+        );
+        assert!(lines[0].contains(r#""inserted":2,"deleted":0,"inserted_quads":["<http://example.com/s> <http://example.com/p> \"1\"^^<http://www.w3.org/2001/XMLSchema#integer> .","#));
+        assert!(lines[1].contains(r#""operation":"query","text":"SELECT * WHERE { ?s ?p ?o }""#));
+        assert!(lines[1].contains(r#""results":2"#));
+        assert!(lines[2].contains(r#""error":"#));
+        assert!(lines[3].contains(
+            r#""client":"127.0.0.1","principal":"alice","operation":"graph_store","text":"DELETE /store?default""#
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn saved_queries() -> Result<()> {
+        let server = ServerTest::new()?;
+        server.test_status(
+            Request::builder(Method::POST, "http://localhost/update".parse()?)
+                .with_header(HeaderName::CONTENT_TYPE, "application/sparql-update")?
+                .with_body(
+                    "INSERT DATA { <http://example.com/s1> <http://example.com/p> \"a\" . <http://example.com/s2> <http://example.com/p> 2 }",
+                ),
+            Status::NO_CONTENT,
+        )?;
+        server.test_status(
+            Request::builder(Method::PUT, "http://localhost/admin/queries/values".parse()?)
+                .with_body(
+                    "query = \"SELECT ?o WHERE { ?s <http://example.com/p> ?o }\"\n[parameters.s]\ntype = \"iri\"\nrequired = true",
+                ),
+            Status::CREATED,
+        )?;
+        server.test_status(
+            Request::builder(Method::PUT, "http://localhost/admin/queries/exists".parse()?)
+                .with_body(
+                    "query = \"ASK { ?s <http://example.com/p> ?v }\"\n[parameters.v]\ntype = \"string\"",
+                ),
+            Status::CREATED,
+        )?;
+        server.test_status(
+            Request::builder(
+                Method::PUT,
+                "http://localhost/admin/queries/invalid".parse()?,
+            )
+            .with_body("query = \"SELECT\""),
+            Status::BAD_REQUEST,
+        )?;
+        server.test_body(
+            Request::builder(Method::GET, "http://localhost/admin/queries".parse()?).build(),
+            r#"["exists","values"]"#,
+        )?;
+
+        // Simplified JSON by default
+        server.test_body(
+            Request::builder(
+                Method::GET,
+                "http://localhost/api/values?s=http%3A%2F%2Fexample.com%2Fs2".parse()?,
+            )
+            .build(),
+            r#"[{"o":2}]"#,
+        )?;
+        server.test_body(
+            Request::builder(
+                Method::GET,
+                "http://localhost/api/values?s=http%3A%2F%2Fexample.com%2Fs1".parse()?,
+            )
+            .with_header(HeaderName::ACCEPT, "text/csv")?
+            .build(),
+            "o\r\na\r\n",
+        )?;
+        server.test_body(
+            Request::builder(Method::GET, "http://localhost/api/exists?v=a".parse()?).build(),
+            "true",
+        )?;
+        server.test_body(
+            Request::builder(Method::GET, "http://localhost/api/exists?v=b".parse()?).build(),
+            "false",
+        )?;
+        // Optional parameters are left unbound
+        server.test_body(
+            Request::builder(Method::GET, "http://localhost/api/exists".parse()?).build(),
+            "true",
+        )?;
+
+        // The values are never parsed as SPARQL
+        server.test_status(
+            Request::builder(
+                Method::GET,
+                "http://localhost/api/values?s=http%3A%2F%2Fexample.com%2Fs1%3E%20%7D%20%23"
+                    .parse()?,
+            )
+            .build(),
+            Status::BAD_REQUEST,
+        )?;
+        server.test_status(
+            Request::builder(Method::GET, "http://localhost/api/values".parse()?).build(),
+            Status::BAD_REQUEST,
+        )?;
+        server.test_status(
+            Request::builder(
+                Method::GET,
+                "http://localhost/api/values?s=http%3A%2F%2Fexample.com%2Fs1&foo=bar".parse()?,
+            )
+            .build(),
+            Status::BAD_REQUEST,
+        )?;
+        server.test_status(
+            Request::builder(Method::GET, "http://localhost/api/foo".parse()?).build(),
+            Status::NOT_FOUND,
+        )?;
+
+        ServerTest::check_status(
+            server.exec_read_only(
+                Request::builder(
+                    Method::DELETE,
+                    "http://localhost/admin/queries/values".parse()?,
+                )
+                .build(),
+            ),
+            Status::FORBIDDEN,
+        )?;
+        server.test_status(
+            Request::builder(
+                Method::DELETE,
+                "http://localhost/admin/queries/values".parse()?,
+            )
+            .build(),
+            Status::NO_CONTENT,
+        )?;
+        server.test_status(
+            Request::builder(
+                Method::GET,
+                "http://localhost/api/values?s=http%3A%2F%2Fexample.com%2Fs1".parse()?,
+            )
+            .build(),
+            Status::NOT_FOUND,
+        )
+    }
+
+    #[test]
+    fn saved_queries_max_result_rows() -> Result<()> {
+        let mut server = ServerTest::new()?;
+        server.context.max_result_rows = Some(1);
+        server.test_status(
+            Request::builder(Method::POST, "http://localhost/update".parse()?)
+                .with_header(HeaderName::CONTENT_TYPE, "application/sparql-update")?
+                .with_body("INSERT DATA { <http://example.com/s> <http://example.com/p> 1, 2 }"),
+            Status::NO_CONTENT,
+        )?;
+        server.test_status(
+            Request::builder(Method::PUT, "http://localhost/admin/queries/all".parse()?).with_body(
+                "query = \"SELECT ?o WHERE { ?s <http://example.com/p> ?o } ORDER BY ?o\"",
+            ),
+            Status::CREATED,
+        )?;
+        // The results are truncated to the maximal number of rows
+        server.test_body(
+            Request::builder(Method::GET, "http://localhost/api/all".parse()?).build(),
+            r#"[{"o":1}]"#,
+        )
+    }
+
+    #[test]
+    fn cors() -> Result<()> {
+        let runtime = Arc::new(RwLock::new(Arc::new(RuntimeConfig {
+            access_control: None,
+            cors_allowed_origins: vec!["https://example.com".into()],
+            log_requests: false,
+        })));
+        let handler = cors_middleware(
+            |_, _| Response::builder(Status::OK).build(),
+            Arc::clone(&runtime),
+        );
+        let header = |method: Method, origin: &str, name: &str| -> Result<Option<String>> {
+            let mut request = Request::builder(method, "http://localhost/query".parse()?)
+                .with_header(HeaderName::from_str("Origin")?, origin)?
+                .with_header(
+                    HeaderName::from_str("Access-Control-Request-Method")?,
+                    "POST",
+                )?
+                .build();
+            let response = handler(&mut request, &Peer::default());
+            Ok(response
+                .header(&HeaderName::from_str(name)?)
+                .map(|h| h.to_str().map(ToOwned::to_owned))
+                .transpose()?)
+        };
+        let allowed_origin =
+            |method: Method, origin: &str| header(method, origin, "Access-Control-Allow-Origin");
+        assert_eq!(
+            allowed_origin(Method::GET, "https://example.com")?.as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            allowed_origin(Method::OPTIONS, "https://example.com")?.as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(allowed_origin(Method::GET, "https://example.org")?, None);
+        // The response depends on the origin even if it is not allowed
+        assert_eq!(
+            header(Method::GET, "https://example.org", "Vary")?.as_deref(),
+            Some("Origin")
+        );
+
+        // The allowed origins are reloaded
+        *runtime.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(RuntimeConfig {
+            access_control: None,
+            cors_allowed_origins: vec!["*".into()],
+            log_requests: false,
+        });
+        assert_eq!(
+            allowed_origin(Method::GET, "https://example.org")?.as_deref(),
+            Some("*")
+        );
+        assert_eq!(header(Method::GET, "https://example.org", "Vary")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn prefixes() -> Result<()> {
+        let store = Store::new()?;
+        store.insert(QuadRef::new(
+            NamedNodeRef::new("http://example.com/s")?,
+            NamedNodeRef::new("http://schema.org/name")?,
+            NamedNodeRef::new("http://example.com/o")?,
+            GraphNameRef::DefaultGraph,
+        ))?;
+        let mut context = ServerTest::context(None);
+        context.prefixes = vec![("schema".into(), "http://schema.org/".into())];
+        let mut request = Request::builder(Method::GET, "http://localhost/store?default".parse()?)
+            .with_header(HeaderName::ACCEPT, "text/turtle")?
+            .build();
+        let mut response = handle_request(
+            &mut request,
+            store,
+            &context,
+            false,
+            &Permissions::all(),
+            &RequestAuditor::default(),
+        )
+        .unwrap_or_else(|(status, message)| error(status, message));
+        assert_eq!(
+            read_to_string(response.body_mut())?,
+            "@prefix schema: <http://schema.org/> .\n<http://example.com/s> schema:name <http://example.com/o> .\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fragments() -> Result<()> {
+        let server = ServerTest::new()?;
+        let ex = NamedNodeRef::new("http://example.com/p")?;
+        let g = NamedNodeRef::new("http://example.com/g")?;
+        for i in 0..150 {
+            server.store.insert(QuadRef::new(
+                NamedNodeRef::new(&format!("http://example.com/s{i}"))?,
+                ex,
+                ex,
+                GraphNameRef::DefaultGraph,
+            ))?;
+        }
+        server.store.insert(QuadRef::new(ex, ex, g, g))?;
+        let get = |url: &str, accept: &str| -> Result<String> {
+            let mut response = server.exec(
+                Request::builder(Method::GET, url.parse()?)
+                    .with_header(HeaderName::ACCEPT, accept)?
+                    .build(),
+            );
+            let body = read_to_string(response.body_mut())?;
+            assert_eq!(response.status(), Status::OK, "Error message: {body}");
+            Ok(body)
+        };
+
+        // Triple pattern fragments of the default graph
+        let body = get(
+            "http://localhost/fragments?predicate=http%3A%2F%2Fexample.com%2Fp",
+            "application/n-triples",
+        )?;
+        assert_eq!(
+            body.lines()
+                .filter(|l| l.contains("<http://example.com/p> <http://example.com/p> ."))
+                .count(),
+            100
+        );
+        assert!(body.contains("<http://www.w3.org/ns/hydra/core#totalItems> \"150\"^^<http://www.w3.org/2001/XMLSchema#integer> ."));
+        assert!(body.contains("<http://www.w3.org/ns/hydra/core#next> <http://localhost/fragments?predicate=http%3A%2F%2Fexample.com%2Fp&page=2> ."));
+        let body = get(
+            "http://localhost/fragments?predicate=http%3A%2F%2Fexample.com%2Fp&page=2",
+            "application/n-triples",
+        )?;
+        assert_eq!(
+            body.lines()
+                .filter(|l| l.contains("<http://example.com/p> <http://example.com/p> ."))
+                .count(),
+            50
+        );
+        assert!(!body.contains("<http://www.w3.org/ns/hydra/core#next>"));
+        assert!(body.contains("<http://www.w3.org/ns/hydra/core#previous>"));
+
+        // Quad pattern fragments
+        let body = get(
+            "http://localhost/fragments?subject=http%3A%2F%2Fexample.com%2Fp&graph=",
+            "application/n-quads",
+        )?;
+        assert!(body.contains("<http://example.com/p> <http://example.com/p> <http://example.com/g> <http://example.com/g> .\n"));
+        assert!(body.contains("\"1\"^^<http://www.w3.org/2001/XMLSchema#integer> <http://localhost/fragments?subject=http%3A%2F%2Fexample.com%2Fp&graph=#metadata> ."));
+        server.test_status(
+            Request::builder(
+                Method::GET,
+                "http://localhost/fragments?graph=http%3A%2F%2Fexample.com%2Fg".parse()?,
+            )
+            .with_header(HeaderName::ACCEPT, "text/turtle")?
+            .build(),
+            Status::BAD_REQUEST,
+        )
+    }
+
+    #[test]
+    fn compression() -> Result<()> {
+        let server = ServerTest::new()?;
+        let store = server.store.clone();
+        let context = ServerTest::context(None);
+        let handler = compression_middleware(
+            move |request, _| {
+                handle_request(
+                    request,
+                    store.clone(),
+                    &context,
+                    false,
+                    &Permissions::all(),
+                    &RequestAuditor::default(),
+                )
+                .unwrap_or_else(|(status, message)| error(status, message))
+            },
+            u64::MAX,
+        );
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(b"<http://example.com/s> <http://example.com/p> <http://example.com/o> .")?;
+        let mut request =
+            Request::builder(Method::PUT, "http://localhost/store/person/1.ttl".parse()?)
+                .with_header(HeaderName::CONTENT_TYPE, "text/turtle")?
+                .with_header(HeaderName::CONTENT_ENCODING, "gzip")?
+                .with_body(encoder.finish()?);
+        ServerTest::check_status(handler(&mut request, &Peer::default()), Status::CREATED)?;
+
+        let mut request =
+            Request::builder(Method::GET, "http://localhost/store/person/1.ttl".parse()?)
+                .with_header(HeaderName::ACCEPT, "application/n-triples")?
+                .with_header(HeaderName::ACCEPT_ENCODING, "gzip")?
+                .build();
+        let mut response = handler(&mut request, &Peer::default());
+        assert_eq!(response.status(), Status::OK);
+        assert_eq!(
+            response
+                .header(&HeaderName::CONTENT_ENCODING)
+                .map(|h| h.to_str())
+                .transpose()?,
+            Some("gzip")
+        );
+        assert_eq!(
+            read_to_string(MultiGzDecoder::new(response.body_mut()))?,
+            "<http://example.com/s> <http://example.com/p> <http://example.com/o> .\n"
+        );
+
+        let mut request =
+            Request::builder(Method::PUT, "http://localhost/store/person/1.ttl".parse()?)
+                .with_header(HeaderName::CONTENT_TYPE, "text/turtle")?
+                .with_header(HeaderName::CONTENT_ENCODING, "compress")?
+                .with_body("");
+        ServerTest::check_status(
+            handler(&mut request, &Peer::default()),
+            Status::UNSUPPORTED_MEDIA_TYPE,
+        )
+    }
+
+    struct ServerTest {
+        store: Store,
+        context: ServerContext,
+    }
+
+    impl ServerTest {
+        fn new() -> Result<Self> {
+            Ok(Self {
+                store: Store::new()?,
+                context: Self::context(None),
+            })
+        }
+
+        fn with_admin(store: Store, admin: AdminOptions) -> Self {
+            Self {
+                store,
+                context: Self::context(Some(admin)),
+            }
+        }
+
+        fn context(admin: Option<AdminOptions>) -> ServerContext {
+            ServerContext {
+                queries: SavedQueries::default(),
+                prefixes: Vec::new(),
+                plans: PlanCache::new(16),
+                max_result_rows: None,
+                cursors: Cursors::new(None, 16, 4, Duration::from_secs(60)),
+                admin,
+            }
         }
 
         fn exec(&self, mut request: Request) -> Response {
-            handle_request(&mut request, self.store.clone(), false)
+            handle_request(
+                &mut request,
+                self.store.clone(),
+                &self.context,
+                false,
+                &Permissions::all(),
+                &RequestAuditor::default(),
+            )
+            .unwrap_or_else(|(status, message)| error(status, message))
+        }
+
+        fn exec_with_access_control(
+            &self,
+            mut request: Request,
+            access_control: &AccessControl,
+        ) -> Response {
+            access_control
+                .authenticate(&request, None)
+                .and_then(|permissions| {
+                    handle_request(
+                        &mut request,
+                        self.store.clone(),
+                        &self.context,
+                        false,
+                        &permissions,
+                        &RequestAuditor::default(),
+                    )
+                })
                 .unwrap_or_else(|(status, message)| error(status, message))
         }
 
         fn exec_read_only(&self, mut request: Request) -> Response {
-            handle_request(&mut request, self.store.clone(), true)
-                .unwrap_or_else(|(status, message)| error(status, message))
+            handle_request(
+                &mut request,
+                self.store.clone(),
+                &self.context,
+                true,
+                &Permissions::all(),
+                &RequestAuditor::default(),
+            )
+            .unwrap_or_else(|(status, message)| error(status, message))
         }
 
         fn test_status(&self, request: Request, expected_status: Status) -> Result<()> {
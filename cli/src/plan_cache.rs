@@ -0,0 +1,236 @@
+//! Cache of the prepared SPARQL queries.
+
+use oxigraph::sparql::PreparedQuery;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Keeps the prepared queries so that queries sent many times are only parsed and optimized once.
+///
+/// The queries are identified by their normalized text (see [`normalize_query`]) and their base IRI.
+/// The least recently used query is evicted when the cache is full.
+pub struct PlanCache {
+    capacity: usize,
+    entries: Mutex<PlanCacheEntries>,
+}
+
+#[derive(Default)]
+struct PlanCacheEntries {
+    plans: HashMap<(String, String), (PreparedQuery, u64)>,
+    clock: u64,
+}
+
+impl PlanCache {
+    /// Builds a cache keeping at most `capacity` queries, `0` disables the cache
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Returns the cached prepared query or prepares it with `prepare` and caches it
+    pub fn get_or_prepare<E>(
+        &self,
+        query: &str,
+        base_iri: &str,
+        prepare: impl FnOnce() -> Result<PreparedQuery, E>,
+    ) -> Result<PreparedQuery, E> {
+        if self.capacity == 0 {
+            return prepare();
+        }
+        let key = (normalize_query(query), base_iri.to_owned());
+        if let Some(plan) = self.entries().get(&key) {
+            return Ok(plan);
+        }
+        let plan = prepare()?;
+        self.entries().insert(key, plan.clone(), self.capacity);
+        Ok(plan)
+    }
+
+    fn entries(&self) -> MutexGuard<'_, PlanCacheEntries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl PlanCacheEntries {
+    fn get(&mut self, key: &(String, String)) -> Option<PreparedQuery> {
+        self.clock += 1;
+        let (plan, last_used) = self.plans.get_mut(key)?;
+        *last_used = self.clock;
+        Some(plan.clone())
+    }
+
+    fn insert(&mut self, key: (String, String), plan: PreparedQuery, capacity: usize) {
+        if self.plans.len() >= capacity && !self.plans.contains_key(&key) {
+            let least_recently_used = self
+                .plans
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                self.plans.remove(&least_recently_used);
+            }
+        }
+        self.clock += 1;
+        self.plans.insert(key, (plan, self.clock));
+    }
+}
+
+/// Collapses the whitespaces and removes the comments outside of the IRIs and string literals
+///
+/// Queries only differing by their formatting get the same normalized text.
+pub fn normalize_query(query: &str) -> String {
+    let chars = query.chars().collect::<Vec<_>>();
+    let mut output = String::with_capacity(query.len());
+    let mut pending_space = false;
+    let mut i = 0;
+    while let Some(&c) = chars.get(i) {
+        i += 1;
+        if c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        if c == '#' {
+            while chars.get(i).is_some_and(|c| !matches!(c, '\n' | '\r')) {
+                i += 1;
+            }
+            pending_space = true;
+            continue;
+        }
+        if pending_space && !output.is_empty() {
+            output.push(' ');
+        }
+        pending_space = false;
+        output.push(c);
+        match c {
+            '\\' => {
+                // Escape in a prefixed name local part like ex:a\#b or ex:a\'b, it does not start a comment or a string
+                if let Some(&escaped) = chars.get(i) {
+                    output.push(escaped);
+                    i += 1;
+                }
+            }
+            '<' => {
+                // We stop on the characters not allowed in IRIs if it was a "less than" operator
+                while let Some(&c) = chars.get(i) {
+                    if c.is_whitespace()
+                        || matches!(c, '<' | '"' | '{' | '}' | '|' | '^' | '`' | '\\')
+                    {
+                        break;
+                    }
+                    output.push(c);
+                    i += 1;
+                    if c == '>' {
+                        break;
+                    }
+                }
+            }
+            '"' | '\'' => {
+                let is_long = chars.get(i) == Some(&c) && chars.get(i + 1) == Some(&c);
+                if is_long {
+                    output.push(c);
+                    output.push(c);
+                    i += 2;
+                } else if chars.get(i) == Some(&c) {
+                    // Empty string
+                    output.push(c);
+                    i += 1;
+                    continue;
+                }
+                while let Some(&s) = chars.get(i) {
+                    output.push(s);
+                    i += 1;
+                    if s == '\\' {
+                        if let Some(&escaped) = chars.get(i) {
+                            output.push(escaped);
+                            i += 1;
+                        }
+                    } else if s == c
+                        && (!is_long || (chars.get(i) == Some(&c) && chars.get(i + 1) == Some(&c)))
+                    {
+                        if is_long {
+                            output.push(c);
+                            output.push(c);
+                            i += 2;
+                        }
+                        break;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use oxigraph::store::Store;
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_query("  SELECT *\n\tWHERE { ?s ?p ?o } # all triples\n"),
+            "SELECT * WHERE { ?s ?p ?o }"
+        );
+        assert_eq!(
+            normalize_query("ASK { <http://example.com/#a> ?p \"a  #b\" }"),
+            "ASK { <http://example.com/#a> ?p \"a  #b\" }"
+        );
+        assert_eq!(
+            normalize_query("ASK { ?s ?p '''a\n  ''b'''  }"),
+            "ASK { ?s ?p '''a\n  ''b''' }"
+        );
+        assert_eq!(
+            normalize_query("ASK { ?s ?p \"a\\\"  \"  ; ?p ''  }"),
+            "ASK { ?s ?p \"a\\\"  \" ; ?p '' }"
+        );
+        assert_eq!(
+            normalize_query("ASK { FILTER(1 <  2) }"),
+            "ASK { FILTER(1 < 2) }"
+        );
+        assert_eq!(
+            normalize_query("PREFIX ex: <http://example.com/> ASK { ex:a\\#b  ex:c\\'d  '#' }"),
+            "PREFIX ex: <http://example.com/> ASK { ex:a\\#b ex:c\\'d '#' }"
+        );
+        assert_ne!(
+            normalize_query("ASK { ex:a\\#b ?p ?o }"),
+            normalize_query("ASK { ex:a\\#c ?p ?o }")
+        );
+    }
+
+    #[test]
+    fn cache() -> Result<()> {
+        let store = Store::new()?;
+        let cache = PlanCache::new(2);
+        let mut prepared = 0;
+        let mut prepare = |query: &str| {
+            cache.get_or_prepare(query, "http://example.com/", || {
+                prepared += 1;
+                store.prepare(query)
+            })
+        };
+        prepare("ASK { ?s ?p ?o }")?;
+        prepare("ASK  {  ?s ?p ?o }")?;
+        assert_eq!(prepared, 1);
+        prepare("ASK { ?s ?p 1 }")?;
+        prepare("ASK { ?s ?p ?o }")?;
+        assert_eq!(prepared, 2);
+        // The least recently used query is evicted
+        prepare("ASK { ?s ?p 2 }")?;
+        prepare("ASK { ?s ?p ?o }")?;
+        assert_eq!(prepared, 3);
+        prepare("ASK { ?s ?p 1 }")?;
+        assert_eq!(prepared, 4);
+        // Invalid queries are not cached
+        assert!(prepare("ASK {").is_err());
+        assert!(prepare("ASK {").is_err());
+        assert_eq!(prepared, 6);
+        prepare("ASK { ?s ?p 1 }")?;
+        assert_eq!(prepared, 6);
+        Ok(())
+    }
+}
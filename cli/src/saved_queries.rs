@@ -0,0 +1,600 @@
+//! Named SPARQL queries with typed parameters exposed at `/api/{name}`.
+//!
+//! Each query is defined by a TOML document like:
+//! ```toml
+//! query = "SELECT ?name WHERE { ?person <http://schema.org/name> ?name ; <http://schema.org/age> ?age }"
+//!
+//! [parameters.age]
+//! type = "integer"
+//! required = true
+//! ```
+//! The parameter values are never inserted in the query string.
+//! They are given to the query evaluation as initial bindings.
+
+use crate::auth::Permissions;
+use crate::{bad_request, internal_server_error, the_server_is_read_only, HttpError};
+use anyhow::{bail, Context};
+use json_event_parser::{JsonEvent, ToWriteJsonWriter};
+use oxhttp::model::{HeaderName, Request, Response, Status};
+use oxigraph::model::vocab::xsd;
+use oxigraph::model::{Literal, NamedNode, Term};
+use oxigraph::sparql::{Query, QueryOptions, QuerySolution, Variable};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+const MAX_DEFINITION_SIZE: u64 = 0x0010_0000;
+
+/// The set of saved queries of a server
+///
+/// If a directory is set, each query is persisted in it as a `{name}.toml` file.
+#[derive(Default)]
+pub struct SavedQueries {
+    directory: Option<PathBuf>,
+    queries: RwLock<BTreeMap<String, Arc<SavedQuery>>>,
+}
+
+impl SavedQueries {
+    /// Loads all the `{name}.toml` files of `directory`
+    pub fn open(directory: PathBuf) -> anyhow::Result<Self> {
+        fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        let mut queries = BTreeMap::new();
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |e| e != "toml") {
+                continue;
+            }
+            let Some(name) = path
+                .file_stem()
+                .and_then(|n| n.to_str())
+                .map(ToOwned::to_owned)
+            else {
+                continue;
+            };
+            if validate_query_name(&name).is_err() {
+                eprintln!(
+                    "Ignoring the file {} that is not named after a valid query name",
+                    path.display()
+                );
+                continue;
+            }
+            let definition = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let query = SavedQuery::parse(&definition)
+                .with_context(|| format!("Invalid saved query {}", path.display()))?;
+            queries.insert(name, Arc::new(query));
+        }
+        Ok(Self {
+            directory: Some(directory),
+            queries: RwLock::new(queries),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Arc<SavedQuery>>, HttpError> {
+        Ok(self.read()?.get(name).cloned())
+    }
+
+    pub fn names(&self) -> Result<Vec<String>, HttpError> {
+        Ok(self.read()?.keys().cloned().collect())
+    }
+
+    /// Adds or replaces a query
+    ///
+    /// Returns `false` if a query with the same name has been replaced.
+    pub fn insert(&self, name: &str, definition: &str) -> Result<bool, HttpError> {
+        validate_query_name(name)?;
+        let query = SavedQuery::parse(definition).map_err(|e| bad_request(format!("{e:#}")))?;
+        let mut queries = self.write()?;
+        if let Some(directory) = &self.directory {
+            fs::write(directory.join(format!("{name}.toml")), definition)
+                .map_err(internal_server_error)?;
+        }
+        Ok(queries.insert(name.into(), Arc::new(query)).is_none())
+    }
+
+    /// Returns `false` if the query does not exist
+    pub fn remove(&self, name: &str) -> Result<bool, HttpError> {
+        let mut queries = self.write()?;
+        if queries.remove(name).is_none() {
+            return Ok(false);
+        }
+        if let Some(directory) = &self.directory {
+            fs::remove_file(directory.join(format!("{name}.toml")))
+                .map_err(internal_server_error)?;
+        }
+        Ok(true)
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, BTreeMap<String, Arc<SavedQuery>>>, HttpError> {
+        self.queries
+            .read()
+            .map_err(|_| internal_server_error("The saved query list lock is poisoned"))
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, BTreeMap<String, Arc<SavedQuery>>>, HttpError> {
+        self.queries
+            .write()
+            .map_err(|_| internal_server_error("The saved query list lock is poisoned"))
+    }
+}
+
+/// Handles the requests to `/admin/queries` and `/admin/queries/{name}`
+pub fn handle_saved_queries_admin_request(
+    request: &mut Request,
+    path: &str,
+    queries: &SavedQueries,
+    read_only: bool,
+    permissions: &Permissions,
+) -> Result<Response, HttpError> {
+    let name = path.strip_prefix("/admin/queries/");
+    let not_found = |name: &str| {
+        (
+            Status::NOT_FOUND,
+            format!("The saved query {name} does not exist"),
+        )
+    };
+    match (name, request.method().as_ref()) {
+        (None, "GET") => {
+            // The queries are only visible to the principals allowed to manage them
+            permissions.check_write_all()?;
+            let mut writer = ToWriteJsonWriter::new(Vec::new());
+            writer
+                .write_event(JsonEvent::StartArray)
+                .map_err(internal_server_error)?;
+            for name in queries.names()? {
+                writer
+                    .write_event(JsonEvent::String(name.into()))
+                    .map_err(internal_server_error)?;
+            }
+            writer
+                .write_event(JsonEvent::EndArray)
+                .map_err(internal_server_error)?;
+            Ok(Response::builder(Status::OK)
+                .with_header(HeaderName::CONTENT_TYPE, "application/json")
+                .unwrap()
+                .with_body(writer.finish().map_err(internal_server_error)?))
+        }
+        (Some(name), "GET") => {
+            permissions.check_write_all()?;
+            let query = queries.get(name)?.ok_or_else(|| not_found(name))?;
+            Ok(Response::builder(Status::OK)
+                .with_header(HeaderName::CONTENT_TYPE, "application/toml")
+                .unwrap()
+                .with_body(query.definition.clone()))
+        }
+        (Some(name), "PUT") => {
+            if read_only {
+                return Err(the_server_is_read_only());
+            }
+            permissions.check_write_all()?;
+            let definition = io::read_to_string(request.body_mut().take(MAX_DEFINITION_SIZE))
+                .map_err(bad_request)?;
+            Ok(Response::builder(if queries.insert(name, &definition)? {
+                Status::CREATED
+            } else {
+                Status::NO_CONTENT
+            })
+            .build())
+        }
+        (Some(name), "DELETE") => {
+            if read_only {
+                return Err(the_server_is_read_only());
+            }
+            permissions.check_write_all()?;
+            if queries.remove(name)? {
+                Ok(Response::builder(Status::NO_CONTENT).build())
+            } else {
+                Err(not_found(name))
+            }
+        }
+        _ => Err((
+            Status::NOT_FOUND,
+            format!(
+                "{} {} is not supported by this server",
+                request.method(),
+                request.url().path()
+            ),
+        )),
+    }
+}
+
+/// A parsed saved query definition
+pub struct SavedQuery {
+    query: spargebra::Query,
+    parameters: Vec<(Variable, ParameterDefinition)>,
+    /// The TOML definition as given by the user
+    definition: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SavedQueryDefinition {
+    query: String,
+    #[serde(default)]
+    parameters: BTreeMap<String, ParameterDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParameterDefinition {
+    #[serde(rename = "type")]
+    kind: ParameterType,
+    /// If the parameter is not required and has no default value, its variable is left unbound
+    #[serde(default)]
+    required: bool,
+    default: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ParameterType {
+    Iri,
+    String,
+    Integer,
+    Decimal,
+    Double,
+    Boolean,
+}
+
+impl SavedQuery {
+    fn parse(definition: &str) -> anyhow::Result<Self> {
+        let parsed: SavedQueryDefinition = toml::from_str(definition)?;
+        let query = spargebra::Query::parse(&parsed.query, None)?;
+        let parameters = parsed
+            .parameters
+            .into_iter()
+            .map(|(name, parameter)| {
+                let variable = Variable::new(&name)
+                    .with_context(|| format!("Invalid parameter name {name}"))?;
+                if let Some(default) = &parameter.default {
+                    if parameter.required {
+                        bail!(
+                            "The parameter {name} can't be both required and have a default value"
+                        )
+                    }
+                    parameter
+                        .kind
+                        .parse_value(default)
+                        .map_err(|e| anyhow::anyhow!("{e}"))
+                        .with_context(|| {
+                            format!("Invalid default value of the parameter {name}")
+                        })?;
+                }
+                Ok((variable, parameter))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            query,
+            parameters,
+            definition: definition.into(),
+        })
+    }
+
+    /// Returns `true` if the query returns RDF triples and not solutions or a boolean
+    pub fn returns_graph(&self) -> bool {
+        matches!(
+            self.query,
+            spargebra::Query::Construct { .. } | spargebra::Query::Describe { .. }
+        )
+    }
+
+    /// Returns the query to evaluate and the options binding its parameters to the given values
+    pub fn bind<'a>(
+        &self,
+        values: impl IntoIterator<Item = (Cow<'a, str>, Cow<'a, str>)>,
+    ) -> Result<(Query, QueryOptions), HttpError> {
+        let mut given = BTreeMap::new();
+        for (name, value) in values {
+            if !self.parameters.iter().any(|(v, _)| v.as_str() == name) {
+                return Err(bad_request(format!("Unknown parameter {name}")));
+            }
+            if given.insert(name.clone(), value).is_some() {
+                return Err(bad_request(format!("The parameter {name} is given twice")));
+            }
+        }
+        let mut options = QueryOptions::default();
+        for (variable, parameter) in &self.parameters {
+            let value = if let Some(value) = given.get(variable.as_str()) {
+                value.as_ref()
+            } else if let Some(default) = &parameter.default {
+                default.as_str()
+            } else if parameter.required {
+                return Err(bad_request(format!(
+                    "The parameter {} is required",
+                    variable.as_str()
+                )));
+            } else {
+                continue;
+            };
+            options = options.with_binding(
+                variable.clone(),
+                parameter.kind.parse_value(value).map_err(|e| {
+                    bad_request(format!(
+                        "Invalid value of the parameter {}: {e}",
+                        variable.as_str()
+                    ))
+                })?,
+            );
+        }
+        Ok((self.query.clone().into(), options))
+    }
+}
+
+impl ParameterType {
+    fn parse_value(self, value: &str) -> Result<Term, String> {
+        Ok(match self {
+            Self::Iri => NamedNode::new(value).map_err(|e| e.to_string())?.into(),
+            Self::String => Literal::new_simple_literal(value).into(),
+            Self::Integer => {
+                if !is_integer(value) {
+                    return Err(format!("{value} is not a valid integer"));
+                }
+                Literal::new_typed_literal(value, xsd::INTEGER).into()
+            }
+            Self::Decimal => {
+                if !is_decimal(value) {
+                    return Err(format!("{value} is not a valid decimal"));
+                }
+                Literal::new_typed_literal(value, xsd::DECIMAL).into()
+            }
+            Self::Double => {
+                if !is_double(value) {
+                    return Err(format!("{value} is not a valid double"));
+                }
+                Literal::new_typed_literal(value, xsd::DOUBLE).into()
+            }
+            Self::Boolean => {
+                if !matches!(value, "true" | "false" | "1" | "0") {
+                    return Err(format!("{value} is not a valid boolean"));
+                }
+                Literal::new_typed_literal(value, xsd::BOOLEAN).into()
+            }
+        })
+    }
+}
+
+/// Checks the [xsd:integer lexical space](https://www.w3.org/TR/xmlschema11-2/#integer-lexical-representation)
+fn is_integer(value: &str) -> bool {
+    let value = value.strip_prefix(['+', '-']).unwrap_or(value);
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Checks the [xsd:double lexical space](https://www.w3.org/TR/xmlschema11-2/#double-lexical-representation)
+///
+/// Unlike the Rust float parser, only `INF`, `+INF`, `-INF` and `NaN` are allowed for the special values.
+fn is_double(value: &str) -> bool {
+    if matches!(value, "INF" | "+INF" | "-INF" | "NaN") {
+        return true;
+    }
+    let (mantissa, exponent) = value
+        .split_once(['e', 'E'])
+        .map_or((value, None), |(m, e)| (m, Some(e)));
+    is_decimal(mantissa) && exponent.map_or(true, is_integer)
+}
+
+/// Checks the [xsd:decimal lexical space](https://www.w3.org/TR/xmlschema11-2/#decimal-lexical-representation)
+fn is_decimal(value: &str) -> bool {
+    let value = value.strip_prefix(['+', '-']).unwrap_or(value);
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    (!integer.is_empty() || !fraction.is_empty())
+        && integer.bytes().all(|b| b.is_ascii_digit())
+        && fraction.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Writes solutions as a JSON array of objects mapping the bound variables to their values
+///
+/// IRIs, blank nodes and most literals are written as JSON strings.
+/// Boolean and numeric literals are written as JSON booleans and numbers when possible.
+pub struct SimpleJsonSolutionsWriter<W: Write> {
+    writer: ToWriteJsonWriter<W>,
+}
+
+impl<W: Write> SimpleJsonSolutionsWriter<W> {
+    pub fn start(write: W) -> io::Result<Self> {
+        let mut writer = ToWriteJsonWriter::new(write);
+        writer.write_event(JsonEvent::StartArray)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, solution: &QuerySolution) -> io::Result<()> {
+        self.writer.write_event(JsonEvent::StartObject)?;
+        for (variable, value) in solution {
+            self.writer
+                .write_event(JsonEvent::ObjectKey(variable.as_str().into()))?;
+            self.writer.write_event(term_to_json(value))?;
+        }
+        self.writer.write_event(JsonEvent::EndObject)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_event(JsonEvent::EndArray)?;
+        self.writer.finish()
+    }
+}
+
+pub fn write_simple_json_boolean<W: Write>(write: W, value: bool) -> io::Result<W> {
+    let mut writer = ToWriteJsonWriter::new(write);
+    writer.write_event(JsonEvent::Boolean(value))?;
+    writer.finish()
+}
+
+fn term_to_json(term: &Term) -> JsonEvent<'_> {
+    match term {
+        Term::NamedNode(node) => JsonEvent::String(node.as_str().into()),
+        Term::Literal(literal) => {
+            let datatype = literal.datatype();
+            let value = literal.value();
+            if datatype == xsd::BOOLEAN {
+                match value {
+                    "true" | "1" => return JsonEvent::Boolean(true),
+                    "false" | "0" => return JsonEvent::Boolean(false),
+                    _ => (),
+                }
+            } else if (datatype == xsd::INTEGER
+                || datatype == xsd::DECIMAL
+                || datatype == xsd::DOUBLE
+                || datatype == xsd::FLOAT
+                || datatype == xsd::INT
+                || datatype == xsd::LONG)
+                && is_json_number(value)
+            {
+                return JsonEvent::Number(value.into());
+            }
+            JsonEvent::String(value.into())
+        }
+        term => JsonEvent::String(term.to_string().into()),
+    }
+}
+
+/// Checks the [JSON number grammar](https://www.rfc-editor.org/rfc/rfc8259#section-6)
+fn is_json_number(value: &str) -> bool {
+    fn digits(value: &str) -> (&str, &str) {
+        let end = value
+            .bytes()
+            .position(|b| !b.is_ascii_digit())
+            .unwrap_or(value.len());
+        value.split_at(end)
+    }
+
+    let value = value.strip_prefix('-').unwrap_or(value);
+    let (integer, mut rest) = digits(value);
+    if integer.is_empty() || (integer.len() > 1 && integer.starts_with('0')) {
+        return false;
+    }
+    if let Some(r) = rest.strip_prefix('.') {
+        let (fraction, r) = digits(r);
+        if fraction.is_empty() {
+            return false;
+        }
+        rest = r;
+    }
+    if let Some(r) = rest.strip_prefix(['e', 'E']) {
+        let (exponent, r) = digits(r.strip_prefix(['+', '-']).unwrap_or(r));
+        if exponent.is_empty() {
+            return false;
+        }
+        rest = r;
+    }
+    rest.is_empty()
+}
+
+/// Saved query names are used both as URL path segments and as file names
+fn validate_query_name(name: &str) -> Result<(), HttpError> {
+    if name.is_empty()
+        || name.len() > 64
+        || !name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(bad_request(format!(
+            "'{name}' is not a valid query name, only up to 64 ASCII letters, digits, '-' and '_' are allowed"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use oxigraph::io::RdfFormat;
+    use oxigraph::sparql::QueryResults;
+    use oxigraph::store::Store;
+
+    #[test]
+    fn test_bind() -> anyhow::Result<()> {
+        let query = SavedQuery::parse(
+            r#"
+            query = "SELECT ?s WHERE { ?s ?p ?o FILTER(?o > ?min) } LIMIT 10"
+
+            [parameters.p]
+            type = "iri"
+            required = true
+
+            [parameters.min]
+            type = "integer"
+            default = "2"
+
+            [parameters.lang]
+            type = "string"
+            "#,
+        )?;
+        let store = Store::new()?;
+        store.load_from_read(
+            RdfFormat::NTriples,
+            b"<http://example.com/s1> <http://example.com/p> \"1\"^^<http://www.w3.org/2001/XMLSchema#integer> .
+            <http://example.com/s2> <http://example.com/p> \"3\"^^<http://www.w3.org/2001/XMLSchema#integer> .
+            <http://example.com/s3> <http://example.com/q> \"5\"^^<http://www.w3.org/2001/XMLSchema#integer> .".as_slice(),
+        )?;
+        let (bound, options) = query
+            .bind([("p".into(), "http://example.com/p".into())])
+            .map_err(|(_, e)| anyhow::anyhow!(e))?;
+        // The query text is not modified
+        assert!(!bound.to_string().contains("example.com"));
+        let QueryResults::Solutions(solutions) = store.query_opt(bound, options)? else {
+            anyhow::bail!("SELECT queries return solutions");
+        };
+        let subjects = solutions
+            .map(|s| Ok(s?.get("s").map(ToString::to_string)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(subjects, [Some("<http://example.com/s2>".into())]);
+        assert!(query.bind([]).is_err());
+        assert!(query.bind([("p".into(), "not an iri".into())]).is_err());
+        assert!(query
+            .bind([
+                ("p".into(), "http://example.com/p".into()),
+                ("foo".into(), "bar".into())
+            ])
+            .is_err());
+        assert!(query
+            .bind([
+                ("p".into(), "http://example.com/p".into()),
+                ("min".into(), "1.5".into())
+            ])
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_definition() {
+        assert!(SavedQuery::parse("query = \"SELECT\"").is_err());
+        assert!(SavedQuery::parse("query = \"ASK {}\"\nfoo = 1").is_err());
+        assert!(SavedQuery::parse(
+            "query = \"ASK {}\"\n[parameters.x]\ntype = \"integer\"\ndefault = \"a\""
+        )
+        .is_err());
+        assert!(SavedQuery::parse("query = \"ASK {}\"\n[parameters.x]\ntype = \"date\"").is_err());
+    }
+
+    #[test]
+    fn test_literal_values() {
+        assert!(is_decimal("-1.5"));
+        assert!(is_decimal(".5"));
+        assert!(!is_decimal("."));
+        assert!(!is_decimal("1e3"));
+        assert!(is_integer("+10"));
+        assert!(is_integer("123456789012345678901234567890"));
+        assert!(!is_integer("1.0"));
+        assert!(!is_integer("-"));
+        assert!(is_double("-1.5E-3"));
+        assert!(is_double(".5e10"));
+        assert!(is_double("INF"));
+        assert!(is_double("NaN"));
+        assert!(!is_double("inf"));
+        assert!(!is_double("infinity"));
+        assert!(!is_double("nan"));
+        assert!(!is_double("1e"));
+        assert!(!is_double("e3"));
+        assert!(is_json_number("-1.5e10"));
+        assert!(is_json_number("0"));
+        assert!(!is_json_number("01"));
+        assert!(!is_json_number("+1"));
+        assert!(!is_json_number(".5"));
+        assert!(!is_json_number("INF"));
+    }
+}
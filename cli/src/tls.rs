@@ -0,0 +1,168 @@
+//! TLS configuration of the HTTP server.
+//!
+//! The TLS connections are terminated by the tokio based server in [`async_server`](crate::async_server).
+
+use anyhow::{bail, Context};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::CertificateDer;
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig, ServerConnection};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use x509_parser::parse_x509_certificate;
+
+/// Set when the certificate should be reloaded from disk (e.g. on SIGHUP)
+static RELOAD_CERTIFICATE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TlsOptions {
+    pub certificate: PathBuf,
+    pub private_key: PathBuf,
+    /// Certificate authorities the client certificates must be signed by, if client authentication is required
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Builds the rustls configuration and starts to reload the certificate on SIGHUP
+pub fn server_config(options: &TlsOptions) -> anyhow::Result<Arc<ServerConfig>> {
+    let provider = Arc::new(ring::default_provider());
+    let resolver = Arc::new(CertificateResolver::new(
+        options.certificate.clone(),
+        options.private_key.clone(),
+        Arc::clone(&provider),
+    )?);
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?;
+    let builder = if let Some(client_ca) = &options.client_ca {
+        let mut roots = RootCertStore::empty();
+        for certificate in load_certificates(client_ca)? {
+            roots.add(certificate)?;
+        }
+        builder.with_client_cert_verifier(
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?,
+        )
+    } else {
+        builder.with_no_client_auth()
+    };
+    let mut config = builder.with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    #[cfg(unix)]
+    reload_certificate_on_sighup()?;
+    Ok(Arc::new(config))
+}
+
+/// The common name of the subject of the client certificate, if the client sent one
+///
+/// The certificate has already been verified against the client certificate authorities during the handshake.
+pub fn client_certificate_subject(connection: &ServerConnection) -> Option<String> {
+    let certificate = connection.peer_certificates()?.first()?;
+    let (_, certificate) = parse_x509_certificate(certificate).ok()?;
+    let subject = certificate
+        .subject()
+        .iter_common_name()
+        .next()?
+        .as_str()
+        .ok()?
+        .to_owned();
+    Some(subject)
+}
+
+/// Resolves the server certificate, reloading it from disk when asked to
+#[derive(Debug)]
+struct CertificateResolver {
+    certificate: PathBuf,
+    private_key: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertificateResolver {
+    fn new(
+        certificate: PathBuf,
+        private_key: PathBuf,
+        provider: Arc<CryptoProvider>,
+    ) -> anyhow::Result<Self> {
+        let current = RwLock::new(Arc::new(load_certified_key(
+            &certificate,
+            &private_key,
+            &provider,
+        )?));
+        Ok(Self {
+            certificate,
+            private_key,
+            provider,
+            current,
+        })
+    }
+
+    fn reload(&self) -> anyhow::Result<()> {
+        let key = load_certified_key(&self.certificate, &self.private_key, &self.provider)?;
+        *self
+            .current
+            .write()
+            .map_err(|_| anyhow::anyhow!("The certificate lock is poisoned"))? = Arc::new(key);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        if RELOAD_CERTIFICATE.swap(false, Ordering::Relaxed) {
+            match self.reload() {
+                Ok(()) => eprintln!("TLS certificate reloaded"),
+                Err(e) => eprintln!(
+                    "Failed to reload the TLS certificate, keeping the previous one: {e:#}"
+                ),
+            }
+        }
+        Some(Arc::clone(&*self.current.read().ok()?))
+    }
+}
+
+fn load_certified_key(
+    certificate: &Path,
+    private_key: &Path,
+    provider: &CryptoProvider,
+) -> anyhow::Result<CertifiedKey> {
+    let certificates = load_certificates(certificate)?;
+    let private_key = rustls_pemfile::private_key(&mut BufReader::new(
+        File::open(private_key)
+            .with_context(|| format!("Failed to open {}", private_key.display()))?,
+    ))?
+    .with_context(|| format!("No private key found in {}", private_key.display()))?;
+    Ok(CertifiedKey::new(
+        certificates,
+        provider.key_provider.load_private_key(private_key)?,
+    ))
+}
+
+fn load_certificates(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    ))
+    .collect::<Result<Vec<_>, _>>()
+    .with_context(|| format!("Failed to parse the certificates in {}", path.display()))?;
+    if certificates.is_empty() {
+        bail!("No certificate found in {}", path.display());
+    }
+    Ok(certificates)
+}
+
+#[cfg(unix)]
+#[allow(unsafe_code, clippy::fn_to_numeric_cast_any)]
+fn reload_certificate_on_sighup() -> std::io::Result<()> {
+    extern "C" fn on_sighup(_: libc::c_int) {
+        RELOAD_CERTIFICATE.store(true, Ordering::Relaxed);
+    }
+
+    // SAFETY: the handler only does an atomic store that is async-signal-safe
+    if unsafe { libc::signal(libc::SIGHUP, on_sighup as libc::sighandler_t) } == libc::SIG_ERR {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -1,10 +1,10 @@
 use crate::model::*;
 use crate::{console_warn, format_err};
-use js_sys::{try_iter, Array, Map, Reflect};
+use js_sys::{try_iter, Array, Map, Object, Reflect};
 use oxigraph::io::{RdfFormat, RdfParser};
 use oxigraph::model::*;
 use oxigraph::sparql::results::QueryResultsFormat;
-use oxigraph::sparql::{Query, QueryResults, Update};
+use oxigraph::sparql::{Query, QueryOptions, QueryResults, Update, Variable};
 use oxigraph::store::Store;
 use wasm_bindgen::prelude::*;
 
@@ -49,6 +49,7 @@ export class Store {
         query: string,
         options?: {
             base_iri?: NamedNode | string;
+            bindings?: Record<string, Term>;
             results_format?: string;
             use_default_graph_as_union?: boolean;
         }
@@ -165,6 +166,7 @@ impl JsStore {
         let mut base_iri = None;
         let mut use_default_graph_as_union = false;
         let mut results_format = None;
+        let mut query_options = QueryOptions::default();
         if !options.is_undefined() {
             base_iri = convert_base_iri(&Reflect::get(options, &JsValue::from_str("base_iri"))?)?;
             use_default_graph_as_union =
@@ -178,13 +180,34 @@ impl JsStore {
                         .ok_or_else(|| format_err!("results_format option must be a string"))?,
                 );
             }
+            let js_bindings = Reflect::get(options, &JsValue::from_str("bindings"))?;
+            if !js_bindings.is_undefined() && !js_bindings.is_null() {
+                let js_bindings = js_bindings
+                    .dyn_ref::<Object>()
+                    .ok_or_else(|| format_err!("bindings option must be an object"))?;
+                for entry in Object::entries(js_bindings).iter() {
+                    let entry = Array::from(&entry);
+                    let variable = Variable::new(
+                        entry
+                            .get(0)
+                            .as_string()
+                            .ok_or_else(|| format_err!("bindings keys must be strings"))?,
+                    )
+                    .map_err(JsError::from)?;
+                    let value = Term::try_from(FROM_JS.with(|c| c.to_term(&entry.get(1)))?)?;
+                    query_options = query_options.with_binding(variable, value);
+                }
+            }
         }
 
         let mut query = Query::parse(query, base_iri.as_deref()).map_err(JsError::from)?;
         if use_default_graph_as_union {
             query.dataset_mut().set_default_graph_as_union();
         }
-        let results = self.store.query(query).map_err(JsError::from)?;
+        let results = self
+            .store
+            .query_opt(query, query_options)
+            .map_err(JsError::from)?;
 
         Ok(match results {
             QueryResults::Solutions(solutions) => {
@@ -112,6 +112,18 @@ describe("Store", () => {
             assert.strictEqual(1, results.length);
         });
 
+        it("SELECT with bindings", () => {
+            const store = new Store([
+                dataModel.quad(ex, ex, dataModel.literal("o")),
+                dataModel.quad(dataModel.namedNode("http://example.com/s"), ex, ex),
+            ]);
+            const results = store.query("SELECT ?o WHERE { ?s ?p ?o }", {
+                bindings: { s: ex },
+            }) as Map<string, Term>[];
+            assert.strictEqual(1, results.length);
+            assert(dataModel.literal("o").equals(results[0]?.get("o")));
+        });
+
         it("SELECT with results format", () => {
             const store = new Store([dataModel.quad(ex, ex, ex)]);
             const results = store.query("SELECT ?s ?p ?o WHERE { ?s ?p ?o }", {
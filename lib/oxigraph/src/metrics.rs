@@ -0,0 +1,448 @@
+//! Metrics about the usage of a [`Store`](crate::store::Store).
+//!
+//! They are collected by the store and all its clones and might be retrieved with [`Store::metrics`](crate::store::Store::metrics).
+//!
+//! Usage example:
+//! ```
+//! use oxigraph::sparql::QueryResults;
+//! use oxigraph::store::Store;
+//!
+//! let store = Store::new()?;
+//! if let QueryResults::Solutions(solutions) = store.query("SELECT * WHERE { ?s ?p ?o }")? {
+//!     for solution in solutions {
+//!         solution?;
+//!     }
+//! }
+//! let metrics = store.metrics();
+//! assert_eq!(metrics.queries().count(), 1);
+//!
+//! let mut prometheus = Vec::new();
+//! metrics.write_in_prometheus(&mut prometheus)?;
+//! # Result::<_, Box<dyn std::error::Error>>::Ok(())
+//! ```
+
+use crate::sparql::Timer;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds in seconds of the duration histogram buckets
+const DURATION_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 10.,
+];
+
+/// The counters shared by a store and its clones
+#[derive(Default)]
+pub(crate) struct MetricsRegistry {
+    pub(crate) queries: OperationRecorder,
+    pub(crate) updates: OperationRecorder,
+    pub(crate) loads: OperationRecorder,
+    pub(crate) loaded_quads: AtomicU64,
+}
+
+impl MetricsRegistry {
+    pub(crate) fn snapshot(&self, storage: StorageStatistics) -> StoreMetrics {
+        StoreMetrics {
+            queries: self.queries.snapshot(),
+            updates: self.updates.snapshot(),
+            loads: self.loads.snapshot(),
+            loaded_quads: self.loaded_quads.load(Ordering::Relaxed),
+            storage,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct OperationRecorder {
+    count: AtomicU64,
+    errors: AtomicU64,
+    duration_in_micros: AtomicU64,
+    buckets: [AtomicU64; DURATION_BUCKETS.len()],
+}
+
+impl OperationRecorder {
+    /// Records an operation started at `start`
+    pub(crate) fn record(&self, start: &Timer, success: bool) {
+        let duration = start
+            .elapsed()
+            .and_then(|duration| Duration::try_from(duration).ok())
+            .unwrap_or_default();
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.duration_in_micros.fetch_add(
+            duration.as_micros().try_into().unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        let seconds = duration.as_secs_f64();
+        for (bucket, upper_bound) in self.buckets.iter().zip(DURATION_BUCKETS) {
+            if seconds <= upper_bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn snapshot(&self) -> OperationMetrics {
+        OperationMetrics {
+            count: self.count.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            total_duration: Duration::from_micros(self.duration_in_micros.load(Ordering::Relaxed)),
+            buckets: self
+                .buckets
+                .iter()
+                .map(|bucket| bucket.load(Ordering::Relaxed))
+                .collect(),
+        }
+    }
+}
+
+/// A snapshot of the metrics of a [`Store`](crate::store::Store).
+#[derive(Debug, Clone)]
+pub struct StoreMetrics {
+    queries: OperationMetrics,
+    updates: OperationMetrics,
+    loads: OperationMetrics,
+    loaded_quads: u64,
+    storage: StorageStatistics,
+}
+
+impl StoreMetrics {
+    /// The SPARQL queries evaluated.
+    ///
+    /// The duration of a query goes from its parsing to the moment its results iterator is exhausted or dropped.
+    #[inline]
+    pub fn queries(&self) -> &OperationMetrics {
+        &self.queries
+    }
+
+    /// The SPARQL updates executed.
+    #[inline]
+    pub fn updates(&self) -> &OperationMetrics {
+        &self.updates
+    }
+
+    /// The RDF files and quad sets loaded with the `load_*` methods and the bulk loader.
+    #[inline]
+    pub fn loads(&self) -> &OperationMetrics {
+        &self.loads
+    }
+
+    /// The number of quads loaded with the `load_*` methods and the bulk loader.
+    #[inline]
+    pub fn loaded_quads(&self) -> u64 {
+        self.loaded_quads
+    }
+
+    /// Statistics about the underlying storage.
+    #[inline]
+    pub fn storage(&self) -> &StorageStatistics {
+        &self.storage
+    }
+
+    /// Writes the metrics in the [Prometheus text exposition format](https://prometheus.io/docs/instrumenting/exposition_formats/).
+    pub fn write_in_prometheus(&self, mut write: impl Write) -> io::Result<()> {
+        write_operation_metrics(&mut write, "query", "SPARQL queries", &self.queries)?;
+        write_operation_metrics(&mut write, "update", "SPARQL updates", &self.updates)?;
+        write_operation_metrics(&mut write, "load", "data loads", &self.loads)?;
+        write_metric(
+            &mut write,
+            "oxigraph_loaded_quads_total",
+            "counter",
+            "Number of quads loaded",
+            Some(self.loaded_quads),
+        )?;
+        for (name, kind, help, value) in [
+            (
+                "oxigraph_storage_estimated_quads",
+                "gauge",
+                "Estimated number of quads in the store",
+                self.storage.estimated_quads,
+            ),
+            (
+                "oxigraph_storage_sst_files_bytes",
+                "gauge",
+                "Total size of the storage data files",
+                self.storage.sst_files_size,
+            ),
+            (
+                "oxigraph_storage_live_data_bytes",
+                "gauge",
+                "Estimated size of the live data in the storage",
+                self.storage.live_data_size,
+            ),
+            (
+                "oxigraph_storage_memtables_bytes",
+                "gauge",
+                "Size of the storage in-memory write buffers",
+                self.storage.memtables_size,
+            ),
+            (
+                "oxigraph_storage_pending_compaction_bytes",
+                "gauge",
+                "Estimated number of bytes that compactions need to rewrite",
+                self.storage.pending_compaction_size,
+            ),
+            (
+                "oxigraph_storage_running_compactions",
+                "gauge",
+                "Number of storage compactions currently running",
+                self.storage.running_compactions,
+            ),
+            (
+                "oxigraph_storage_block_cache_usage_bytes",
+                "gauge",
+                "Memory used by the storage block cache",
+                self.storage.block_cache_usage,
+            ),
+            (
+                "oxigraph_storage_block_cache_capacity_bytes",
+                "gauge",
+                "Capacity of the storage block cache",
+                self.storage.block_cache_capacity,
+            ),
+            (
+                "oxigraph_storage_block_cache_hits_total",
+                "counter",
+                "Number of storage block cache hits",
+                self.storage.block_cache_hits,
+            ),
+            (
+                "oxigraph_storage_block_cache_misses_total",
+                "counter",
+                "Number of storage block cache misses",
+                self.storage.block_cache_misses,
+            ),
+        ] {
+            write_metric(&mut write, name, kind, help, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_operation_metrics(
+    write: &mut impl Write,
+    name: &str,
+    description: &str,
+    metrics: &OperationMetrics,
+) -> io::Result<()> {
+    write_metric(
+        write,
+        &format!("oxigraph_{name}_total"),
+        "counter",
+        &format!("Number of {description}"),
+        Some(metrics.count),
+    )?;
+    write_metric(
+        write,
+        &format!("oxigraph_{name}_errors_total"),
+        "counter",
+        &format!("Number of failed {description}"),
+        Some(metrics.errors),
+    )?;
+    writeln!(
+        write,
+        "# HELP oxigraph_{name}_duration_seconds Duration of the {description}"
+    )?;
+    writeln!(write, "# TYPE oxigraph_{name}_duration_seconds histogram")?;
+    for (upper_bound, count) in DURATION_BUCKETS.iter().zip(&metrics.buckets) {
+        writeln!(
+            write,
+            "oxigraph_{name}_duration_seconds_bucket{{le=\"{upper_bound}\"}} {count}"
+        )?;
+    }
+    writeln!(
+        write,
+        "oxigraph_{name}_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+        metrics.count
+    )?;
+    writeln!(
+        write,
+        "oxigraph_{name}_duration_seconds_sum {}",
+        metrics.total_duration.as_secs_f64()
+    )?;
+    writeln!(
+        write,
+        "oxigraph_{name}_duration_seconds_count {}",
+        metrics.count
+    )
+}
+
+fn write_metric(
+    write: &mut impl Write,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: Option<u64>,
+) -> io::Result<()> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+    writeln!(write, "# HELP {name} {help}")?;
+    writeln!(write, "# TYPE {name} {kind}")?;
+    writeln!(write, "{name} {value}")
+}
+
+/// Counters about a kind of operation.
+#[derive(Debug, Clone)]
+pub struct OperationMetrics {
+    count: u64,
+    errors: u64,
+    total_duration: Duration,
+    buckets: Vec<u64>,
+}
+
+impl OperationMetrics {
+    /// The number of operations, including the failed ones.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The number of failed operations.
+    #[inline]
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// The sum of the durations of all the operations.
+    #[inline]
+    pub fn total_duration(&self) -> Duration {
+        self.total_duration
+    }
+
+    /// The duration histogram as a list of upper bounds and the number of operations that lasted at most this duration.
+    pub fn duration_histogram(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        DURATION_BUCKETS
+            .iter()
+            .zip(&self.buckets)
+            .map(|(upper_bound, count)| (Duration::from_secs_f64(*upper_bound), *count))
+    }
+}
+
+/// Statistics about the storage of a [`Store`](crate::store::Store).
+///
+/// Most of them are only available with the on-disk storage.
+/// The block cache hits and misses are only available if the statistics have been enabled when opening the store.
+#[derive(Debug, Clone, Default)]
+pub struct StorageStatistics {
+    pub(crate) estimated_quads: Option<u64>,
+    pub(crate) sst_files_size: Option<u64>,
+    pub(crate) live_data_size: Option<u64>,
+    pub(crate) memtables_size: Option<u64>,
+    pub(crate) pending_compaction_size: Option<u64>,
+    pub(crate) running_compactions: Option<u64>,
+    pub(crate) block_cache_usage: Option<u64>,
+    pub(crate) block_cache_capacity: Option<u64>,
+    pub(crate) block_cache_hits: Option<u64>,
+    pub(crate) block_cache_misses: Option<u64>,
+}
+
+impl StorageStatistics {
+    /// Estimated number of quads in the store.
+    #[inline]
+    pub fn estimated_quads(&self) -> Option<u64> {
+        self.estimated_quads
+    }
+
+    /// Total size in bytes of the data files.
+    #[inline]
+    pub fn sst_files_size(&self) -> Option<u64> {
+        self.sst_files_size
+    }
+
+    /// Estimated size in bytes of the live data.
+    #[inline]
+    pub fn live_data_size(&self) -> Option<u64> {
+        self.live_data_size
+    }
+
+    /// Size in bytes of the in-memory write buffers.
+    #[inline]
+    pub fn memtables_size(&self) -> Option<u64> {
+        self.memtables_size
+    }
+
+    /// Estimated number of bytes the pending compactions need to rewrite.
+    #[inline]
+    pub fn pending_compaction_size(&self) -> Option<u64> {
+        self.pending_compaction_size
+    }
+
+    /// Number of compactions currently running.
+    #[inline]
+    pub fn running_compactions(&self) -> Option<u64> {
+        self.running_compactions
+    }
+
+    /// Memory used in bytes by the block cache.
+    #[inline]
+    pub fn block_cache_usage(&self) -> Option<u64> {
+        self.block_cache_usage
+    }
+
+    /// Capacity in bytes of the block cache.
+    #[inline]
+    pub fn block_cache_capacity(&self) -> Option<u64> {
+        self.block_cache_capacity
+    }
+
+    /// Number of block cache hits since the store has been opened.
+    #[inline]
+    pub fn block_cache_hits(&self) -> Option<u64> {
+        self.block_cache_hits
+    }
+
+    /// Number of block cache misses since the store has been opened.
+    #[inline]
+    pub fn block_cache_misses(&self) -> Option<u64> {
+        self.block_cache_misses
+    }
+}
+
+/// Calls a hook with the success status when the wrapped iterator ends or is dropped
+pub(crate) struct EndHookIterator<I, F: FnOnce(bool)> {
+    inner: I,
+    success: bool,
+    on_end: Option<F>,
+}
+
+impl<I, F: FnOnce(bool)> EndHookIterator<I, F> {
+    pub(crate) fn new(inner: I, on_end: F) -> Self {
+        Self {
+            inner,
+            success: true,
+            on_end: Some(on_end),
+        }
+    }
+
+    fn end(&mut self) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end(self.success)
+        }
+    }
+}
+
+impl<T, E, I: Iterator<Item = Result<T, E>>, F: FnOnce(bool)> Iterator for EndHookIterator<I, F> {
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.inner.next();
+        match &result {
+            Some(Ok(_)) => (),
+            Some(Err(_)) => self.success = false,
+            None => self.end(),
+        }
+        result
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<I, F: FnOnce(bool)> Drop for EndHookIterator<I, F> {
+    fn drop(&mut self) {
+        self.end()
+    }
+}
@@ -5,6 +5,8 @@
 use crate::model::*;
 use crate::sparql::eval::Timer;
 use oxsdatatypes::DayTimeDuration;
+use spargebra::algebra::GraphTarget;
+use spargebra::term::{GraphNamePattern, GroundQuadPattern, QuadPattern};
 use spargebra::GraphUpdateOperation;
 use std::fmt;
 use std::str::FromStr;
@@ -142,6 +144,90 @@ impl Update {
     pub fn using_datasets_mut(&mut self) -> impl Iterator<Item = &mut QueryDataset> {
         self.using_datasets.iter_mut().filter_map(Option::as_mut)
     }
+
+    /// Returns the graphs this update might modify
+    /// or `None` if they are not statically known (e.g. `CLEAR ALL` or a `GRAPH ?g` template in a [DELETE/INSERT operation](https://www.w3.org/TR/sparql11-update/#deleteInsert)).
+    ///
+    /// ```
+    /// use oxigraph::model::{GraphName, NamedNode};
+    /// use oxigraph::sparql::Update;
+    ///
+    /// let update = Update::parse(
+    ///     "INSERT DATA { GRAPH <http://example.com/g> { <http://example.com/s> <http://example.com/p> <http://example.com/o> } }",
+    ///     None,
+    /// )?;
+    /// assert_eq!(
+    ///     update.modified_graphs(),
+    ///     Some(vec![NamedNode::new("http://example.com/g")?.into()])
+    /// );
+    /// assert_eq!(Update::parse("CLEAR ALL", None)?.modified_graphs(), None);
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn modified_graphs(&self) -> Option<Vec<GraphName>> {
+        fn add(graphs: &mut Vec<GraphName>, graph: GraphName) {
+            if !graphs.contains(&graph) {
+                graphs.push(graph);
+            }
+        }
+
+        fn from_algebra(graph_name: &spargebra::term::GraphName) -> GraphName {
+            match graph_name {
+                spargebra::term::GraphName::NamedNode(graph_name) => graph_name.clone().into(),
+                spargebra::term::GraphName::DefaultGraph => GraphName::DefaultGraph,
+            }
+        }
+
+        fn from_pattern(graph_name: &GraphNamePattern) -> Option<GraphName> {
+            match graph_name {
+                GraphNamePattern::NamedNode(graph_name) => Some(graph_name.clone().into()),
+                GraphNamePattern::DefaultGraph => Some(GraphName::DefaultGraph),
+                GraphNamePattern::Variable(_) => None,
+            }
+        }
+
+        fn from_target(target: &GraphTarget) -> Option<GraphName> {
+            match target {
+                GraphTarget::NamedNode(graph_name) => Some(graph_name.clone().into()),
+                GraphTarget::DefaultGraph => Some(GraphName::DefaultGraph),
+                GraphTarget::NamedGraphs | GraphTarget::AllGraphs => None,
+            }
+        }
+
+        let mut graphs = Vec::new();
+        for operation in &self.inner.operations {
+            match operation {
+                GraphUpdateOperation::InsertData { data } => {
+                    for quad in data {
+                        add(&mut graphs, from_algebra(&quad.graph_name));
+                    }
+                }
+                GraphUpdateOperation::DeleteData { data } => {
+                    for quad in data {
+                        add(&mut graphs, from_algebra(&quad.graph_name));
+                    }
+                }
+                GraphUpdateOperation::DeleteInsert { delete, insert, .. } => {
+                    for GroundQuadPattern { graph_name, .. } in delete {
+                        add(&mut graphs, from_pattern(graph_name)?);
+                    }
+                    for QuadPattern { graph_name, .. } in insert {
+                        add(&mut graphs, from_pattern(graph_name)?);
+                    }
+                }
+                GraphUpdateOperation::Load { destination, .. } => {
+                    add(&mut graphs, from_algebra(destination));
+                }
+                GraphUpdateOperation::Clear { graph, .. }
+                | GraphUpdateOperation::Drop { graph, .. } => {
+                    add(&mut graphs, from_target(graph)?);
+                }
+                GraphUpdateOperation::Create { graph, .. } => {
+                    add(&mut graphs, graph.clone().into());
+                }
+            }
+        }
+        Some(graphs)
+    }
 }
 
 impl fmt::Display for Update {
@@ -308,6 +394,53 @@ impl QueryDataset {
     pub fn set_available_named_graphs(&mut self, named_graphs: Vec<NamedOrBlankNode>) {
         self.named = Some(named_graphs);
     }
+
+    /// Restricts the dataset to the given store graphs.
+    ///
+    /// The graphs not in `allowed` are removed from the default graph and from the available named graphs.
+    /// If the default graph was the union of all graphs, it becomes the union of the allowed graphs.
+    /// ```
+    /// use oxigraph::model::{GraphName, NamedNode};
+    /// use oxigraph::sparql::Query;
+    ///
+    /// let mut query = Query::parse("SELECT ?s ?p ?o WHERE { ?s ?p ?o . }", None)?;
+    /// let allowed = NamedNode::new("http://example.com")?;
+    /// query
+    ///     .dataset_mut()
+    ///     .restrict_to_graphs(&[allowed.clone().into()]);
+    /// assert_eq!(query.dataset().default_graph_graphs(), Some([].as_slice()));
+    /// assert_eq!(
+    ///     query.dataset().available_named_graphs(),
+    ///     Some([allowed.into()].as_slice())
+    /// );
+    ///
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn restrict_to_graphs(&mut self, allowed: &[GraphName]) {
+        self.default = Some(if let Some(default) = self.default.take() {
+            default
+                .into_iter()
+                .filter(|g| allowed.contains(g))
+                .collect()
+        } else {
+            allowed.to_vec()
+        });
+        self.named = Some(if let Some(named) = self.named.take() {
+            named
+                .into_iter()
+                .filter(|g| allowed.contains(&g.clone().into()))
+                .collect()
+        } else {
+            allowed
+                .iter()
+                .filter_map(|g| match g {
+                    GraphName::NamedNode(g) => Some(g.clone().into()),
+                    GraphName::BlankNode(g) => Some(g.clone().into()),
+                    GraphName::DefaultGraph => None,
+                })
+                .collect()
+        });
+    }
 }
 
 #[cfg(test)]
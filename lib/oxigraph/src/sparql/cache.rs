@@ -0,0 +1,188 @@
+use crate::model::{Term, Triple};
+use crate::sparql::algebra::QueryDataset;
+use crate::sparql::error::EvaluationError;
+use crate::sparql::model::{QueryResults, QuerySolutionIter, QueryTripleIter};
+use crate::sparql::RowLimitPolicy;
+use oxrdf::Variable;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A cache of query results shared between query evaluations.
+///
+/// The results are identified by the store, the query, its dataset, the bindings and the row limit given in the [`QueryOptions`](super::QueryOptions)
+/// and the [`version`](crate::store::Store::version) of the store.
+/// Each committed change creates a new version so the cached results are never stale.
+/// The results of the previous versions of a store are evicted as soon as results for a newer version are cached.
+/// The same cache can be used with multiple stores.
+///
+/// The results are fully loaded in memory before being returned,
+/// hence the cache should only be used with queries returning small results.
+/// It should not be shared between evaluations with different custom functions or service handlers.
+///
+/// Cloning the cache is cheap, all the clones share the same entries.
+///
+/// ```
+/// use oxigraph::model::*;
+/// use oxigraph::sparql::{QueryOptions, QueryResults, QueryResultsCache};
+/// use oxigraph::store::Store;
+///
+/// let store = Store::new()?;
+/// let cache = QueryResultsCache::new(100);
+/// let count = |store: &Store| -> Result<usize, Box<dyn std::error::Error>> {
+///     let QueryResults::Solutions(solutions) = store.query_opt(
+///         "SELECT * WHERE { ?s ?p ?o }",
+///         QueryOptions::default().with_results_cache(cache.clone()),
+///     )?
+///     else {
+///         return Err("SELECT returns solutions".into());
+///     };
+///     Ok(solutions.count())
+/// };
+/// assert_eq!(count(&store)?, 0);
+/// let ex = NamedNodeRef::new("http://example.com")?;
+/// store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+/// // The cached results are not used anymore after a change
+/// assert_eq!(count(&store)?, 1);
+/// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+/// ```
+#[derive(Clone)]
+pub struct QueryResultsCache {
+    capacity: usize,
+    entries: Arc<Mutex<CacheEntries>>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    /// The version of the cached results of each store
+    versions: HashMap<u64, u64>,
+    results: HashMap<ResultsKey, CachedResults>,
+}
+
+#[derive(Eq, PartialEq, Hash)]
+pub(crate) struct ResultsKey {
+    /// The identifier of the store, shared by its clones
+    pub store: u64,
+    pub query: String,
+    pub dataset: QueryDataset,
+    pub bindings: Vec<(Variable, Term)>,
+    pub row_limit: Option<(usize, RowLimitPolicy)>,
+}
+
+#[derive(Clone)]
+enum CachedResults {
+    Solutions {
+        variables: Arc<[Variable]>,
+        solutions: Arc<[Vec<Option<Term>>]>,
+        truncated: bool,
+    },
+    Boolean(bool),
+    Graph {
+        triples: Arc<[Triple]>,
+        truncated: bool,
+    },
+}
+
+impl QueryResultsCache {
+    /// Builds a cache keeping the results of at most `capacity` queries.
+    #[inline]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Arc::default(),
+        }
+    }
+
+    /// Returns the cached results for the query or evaluates it with `evaluate` and caches its results
+    ///
+    /// The results are evaluated again if some errors are returned.
+    pub(crate) fn get_or_evaluate(
+        &self,
+        key: ResultsKey,
+        version: u64,
+        evaluate: impl FnOnce() -> Result<QueryResults, EvaluationError>,
+    ) -> Result<QueryResults, EvaluationError> {
+        if self.capacity == 0 {
+            return evaluate();
+        }
+        {
+            let entries = self.entries();
+            if entries.versions.get(&key.store) == Some(&version) {
+                if let Some(results) = entries.results.get(&key) {
+                    return Ok(results.clone().into());
+                }
+            }
+        }
+        let results = CachedResults::load(evaluate()?)?;
+        let mut entries = self.entries();
+        let current_version = *entries.versions.entry(key.store).or_insert(version);
+        if current_version < version {
+            // The results of the previous versions of the store can't be used anymore
+            entries.results.retain(|k, _| k.store != key.store);
+            entries.versions.insert(key.store, version);
+        }
+        if current_version <= version
+            && (entries.results.len() < self.capacity || entries.results.contains_key(&key))
+        {
+            entries.results.insert(key, results.clone());
+        }
+        Ok(results.into())
+    }
+
+    fn entries(&self) -> MutexGuard<'_, CacheEntries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CachedResults {
+    fn load(results: QueryResults) -> Result<Self, EvaluationError> {
+        Ok(match results {
+            QueryResults::Solutions(mut solutions) => {
+                let variables = Arc::from(solutions.variables());
+                let values = solutions
+                    .by_ref()
+                    .map(|solution| Ok(solution?.values().to_vec()))
+                    .collect::<Result<_, EvaluationError>>()?;
+                Self::Solutions {
+                    variables,
+                    solutions: values,
+                    truncated: solutions.is_truncated(),
+                }
+            }
+            QueryResults::Boolean(value) => Self::Boolean(value),
+            QueryResults::Graph(mut triples) => Self::Graph {
+                triples: triples.by_ref().collect::<Result<_, _>>()?,
+                truncated: triples.is_truncated(),
+            },
+        })
+    }
+}
+
+impl From<CachedResults> for QueryResults {
+    fn from(results: CachedResults) -> Self {
+        match results {
+            CachedResults::Solutions {
+                variables,
+                solutions,
+                truncated,
+            } => {
+                let solutions = QuerySolutionIter::new(
+                    variables,
+                    (0..solutions.len()).map(move |i| Ok(solutions[i].clone())),
+                );
+                if truncated {
+                    solutions.mark_truncated();
+                }
+                Self::Solutions(solutions)
+            }
+            CachedResults::Boolean(value) => Self::Boolean(value),
+            CachedResults::Graph { triples, truncated } => {
+                let triples =
+                    QueryTripleIter::new((0..triples.len()).map(move |i| Ok(triples[i].clone())));
+                if truncated {
+                    triples.mark_truncated();
+                }
+                Self::Graph(triples)
+            }
+        }
+    }
+}
@@ -151,6 +151,79 @@ impl DatasetView {
         }
     }
 
+    /// Returns disjoint iterators covering together the quads matching the pattern in the given graph.
+    ///
+    /// The iterators can be consumed from different threads.
+    /// Returns `None` if the graph is a union of multiple graphs of the store.
+    #[allow(clippy::type_complexity)]
+    pub fn encoded_quads_for_pattern_partitioned(
+        &self,
+        subject: Option<&EncodedTerm>,
+        predicate: Option<&EncodedTerm>,
+        object: Option<&EncodedTerm>,
+        graph_name: &EncodedTerm,
+        partitions: usize,
+        min_partitioned_size: usize,
+    ) -> Result<
+        Option<Vec<Box<dyn Iterator<Item = Result<EncodedQuad, EvaluationError>> + Send>>>,
+        EvaluationError,
+    > {
+        if graph_name.is_default_graph() {
+            let Some([default_graph]) = self.dataset.default.as_deref() else {
+                return Ok(None);
+            };
+            Ok(Some(
+                self.reader
+                    .quads_for_pattern_partitioned(
+                        subject,
+                        predicate,
+                        object,
+                        default_graph,
+                        partitions,
+                        min_partitioned_size,
+                    )?
+                    .into_iter()
+                    .map(|iter| {
+                        Box::new(iter.map(|quad| {
+                            let quad = quad?;
+                            Ok(EncodedQuad::new(
+                                quad.subject,
+                                quad.predicate,
+                                quad.object,
+                                EncodedTerm::DefaultGraph,
+                            ))
+                        })) as Box<dyn Iterator<Item = _> + Send>
+                    })
+                    .collect(),
+            ))
+        } else if self
+            .dataset
+            .named
+            .as_ref()
+            .map_or(true, |d| d.contains(graph_name))
+        {
+            Ok(Some(
+                self.reader
+                    .quads_for_pattern_partitioned(
+                        subject,
+                        predicate,
+                        object,
+                        graph_name,
+                        partitions,
+                        min_partitioned_size,
+                    )?
+                    .into_iter()
+                    .map(|iter| {
+                        Box::new(iter.map(|quad| quad.map_err(Into::into)))
+                            as Box<dyn Iterator<Item = _> + Send>
+                    })
+                    .collect(),
+            ))
+        } else {
+            Ok(Some(Vec::new()))
+        }
+    }
+
     pub fn encode_term<'a>(&self, term: impl Into<TermRef<'a>>) -> EncodedTerm {
         let term = term.into();
         let encoded = term.into();
@@ -1,7 +1,7 @@
 use crate::io::RdfParseError;
 use crate::model::NamedNode;
 use crate::sparql::results::QueryResultsParseError as ResultsParseError;
-use crate::sparql::SparqlSyntaxError;
+use crate::sparql::{SparqlSyntaxError, Variable};
 use crate::storage::StorageError;
 use std::convert::Infallible;
 use std::error::Error;
@@ -50,6 +50,24 @@ pub enum EvaluationError {
     /// The results are not a RDF graph
     #[error("The query results are not a RDF graph")]
     NotAGraph,
+    /// The value given as initial binding of the variable contains a blank node or the variable is assigned by a `BIND`
+    #[error("The variable {0} can't be bound: its value contains a blank node or it is assigned by a BIND")]
+    InvalidBinding(Variable),
+    /// No value has been given to a parameter of a prepared query
+    #[error("The parameter {0} is not bound")]
+    UnboundParameter(Variable),
+    /// Error returned by a property function
+    #[error("{0}")]
+    PropertyFunction(#[source] Box<dyn Error + Send + Sync + 'static>),
+    /// The content of a path search `SERVICE` call is invalid
+    #[error("Invalid path search: {0}")]
+    InvalidPathSearch(String),
+    /// The query returns more results than allowed by [`QueryOptions::with_max_result_rows`](super::QueryOptions::with_max_result_rows)
+    #[error("The query returns more than {0} results")]
+    ResultRowsLimitExceeded(usize),
+    /// A pattern of a leapfrog join has returned a solution without a value for one of its join variables
+    #[error("The join variable {0} is not bound by a pattern of a leapfrog join")]
+    UnboundJoinVariable(Variable),
 }
 
 impl From<Infallible> for EvaluationError {
@@ -68,17 +86,26 @@ impl From<EvaluationError> for io::Error {
             EvaluationError::ResultsParsing(error) => error.into(),
             EvaluationError::ResultsSerialization(error) => error,
             EvaluationError::Storage(error) => error.into(),
-            EvaluationError::Service(error) => match error.downcast() {
-                Ok(error) => *error,
-                Err(error) => Self::other(error),
-            },
+            EvaluationError::Service(error) | EvaluationError::PropertyFunction(error) => {
+                match error.downcast() {
+                    Ok(error) => *error,
+                    Err(error) => Self::other(error),
+                }
+            }
             EvaluationError::GraphAlreadyExists(_)
             | EvaluationError::GraphDoesNotExist(_)
             | EvaluationError::UnboundService
             | EvaluationError::UnsupportedService(_)
             | EvaluationError::UnsupportedContentType(_)
             | EvaluationError::ServiceDoesNotReturnSolutions
-            | EvaluationError::NotAGraph => Self::new(io::ErrorKind::InvalidInput, error),
+            | EvaluationError::NotAGraph
+            | EvaluationError::InvalidBinding(_)
+            | EvaluationError::UnboundParameter(_)
+            | EvaluationError::InvalidPathSearch(_)
+            | EvaluationError::ResultRowsLimitExceeded(_) => {
+                Self::new(io::ErrorKind::InvalidInput, error)
+            }
+            EvaluationError::UnboundJoinVariable(_) => Self::other(error),
         }
     }
 }
@@ -4,10 +4,17 @@ use crate::sparql::algebra::{Query, QueryDataset};
 use crate::sparql::dataset::DatasetView;
 use crate::sparql::error::EvaluationError;
 use crate::sparql::model::*;
+use crate::sparql::parallel::{
+    idle_workers, map_in_parallel, partition_of, ParallelIterator, CHUNK_SIZE, MIN_PARALLEL_SIZE,
+};
+use crate::sparql::path::{
+    shortest_paths, FoundPath, PathSearch, PathSearchMode, SimplePathsIterator,
+};
 use crate::sparql::service::ServiceHandler;
-use crate::sparql::CustomFunctionRegistry;
+use crate::sparql::{CustomFunctionRegistry, PropertyFunctionEvaluator, PropertyFunctionRegistry};
 use crate::storage::numeric_encoder::*;
 use crate::storage::small_string::SmallString;
+use crate::storage::StorageError;
 use digest::Digest;
 use json_event_parser::{JsonEvent, ToWriteJsonWriter};
 use md5::Md5;
@@ -30,11 +37,15 @@ use sparopt::algebra::{
     MinusAlgorithm, OrderExpression,
 };
 use std::cell::Cell;
-use std::cmp::Ordering;
-use std::collections::hash_map::DefaultHasher;
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::VecDeque;
 use std::hash::{Hash, Hasher};
-use std::iter::{empty, once};
+use std::iter::{empty, once, Fuse};
+use std::mem::take;
+use std::ops::Range;
 use std::rc::Rc;
+use std::sync::mpsc::sync_channel;
 use std::sync::Arc;
 use std::{fmt, io, str};
 
@@ -127,7 +138,11 @@ pub struct SimpleEvaluator {
     base_iri: Option<Rc<Iri<String>>>,
     now: DateTime,
     service_handler: Arc<dyn ServiceHandler<Error = EvaluationError>>,
+    /// The size of the bind join batches, the bind joins are disabled if not set
+    service_batch_size: Option<usize>,
+    parallelism: usize,
     custom_functions: Arc<CustomFunctionRegistry>,
+    property_functions: Arc<PropertyFunctionRegistry>,
     run_stats: bool,
 }
 
@@ -136,7 +151,10 @@ impl SimpleEvaluator {
         dataset: Rc<DatasetView>,
         base_iri: Option<Rc<Iri<String>>>,
         service_handler: Arc<dyn ServiceHandler<Error = EvaluationError>>,
+        service_batch_size: Option<usize>,
+        parallelism: usize,
         custom_functions: Arc<CustomFunctionRegistry>,
+        property_functions: Arc<PropertyFunctionRegistry>,
         run_stats: bool,
     ) -> Self {
         Self {
@@ -144,7 +162,10 @@ impl SimpleEvaluator {
             base_iri,
             now: DateTime::now(),
             service_handler,
+            service_batch_size,
+            parallelism,
             custom_functions,
+            property_functions,
             run_stats,
         }
     }
@@ -207,15 +228,13 @@ impl SimpleEvaluator {
             .collect();
         let from = EncodedTuple::with_capacity(variables.len());
         (
-            QueryResults::Graph(QueryTripleIter {
-                iter: Box::new(ConstructIterator {
-                    eval: self.clone(),
-                    iter: eval(from),
-                    template,
-                    buffered_results: Vec::default(),
-                    bnodes: Vec::default(),
-                }),
-            }),
+            QueryResults::Graph(QueryTripleIter::new(ConstructIterator {
+                eval: self.clone(),
+                iter: eval(from),
+                template,
+                buffered_results: Vec::default(),
+                bnodes: Vec::default(),
+            })),
             stats,
         )
     }
@@ -228,15 +247,13 @@ impl SimpleEvaluator {
         let (eval, stats) = self.graph_pattern_evaluator(pattern, &mut variables);
         let from = EncodedTuple::with_capacity(variables.len());
         (
-            QueryResults::Graph(QueryTripleIter {
-                iter: Box::new(DescribeIterator {
-                    eval: self.clone(),
-                    tuples_to_describe: eval(from),
-                    nodes_described: FxHashSet::default(),
-                    nodes_to_describe: Vec::default(),
-                    quads: Box::new(empty()),
-                }),
-            }),
+            QueryResults::Graph(QueryTripleIter::new(DescribeIterator {
+                eval: self.clone(),
+                tuples_to_describe: eval(from),
+                nodes_described: FxHashSet::default(),
+                nodes_to_describe: Vec::default(),
+                quads: Box::new(empty()),
+            })),
             stats,
         )
     }
@@ -253,7 +270,7 @@ impl SimpleEvaluator {
         let mut evaluator =
             self.build_graph_pattern_evaluator(pattern, encoded_variables, &mut stat_children);
         let stats = Rc::new(EvalNodeWithStats {
-            label: eval_node_label(pattern),
+            label: eval_node_label(pattern, self.service_batch_size.is_some()),
             children: stat_children,
             exec_count: Cell::new(0),
             exec_duration: Cell::new(self.run_stats.then(DayTimeDuration::default)),
@@ -278,6 +295,190 @@ impl SimpleEvaluator {
         (evaluator, stats)
     }
 
+    /// Calls the property function with the subject and object values of each input tuple
+    fn property_function_evaluator(
+        &self,
+        subject: &GroundTermPattern,
+        function: Arc<PropertyFunctionEvaluator>,
+        object: &GroundTermPattern,
+        encoded_variables: &mut Vec<Variable>,
+    ) -> Rc<dyn Fn(EncodedTuple) -> EncodedTuplesIterator> {
+        let subject =
+            TupleSelector::from_ground_term_pattern(subject, encoded_variables, &self.dataset);
+        let object =
+            TupleSelector::from_ground_term_pattern(object, encoded_variables, &self.dataset);
+        let dataset = Rc::clone(&self.dataset);
+        Rc::new(move |from| {
+            let decode = |selector: &TupleSelector| {
+                selector
+                    .get_pattern_value(&from)
+                    .map(|value| dataset.decode_term(&value))
+                    .transpose()
+            };
+            let (subject_value, object_value) = match (decode(&subject), decode(&object)) {
+                (Ok(subject_value), Ok(object_value)) => (subject_value, object_value),
+                (Err(error), _) | (_, Err(error)) => return Box::new(once(Err(error.into()))),
+            };
+            let pairs = function(subject_value.as_ref(), object_value.as_ref());
+            let subject = subject.clone();
+            let object = object.clone();
+            let dataset = Rc::clone(&dataset);
+            Box::new(pairs.filter_map(move |pair| match pair {
+                Ok((subject_value, object_value)) => {
+                    let mut new_tuple = from.clone();
+                    put_pattern_value(
+                        &subject,
+                        dataset.encode_term(&subject_value),
+                        &mut new_tuple,
+                    )?;
+                    put_pattern_value(&object, dataset.encode_term(&object_value), &mut new_tuple)?;
+                    Some(Ok(new_tuple))
+                }
+                Err(error) => Some(Err(error)),
+            }))
+        })
+    }
+
+    fn path_search_evaluator(
+        &self,
+        search: Result<PathSearch, String>,
+        silent: bool,
+        encoded_variables: &mut Vec<Variable>,
+    ) -> Rc<dyn Fn(EncodedTuple) -> EncodedTuplesIterator> {
+        let search = match search {
+            Ok(search) => search,
+            Err(error) => {
+                return Rc::new(move |from| {
+                    if silent {
+                        Box::new(once(Ok(from)))
+                    } else {
+                        Box::new(once(Err(EvaluationError::InvalidPathSearch(error.clone()))))
+                    }
+                })
+            }
+        };
+        let start = TupleSelector::from_ground_term_pattern(
+            &search.subject,
+            encoded_variables,
+            &self.dataset,
+        );
+        let end = TupleSelector::from_ground_term_pattern(
+            &search.object,
+            encoded_variables,
+            &self.dataset,
+        );
+        let graph_name = search.graph_name.as_ref().map(|graph_name| {
+            TupleSelector::from_named_node_pattern(graph_name, encoded_variables, &self.dataset)
+        });
+        let mut encode = |variable: Option<&Variable>| {
+            variable.map(|variable| encode_variable(encoded_variables, variable))
+        };
+        let index = encode(search.index.as_ref());
+        let length = encode(search.length.as_ref());
+        let position = encode(search.position.as_ref());
+        let node = encode(search.node.as_ref());
+        let edge = encode(search.edge.as_ref());
+        let step = self.encode_property_path(&search.step);
+        let path_eval = PathEvaluator {
+            dataset: Rc::clone(&self.dataset),
+        };
+        let (mode, min_length, max_length) = (search.mode, search.min_length, search.max_length);
+        Rc::new(move |from| {
+            let Some(start_value) = start.get_pattern_value(&from) else {
+                return Box::new(once(Err(EvaluationError::InvalidPathSearch(
+                    "the path start should be bound".into(),
+                ))));
+            };
+            let end_value = end.get_pattern_value(&from);
+            let graph_name_value = match &graph_name {
+                Some(graph_name) => {
+                    let Some(graph_name) = graph_name.get_pattern_value(&from) else {
+                        return Box::new(once(Err(EvaluationError::InvalidPathSearch(
+                            "the path graph should be bound".into(),
+                        ))));
+                    };
+                    graph_name
+                }
+                None => EncodedTerm::DefaultGraph,
+            };
+            let steps = {
+                let path_eval = path_eval.clone();
+                let step = Rc::clone(&step);
+                move |node: &EncodedTerm| {
+                    path_eval.eval_edges_from_in_graph(&step, node, &graph_name_value)
+                }
+            };
+            let paths: Box<dyn Iterator<Item = Result<FoundPath, EvaluationError>>> = match mode {
+                PathSearchMode::Shortest => match shortest_paths(
+                    steps,
+                    &start_value,
+                    end_value.as_ref(),
+                    min_length,
+                    max_length,
+                ) {
+                    Ok(paths) => Box::new(paths.into_iter().map(Ok)),
+                    Err(e) => return Box::new(once(Err(e))),
+                },
+                PathSearchMode::All => Box::new(SimplePathsIterator::new(
+                    steps,
+                    start_value,
+                    end_value,
+                    min_length,
+                    max_length.unwrap_or(0),
+                )),
+            };
+            let start = start.clone();
+            let end = end.clone();
+            let one_row_per_node = position.is_some() || node.is_some() || edge.is_some();
+            Box::new(paths.enumerate().flat_map(move |(i, path)| {
+                let path = match path {
+                    Ok(path) => path,
+                    Err(e) => return vec![Err(e)],
+                };
+                let mut tuple = from.clone();
+                let (Some(first), Some(last)) = (path.nodes.first(), path.nodes.last()) else {
+                    return Vec::new();
+                };
+                if put_pattern_value(&start, first.clone(), &mut tuple).is_none()
+                    || put_pattern_value(&end, last.clone(), &mut tuple).is_none()
+                {
+                    return Vec::new();
+                }
+                let integer = |value: usize| -> EncodedTerm {
+                    i64::try_from(value).unwrap_or(i64::MAX).into()
+                };
+                if let Some(index) = index {
+                    tuple.set(index, integer(i));
+                }
+                if let Some(length) = length {
+                    tuple.set(length, integer(path.edges.len()));
+                }
+                if !one_row_per_node {
+                    return vec![Ok(tuple)];
+                }
+                path.nodes
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        let mut tuple = tuple.clone();
+                        if let Some(position) = position {
+                            tuple.set(position, integer(i));
+                        }
+                        if let Some(node) = node {
+                            tuple.set(node, value);
+                        }
+                        if let (Some(edge), Some(Some(value))) =
+                            (edge, i.checked_sub(1).and_then(|i| path.edges.get(i)))
+                        {
+                            tuple.set(edge, value.clone());
+                        }
+                        Ok(tuple)
+                    })
+                    .collect()
+            }))
+        })
+    }
+
     fn build_graph_pattern_evaluator(
         &self,
         pattern: &GraphPattern,
@@ -329,6 +530,11 @@ impl SimpleEvaluator {
             } => {
                 #[allow(clippy::shadow_same)]
                 let silent = *silent;
+                if let NamedNodePattern::NamedNode(name) = name {
+                    if let Some(search) = PathSearch::parse(name, inner) {
+                        return self.path_search_evaluator(search, silent, encoded_variables);
+                    }
+                }
                 let service_name =
                     TupleSelector::from_named_node_pattern(name, encoded_variables, &self.dataset);
                 self.build_graph_pattern_evaluator(inner, encoded_variables, &mut Vec::new()); // We call recursively to fill "encoded_variables"
@@ -363,6 +569,17 @@ impl SimpleEvaluator {
                 object,
                 graph_name,
             } => {
+                if let NamedNodePattern::NamedNode(name) = predicate {
+                    // The query has been planned without knowing the property function
+                    if let Some((_, function)) = self.property_functions.get(name) {
+                        return self.property_function_evaluator(
+                            subject,
+                            Arc::clone(function),
+                            object,
+                            encoded_variables,
+                        );
+                    }
+                }
                 let subject = TupleSelector::from_ground_term_pattern(
                     subject,
                     encoded_variables,
@@ -384,13 +601,35 @@ impl SimpleEvaluator {
                     &self.dataset,
                 );
                 let dataset = Rc::clone(&self.dataset);
+                let parallelism = self.parallelism;
+                // A pattern evaluated again is on the right side of a for-loop join, only its first scan is parallelized
+                let is_first_evaluation = Cell::new(true);
                 Rc::new(move |from| {
-                    let iter = dataset.encoded_quads_for_pattern(
-                        subject.get_pattern_value(&from).as_ref(),
-                        predicate.get_pattern_value(&from).as_ref(),
-                        object.get_pattern_value(&from).as_ref(),
-                        graph_name.get_pattern_value(&from).as_ref(),
-                    );
+                    let subject_value = subject.get_pattern_value(&from);
+                    let predicate_value = predicate.get_pattern_value(&from);
+                    let object_value = object.get_pattern_value(&from);
+                    let graph_name_value = graph_name.get_pattern_value(&from);
+                    let iter = match parallel_quads_for_pattern(
+                        &dataset,
+                        if is_first_evaluation.replace(false) {
+                            parallelism
+                        } else {
+                            1
+                        },
+                        subject_value.as_ref(),
+                        predicate_value.as_ref(),
+                        object_value.as_ref(),
+                        graph_name_value.as_ref(),
+                    ) {
+                        Ok(Some(iter)) => iter,
+                        Ok(None) => dataset.encoded_quads_for_pattern(
+                            subject_value.as_ref(),
+                            predicate_value.as_ref(),
+                            object_value.as_ref(),
+                            graph_name_value.as_ref(),
+                        ),
+                        Err(error) => return Box::new(once(Err(error))),
+                    };
                     let subject = subject.clone();
                     let predicate = predicate.clone();
                     let object = object.clone();
@@ -408,6 +647,27 @@ impl SimpleEvaluator {
                     }))
                 })
             }
+            GraphPattern::PropertyFunction {
+                subject,
+                name,
+                object,
+                ..
+            } => {
+                let Some((_, function)) = self.property_functions.get(name) else {
+                    let name = name.clone();
+                    return Rc::new(move |_| {
+                        Box::new(once(Err(EvaluationError::PropertyFunction(
+                            format!("The property function {name} is not defined").into(),
+                        ))))
+                    });
+                };
+                self.property_function_evaluator(
+                    subject,
+                    Arc::clone(function),
+                    object,
+                    encoded_variables,
+                )
+            }
             GraphPattern::Path {
                 subject,
                 path,
@@ -578,6 +838,23 @@ impl SimpleEvaluator {
                 right,
                 algorithm,
             } => {
+                if let (Some(batch_size), Some((input, name, inner, silent, keys))) = (
+                    self.service_batch_size,
+                    bind_join_operands(left, right, algorithm),
+                ) {
+                    let (input, input_stats) =
+                        self.graph_pattern_evaluator(input, encoded_variables);
+                    stat_children.push(input_stats);
+                    return self.bind_join_evaluator(
+                        input,
+                        name,
+                        inner,
+                        silent,
+                        keys,
+                        batch_size,
+                        encoded_variables,
+                    );
+                }
                 let (left, left_stats) = self.graph_pattern_evaluator(left, encoded_variables);
                 stat_children.push(left_stats);
                 let (right, right_stats) = self.graph_pattern_evaluator(right, encoded_variables);
@@ -612,6 +889,31 @@ impl SimpleEvaluator {
                                 .iter()
                                 .map(|v| encode_variable(encoded_variables, v))
                                 .collect::<Vec<_>>();
+                            let parallelism = self.parallelism;
+                            if parallelism > 1 {
+                                return Rc::new(move |from| {
+                                    let mut errors = VecDeque::default();
+                                    let built_values = build(from.clone())
+                                        .filter_map(|result| match result {
+                                            Ok(result) => Some(result),
+                                            Err(error) => {
+                                                errors.push_back(Err(error));
+                                                None
+                                            }
+                                        })
+                                        .collect::<Vec<_>>();
+                                    Box::new(ParallelHashJoinIterator {
+                                        probe_iter: probe(from),
+                                        built: Arc::new(PartitionedTupleSet::build(
+                                            keys.clone(),
+                                            built_values,
+                                            parallelism,
+                                        )),
+                                        parallelism,
+                                        buffered_results: errors,
+                                    })
+                                });
+                            }
                             Rc::new(move |from| {
                                 let mut errors = Vec::default();
                                 let mut built_values = EncodedTupleSet::new(keys.clone());
@@ -634,6 +936,66 @@ impl SimpleEvaluator {
                     }
                 }
             }
+            GraphPattern::LeapfrogJoin { inner, variables } => {
+                let mut relations = Vec::with_capacity(inner.len());
+                let mut all_columns = Vec::new();
+                for inner in inner {
+                    let mut inner_variables = Vec::new();
+                    inner.lookup_used_variables(&mut |v| inner_variables.push(v.clone()));
+                    let columns = inner_variables
+                        .iter()
+                        .map(|v| encode_variable(encoded_variables, v))
+                        .collect::<Vec<_>>();
+                    all_columns.extend(columns.iter().copied());
+                    // The filters pushed in the pattern can only be evaluated once all its variables are bound
+                    let mut scan = inner;
+                    while let GraphPattern::Filter { inner, .. } = scan {
+                        scan = inner;
+                    }
+                    let (scan, _) = self.graph_pattern_evaluator(scan, encoded_variables);
+                    let (eval, stats) = self.graph_pattern_evaluator(inner, encoded_variables);
+                    stat_children.push(stats);
+                    relations.push(LeapfrogRelation {
+                        columns,
+                        scan,
+                        eval,
+                    });
+                }
+                let variables = variables
+                    .iter()
+                    .filter_map(|v| {
+                        let id = encode_variable(encoded_variables, v);
+                        all_columns.contains(&id).then(|| (id, v.clone()))
+                    })
+                    .collect::<Vec<_>>();
+                // For each variable, the relations using it, the most selective one first:
+                // it is the one with the most variables already bound when the variable is reached.
+                // The order is only a tie break, the relation with the fewest matches is found at runtime.
+                let participants = variables
+                    .iter()
+                    .enumerate()
+                    .map(|(depth, (variable, _))| {
+                        let mut participants = (0..relations.len())
+                            .filter(|i| relations[*i].columns.contains(variable))
+                            .collect::<Vec<_>>();
+                        participants.sort_by_key(|i| {
+                            let columns = &relations[*i].columns;
+                            let bound = variables[..depth]
+                                .iter()
+                                .filter(|(v, _)| columns.contains(v))
+                                .count();
+                            (Reverse(bound), columns.len())
+                        });
+                        participants
+                    })
+                    .collect::<Vec<_>>();
+                let join = Rc::new(LeapfrogJoin {
+                    variables,
+                    participants,
+                    relations,
+                });
+                Rc::new(move |from| Box::new(LeapfrogJoinIterator::new(Rc::clone(&join), from)))
+            }
             GraphPattern::Lateral { left, right } => {
                 let (left, left_stats) = self.graph_pattern_evaluator(left, encoded_variables);
                 stat_children.push(left_stats);
@@ -844,44 +1206,102 @@ impl SimpleEvaluator {
                 let dataset = Rc::clone(&self.dataset);
                 Rc::new(move |from| {
                     let mut errors = Vec::default();
-                    let mut values = child(from)
-                        .filter_map(|result| match result {
-                            Ok(result) => Some(result),
-                            Err(error) => {
-                                errors.push(Err(error));
-                                None
+                    let mut values = Vec::new();
+                    // The sort is skipped if the solutions already come in order,
+                    // e.g. from an index scan on a key whose encoding preserves the order like non-negative integers
+                    let mut sorted = true;
+                    for result in child(from) {
+                        match result {
+                            Ok(result) => {
+                                if sorted
+                                    && values.last().is_some_and(|previous| {
+                                        cmp_solutions(&dataset, &by, previous, &result).is_gt()
+                                    })
+                                {
+                                    sorted = false;
+                                }
+                                values.push(result);
                             }
-                        })
-                        .collect::<Vec<_>>();
-                    values.sort_unstable_by(|a, b| {
-                        for comp in &by {
-                            match comp {
-                                ComparatorFunction::Asc(expression) => {
-                                    match cmp_terms(
-                                        &dataset,
-                                        expression(a).as_ref(),
-                                        expression(b).as_ref(),
-                                    ) {
-                                        Ordering::Greater => return Ordering::Greater,
-                                        Ordering::Less => return Ordering::Less,
-                                        Ordering::Equal => (),
+                            Err(error) => errors.push(Err(error)),
+                        }
+                    }
+                    if !sorted {
+                        values.sort_unstable_by(|a, b| cmp_solutions(&dataset, &by, a, b));
+                    }
+                    Box::new(errors.into_iter().chain(values.into_iter().map(Ok)))
+                })
+            }
+            GraphPattern::TopK {
+                inner,
+                expression,
+                limit,
+            } => {
+                let (child, child_stats) = self.graph_pattern_evaluator(inner, encoded_variables);
+                stat_children.push(child_stats);
+                let by = expression
+                    .iter()
+                    .map(|comp| match comp {
+                        OrderExpression::Asc(expression) => ComparatorFunction::Asc(
+                            self.expression_evaluator(expression, encoded_variables, stat_children),
+                        ),
+                        OrderExpression::Desc(expression) => ComparatorFunction::Desc(
+                            self.expression_evaluator(expression, encoded_variables, stat_children),
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+                let dataset = Rc::clone(&self.dataset);
+                let limit = *limit;
+                Rc::new(move |from| {
+                    let Some(last) = limit.checked_sub(1) else {
+                        return Box::new(empty());
+                    };
+                    let compare =
+                        |a: &EncodedTuple, b: &EncodedTuple| cmp_solutions(&dataset, &by, a, b);
+                    let mut errors = Vec::default();
+                    let mut values = Vec::new();
+                    // While the solutions come in order only the `limit` first ones are kept,
+                    // the following ones are only compared with the last dropped one to check the order.
+                    // They can't be streamed out early: the rest of an index scan might still contain lower values
+                    // because of the other term types or the negative numbers.
+                    let mut sorted = true;
+                    let mut dropped = None;
+                    for result in child(from) {
+                        match result {
+                            Ok(result) => {
+                                if sorted {
+                                    if dropped
+                                        .as_ref()
+                                        .or(values.last())
+                                        .map_or(true, |previous| compare(previous, &result).is_le())
+                                    {
+                                        if values.len() < limit {
+                                            values.push(result);
+                                        } else {
+                                            dropped = Some(result);
+                                        }
+                                        continue;
                                     }
+                                    // The dropped solutions are not lower than the `limit` kept ones
+                                    sorted = false;
+                                    dropped = None;
                                 }
-                                ComparatorFunction::Desc(expression) => {
-                                    match cmp_terms(
-                                        &dataset,
-                                        expression(a).as_ref(),
-                                        expression(b).as_ref(),
-                                    ) {
-                                        Ordering::Greater => return Ordering::Less,
-                                        Ordering::Less => return Ordering::Greater,
-                                        Ordering::Equal => (),
-                                    }
+                                values.push(result);
+                                // We only keep the `limit` first solutions once the buffer is full
+                                if values.len() >= limit.saturating_mul(2) {
+                                    values.select_nth_unstable_by(last, compare);
+                                    values.truncate(limit);
                                 }
                             }
+                            Err(error) => errors.push(Err(error)),
                         }
-                        Ordering::Equal
-                    });
+                    }
+                    if !sorted {
+                        if values.len() > limit {
+                            values.select_nth_unstable_by(last, compare);
+                            values.truncate(limit);
+                        }
+                        values.sort_unstable_by(compare);
+                    }
                     Box::new(errors.into_iter().chain(values.into_iter().map(Ok)))
                 })
             }
@@ -989,9 +1409,50 @@ impl SimpleEvaluator {
                     .iter()
                     .map(|(variable, _)| encode_variable(encoded_variables, variable))
                     .collect::<Vec<_>>();
+                let mergeable_accumulators = if self.parallelism > 1 {
+                    aggregates
+                        .iter()
+                        .map(|(_, aggregate)| MergeableAccumulator::new(aggregate))
+                        .collect::<Option<Vec<_>>>()
+                } else {
+                    None
+                };
+                let parallelism = self.parallelism;
                 Rc::new(move |from| {
                     let tuple_size = from.capacity();
                     let key_variables = Rc::clone(&key_variables);
+                    if let Some(mergeable_accumulators) = &mergeable_accumulators {
+                        let (errors, mut groups) = aggregate_in_parallel(
+                            child(from),
+                            &key_variables,
+                            &aggregate_input_expressions,
+                            mergeable_accumulators,
+                            parallelism,
+                        );
+                        if key_variables.is_empty() && groups.is_empty() {
+                            // There is always a single group if there is no GROUP BY
+                            groups.insert(Vec::new(), mergeable_accumulators.clone());
+                        }
+                        let accumulator_variables = accumulator_variables.clone();
+                        return Box::new(errors.into_iter().map(Err).chain(
+                            groups.into_iter().map(move |(key, accumulators)| {
+                                let mut result = EncodedTuple::with_capacity(tuple_size);
+                                for (variable, value) in key_variables.iter().zip(key) {
+                                    if let Some(value) = value {
+                                        result.set(*variable, value);
+                                    }
+                                }
+                                for (accumulator, variable) in
+                                    accumulators.iter().zip(&accumulator_variables)
+                                {
+                                    if let Some(value) = accumulator.state() {
+                                        result.set(*variable, value);
+                                    }
+                                }
+                                Ok(result)
+                            }),
+                        ));
+                    }
                     let mut errors = Vec::default();
                     let mut accumulators_for_group =
                         FxHashMap::<Vec<Option<EncodedTerm>>, Vec<Box<dyn Accumulator>>>::default();
@@ -1090,6 +1551,150 @@ impl SimpleEvaluator {
         }
     }
 
+    /// Evaluates the input pattern then sends its solutions by blocks to the service
+    fn bind_join_evaluator(
+        &self,
+        input: Rc<dyn Fn(EncodedTuple) -> EncodedTuplesIterator>,
+        name: &NamedNodePattern,
+        inner: &GraphPattern,
+        silent: bool,
+        keys: &[Variable],
+        batch_size: usize,
+        encoded_variables: &mut Vec<Variable>,
+    ) -> Rc<dyn Fn(EncodedTuple) -> EncodedTuplesIterator> {
+        let service_name =
+            TupleSelector::from_named_node_pattern(name, encoded_variables, &self.dataset);
+        self.build_graph_pattern_evaluator(inner, encoded_variables, &mut Vec::new()); // We call recursively to fill "encoded_variables"
+        let graph_pattern = Rc::new(spargebra::algebra::GraphPattern::from(inner));
+        // Only the join keys are sent: they are bound in all tuples
+        // while sending an optional variable would return the same service solution once per value of it
+        let keys = keys
+            .iter()
+            .map(|v| (v.clone(), encode_variable(encoded_variables, v)))
+            .collect::<Rc<[_]>>();
+        let variables = Rc::from(encoded_variables.as_slice());
+        let eval = self.clone();
+        Rc::new(move |from| {
+            Box::new(BindJoinIterator {
+                eval: eval.clone(),
+                input: input(from).fuse(),
+                service_name: service_name.clone(),
+                graph_pattern: Rc::clone(&graph_pattern),
+                keys: Rc::clone(&keys),
+                variables: Rc::clone(&variables),
+                silent,
+                batch_size,
+                batch: Vec::new(),
+                current: Box::new(empty()),
+            })
+        })
+    }
+
+    /// Sends a block of tuples to their services and joins back the results
+    fn evaluate_service_batch(
+        &self,
+        service_name: &TupleSelector,
+        graph_pattern: &spargebra::algebra::GraphPattern,
+        keys: &[(Variable, usize)],
+        variables: &Rc<[Variable]>,
+        silent: bool,
+        batch: Vec<EncodedTuple>,
+    ) -> EncodedTuplesIterator {
+        // We group the tuples by service name, there is usually a single one
+        let mut groups = Vec::<(EncodedTerm, Vec<EncodedTuple>)>::new();
+        let mut results = Vec::<EncodedTuplesIterator>::new();
+        for tuple in batch {
+            let Some(name) = service_name.get_pattern_value(&tuple) else {
+                results.push(if silent {
+                    Box::new(once(Ok(tuple)))
+                } else {
+                    Box::new(once(Err(EvaluationError::UnboundService)))
+                });
+                continue;
+            };
+            if let Some((_, tuples)) = groups.iter_mut().find(|(n, _)| *n == name) {
+                tuples.push(tuple);
+            } else {
+                groups.push((name, vec![tuple]));
+            }
+        }
+        for (name, tuples) in groups {
+            results.push(
+                match self.call_service_batch(
+                    &name,
+                    graph_pattern,
+                    keys,
+                    Rc::clone(variables),
+                    &tuples,
+                ) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        if silent {
+                            Box::new(tuples.into_iter().map(Ok))
+                        } else {
+                            Box::new(once(Err(e)))
+                        }
+                    }
+                },
+            );
+        }
+        Box::new(results.into_iter().flatten())
+    }
+
+    fn call_service_batch(
+        &self,
+        service_name: &EncodedTerm,
+        graph_pattern: &spargebra::algebra::GraphPattern,
+        keys: &[(Variable, usize)],
+        variables: Rc<[Variable]>,
+        tuples: &[EncodedTuple],
+    ) -> Result<EncodedTuplesIterator, EvaluationError> {
+        let bindings_variables = keys.iter().map(|(v, _)| v.clone()).collect::<Arc<[_]>>();
+        let mut seen = FxHashSet::default();
+        let mut bindings = Vec::new();
+        for tuple in tuples {
+            let values = keys
+                .iter()
+                .map(|(_, i)| tuple.get(*i).cloned())
+                .collect::<Vec<_>>();
+            if seen.contains(&values) {
+                continue;
+            }
+            bindings.push(QuerySolution::from((
+                Arc::clone(&bindings_variables),
+                values
+                    .iter()
+                    .map(|v| v.as_ref().map(|v| self.dataset.decode_term(v)).transpose())
+                    .collect::<Result<Vec<_>, _>>()?,
+            )));
+            seen.insert(values);
+        }
+        let QueryResults::Solutions(iter) = self.service_handler.handle_batch(
+            self.dataset.decode_named_node(service_name)?,
+            Query {
+                inner: spargebra::Query::Select {
+                    dataset: None,
+                    pattern: graph_pattern.clone(),
+                    #[allow(clippy::useless_asref)]
+                    base_iri: self.base_iri.as_ref().map(|iri| iri.as_ref().clone()),
+                },
+                dataset: QueryDataset::new(),
+                parsing_duration: None,
+            },
+            bindings,
+        )?
+        else {
+            return Err(EvaluationError::ServiceDoesNotReturnSolutions);
+        };
+        let mut built = EncodedTupleSet::new(keys.iter().map(|(_, i)| *i).collect());
+        built.extend(tuples.iter().cloned());
+        Ok(Box::new(HashJoinIterator {
+            probe_iter: encode_bindings(Rc::clone(&self.dataset), variables, iter),
+            built,
+            buffered_results: Vec::new(),
+        }))
+    }
+
     #[allow(clippy::redundant_closure)] // False positive in 1.60
     fn accumulator_builder(
         dataset: &Rc<DatasetView>,
@@ -1472,6 +2077,9 @@ impl SimpleEvaluator {
                             | EncodedTerm::BigBigLangStringLiteral { language_id, .. } => {
                                 Some(build_string_literal_from_id(language_id.into()))
                             }
+                            EncodedTerm::MediumLangStringLiteral(value) => Some(
+                                build_string_literal(&dataset, split_medium_lang_string(&value).1),
+                            ),
                             e if e.is_literal() => Some(build_string_literal(&dataset, "")),
                             _ => None,
                         })
@@ -2238,6 +2846,7 @@ impl SimpleEvaluator {
                         let dataset = Rc::clone(&self.dataset);
                         Rc::new(move |tuple| {
                             Some(build_lang_string_literal_from_id(
+                                &dataset,
                                 to_simple_string_id(&lexical_form(tuple)?)?,
                                 build_language_id(&dataset, &lang_tag(tuple)?)?,
                             ))
@@ -2257,15 +2866,14 @@ impl SimpleEvaluator {
                         let dataset = Rc::clone(&self.dataset);
                         Rc::new(move |tuple| {
                             let value = to_simple_string(&dataset, &lexical_form(tuple)?)?;
-                            let datatype =
-                                if let EncodedTerm::NamedNode { iri_id } = datatype(tuple)? {
-                                    dataset.get_str(&iri_id).ok()?
-                                } else {
-                                    None
-                                }?;
+                            let datatype = datatype(tuple)?;
+                            if !datatype.is_named_node() {
+                                return None;
+                            }
+                            let datatype = dataset.decode_named_node(&datatype).ok()?;
                             Some(dataset.encode_term(LiteralRef::new_typed_literal(
                                 &value,
-                                NamedNodeRef::new_unchecked(&datatype),
+                                datatype.as_ref(),
                             )))
                         })
                     }
@@ -2808,6 +3416,9 @@ impl SimpleEvaluator {
             PropertyPathExpression::NegatedPropertySet(ps) => {
                 PropertyPath::NegatedPropertySet(ps.iter().map(|p| self.encode_term(p)).collect())
             }
+            PropertyPathExpression::Repeat(p, min, max) => {
+                PropertyPath::Repeat(self.encode_property_path(p), *min, *max)
+            }
         })
     }
 
@@ -2896,6 +3507,14 @@ fn to_string_id(dataset: &DatasetView, term: &EncodedTerm) -> Option<SmallString
                 SmallStringOrId::Big(*iri_id)
             },
         ),
+        EncodedTerm::PrefixedNamedNode {
+            prefix_id,
+            local_name,
+        } => {
+            let mut iri = dataset.get_str(prefix_id).ok()??;
+            iri.push_str(local_name);
+            Some(build_string_id(dataset, &iri))
+        }
         EncodedTerm::DefaultGraph
         | EncodedTerm::NumericalBlankNode { .. }
         | EncodedTerm::SmallBlankNode { .. }
@@ -2909,6 +3528,10 @@ fn to_string_id(dataset: &DatasetView, term: &EncodedTerm) -> Option<SmallString
         | EncodedTerm::BigSmallLangStringLiteral { value_id, .. }
         | EncodedTerm::BigBigLangStringLiteral { value_id, .. }
         | EncodedTerm::BigTypedLiteral { value_id, .. } => Some((*value_id).into()),
+        EncodedTerm::MediumLangStringLiteral(value) => {
+            Some(build_string_id(dataset, split_medium_lang_string(value).0))
+        }
+        EncodedTerm::MediumAnyUriLiteral(value) => Some(build_string_id(dataset, value)),
         EncodedTerm::BooleanLiteral(value) => Some(build_string_id(
             dataset,
             if bool::from(*value) { "true" } else { "false" },
@@ -2961,6 +3584,9 @@ fn to_string(dataset: &DatasetView, term: &EncodedTerm) -> Option<String> {
         | EncodedTerm::BigBigLangStringLiteral { value_id, .. } => {
             dataset.get_str(value_id).ok()?
         }
+        EncodedTerm::MediumLangStringLiteral(value) => {
+            Some(split_medium_lang_string(value).0.into())
+        }
         _ => None,
     }
 }
@@ -2990,6 +3616,10 @@ fn to_string_and_language(
             dataset.get_str(value_id).ok()??,
             Some((*language_id).into()),
         )),
+        EncodedTerm::MediumLangStringLiteral(value) => {
+            let (value, language) = split_medium_lang_string(value);
+            Some((value.into(), Some(build_string_id(dataset, language))))
+        }
         _ => None,
     }
 }
@@ -3018,13 +3648,29 @@ fn build_lang_string_literal(
     value: &str,
     language_id: SmallStringOrId,
 ) -> EncodedTerm {
-    build_lang_string_literal_from_id(build_string_id(dataset, value), language_id)
+    build_lang_string_literal_from_id(dataset, build_string_id(dataset, value), language_id)
 }
 
 fn build_lang_string_literal_from_id(
+    dataset: &DatasetView,
     value_id: SmallStringOrId,
     language_id: SmallStringOrId,
 ) -> EncodedTerm {
+    if matches!(
+        (value_id, language_id),
+        (SmallStringOrId::Small(_), SmallStringOrId::Big(_))
+            | (SmallStringOrId::Big(_), SmallStringOrId::Small(_))
+    ) {
+        // Must stay consistent with the storage encoding that inlines short enough literals
+        if let (Some(value), Some(language)) = (
+            string_id_to_string(dataset, value_id),
+            string_id_to_string(dataset, language_id),
+        ) {
+            if let Some(term) = EncodedTerm::medium_lang_string_literal(&value, &language) {
+                return term;
+            }
+        }
+    }
     match (value_id, language_id) {
         (SmallStringOrId::Small(value), SmallStringOrId::Small(language)) => {
             EncodedTerm::SmallSmallLangStringLiteral { value, language }
@@ -3066,6 +3712,13 @@ fn build_string_id(dataset: &DatasetView, value: &str) -> SmallStringOrId {
     }
 }
 
+fn string_id_to_string(dataset: &DatasetView, id: SmallStringOrId) -> Option<String> {
+    match id {
+        SmallStringOrId::Small(value) => Some(value.into()),
+        SmallStringOrId::Big(id) => dataset.get_str(&id).ok()?,
+    }
+}
+
 fn build_language_id(dataset: &DatasetView, value: &EncodedTerm) -> Option<SmallStringOrId> {
     let mut language = to_simple_string(dataset, value)?;
     language.make_ascii_lowercase();
@@ -3179,26 +3832,33 @@ fn equals(a: &EncodedTerm, b: &EncodedTerm) -> Option<bool> {
     match a {
         EncodedTerm::DefaultGraph
         | EncodedTerm::NamedNode { .. }
+        | EncodedTerm::PrefixedNamedNode { .. }
         | EncodedTerm::NumericalBlankNode { .. }
         | EncodedTerm::SmallBlankNode { .. }
         | EncodedTerm::BigBlankNode { .. }
         | EncodedTerm::SmallSmallLangStringLiteral { .. }
         | EncodedTerm::SmallBigLangStringLiteral { .. }
         | EncodedTerm::BigSmallLangStringLiteral { .. }
-        | EncodedTerm::BigBigLangStringLiteral { .. } => Some(a == b),
+        | EncodedTerm::BigBigLangStringLiteral { .. }
+        | EncodedTerm::MediumLangStringLiteral(_) => Some(a == b),
         EncodedTerm::SmallStringLiteral(a) => match b {
             EncodedTerm::SmallStringLiteral(b) => Some(a == b),
-            EncodedTerm::SmallTypedLiteral { .. } | EncodedTerm::BigTypedLiteral { .. } => None,
+            EncodedTerm::SmallTypedLiteral { .. }
+            | EncodedTerm::BigTypedLiteral { .. }
+            | EncodedTerm::MediumAnyUriLiteral(_) => None,
             _ => Some(false),
         },
         EncodedTerm::BigStringLiteral { value_id: a } => match b {
             EncodedTerm::BigStringLiteral { value_id: b } => Some(a == b),
-            EncodedTerm::SmallTypedLiteral { .. } | EncodedTerm::BigTypedLiteral { .. } => None,
+            EncodedTerm::SmallTypedLiteral { .. }
+            | EncodedTerm::BigTypedLiteral { .. }
+            | EncodedTerm::MediumAnyUriLiteral(_) => None,
             _ => Some(false),
         },
         EncodedTerm::SmallTypedLiteral { .. } => match b {
             EncodedTerm::SmallTypedLiteral { .. } if a == b => Some(true),
             EncodedTerm::NamedNode { .. }
+            | EncodedTerm::PrefixedNamedNode { .. }
             | EncodedTerm::NumericalBlankNode { .. }
             | EncodedTerm::SmallBlankNode { .. }
             | EncodedTerm::BigBlankNode { .. }
@@ -3206,12 +3866,31 @@ fn equals(a: &EncodedTerm, b: &EncodedTerm) -> Option<bool> {
             | EncodedTerm::SmallBigLangStringLiteral { .. }
             | EncodedTerm::BigSmallLangStringLiteral { .. }
             | EncodedTerm::BigBigLangStringLiteral { .. }
-            | EncodedTerm::BigTypedLiteral { .. } => Some(false),
+            | EncodedTerm::MediumLangStringLiteral(_)
+            | EncodedTerm::BigTypedLiteral { .. }
+            | EncodedTerm::MediumAnyUriLiteral(_) => Some(false),
             _ => None,
         },
         EncodedTerm::BigTypedLiteral { .. } => match b {
             EncodedTerm::BigTypedLiteral { .. } if a == b => Some(true),
             EncodedTerm::NamedNode { .. }
+            | EncodedTerm::PrefixedNamedNode { .. }
+            | EncodedTerm::NumericalBlankNode { .. }
+            | EncodedTerm::SmallBlankNode { .. }
+            | EncodedTerm::BigBlankNode { .. }
+            | EncodedTerm::SmallSmallLangStringLiteral { .. }
+            | EncodedTerm::SmallBigLangStringLiteral { .. }
+            | EncodedTerm::BigSmallLangStringLiteral { .. }
+            | EncodedTerm::BigBigLangStringLiteral { .. }
+            | EncodedTerm::MediumLangStringLiteral(_)
+            | EncodedTerm::SmallTypedLiteral { .. }
+            | EncodedTerm::MediumAnyUriLiteral(_) => Some(false),
+            _ => None,
+        },
+        EncodedTerm::MediumAnyUriLiteral(_) => match b {
+            EncodedTerm::MediumAnyUriLiteral(_) if a == b => Some(true),
+            EncodedTerm::NamedNode { .. }
+            | EncodedTerm::PrefixedNamedNode { .. }
             | EncodedTerm::NumericalBlankNode { .. }
             | EncodedTerm::SmallBlankNode { .. }
             | EncodedTerm::BigBlankNode { .. }
@@ -3219,7 +3898,10 @@ fn equals(a: &EncodedTerm, b: &EncodedTerm) -> Option<bool> {
             | EncodedTerm::SmallBigLangStringLiteral { .. }
             | EncodedTerm::BigSmallLangStringLiteral { .. }
             | EncodedTerm::BigBigLangStringLiteral { .. }
-            | EncodedTerm::SmallTypedLiteral { .. } => Some(false),
+            | EncodedTerm::MediumLangStringLiteral(_)
+            | EncodedTerm::SmallTypedLiteral { .. }
+            | EncodedTerm::BigTypedLiteral { .. }
+            | EncodedTerm::MediumAnyUriLiteral(_) => Some(false),
             _ => None,
         },
         EncodedTerm::BooleanLiteral(a) => match b {
@@ -3334,6 +4016,33 @@ fn equals(a: &EncodedTerm, b: &EncodedTerm) -> Option<bool> {
     }
 }
 
+fn cmp_solutions(
+    dataset: &DatasetView,
+    by: &[ComparatorFunction],
+    a: &EncodedTuple,
+    b: &EncodedTuple,
+) -> Ordering {
+    for comp in by {
+        match comp {
+            ComparatorFunction::Asc(expression) => {
+                match cmp_terms(dataset, expression(a).as_ref(), expression(b).as_ref()) {
+                    Ordering::Greater => return Ordering::Greater,
+                    Ordering::Less => return Ordering::Less,
+                    Ordering::Equal => (),
+                }
+            }
+            ComparatorFunction::Desc(expression) => {
+                match cmp_terms(dataset, expression(a).as_ref(), expression(b).as_ref()) {
+                    Ordering::Greater => return Ordering::Less,
+                    Ordering::Less => return Ordering::Greater,
+                    Ordering::Equal => (),
+                }
+            }
+        }
+    }
+    Ordering::Equal
+}
+
 fn cmp_terms(dataset: &DatasetView, a: Option<&EncodedTerm>, b: Option<&EncodedTerm>) -> Ordering {
     match (a, b) {
         (Some(a), Some(b)) => match a {
@@ -3375,13 +4084,15 @@ fn cmp_terms(dataset: &DatasetView, a: Option<&EncodedTerm>, b: Option<&EncodedT
                     _ => Ordering::Less,
                 }
             }
-            EncodedTerm::NamedNode { iri_id: a } => match b {
-                EncodedTerm::NamedNode { iri_id: b } => {
-                    compare_str_ids(dataset, a, b).unwrap_or(Ordering::Equal)
+            EncodedTerm::NamedNode { .. } | EncodedTerm::PrefixedNamedNode { .. } => {
+                if b.is_named_node() {
+                    compare_named_nodes(dataset, a, b).unwrap_or(Ordering::Equal)
+                } else if b.is_blank_node() {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
                 }
-                _ if b.is_blank_node() => Ordering::Greater,
-                _ => Ordering::Less,
-            },
+            }
             EncodedTerm::Triple(a) => match b {
                 EncodedTerm::Triple(b) => {
                     match cmp_terms(dataset, Some(&a.subject), Some(&b.subject)) {
@@ -3449,6 +4160,13 @@ fn partial_cmp_literals(
     a: &EncodedTerm,
     b: &EncodedTerm,
 ) -> Option<Ordering> {
+    if matches!(a, EncodedTerm::MediumLangStringLiteral(_))
+        || matches!(b, EncodedTerm::MediumLangStringLiteral(_))
+    {
+        let (a, la) = to_string_and_language(dataset, a)?;
+        let (b, lb) = to_string_and_language(dataset, b)?;
+        return (la.is_some() && la == lb).then(|| a.cmp(&b));
+    }
     match a {
         EncodedTerm::SmallStringLiteral(a) => match b {
             EncodedTerm::SmallStringLiteral(b) => a.partial_cmp(b),
@@ -3626,9 +4344,55 @@ fn compare_str_ids(dataset: &DatasetView, a: &StrHash, b: &StrHash) -> Option<Or
     Some(dataset.get_str(a).ok()??.cmp(&dataset.get_str(b).ok()??))
 }
 
-fn compare_str_id_str(dataset: &DatasetView, a: &StrHash, b: &str) -> Option<Ordering> {
-    Some(dataset.get_str(a).ok()??.as_str().cmp(b))
-}
+/// Compares the IRIs of two named nodes without allocating the full IRIs of the prefixed ones
+fn compare_named_nodes(
+    dataset: &DatasetView,
+    a: &EncodedTerm,
+    b: &EncodedTerm,
+) -> Option<Ordering> {
+    match (a, b) {
+        (EncodedTerm::NamedNode { iri_id: a }, EncodedTerm::NamedNode { iri_id: b }) => {
+            compare_str_ids(dataset, a, b)
+        }
+        (
+            EncodedTerm::PrefixedNamedNode {
+                prefix_id: a_prefix,
+                local_name: a_local_name,
+            },
+            EncodedTerm::PrefixedNamedNode {
+                prefix_id: b_prefix,
+                local_name: b_local_name,
+            },
+        ) if a_prefix == b_prefix => Some(a_local_name.as_str().cmp(b_local_name.as_str())),
+        _ => {
+            let (a_prefix, a_local_name) = named_node_parts(dataset, a)?;
+            let (b_prefix, b_local_name) = named_node_parts(dataset, b)?;
+            // str ordering is the byte ordering
+            Some(
+                a_prefix
+                    .bytes()
+                    .chain(a_local_name.bytes())
+                    .cmp(b_prefix.bytes().chain(b_local_name.bytes())),
+            )
+        }
+    }
+}
+
+/// The prefix and the local name of a named node IRI, the full IRI being the prefix if it is not split
+fn named_node_parts<'a>(dataset: &DatasetView, term: &'a EncodedTerm) -> Option<(String, &'a str)> {
+    match term {
+        EncodedTerm::NamedNode { iri_id } => Some((dataset.get_str(iri_id).ok()??, "")),
+        EncodedTerm::PrefixedNamedNode {
+            prefix_id,
+            local_name,
+        } => Some((dataset.get_str(prefix_id).ok()??, local_name.as_str())),
+        _ => None,
+    }
+}
+
+fn compare_str_id_str(dataset: &DatasetView, a: &StrHash, b: &str) -> Option<Ordering> {
+    Some(dataset.get_str(a).ok()??.as_str().cmp(b))
+}
 
 fn compare_str_str_id(dataset: &DatasetView, a: &str, b: &StrHash) -> Option<Ordering> {
     Some(a.cmp(dataset.get_str(b).ok()??.as_str()))
@@ -3638,6 +4402,7 @@ fn datatype(dataset: &DatasetView, value: &EncodedTerm) -> Option<EncodedTerm> {
     // TODO: optimize?
     match value {
         EncodedTerm::NamedNode { .. }
+        | EncodedTerm::PrefixedNamedNode { .. }
         | EncodedTerm::SmallBlankNode { .. }
         | EncodedTerm::BigBlankNode { .. }
         | EncodedTerm::NumericalBlankNode { .. }
@@ -3649,13 +4414,16 @@ fn datatype(dataset: &DatasetView, value: &EncodedTerm) -> Option<EncodedTerm> {
         EncodedTerm::SmallSmallLangStringLiteral { .. }
         | EncodedTerm::SmallBigLangStringLiteral { .. }
         | EncodedTerm::BigSmallLangStringLiteral { .. }
-        | EncodedTerm::BigBigLangStringLiteral { .. } => {
+        | EncodedTerm::BigBigLangStringLiteral { .. }
+        | EncodedTerm::MediumLangStringLiteral(_) => {
             Some(encode_named_node(dataset, rdf::LANG_STRING))
         }
         EncodedTerm::SmallTypedLiteral { datatype_id, .. }
-        | EncodedTerm::BigTypedLiteral { datatype_id, .. } => Some(EncodedTerm::NamedNode {
-            iri_id: *datatype_id,
-        }),
+        | EncodedTerm::BigTypedLiteral { datatype_id, .. } => Some(build_named_node(
+            dataset,
+            &dataset.get_str(datatype_id).ok()??,
+        )),
+        EncodedTerm::MediumAnyUriLiteral(_) => Some(encode_named_node(dataset, xsd::ANY_URI)),
         EncodedTerm::BooleanLiteral(..) => Some(encode_named_node(dataset, xsd::BOOLEAN)),
         EncodedTerm::FloatLiteral(..) => Some(encode_named_node(dataset, xsd::FLOAT)),
         EncodedTerm::DoubleLiteral(..) => Some(encode_named_node(dataset, xsd::DOUBLE)),
@@ -3978,6 +4746,8 @@ pub enum PropertyPath {
     OneOrMore(Rc<Self>),
     ZeroOrOne(Rc<Self>),
     NegatedPropertySet(Rc<[EncodedTerm]>),
+    /// Between `min` and `max` (unbounded if `None`) repetitions of the path
+    Repeat(Rc<Self>, usize, Option<usize>),
 }
 
 #[derive(Clone)]
@@ -4056,6 +4826,11 @@ impl PathEvaluator {
                 })
                 .transpose()?
                 .is_some(),
+            PropertyPath::Repeat(..) => self
+                .eval_from_in_graph(path, start, graph_name)
+                .find(|e| e.as_ref().map_or(true, |e| e == end))
+                .transpose()?
+                .is_some(),
         })
     }
 
@@ -4154,6 +4929,17 @@ impl PathEvaluator {
                         }),
                 )
             }
+            PropertyPath::Repeat(p, min, max) => {
+                let eval = self.clone();
+                let path = PropertyPath::Repeat(Rc::clone(p), *min, *max);
+                let start2 = start.clone();
+                let end = end.clone();
+                self.run_if_term_is_a_dataset_node(start, move |graph_name| {
+                    eval.eval_closed_in_graph(&path, &start2, &end, &graph_name)
+                        .map(|is_found| is_found.then_some(graph_name))
+                        .transpose()
+                })
+            }
         }
     }
 
@@ -4229,6 +5015,92 @@ impl PathEvaluator {
                         }),
                 )
             }
+            PropertyPath::Repeat(p, min, max) => {
+                let p = Rc::clone(p);
+                self.eval_repeat_in_graph(start, graph_name, *min, *max, move |eval, e, g| {
+                    eval.eval_from_in_graph(&p, e, g)
+                })
+            }
+        }
+    }
+
+    /// Returns the nodes reachable from `start` with a single `path` step
+    /// with the predicate of the traversed triple if there is a single one
+    fn eval_edges_from_in_graph(
+        &self,
+        path: &PropertyPath,
+        start: &EncodedTerm,
+        graph_name: &EncodedTerm,
+    ) -> Box<dyn Iterator<Item = Result<(Option<EncodedTerm>, EncodedTerm), EvaluationError>>> {
+        match path {
+            PropertyPath::Path(p) => Box::new(
+                self.dataset
+                    .encoded_quads_for_pattern(Some(start), Some(p), None, Some(graph_name))
+                    .map(|t| {
+                        let t = t?;
+                        Ok((Some(t.predicate), t.object))
+                    }),
+            ),
+            PropertyPath::Reverse(p) => match p.as_ref() {
+                PropertyPath::Path(p) => Box::new(
+                    self.dataset
+                        .encoded_quads_for_pattern(None, Some(p), Some(start), Some(graph_name))
+                        .map(|t| {
+                            let t = t?;
+                            Ok((Some(t.predicate), t.subject))
+                        }),
+                ),
+                PropertyPath::NegatedPropertySet(ps) => {
+                    let ps = Rc::clone(ps);
+                    Box::new(
+                        self.dataset
+                            .encoded_quads_for_pattern(None, None, Some(start), Some(graph_name))
+                            .filter_map(move |t| match t {
+                                Ok(t) => {
+                                    if ps.contains(&t.predicate) {
+                                        None
+                                    } else {
+                                        Some(Ok((Some(t.predicate), t.subject)))
+                                    }
+                                }
+                                Err(e) => Some(Err(e)),
+                            }),
+                    )
+                }
+                _ => Box::new(
+                    self.eval_from_in_graph(path, start, graph_name)
+                        .map(|node| Ok((None, node?))),
+                ),
+            },
+            PropertyPath::Alternative(a, b) => Box::new(hash_deduplicate(
+                self.eval_edges_from_in_graph(a, start, graph_name)
+                    .chain(self.eval_edges_from_in_graph(b, start, graph_name)),
+            )),
+            PropertyPath::NegatedPropertySet(ps) => {
+                let ps = Rc::clone(ps);
+                Box::new(
+                    self.dataset
+                        .encoded_quads_for_pattern(Some(start), None, None, Some(graph_name))
+                        .filter_map(move |t| match t {
+                            Ok(t) => {
+                                if ps.contains(&t.predicate) {
+                                    None
+                                } else {
+                                    Some(Ok((Some(t.predicate), t.object)))
+                                }
+                            }
+                            Err(e) => Some(Err(e)),
+                        }),
+                )
+            }
+            PropertyPath::Sequence(..)
+            | PropertyPath::ZeroOrMore(_)
+            | PropertyPath::OneOrMore(_)
+            | PropertyPath::ZeroOrOne(_)
+            | PropertyPath::Repeat(..) => Box::new(
+                self.eval_from_in_graph(path, start, graph_name)
+                    .map(|node| Ok((None, node?))),
+            ),
         }
     }
 
@@ -4316,6 +5188,15 @@ impl PathEvaluator {
                         }),
                 )
             }
+            PropertyPath::Repeat(p, min, max) => {
+                let eval = self.clone();
+                let path = PropertyPath::Repeat(Rc::clone(p), *min, *max);
+                let start2 = start.clone();
+                self.run_if_term_is_a_dataset_node(start, move |graph_name| {
+                    eval.eval_from_in_graph(&path, &start2, &graph_name)
+                        .map(move |e| Ok((e?, graph_name.clone())))
+                })
+            }
         }
     }
 
@@ -4388,6 +5269,12 @@ impl PathEvaluator {
                         }),
                 )
             }
+            PropertyPath::Repeat(p, min, max) => {
+                let p = Rc::clone(p);
+                self.eval_repeat_in_graph(end, graph_name, *min, *max, move |eval, e, g| {
+                    eval.eval_to_in_graph(&p, e, g)
+                })
+            }
         }
     }
 
@@ -4475,6 +5362,15 @@ impl PathEvaluator {
                         }),
                 )
             }
+            PropertyPath::Repeat(p, min, max) => {
+                let eval = self.clone();
+                let path = PropertyPath::Repeat(Rc::clone(p), *min, *max);
+                let end2 = end.clone();
+                self.run_if_term_is_a_dataset_node(end, move |graph_name| {
+                    eval.eval_to_in_graph(&path, &end2, &graph_name)
+                        .map(move |e| Ok((e?, graph_name.clone())))
+                })
+            }
         }
     }
 
@@ -4553,6 +5449,31 @@ impl PathEvaluator {
                         }),
                 )
             }
+            PropertyPath::Repeat(p, min, max) => {
+                let eval = self.clone();
+                let step = Rc::clone(p);
+                let graph_name2 = graph_name.clone();
+                let next = move |(start, middle): (EncodedTerm, EncodedTerm)| {
+                    eval.eval_from_in_graph(&step, &middle, &graph_name2)
+                        .map(move |end| Ok((start.clone(), end?)))
+                };
+                if *min == 0 {
+                    Box::new(bounded_closure(
+                        self.get_subject_or_object_identity_pairs_in_graph(graph_name),
+                        next,
+                        0,
+                        *max,
+                    ))
+                } else {
+                    // The first repetition is evaluated as a whole to only start from the relevant nodes
+                    Box::new(bounded_closure(
+                        self.eval_open_in_graph(p, graph_name),
+                        next,
+                        min - 1,
+                        max.map(|max| max - 1),
+                    ))
+                }
+            }
         }
     }
 
@@ -4628,6 +5549,64 @@ impl PathEvaluator {
                         }),
                 )
             }
+            PropertyPath::Repeat(p, min, max) => {
+                let eval = self.clone();
+                let step = Rc::clone(p);
+                let next =
+                    move |(start, middle, graph_name): (EncodedTerm, EncodedTerm, EncodedTerm)| {
+                        eval.eval_from_in_graph(&step, &middle, &graph_name)
+                            .map(move |end| Ok((start.clone(), end?, graph_name.clone())))
+                    };
+                if *min == 0 {
+                    Box::new(bounded_closure(
+                        self.get_subject_or_object_identity_pairs_in_dataset(),
+                        next,
+                        0,
+                        *max,
+                    ))
+                } else {
+                    // The first repetition is evaluated as a whole to only start from the relevant nodes
+                    Box::new(bounded_closure(
+                        self.eval_open_in_unknown_graph(p),
+                        next,
+                        min - 1,
+                        max.map(|max| max - 1),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Evaluates `p{min,max}` from `start` using `step` to evaluate `p`
+    fn eval_repeat_in_graph(
+        &self,
+        start: &EncodedTerm,
+        graph_name: &EncodedTerm,
+        min: usize,
+        max: Option<usize>,
+        step: impl Fn(
+                &Self,
+                &EncodedTerm,
+                &EncodedTerm,
+            ) -> Box<dyn Iterator<Item = Result<EncodedTerm, EvaluationError>>>
+            + 'static,
+    ) -> Box<dyn Iterator<Item = Result<EncodedTerm, EvaluationError>>> {
+        let eval = self.clone();
+        let graph_name2 = graph_name.clone();
+        let start2 = start.clone();
+        let closure = move || {
+            bounded_closure(
+                once(Ok(start2)),
+                move |e| step(&eval, &e, &graph_name2),
+                min,
+                max,
+            )
+        };
+        if min == 0 {
+            // The empty path only matches the nodes of the graph
+            self.run_if_term_is_a_graph_node(start, graph_name, closure)
+        } else {
+            Box::new(closure())
         }
     }
 
@@ -4762,6 +5741,203 @@ impl Iterator for CartesianProductJoinIterator {
     }
 }
 
+/// Scans the quads matching a broad pattern using multiple threads if the storage allows it
+///
+/// Only scans of more than [`MIN_PARALLEL_SIZE`] quads are split and only if pool workers are idle.
+fn parallel_quads_for_pattern(
+    dataset: &DatasetView,
+    parallelism: usize,
+    subject: Option<&EncodedTerm>,
+    predicate: Option<&EncodedTerm>,
+    object: Option<&EncodedTerm>,
+    graph_name: Option<&EncodedTerm>,
+) -> Result<Option<Box<dyn Iterator<Item = Result<EncodedQuad, EvaluationError>>>>, EvaluationError>
+{
+    if parallelism <= 1 {
+        return Ok(None);
+    }
+    let Some(graph_name) = graph_name else {
+        return Ok(None);
+    };
+    if [subject, predicate, object]
+        .iter()
+        .filter(|term| term.is_some())
+        .count()
+        > 1
+    {
+        return Ok(None); // Selective patterns are cheaper to evaluate on the current thread
+    }
+    let workers = idle_workers(parallelism);
+    if workers.len() < 2 {
+        return Ok(None);
+    }
+    let Some(mut partitions) = dataset.encoded_quads_for_pattern_partitioned(
+        subject,
+        predicate,
+        object,
+        graph_name,
+        workers.len(),
+        MIN_PARALLEL_SIZE,
+    )?
+    else {
+        return Ok(None);
+    };
+    if partitions.len() > 1 {
+        Ok(Some(Box::new(ParallelIterator::new(partitions, workers))))
+    } else if let Some(partition) = partitions.pop() {
+        Ok(Some(partition))
+    } else {
+        Ok(Some(Box::new(empty())))
+    }
+}
+
+/// Finds the input pattern and the `SERVICE` of a join that should be evaluated as a bind join
+fn bind_join_operands<'a>(
+    left: &'a GraphPattern,
+    right: &'a GraphPattern,
+    algorithm: &'a JoinAlgorithm,
+) -> Option<(
+    &'a GraphPattern,
+    &'a NamedNodePattern,
+    &'a GraphPattern,
+    bool,
+    &'a [Variable],
+)> {
+    let JoinAlgorithm::HashBuildLeftProbeRight { keys } = algorithm;
+    for (input, service) in [(left, right), (right, left)] {
+        if let GraphPattern::Service {
+            name,
+            inner,
+            silent,
+        } = service
+        {
+            if let NamedNodePattern::NamedNode(name) = name {
+                if PathSearch::parse(name, inner).is_some() {
+                    // Path searches are evaluated locally
+                    continue;
+                }
+            }
+            // Without shared variables nothing would be gained from sending the input solutions
+            if !keys.is_empty() || matches!(name, NamedNodePattern::Variable(_)) {
+                return Some((input, name, inner.as_ref(), *silent, keys.as_slice()));
+            }
+        }
+    }
+    None
+}
+
+struct BindJoinIterator {
+    eval: SimpleEvaluator,
+    input: Fuse<EncodedTuplesIterator>,
+    service_name: TupleSelector,
+    graph_pattern: Rc<spargebra::algebra::GraphPattern>,
+    keys: Rc<[(Variable, usize)]>,
+    variables: Rc<[Variable]>,
+    silent: bool,
+    batch_size: usize,
+    batch: Vec<EncodedTuple>,
+    current: EncodedTuplesIterator,
+}
+
+impl Iterator for BindJoinIterator {
+    type Item = Result<EncodedTuple, EvaluationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.current.next() {
+                return Some(result);
+            }
+            while self.batch.len() < self.batch_size {
+                match self.input.next() {
+                    Some(Ok(tuple)) => self.batch.push(tuple),
+                    Some(Err(error)) => return Some(Err(error)),
+                    None => break,
+                }
+            }
+            if self.batch.is_empty() {
+                return None;
+            }
+            self.current = self.eval.evaluate_service_batch(
+                &self.service_name,
+                &self.graph_pattern,
+                &self.keys,
+                &self.variables,
+                self.silent,
+                take(&mut self.batch),
+            );
+        }
+    }
+}
+
+/// Hash join probing chunks of tuples on multiple threads
+struct ParallelHashJoinIterator {
+    probe_iter: EncodedTuplesIterator,
+    built: Arc<PartitionedTupleSet>,
+    parallelism: usize,
+    buffered_results: VecDeque<Result<EncodedTuple, EvaluationError>>,
+}
+
+impl Iterator for ParallelHashJoinIterator {
+    type Item = Result<EncodedTuple, EvaluationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.buffered_results.pop_front() {
+                return Some(result);
+            }
+            let mut probe_tuples = Vec::new();
+            for result in self.probe_iter.by_ref().take(CHUNK_SIZE * self.parallelism) {
+                match result {
+                    Ok(tuple) => probe_tuples.push(tuple),
+                    Err(error) => self.buffered_results.push_back(Err(error)),
+                }
+            }
+            if probe_tuples.is_empty() {
+                if self.buffered_results.is_empty() {
+                    return None;
+                }
+                continue;
+            }
+            if probe_tuples.len() < CHUNK_SIZE || self.built.len() < CHUNK_SIZE {
+                self.buffered_results.extend(
+                    probe_partitioned(&self.built, &probe_tuples)
+                        .into_iter()
+                        .map(Ok),
+                );
+                continue;
+            }
+            let chunk_size = probe_tuples.len().div_ceil(self.parallelism);
+            let mut chunks = Vec::with_capacity(self.parallelism);
+            while probe_tuples.len() > chunk_size {
+                let rest = probe_tuples.split_off(chunk_size);
+                chunks.push(probe_tuples);
+                probe_tuples = rest;
+            }
+            chunks.push(probe_tuples);
+            let built = Arc::clone(&self.built);
+            let results = map_in_parallel(chunks, move |chunk| probe_partitioned(&built, &chunk));
+            self.buffered_results
+                .extend(results.into_iter().flatten().map(Ok));
+        }
+    }
+}
+
+fn probe_partitioned(
+    built: &PartitionedTupleSet,
+    probe_tuples: &[EncodedTuple],
+) -> Vec<EncodedTuple> {
+    let mut results = Vec::new();
+    for probe_tuple in probe_tuples {
+        results.extend(
+            built
+                .get(probe_tuple)
+                .iter()
+                .filter_map(|built_tuple| probe_tuple.combine_with(built_tuple)),
+        )
+    }
+    results
+}
+
 struct HashJoinIterator {
     probe_iter: EncodedTuplesIterator,
     built: EncodedTupleSet,
@@ -4800,6 +5976,202 @@ impl Iterator for HashJoinIterator {
     }
 }
 
+/// A pattern joined by a [`LeapfrogJoinIterator`]
+struct LeapfrogRelation {
+    /// The variables of the pattern
+    columns: Vec<usize>,
+    /// Evaluates the pattern without its filters, used to find and check the values of a variable
+    scan: Rc<dyn Fn(EncodedTuple) -> EncodedTuplesIterator>,
+    /// Evaluates the full pattern once all its variables are bound
+    eval: Rc<dyn Fn(EncodedTuple) -> EncodedTuplesIterator>,
+}
+
+struct LeapfrogJoin {
+    variables: Vec<(usize, Variable)>,
+    /// For each variable, the relations using it, the most selective one first
+    participants: Vec<Vec<usize>>,
+    relations: Vec<LeapfrogRelation>,
+}
+
+/// The candidate values of a variable of a leapfrog join
+struct LeapfrogLevel {
+    /// The tuple with the values of the previous variables
+    tuple: EncodedTuple,
+    candidates: Box<dyn Iterator<Item = Result<EncodedTerm, EvaluationError>>>,
+    /// The relations the candidates still have to be checked against
+    others: Vec<usize>,
+    /// The values already tried
+    seen: FxHashSet<EncodedTerm>,
+}
+
+/// Worst-case optimal join in the spirit of the [generic join](https://arxiv.org/abs/1310.3314) algorithm.
+///
+/// Variables are bound one after the other.
+/// For each variable, the scans of the relations using it are read in turn, with the variables bound so far,
+/// until one of them is exhausted: this relation has the fewest matches and is found after reading at most
+/// as many rows from each relation.
+/// Its values are kept only if all the other relations using the variable have a match,
+/// each check being an index range lookup.
+/// The cost of a variable is hence bounded by the smallest relation for the current prefix,
+/// as required for the worst-case optimality (e.g. `O(N^1.5)` for triangles).
+/// Once all variables are bound, the full patterns are evaluated to apply their filters and count the duplicates.
+/// The results are computed lazily so that a `LIMIT` stops the evaluation.
+struct LeapfrogJoinIterator {
+    join: Rc<LeapfrogJoin>,
+    levels: Vec<LeapfrogLevel>,
+    /// A result to return multiple times because of duplicated rows
+    pending: Option<(EncodedTuple, usize)>,
+}
+
+impl LeapfrogJoinIterator {
+    fn new(join: Rc<LeapfrogJoin>, from: EncodedTuple) -> Self {
+        let mut iter = Self {
+            join,
+            levels: Vec::new(),
+            pending: None,
+        };
+        if iter.join.variables.is_empty() {
+            iter.pending = Some((from, 1));
+        } else {
+            iter.levels.push(iter.level(0, from));
+        }
+        iter
+    }
+
+    fn level(&self, depth: usize, tuple: EncodedTuple) -> LeapfrogLevel {
+        let (variable, variable_name) = &self.join.variables[depth];
+        let (variable, variable_name) = (*variable, variable_name.clone());
+        let value_of = move |result: Result<EncodedTuple, EvaluationError>| {
+            result?
+                .get(variable)
+                .cloned()
+                .ok_or_else(|| EvaluationError::UnboundJoinVariable(variable_name.clone()))
+        };
+        let participants = &self.join.participants[depth];
+        if let [relation] = participants.as_slice() {
+            return LeapfrogLevel {
+                candidates: Box::new(
+                    (self.join.relations[*relation].scan)(tuple.clone()).map(value_of),
+                ),
+                tuple,
+                others: Vec::new(),
+                seen: FxHashSet::default(),
+            };
+        }
+        // We read the scans in turn until the smallest one is exhausted
+        let mut scans = participants
+            .iter()
+            .map(|relation| (self.join.relations[*relation].scan)(tuple.clone()))
+            .collect::<Vec<_>>();
+        let mut values = vec![Vec::new(); scans.len()];
+        let smallest = 'read: loop {
+            for (i, scan) in scans.iter_mut().enumerate() {
+                let Some(result) = scan.next() else {
+                    break 'read i;
+                };
+                match value_of(result) {
+                    Ok(value) => values[i].push(value),
+                    Err(error) => {
+                        return LeapfrogLevel {
+                            candidates: Box::new(once(Err(error))),
+                            tuple,
+                            others: Vec::new(),
+                            seen: FxHashSet::default(),
+                        }
+                    }
+                }
+            }
+        };
+        LeapfrogLevel {
+            candidates: Box::new(values.swap_remove(smallest).into_iter().map(Ok)),
+            tuple,
+            others: participants
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != smallest)
+                .map(|(_, relation)| *relation)
+                .collect(),
+            seen: FxHashSet::default(),
+        }
+    }
+
+    /// Checks that all the other relations using the variable at `depth` have a match for the tuple
+    fn check(&self, depth: usize, tuple: &EncodedTuple) -> Result<bool, EvaluationError> {
+        for relation in &self.levels[depth].others {
+            match (self.join.relations[*relation].scan)(tuple.clone()).next() {
+                Some(Ok(_)) => (),
+                Some(Err(error)) => return Err(error),
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Number of times the result is repeated, 0 if it is filtered out
+    fn multiplicity(&self, tuple: &EncodedTuple) -> Result<usize, EvaluationError> {
+        let mut multiplicity = 1;
+        for relation in &self.join.relations {
+            let mut count = 0;
+            for result in (relation.eval)(tuple.clone()) {
+                result?;
+                count += 1;
+            }
+            multiplicity *= count;
+            if multiplicity == 0 {
+                break;
+            }
+        }
+        Ok(multiplicity)
+    }
+}
+
+impl Iterator for LeapfrogJoinIterator {
+    type Item = Result<EncodedTuple, EvaluationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((tuple, count)) = &mut self.pending {
+                if *count > 1 {
+                    *count -= 1;
+                    return Some(Ok(tuple.clone()));
+                }
+                return self.pending.take().map(|(tuple, _)| Ok(tuple));
+            }
+            let depth = self.levels.len().checked_sub(1)?;
+            let (variable, _) = &self.join.variables[depth];
+            let level = &mut self.levels[depth];
+            let value = match level.candidates.next() {
+                Some(Ok(value)) => value,
+                Some(Err(error)) => return Some(Err(error)),
+                None => {
+                    self.levels.pop();
+                    continue;
+                }
+            };
+            if !level.seen.insert(value.clone()) {
+                continue;
+            }
+            let mut tuple = level.tuple.clone();
+            tuple.set(*variable, value);
+            match self.check(depth, &tuple) {
+                Ok(true) => (),
+                Ok(false) => continue,
+                Err(error) => return Some(Err(error)),
+            }
+            if depth + 1 < self.join.variables.len() {
+                let level = self.level(depth + 1, tuple);
+                self.levels.push(level);
+            } else {
+                match self.multiplicity(&tuple) {
+                    Ok(0) => (),
+                    Ok(count) => self.pending = Some((tuple, count)),
+                    Err(error) => return Some(Err(error)),
+                }
+            }
+        }
+    }
+}
+
 struct HashLeftJoinIterator {
     left_iter: EncodedTuplesIterator,
     right: EncodedTupleSet,
@@ -5150,6 +6522,61 @@ fn transitive_closure<T: Clone + Eq + Hash, NI: Iterator<Item = Result<T, Evalua
     errors.into_iter().map(Err).chain(all.into_iter().map(Ok))
 }
 
+/// Returns the elements reachable from `start` with between `min` and `max` (unbounded if `None`) calls to `next`
+///
+/// It is a breadth-first search: its memory use does not depend on the bounds.
+fn bounded_closure<T: Clone + Eq + Hash, NI: Iterator<Item = Result<T, EvaluationError>>>(
+    start: impl IntoIterator<Item = Result<T, EvaluationError>>,
+    mut next: impl FnMut(T) -> NI,
+    min: usize,
+    max: Option<usize>,
+) -> impl Iterator<Item = Result<T, EvaluationError>> {
+    let mut errors = Vec::new();
+    let mut current = FxHashSet::default();
+    for e in start {
+        match e {
+            Ok(e) => {
+                current.insert(e);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    let mut expand = |todo: Vec<T>, all: &mut FxHashSet<T>| {
+        let mut new = Vec::new();
+        for e in todo {
+            for e in next(e) {
+                match e {
+                    Ok(e) => {
+                        if all.insert(e.clone()) {
+                            new.push(e)
+                        }
+                    }
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+        new
+    };
+    let mut depth = 0;
+    // The elements reachable with exactly `min` steps
+    while depth < min && !current.is_empty() {
+        let mut new = FxHashSet::default();
+        expand(current.into_iter().collect(), &mut new);
+        current = new;
+        depth += 1;
+    }
+    // From them, the elements reachable with at most `max - min` more steps
+    let mut todo = current.iter().cloned().collect::<Vec<_>>();
+    while !todo.is_empty() && max.map_or(true, |max| depth < max) {
+        todo = expand(todo, &mut current);
+        depth += 1;
+    }
+    errors
+        .into_iter()
+        .map(Err)
+        .chain(current.into_iter().map(Ok))
+}
+
 fn look_in_transitive_closure<
     T: Clone + Eq + Hash,
     NI: Iterator<Item = Result<T, EvaluationError>>,
@@ -5282,7 +6709,7 @@ impl Accumulator for Deduplicate {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 struct CountAccumulator {
     count: i64,
 }
@@ -5297,6 +6724,7 @@ impl Accumulator for CountAccumulator {
     }
 }
 
+#[derive(Clone)]
 struct SumAccumulator {
     sum: Option<EncodedTerm>,
 }
@@ -5340,7 +6768,7 @@ impl Accumulator for SumAccumulator {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct AvgAccumulator {
     sum: SumAccumulator,
     count: i64,
@@ -5429,7 +6857,7 @@ impl Accumulator for MaxAccumulator {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 struct SampleAccumulator {
     value: Option<EncodedTerm>,
 }
@@ -5491,6 +6919,182 @@ impl Accumulator for GroupConcatAccumulator {
     }
 }
 
+/// An accumulator whose partial states computed on different threads can be merged
+#[derive(Clone)]
+enum MergeableAccumulator {
+    Count(CountAccumulator),
+    Sum(SumAccumulator),
+    Avg(AvgAccumulator),
+    Sample(SampleAccumulator),
+}
+
+impl MergeableAccumulator {
+    fn new(expression: &AggregateExpression) -> Option<Self> {
+        match expression {
+            AggregateExpression::CountSolutions { distinct: false } => {
+                Some(Self::Count(CountAccumulator::default()))
+            }
+            AggregateExpression::FunctionCall {
+                name,
+                distinct: false,
+                ..
+            } => match name {
+                AggregateFunction::Count => Some(Self::Count(CountAccumulator::default())),
+                AggregateFunction::Sum => Some(Self::Sum(SumAccumulator::default())),
+                AggregateFunction::Avg => Some(Self::Avg(AvgAccumulator::default())),
+                AggregateFunction::Sample => Some(Self::Sample(SampleAccumulator::default())),
+                _ => None,
+            },
+            AggregateExpression::CountSolutions { distinct: true }
+            | AggregateExpression::FunctionCall { distinct: true, .. } => None,
+        }
+    }
+
+    fn merge(&mut self, other: Self) {
+        match (self, other) {
+            (Self::Count(a), Self::Count(b)) => a.count += b.count,
+            (Self::Sum(a), Self::Sum(b)) => a.add(b.state()),
+            (Self::Avg(a), Self::Avg(b)) => {
+                a.sum.add(b.sum.state());
+                a.count += b.count;
+            }
+            (Self::Sample(a), Self::Sample(b)) => {
+                if a.value.is_none() {
+                    a.value = b.value;
+                }
+            }
+            _ => (), // Never happens, the accumulators are built from the same aggregates
+        }
+    }
+}
+
+impl Accumulator for MergeableAccumulator {
+    fn add(&mut self, element: Option<EncodedTerm>) {
+        match self {
+            Self::Count(accumulator) => accumulator.add(element),
+            Self::Sum(accumulator) => accumulator.add(element),
+            Self::Avg(accumulator) => accumulator.add(element),
+            Self::Sample(accumulator) => accumulator.add(element),
+        }
+    }
+
+    fn state(&self) -> Option<EncodedTerm> {
+        match self {
+            Self::Count(accumulator) => accumulator.state(),
+            Self::Sum(accumulator) => accumulator.state(),
+            Self::Avg(accumulator) => accumulator.state(),
+            Self::Sample(accumulator) => accumulator.state(),
+        }
+    }
+}
+
+/// The group key and the aggregated values of a tuple
+type GroupRow = (Vec<Option<EncodedTerm>>, Vec<Option<EncodedTerm>>);
+
+type MergeableGroups = FxHashMap<Vec<Option<EncodedTerm>>, Vec<MergeableAccumulator>>;
+
+/// Groups the input tuples and computes the aggregates, sending blocks of tuples to multiple threads if the input is large
+///
+/// Each thread computes partial aggregates on its blocks, they are then merged together.
+fn aggregate_in_parallel(
+    input: EncodedTuplesIterator,
+    key_variables: &[usize],
+    input_expressions: &[Option<Rc<dyn Fn(&EncodedTuple) -> Option<EncodedTerm>>>],
+    accumulators: &[MergeableAccumulator],
+    parallelism: usize,
+) -> (Vec<EvaluationError>, MergeableGroups) {
+    let mut errors = Vec::new();
+    let groups = {
+        let mut rows = input.filter_map(|result| match result {
+            Ok(tuple) => Some((
+                key_variables
+                    .iter()
+                    .map(|v| tuple.get(*v).cloned())
+                    .collect::<Vec<_>>(),
+                input_expressions
+                    .iter()
+                    .map(|expression| expression.as_ref().and_then(|e| e(&tuple)))
+                    .collect::<Vec<_>>(),
+            )),
+            Err(error) => {
+                errors.push(error);
+                None
+            }
+        });
+        let first_rows = rows.by_ref().take(MIN_PARALLEL_SIZE).collect::<Vec<_>>();
+        let workers = if first_rows.len() < MIN_PARALLEL_SIZE {
+            Vec::new()
+        } else {
+            idle_workers(parallelism)
+        };
+        if workers.is_empty() {
+            aggregate_rows(first_rows.into_iter().chain(rows), accumulators)
+        } else {
+            let (senders, tasks): (Vec<_>, Vec<_>) = workers
+                .into_iter()
+                .map(|worker| {
+                    let (sender, receiver) = sync_channel::<Vec<GroupRow>>(2);
+                    let accumulators = accumulators.to_vec();
+                    let task = worker.spawn(move || {
+                        aggregate_rows(receiver.into_iter().flatten(), &accumulators)
+                    });
+                    (sender, task)
+                })
+                .unzip();
+            let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+            let mut next_worker = 0;
+            for row in first_rows.into_iter().chain(rows) {
+                chunk.push(row);
+                if chunk.len() >= CHUNK_SIZE {
+                    if senders[next_worker].send(take(&mut chunk)).is_err() {
+                        break; // The worker panicked, we are going to propagate the panic
+                    }
+                    next_worker = (next_worker + 1) % senders.len();
+                }
+            }
+            if !chunk.is_empty() {
+                senders[next_worker].send(chunk).ok();
+            }
+            drop(senders);
+            let mut groups = MergeableGroups::default();
+            for task in tasks {
+                for (key, partial) in task.join() {
+                    match groups.entry(key) {
+                        Entry::Occupied(mut e) => {
+                            for (accumulator, partial) in e.get_mut().iter_mut().zip(partial) {
+                                accumulator.merge(partial);
+                            }
+                        }
+                        Entry::Vacant(e) => {
+                            e.insert(partial);
+                        }
+                    }
+                }
+            }
+            groups
+        }
+    };
+    (errors, groups)
+}
+
+fn aggregate_rows(
+    rows: impl IntoIterator<Item = GroupRow>,
+    accumulators: &[MergeableAccumulator],
+) -> MergeableGroups {
+    let mut groups = MergeableGroups::default();
+    for (key, values) in rows {
+        for (accumulator, value) in groups
+            .entry(key)
+            .or_insert_with(|| accumulators.to_vec())
+            .iter_mut()
+            .zip(values)
+        {
+            accumulator.add(value);
+        }
+    }
+    groups
+}
+
 struct FailingAccumulator;
 
 impl Accumulator for FailingAccumulator {
@@ -5600,29 +7204,99 @@ impl EncodedTupleSet {
     }
 
     fn insert(&mut self, tuple: EncodedTuple) {
-        self.map
-            .entry(self.tuple_key(&tuple))
-            .or_default()
-            .push(tuple);
+        self.insert_with_hash(tuple_key_hash(&self.key, &tuple), tuple)
+    }
+
+    fn insert_with_hash(&mut self, hash: u64, tuple: EncodedTuple) {
+        self.map.entry(hash).or_default().push(tuple);
         self.len += 1;
     }
 
     fn get(&self, tuple: &EncodedTuple) -> &[EncodedTuple] {
-        self.map.get(&self.tuple_key(tuple)).map_or(&[], |v| v)
+        self.get_with_hash(tuple_key_hash(&self.key, tuple))
     }
 
-    fn tuple_key(&self, tuple: &EncodedTuple) -> u64 {
-        let mut hasher = DefaultHasher::default();
-        for v in &self.key {
-            if let Some(val) = tuple.get(*v) {
-                val.hash(&mut hasher);
+    fn get_with_hash(&self, hash: u64) -> &[EncodedTuple] {
+        self.map.get(&hash).map_or(&[], |v| v)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+fn tuple_key_hash(key: &[usize], tuple: &EncodedTuple) -> u64 {
+    let mut hasher = DefaultHasher::default();
+    for v in key {
+        if let Some(val) = tuple.get(*v) {
+            val.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// An [`EncodedTupleSet`] split by key hash into partitions that are built in parallel
+struct PartitionedTupleSet {
+    key: Vec<usize>,
+    partitions: Vec<EncodedTupleSet>,
+}
+
+impl PartitionedTupleSet {
+    fn build(key: Vec<usize>, tuples: Vec<EncodedTuple>, parallelism: usize) -> Self {
+        if tuples.len() < MIN_PARALLEL_SIZE {
+            let mut partition = EncodedTupleSet::new(key.clone());
+            partition.extend(tuples);
+            return Self {
+                key,
+                partitions: vec![partition],
+            };
+        }
+
+        // Each thread hashes a slice of the tuples and dispatches them between the partitions
+        let chunk_size = tuples.len().div_ceil(parallelism);
+        let mut chunks = Vec::with_capacity(parallelism);
+        let mut tuples = tuples;
+        while !tuples.is_empty() {
+            chunks.push(tuples.split_off(tuples.len().saturating_sub(chunk_size)));
+        }
+        let mut dispatched = (0..parallelism)
+            .map(|_| Vec::with_capacity(chunks.len()))
+            .collect::<Vec<_>>();
+        let chunk_key = key.clone();
+        for chunk_partitions in map_in_parallel(chunks, move |chunk| {
+            let mut partitions = (0..parallelism)
+                .map(|_| Vec::with_capacity(chunk.len() / parallelism))
+                .collect::<Vec<_>>();
+            for tuple in chunk {
+                let hash = tuple_key_hash(&chunk_key, &tuple);
+                partitions[partition_of(hash, parallelism)].push((hash, tuple));
+            }
+            partitions
+        }) {
+            for (target, partition) in dispatched.iter_mut().zip(chunk_partitions) {
+                target.push(partition);
             }
         }
-        hasher.finish()
+
+        // Each thread builds a partition
+        let partition_key = key.clone();
+        let partitions = map_in_parallel(dispatched, move |parts| {
+            let mut partition = EncodedTupleSet::new(partition_key.clone());
+            for (hash, tuple) in parts.into_iter().flatten() {
+                partition.insert_with_hash(hash, tuple);
+            }
+            partition
+        });
+        Self { key, partitions }
+    }
+
+    fn get(&self, tuple: &EncodedTuple) -> &[EncodedTuple] {
+        let hash = tuple_key_hash(&self.key, tuple);
+        self.partitions[partition_of(hash, self.partitions.len())].get_with_hash(hash)
     }
 
     fn len(&self) -> usize {
-        self.len
+        self.partitions.iter().map(EncodedTupleSet::len).sum()
     }
 }
 
@@ -5692,6 +7366,80 @@ impl EvalNodeWithStats {
         writer.write_event(JsonEvent::EndArray)?;
         writer.write_event(JsonEvent::EndObject)
     }
+
+    /// Writes the node as a line prefixed by `indent` and then its children as an indented tree
+    pub fn text_node(
+        &self,
+        write: &mut impl io::Write,
+        indent: &str,
+        with_stats: bool,
+    ) -> io::Result<()> {
+        writeln!(write, "{}{}", self.label, self.stats_suffix(with_stats))?;
+        for (i, child) in self.children.iter().enumerate() {
+            let (branch, child_indent) = if i + 1 == self.children.len() {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            write!(write, "{indent}{branch}")?;
+            child.text_node(write, &format!("{indent}{child_indent}"), with_stats)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the node and its children as Graphviz DOT statements
+    ///
+    /// `next_id` is the identifier to use for this node, the next available identifier is returned.
+    pub fn dot_node(
+        &self,
+        write: &mut impl io::Write,
+        prefix: &str,
+        next_id: usize,
+        with_stats: bool,
+    ) -> io::Result<usize> {
+        let id = next_id;
+        writeln!(
+            write,
+            "  {prefix}{id} [label=\"{}\"];",
+            escape_dot(&format!("{}{}", self.label, self.stats_suffix(with_stats)))
+        )?;
+        let mut next_id = id + 1;
+        for child in &self.children {
+            writeln!(write, "  {prefix}{id} -> {prefix}{next_id};")?;
+            next_id = child.dot_node(write, prefix, next_id, with_stats)?;
+        }
+        Ok(next_id)
+    }
+
+    fn stats_suffix(&self, with_stats: bool) -> String {
+        if !with_stats {
+            return String::new();
+        }
+        if let Some(duration) = self.exec_duration.get() {
+            format!(
+                " [{} results in {}s]",
+                self.exec_count.get(),
+                duration.as_seconds()
+            )
+        } else {
+            format!(" [{} results]", self.exec_count.get())
+        }
+    }
+}
+
+fn escape_dot(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 impl fmt::Debug for EvalNodeWithStats {
@@ -5712,7 +7460,7 @@ impl fmt::Debug for EvalNodeWithStats {
     }
 }
 
-fn eval_node_label(node: &GraphPattern) -> String {
+fn eval_node_label(node: &GraphPattern, bind_joins: bool) -> String {
     match node {
         GraphPattern::Distinct { .. } => "Distinct(Hash)".to_owned(),
         GraphPattern::Extend {
@@ -5742,12 +7490,24 @@ fn eval_node_label(node: &GraphPattern) -> String {
                 ))
             )
         }
-        GraphPattern::Join { algorithm, .. } => match algorithm {
+        GraphPattern::Join {
+            left,
+            right,
+            algorithm,
+        } => match algorithm {
+            JoinAlgorithm::HashBuildLeftProbeRight { keys }
+                if bind_joins && bind_join_operands(left, right, algorithm).is_some() =>
+            {
+                format!("Join(BindJoin, keys = {})", format_list(keys))
+            }
             JoinAlgorithm::HashBuildLeftProbeRight { keys } => format!(
                 "LeftJoin(HashBuildLeftProbeRight, keys = {})",
                 format_list(keys)
             ),
         },
+        GraphPattern::LeapfrogJoin { variables, .. } => {
+            format!("LeapfrogJoin(variables = {})", format_list(variables))
+        }
         GraphPattern::Lateral { right, .. } => {
             if let GraphPattern::LeftJoin {
                 left: nested_left,
@@ -5792,6 +7552,18 @@ fn eval_node_label(node: &GraphPattern) -> String {
                 )
             )
         }
+        GraphPattern::TopK {
+            expression, limit, ..
+        } => {
+            format!(
+                "TopK({}, limit = {limit})",
+                format_list(
+                    expression
+                        .iter()
+                        .map(spargebra::algebra::OrderExpression::from)
+                )
+            )
+        }
         GraphPattern::Path {
             subject,
             path,
@@ -5807,6 +7579,12 @@ fn eval_node_label(node: &GraphPattern) -> String {
         GraphPattern::Project { variables, .. } => {
             format!("Project({})", format_list(variables))
         }
+        GraphPattern::PropertyFunction {
+            subject,
+            name,
+            object,
+            cost,
+        } => format!("PropertyFunction({subject} {name} {object}, cost = {cost})"),
         GraphPattern::QuadPattern {
             subject,
             predicate,
@@ -5881,4 +7659,56 @@ mod tests {
             "{buffer} is not a valid UUID"
         );
     }
+
+    #[test]
+    fn leapfrog_join_reads_the_smallest_relation() {
+        // A star: all the edges go from or to the hub 0, the pairwise joins have n^2 intermediate results
+        let n = 500;
+        let edges = (1..=n).flat_map(|i| [(0, i), (i, 0)]).collect::<Vec<_>>();
+        let edges = Rc::new(edges);
+        let read = Rc::new(Cell::new(0));
+        // The relation of the triangle pattern on the columns (from, to)
+        let relation = |from: usize, to: usize| {
+            let edges = Rc::clone(&edges);
+            let read = Rc::clone(&read);
+            let scan: Rc<dyn Fn(EncodedTuple) -> EncodedTuplesIterator> =
+                Rc::new(move |tuple: EncodedTuple| {
+                    let edges = Rc::clone(&edges);
+                    let read = Rc::clone(&read);
+                    // Only the returned rows are counted, as with an index lookup
+                    Box::new((0..edges.len()).filter_map(move |i| {
+                        let (s, o) = edges[i];
+                        let (s, o) = (EncodedTerm::from(s), EncodedTerm::from(o));
+                        if tuple.get(from).is_some_and(|v| *v != s)
+                            || tuple.get(to).is_some_and(|v| *v != o)
+                        {
+                            return None;
+                        }
+                        read.set(read.get() + 1);
+                        let mut tuple = tuple.clone();
+                        tuple.set(from, s);
+                        tuple.set(to, o);
+                        Some(Ok(tuple))
+                    }))
+                });
+            LeapfrogRelation {
+                columns: vec![from, to],
+                scan: Rc::clone(&scan),
+                eval: scan,
+            }
+        };
+        let join = Rc::new(LeapfrogJoin {
+            variables: ["a", "b", "c"]
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| (i, Variable::new_unchecked(v)))
+                .collect(),
+            // The static order would scan ?b ?c for each ?a ?b: n^2 rows
+            participants: vec![vec![0, 2], vec![0, 1], vec![1, 2]],
+            relations: vec![relation(0, 1), relation(1, 2), relation(0, 2)],
+        });
+        let results = LeapfrogJoinIterator::new(join, EncodedTuple::with_capacity(3)).count();
+        assert_eq!(results, 0);
+        assert!(read.get() < 30 * n, "{} rows read", read.get());
+    }
 }
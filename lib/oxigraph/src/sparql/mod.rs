@@ -1,141 +1,397 @@
 //! [SPARQL](https://www.w3.org/TR/sparql11-overview/) implementation.
 //!
 //! Stores execute SPARQL. See [`Store`](crate::store::Store::query()) for an example.
+//!
+//! Besides the property paths of SPARQL 1.1, the bounded repetitions `p{n}`, `p{n,m}`, `p{n,}` and `p{,m}` are supported.
+//! The paths themselves can be returned using the `<http://oxigraph.org/path#shortest>` and `<http://oxigraph.org/path#all>` services
+//! that are evaluated locally and return respectively the shortest path to each end node and all the paths without cycles:
+//! ```
+//! use oxigraph::sparql::QueryResults;
+//! use oxigraph::store::Store;
+//!
+//! let store = Store::new()?;
+//! store.update("INSERT DATA { <http://example.com/a> <http://example.com/knows> <http://example.com/b> . <http://example.com/b> <http://example.com/knows> <http://example.com/c> }")?;
+//! // One solution per node of the path with its position and the predicate of the edge leading to it
+//! if let QueryResults::Solutions(solutions) = store.query("PREFIX path: <http://oxigraph.org/path#>
+//!     SELECT ?step ?node ?edge WHERE {
+//!         SERVICE path:shortest {
+//!             <http://example.com/a> <http://example.com/knows>+ <http://example.com/c> .
+//!             [] path:step ?step ; path:node ?node ; path:edge ?edge
+//!         }
+//!     }")? {
+//!     assert_eq!(solutions.count(), 3);
+//! }
+//! # Result::<_, Box<dyn std::error::Error>>::Ok(())
+//! ```
+//! The start of the path must be bound, use `LATERAL` to take it from the other patterns of the query.
+//! `path:index` and `path:length` bind the index of the path and its number of steps.
+//! With `path:all`, the path length must be bounded with `p{n,m}` or with `[] path:maxLength 10`.
 
 mod algebra;
+mod cache;
 mod dataset;
 mod error;
 mod eval;
 mod http;
 mod model;
+mod parallel;
+mod path;
 pub mod results;
 mod service;
 mod update;
+mod view;
 
-use crate::model::{NamedNode, Term};
+use crate::model::{Literal, NamedNode, Term};
 pub use crate::sparql::algebra::{Query, QueryDataset, Update};
+pub use crate::sparql::cache::QueryResultsCache;
+pub(crate) use crate::sparql::cache::ResultsKey;
 use crate::sparql::dataset::DatasetView;
 pub use crate::sparql::error::EvaluationError;
-use crate::sparql::eval::{EvalNodeWithStats, SimpleEvaluator, Timer};
+pub(crate) use crate::sparql::eval::Timer;
+use crate::sparql::eval::{EvalNodeWithStats, SimpleEvaluator};
 pub use crate::sparql::model::{QueryResults, QuerySolution, QuerySolutionIter, QueryTripleIter};
 pub use crate::sparql::service::ServiceHandler;
 use crate::sparql::service::{EmptyServiceHandler, ErrorConversionServiceHandler};
-pub(crate) use crate::sparql::update::evaluate_update;
+pub(crate) use crate::sparql::update::{evaluate_update, explain_update};
+pub use crate::sparql::update::{UpdateExplanation, UpdateOperationExplanation, UpdateSummary};
+pub use crate::sparql::view::ViewRefresh;
+pub(crate) use crate::sparql::view::{view_quads, MaterializedView, ViewRegistry};
 use crate::storage::StorageReader;
 use json_event_parser::{JsonEvent, ToWriteJsonWriter};
+use oxiri::Iri;
 pub use oxrdf::{Variable, VariableNameParseError};
 use oxsdatatypes::{DayTimeDuration, Float};
+use spargebra::term::TriplePattern;
 pub use spargebra::SparqlSyntaxError;
-use sparopt::algebra::GraphPattern;
+use sparopt::algebra::{GraphPattern, GroundTerm, JoinAlgorithm};
 use sparopt::Optimizer;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::error::Error;
+use std::iter::once;
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 use std::{fmt, io};
 
-#[allow(clippy::needless_pass_by_value)]
 pub(crate) fn evaluate_query(
     reader: StorageReader,
     query: impl TryInto<Query, Error = impl Into<EvaluationError>>,
     options: QueryOptions,
     run_stats: bool,
 ) -> Result<(Result<QueryResults, EvaluationError>, QueryExplanation), EvaluationError> {
-    let query = query.try_into().map_err(Into::into)?;
-    let dataset = DatasetView::new(reader, &query.dataset);
-    let start_planning = Timer::now();
-    let (results, plan_node_with_stats, planning_duration) = match query.inner {
-        spargebra::Query::Select {
-            pattern, base_iri, ..
-        } => {
-            let mut pattern = GraphPattern::from(&pattern);
-            if !options.without_optimizations {
-                pattern = Optimizer::optimize_graph_pattern(pattern);
-            }
-            let planning_duration = start_planning.elapsed();
-            let (results, explanation) = SimpleEvaluator::new(
-                Rc::new(dataset),
-                base_iri.map(Rc::new),
-                options.service_handler(),
-                Arc::new(options.custom_functions),
-                run_stats,
-            )
-            .evaluate_select(&pattern);
-            (Ok(results), explanation, planning_duration)
-        }
-        spargebra::Query::Ask {
-            pattern, base_iri, ..
-        } => {
-            let mut pattern = GraphPattern::from(&pattern);
-            if !options.without_optimizations {
-                pattern = Optimizer::optimize_graph_pattern(GraphPattern::Reduced {
-                    inner: Box::new(pattern),
-                });
-            }
-            let planning_duration = start_planning.elapsed();
-            let (results, explanation) = SimpleEvaluator::new(
-                Rc::new(dataset),
-                base_iri.map(Rc::new),
-                options.service_handler(),
-                Arc::new(options.custom_functions),
-                run_stats,
-            )
-            .evaluate_ask(&pattern);
-            (results, explanation, planning_duration)
+    let query = prepare_query(query, &options)?;
+    evaluate_prepared_query(reader, &query, options, run_stats)
+}
+
+/// Prepares the query for a single evaluation with the given options, their bindings are the parameters
+pub(crate) fn prepare_query(
+    query: impl TryInto<Query, Error = impl Into<EvaluationError>>,
+    options: &QueryOptions,
+) -> Result<PreparedQuery, EvaluationError> {
+    PreparedQuery::new(
+        query.try_into().map_err(Into::into)?,
+        options.bindings.iter().map(|(v, _)| v.clone()).collect(),
+        options,
+    )
+}
+
+pub(crate) fn evaluate_prepared_query(
+    reader: StorageReader,
+    query: &PreparedQuery,
+    options: QueryOptions,
+    run_stats: bool,
+) -> Result<(Result<QueryResults, EvaluationError>, QueryExplanation), EvaluationError> {
+    let pattern = query.plan.pattern_with_bindings(&options.bindings)?;
+    let max_result_rows = options.max_result_rows;
+    let evaluator = SimpleEvaluator::new(
+        Rc::new(DatasetView::new(reader, &query.dataset)),
+        query.plan.base_iri.clone().map(Rc::new),
+        options.service_handler(),
+        options.service_batch_size(),
+        options.parallelism(),
+        Arc::new(options.custom_functions),
+        Arc::new(options.property_functions),
+        run_stats,
+    );
+    let (results, plan_node_with_stats) = match &query.plan.form {
+        QueryForm::Select => {
+            let (results, explanation) = evaluator.evaluate_select(&pattern);
+            (Ok(results), explanation)
         }
-        spargebra::Query::Construct {
-            template,
-            pattern,
-            base_iri,
-            ..
-        } => {
-            let mut pattern = GraphPattern::from(&pattern);
-            if !options.without_optimizations {
-                pattern = Optimizer::optimize_graph_pattern(GraphPattern::Reduced {
-                    inner: Box::new(pattern),
-                });
-            }
-            let planning_duration = start_planning.elapsed();
-            let (results, explanation) = SimpleEvaluator::new(
-                Rc::new(dataset),
-                base_iri.map(Rc::new),
-                options.service_handler(),
-                Arc::new(options.custom_functions),
-                run_stats,
-            )
-            .evaluate_construct(&pattern, &template);
-            (Ok(results), explanation, planning_duration)
+        QueryForm::Ask => evaluator.evaluate_ask(&pattern),
+        QueryForm::Construct { template } => {
+            let (results, explanation) = evaluator.evaluate_construct(&pattern, template);
+            (Ok(results), explanation)
         }
-        spargebra::Query::Describe {
-            pattern, base_iri, ..
-        } => {
-            let mut pattern = GraphPattern::from(&pattern);
-            if !options.without_optimizations {
-                pattern = Optimizer::optimize_graph_pattern(GraphPattern::Reduced {
-                    inner: Box::new(pattern),
-                });
-            }
-            let planning_duration = start_planning.elapsed();
-            let (results, explanation) = SimpleEvaluator::new(
-                Rc::new(dataset),
-                base_iri.map(Rc::new),
-                options.service_handler(),
-                Arc::new(options.custom_functions),
-                run_stats,
-            )
-            .evaluate_describe(&pattern);
-            (Ok(results), explanation, planning_duration)
+        QueryForm::Describe => {
+            let (results, explanation) = evaluator.evaluate_describe(&pattern);
+            (Ok(results), explanation)
         }
     };
+    let results = if let Some((max_rows, policy)) = max_result_rows {
+        results.map(|results| results.with_row_limit(max_rows, policy))
+    } else {
+        results
+    };
     let explanation = QueryExplanation {
         inner: plan_node_with_stats,
         with_stats: run_stats,
-        parsing_duration: query.parsing_duration,
-        planning_duration,
+        parsing_duration: query.plan.parsing_duration,
+        planning_duration: query.plan.planning_duration,
     };
     Ok((results, explanation))
 }
 
+/// A [SPARQL query](https://www.w3.org/TR/sparql11-query/) parsed and optimized once to be evaluated many times.
+///
+/// It is built with [`Store::prepare`](crate::store::Store::prepare) or [`Store::prepare_with_parameters`](crate::store::Store::prepare_with_parameters)
+/// and evaluated with [`Store::query_prepared`](crate::store::Store::query_prepared).
+/// The query plan is shared between the clones so cloning is cheap
+/// and a prepared query can be sent to other threads.
+///
+/// ```
+/// use oxigraph::model::*;
+/// use oxigraph::sparql::{QueryOptions, QueryResults, Variable};
+/// use oxigraph::store::Store;
+///
+/// let store = Store::new()?;
+/// let ex = NamedNodeRef::new("http://example.com")?;
+/// store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+///
+/// let s = Variable::new("s")?;
+/// let query = store.prepare_with_parameters("SELECT ?o WHERE { ?s ?p ?o }", [s.clone()])?;
+/// if let QueryResults::Solutions(mut solutions) =
+///     store.query_prepared(&query, QueryOptions::default().with_binding(s, ex.into_owned()))?
+/// {
+///     assert_eq!(solutions.next().unwrap()?.get("o"), Some(&ex.into()));
+/// }
+/// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+/// ```
+#[derive(Clone)]
+pub struct PreparedQuery {
+    plan: Arc<QueryPlan>,
+    dataset: QueryDataset,
+}
+
+impl PreparedQuery {
+    /// Only the property functions and the optimization flag of the options are used
+    ///
+    /// Fails with [`EvaluationError::InvalidBinding`] if a parameter is assigned by a `BIND`.
+    pub(crate) fn new(
+        query: Query,
+        parameters: Vec<Variable>,
+        options: &QueryOptions,
+    ) -> Result<Self, EvaluationError> {
+        let start_planning = Timer::now();
+        let (form, pattern, base_iri) = match &query.inner {
+            spargebra::Query::Select {
+                pattern, base_iri, ..
+            } => (QueryForm::Select, pattern, base_iri),
+            spargebra::Query::Ask {
+                pattern, base_iri, ..
+            } => (QueryForm::Ask, pattern, base_iri),
+            spargebra::Query::Construct {
+                template,
+                pattern,
+                base_iri,
+                ..
+            } => (
+                QueryForm::Construct {
+                    template: template.clone(),
+                },
+                pattern,
+                base_iri,
+            ),
+            spargebra::Query::Describe {
+                pattern, base_iri, ..
+            } => (QueryForm::Describe, pattern, base_iri),
+        };
+        let base_iri = base_iri.clone();
+        let mut pattern = GraphPattern::from(pattern);
+        options.replace_property_functions(&mut pattern);
+        if !parameters.is_empty() {
+            pattern = join_with_values(pattern, parameters_placeholder(&parameters))?;
+        }
+        if !options.without_optimizations {
+            pattern = Optimizer::optimize_graph_pattern(if matches!(form, QueryForm::Select) {
+                pattern
+            } else {
+                GraphPattern::Reduced {
+                    inner: Box::new(pattern),
+                }
+            });
+        }
+        Ok(Self {
+            plan: Arc::new(QueryPlan {
+                source: query.inner,
+                text: OnceLock::new(),
+                form,
+                pattern,
+                base_iri,
+                parameters,
+                parsing_duration: query.parsing_duration,
+                planning_duration: start_planning.elapsed(),
+            }),
+            dataset: query.dataset,
+        })
+    }
+
+    /// The variables whose values are given at evaluation time.
+    pub fn parameters(&self) -> &[Variable] {
+        &self.plan.parameters
+    }
+
+    /// Returns [the query dataset specification](https://www.w3.org/TR/sparql11-query/#specifyingDataset)
+    pub fn dataset(&self) -> &QueryDataset {
+        &self.dataset
+    }
+
+    /// Returns [the query dataset specification](https://www.w3.org/TR/sparql11-query/#specifyingDataset)
+    ///
+    /// The dataset is not used during the query optimization so it can be changed without preparing again the query.
+    pub fn dataset_mut(&mut self) -> &mut QueryDataset {
+        &mut self.dataset
+    }
+
+    /// The serialization of the query, only computed when needed
+    pub(crate) fn text(&self) -> &str {
+        self.plan.text.get_or_init(|| self.plan.source.to_string())
+    }
+}
+
+struct QueryPlan {
+    /// The query as parsed, kept to be serialized
+    source: spargebra::Query,
+    /// The serialization of `source`, used as cache key
+    text: OnceLock<String>,
+    form: QueryForm,
+    pattern: GraphPattern,
+    base_iri: Option<Iri<String>>,
+    parameters: Vec<Variable>,
+    parsing_duration: Option<DayTimeDuration>,
+    planning_duration: Option<DayTimeDuration>,
+}
+
+impl QueryPlan {
+    /// Sets the parameter values in the plan and joins the other bindings with it
+    fn pattern_with_bindings(
+        &self,
+        bindings: &[(Variable, Term)],
+    ) -> Result<Cow<'_, GraphPattern>, EvaluationError> {
+        if self.parameters.is_empty() && bindings.is_empty() {
+            return Ok(Cow::Borrowed(&self.pattern));
+        }
+        let mut parameter_values = vec![None; self.parameters.len()];
+        let mut other_variables = Vec::new();
+        let mut other_values = Vec::new();
+        for (variable, value) in bindings {
+            let value = GroundTerm::try_from(value.clone())
+                .map_err(|()| EvaluationError::InvalidBinding(variable.clone()))?;
+            if let Some(i) = self.parameters.iter().position(|p| p == variable) {
+                parameter_values[i] = Some(value);
+            } else {
+                other_variables.push(variable.clone());
+                other_values.push(Some(value));
+            }
+        }
+        let mut pattern = self.pattern.clone();
+        if !self.parameters.is_empty() {
+            let values = GraphPattern::Values {
+                variables: self.parameters.clone(),
+                bindings: vec![parameter_values
+                    .into_iter()
+                    .zip(&self.parameters)
+                    .map(|(value, parameter)| {
+                        value
+                            .map(Some)
+                            .ok_or_else(|| EvaluationError::UnboundParameter(parameter.clone()))
+                    })
+                    .collect::<Result<_, _>>()?],
+            };
+            let placeholder = parameters_placeholder(&self.parameters);
+            if let Some(values) = replace_pattern(&mut pattern, &placeholder, values) {
+                // The optimizer got rid of the placeholder, we still make sure the values are set
+                pattern = join_with_values(pattern, values)?;
+            }
+        }
+        if !other_variables.is_empty() {
+            pattern = join_with_values(
+                pattern,
+                GraphPattern::Values {
+                    variables: other_variables,
+                    bindings: vec![other_values],
+                },
+            )?;
+        }
+        Ok(Cow::Owned(pattern))
+    }
+}
+
+enum QueryForm {
+    Select,
+    Ask,
+    Construct { template: Vec<TriplePattern> },
+    Describe,
+}
+
+/// The values standing for the parameters during the query optimization.
+///
+/// There is a row with an IRI and a row with a literal so that the optimizer assumes the parameters are bound
+/// without relying on their type.
+fn parameters_placeholder(parameters: &[Variable]) -> GraphPattern {
+    let iri = GroundTerm::from(NamedNode::new_unchecked("urn:oxigraph:parameter"));
+    let literal = GroundTerm::from(Literal::new_simple_literal("parameter"));
+    GraphPattern::Values {
+        variables: parameters.to_vec(),
+        bindings: vec![
+            vec![Some(iri); parameters.len()],
+            vec![Some(literal); parameters.len()],
+        ],
+    }
+}
+
+/// Replaces the first occurrence of `target` in `pattern` by `replacement`
+///
+/// Returns the replacement if `target` has not been found.
+fn replace_pattern(
+    pattern: &mut GraphPattern,
+    target: &GraphPattern,
+    replacement: GraphPattern,
+) -> Option<GraphPattern> {
+    if pattern == target {
+        *pattern = replacement;
+        return None;
+    }
+    match pattern {
+        GraphPattern::QuadPattern { .. }
+        | GraphPattern::Path { .. }
+        | GraphPattern::PropertyFunction { .. }
+        | GraphPattern::Values { .. } => Some(replacement),
+        GraphPattern::Join { left, right, .. }
+        | GraphPattern::LeftJoin { left, right, .. }
+        | GraphPattern::Lateral { left, right }
+        | GraphPattern::Minus { left, right, .. } => {
+            replace_pattern(right, target, replace_pattern(left, target, replacement)?)
+        }
+        GraphPattern::Union { inner } | GraphPattern::LeapfrogJoin { inner, .. } => inner
+            .iter_mut()
+            .try_fold(replacement, |replacement, inner| {
+                replace_pattern(inner, target, replacement)
+            }),
+        GraphPattern::Filter { inner, .. }
+        | GraphPattern::Extend { inner, .. }
+        | GraphPattern::OrderBy { inner, .. }
+        | GraphPattern::TopK { inner, .. }
+        | GraphPattern::Project { inner, .. }
+        | GraphPattern::Distinct { inner }
+        | GraphPattern::Reduced { inner }
+        | GraphPattern::Slice { inner, .. }
+        | GraphPattern::Group { inner, .. }
+        | GraphPattern::Service { inner, .. } => replace_pattern(inner, target, replacement),
+    }
+}
+
 /// Options for SPARQL query evaluation.
 ///
 ///
@@ -158,14 +414,41 @@ pub(crate) fn evaluate_query(
 pub struct QueryOptions {
     service_handler: Option<Arc<dyn ServiceHandler<Error = EvaluationError>>>,
     custom_functions: CustomFunctionRegistry,
+    property_functions: PropertyFunctionRegistry,
     http_timeout: Option<Duration>,
     http_redirection_limit: usize,
     without_optimizations: bool,
+    bindings: Vec<(Variable, Term)>,
+    service_batch_size: Option<usize>,
+    parallelism: Option<usize>,
+    results_cache: Option<QueryResultsCache>,
+    max_result_rows: Option<(usize, RowLimitPolicy)>,
+}
+
+/// What to do when a query returns more solutions or triples than allowed by [`QueryOptions::with_max_result_rows`].
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+#[non_exhaustive]
+pub enum RowLimitPolicy {
+    /// The results are stopped at the limit and flagged as truncated,
+    /// see [`QuerySolutionIter::is_truncated`] and [`QueryTripleIter::is_truncated`].
+    Truncate,
+    /// An [`EvaluationError::ResultRowsLimitExceeded`] error is returned after the last allowed result.
+    Error,
 }
 
 pub(crate) type CustomFunctionRegistry =
     HashMap<NamedNode, Arc<dyn (Fn(&[Term]) -> Option<Term>) + Send + Sync>>;
 
+pub(crate) type PropertyFunctionRegistry =
+    HashMap<NamedNode, (usize, Arc<PropertyFunctionEvaluator>)>;
+
+pub(crate) type PropertyFunctionEvaluator = dyn (Fn(
+        Option<&Term>,
+        Option<&Term>,
+    ) -> Box<dyn Iterator<Item = Result<(Term, Term), EvaluationError>>>)
+    + Send
+    + Sync;
+
 impl QueryOptions {
     /// Use a given [`ServiceHandler`] to execute [SPARQL 1.1 Federated Query](https://www.w3.org/TR/sparql11-federated-query/) SERVICE calls.
     #[inline]
@@ -185,6 +468,90 @@ impl QueryOptions {
         self
     }
 
+    /// Enables the bind joins with `SERVICE` and sets the maximal number of solutions sent at once to a service.
+    ///
+    /// When a `SERVICE` pattern is joined with other patterns, the solutions of the other patterns are computed first
+    /// and their values of the join variables are sent to the service by blocks of this size using [`ServiceHandler::handle_batch`]
+    /// (by default as a `VALUES` clause that SPARQL 1.0 endpoints do not support).
+    /// The service results are then joined back with the solutions they originate from.
+    /// A size of `1` sends one request per solution.
+    ///
+    /// By default, the bind joins are disabled: the `SERVICE` pattern is sent once, without the other solutions.
+    #[inline]
+    #[must_use]
+    pub fn with_service_batch_size(mut self, batch_size: usize) -> Self {
+        self.service_batch_size = Some(batch_size.max(1));
+        self
+    }
+
+    /// Sets the number of threads the evaluation of a single query might use.
+    ///
+    /// The evaluation of the expensive operators is then split between threads:
+    /// large index scans are partitioned by key ranges (only with the on-disk storage),
+    /// the hash tables of joins are built and probed in parallel
+    /// and the `COUNT`, `SUM`, `AVG` and `SAMPLE` aggregates (without `DISTINCT`) are computed by partial aggregation.
+    /// Small inputs are still processed on the calling thread
+    /// and only the first scan of a pattern is partitioned, not the scans done for each solution of a join.
+    /// The threads are taken from a pool shared by all queries with one thread per CPU,
+    /// the calling thread does the work when no pool thread is idle.
+    ///
+    /// By default this value is `1` i.e. the query is evaluated on the calling thread only.
+    /// This option is ignored on WebAssembly.
+    ///
+    /// ```
+    /// use oxigraph::sparql::QueryOptions;
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// store.query_opt(
+    ///     "SELECT (COUNT(*) AS ?c) WHERE { ?s ?p ?o }",
+    ///     QueryOptions::default().with_parallelism(4),
+    /// )?;
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = Some(parallelism.max(1));
+        self
+    }
+
+    /// Uses the given cache to reuse the results of the same query evaluated on the same version of the store.
+    ///
+    /// See [`QueryResultsCache`] for an example.
+    #[inline]
+    #[must_use]
+    pub fn with_results_cache(mut self, cache: QueryResultsCache) -> Self {
+        self.results_cache = Some(cache);
+        self
+    }
+
+    /// Limits the number of solutions or triples returned by the query to `max_rows`.
+    ///
+    /// The evaluation is stopped as soon as the limit is exceeded and `policy` decides if the results are truncated or if an error is returned.
+    ///
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::sparql::{QueryOptions, QueryResults, RowLimitPolicy};
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// if let QueryResults::Solutions(mut solutions) = store.query_opt(
+    ///     "SELECT ?i WHERE { VALUES ?i { 1 2 3 } }",
+    ///     QueryOptions::default().with_max_result_rows(2, RowLimitPolicy::Truncate),
+    /// )? {
+    ///     assert_eq!(solutions.by_ref().count(), 2);
+    ///     assert!(solutions.is_truncated());
+    /// }
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_max_result_rows(mut self, max_rows: usize, policy: RowLimitPolicy) -> Self {
+        self.max_result_rows = Some((max_rows, policy));
+        self
+    }
+
     /// Sets a timeout for HTTP requests done during SPARQL evaluation.
     #[cfg(feature = "http-client")]
     #[inline]
@@ -240,6 +607,169 @@ impl QueryOptions {
         self
     }
 
+    /// Adds a property function, also known as magic predicate.
+    ///
+    /// The triple patterns whose predicate is `name` are not matched against the dataset.
+    /// Their solutions are computed by `evaluator` that gets the values of the subject and the object, `None` if unbound,
+    /// and returns the `(subject, object)` pairs to bind.
+    /// The returned pairs that are not compatible with the bound values are ignored.
+    /// The graph name of the triple pattern is ignored and the triple patterns inside of `SERVICE` are not evaluated with the property functions.
+    ///
+    /// The query optimizer does not know anything about the function except `cost`,
+    /// the estimated number of pairs returned by a call with a bound subject or object.
+    /// The calls with unbound subject and object are assumed to be much more expensive so the optimizer tries to bind one of them first.
+    /// Queries prepared with [`Store::prepare`](crate::store::Store::prepare) are planned without knowing the property functions given at evaluation,
+    /// their triple patterns are still evaluated with them.
+    ///
+    /// Example with a function splitting a string into words:
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::sparql::{QueryOptions, QueryResults};
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    ///
+    /// if let QueryResults::Solutions(solutions) = store.query_opt(
+    ///     "SELECT ?word WHERE { \"Hello World\" <http://example.com/splitIntoWords> ?word }",
+    ///     QueryOptions::default().with_property_function(
+    ///         NamedNode::new("http://example.com/splitIntoWords")?,
+    ///         10,
+    ///         |subject, _| {
+    ///             let Some(Term::Literal(text)) = subject else {
+    ///                 return Err("The subject must be a bound string".into());
+    ///             };
+    ///             let subject = Term::from(text.clone());
+    ///             Ok(text
+    ///                 .value()
+    ///                 .split_whitespace()
+    ///                 .map(|word| Ok((subject.clone(), Literal::from(word).into())))
+    ///                 .collect::<Vec<_>>())
+    ///         },
+    ///     ),
+    /// )? {
+    ///     let words = solutions
+    ///         .map(|s| Ok(s?.get("word").cloned()))
+    ///         .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+    ///     assert_eq!(
+    ///         words,
+    ///         [Some(Literal::from("Hello").into()), Some(Literal::from("World").into())]
+    ///     );
+    /// }
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_property_function<I>(
+        mut self,
+        name: NamedNode,
+        cost: usize,
+        evaluator: impl Fn(Option<&Term>, Option<&Term>) -> Result<I, Box<dyn Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self
+    where
+        I: IntoIterator<Item = Result<(Term, Term), Box<dyn Error + Send + Sync>>>,
+        I::IntoIter: 'static,
+    {
+        let evaluator: Arc<PropertyFunctionEvaluator> = Arc::new(
+            move |subject: Option<&Term>,
+                  object: Option<&Term>|
+                  -> Box<dyn Iterator<Item = Result<(Term, Term), EvaluationError>>> {
+                match evaluator(subject, object) {
+                    Ok(pairs) => Box::new(
+                        pairs
+                            .into_iter()
+                            .map(|pair| pair.map_err(EvaluationError::PropertyFunction)),
+                    ),
+                    Err(error) => Box::new(once(Err(EvaluationError::PropertyFunction(error)))),
+                }
+            },
+        );
+        self.property_functions.insert(name, (cost, evaluator));
+        self
+    }
+
+    /// Binds a variable to a given value before evaluating the query.
+    ///
+    /// It is a safe alternative to the insertion of values in the query string:
+    /// the query is evaluated as if its pattern was joined with a `VALUES` clause binding the variable.
+    /// The values are joined inside of the solution modifiers and aggregations so the variable does not need to be selected,
+    /// and the query optimizer is aware of them.
+    /// Binding again a variable replaces its previous value.
+    ///
+    /// Blank nodes are not allowed as values and variables assigned by a `BIND` can't be bound,
+    /// the evaluation fails with [`EvaluationError::InvalidBinding`] in these cases.
+    /// The bindings are only used by queries and not by updates.
+    ///
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::sparql::{QueryOptions, QueryResults, Variable};
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    ///
+    /// if let QueryResults::Solutions(mut solutions) = store.query_opt(
+    ///     "SELECT ?o WHERE { ?s ?p ?o }",
+    ///     QueryOptions::default().with_binding(Variable::new("s")?, ex.into_owned()),
+    /// )? {
+    ///     assert_eq!(solutions.next().unwrap()?.get("o"), Some(&ex.into()));
+    /// }
+    /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_binding(mut self, variable: Variable, value: impl Into<Term>) -> Self {
+        let value = value.into();
+        if let Some((_, existing)) = self.bindings.iter_mut().find(|(v, _)| *v == variable) {
+            *existing = value;
+        } else {
+            self.bindings.push((variable, value));
+        }
+        self
+    }
+
+    fn parallelism(&self) -> usize {
+        if cfg!(target_family = "wasm") {
+            1
+        } else {
+            self.parallelism.unwrap_or(1)
+        }
+    }
+
+    /// Returns the results cache with the key of the query in it
+    pub(crate) fn results_cache_entry(
+        &self,
+        store: u64,
+        query: String,
+        dataset: &QueryDataset,
+    ) -> Option<(QueryResultsCache, ResultsKey)> {
+        Some((
+            self.results_cache.clone()?,
+            ResultsKey {
+                store,
+                query,
+                dataset: dataset.clone(),
+                bindings: self.bindings.clone(),
+                row_limit: self.max_result_rows,
+            },
+        ))
+    }
+
+    fn service_batch_size(&self) -> Option<usize> {
+        self.service_batch_size
+    }
+
+    fn replace_property_functions(&self, pattern: &mut GraphPattern) {
+        if !self.property_functions.is_empty() {
+            pattern.replace_property_functions(&|name| {
+                self.property_functions.get(name).map(|(cost, _)| *cost)
+            });
+        }
+    }
+
     fn service_handler(&self) -> Arc<dyn ServiceHandler<Error = EvaluationError>> {
         self.service_handler.clone().unwrap_or_else(|| {
             if cfg!(feature = "http-client") {
@@ -262,16 +792,106 @@ impl QueryOptions {
     }
 }
 
+/// Joins the values with the pattern, below the solution modifiers, filters and aggregations
+///
+/// This way the bound variables are visible from the `FILTER`, `BIND` and `GROUP BY` expressions.
+/// Fails with [`EvaluationError::InvalidBinding`] if a bound variable is assigned by a `BIND`.
+fn join_with_values(
+    pattern: GraphPattern,
+    values: GraphPattern,
+) -> Result<GraphPattern, EvaluationError> {
+    Ok(match pattern {
+        GraphPattern::Slice {
+            inner,
+            start,
+            length,
+        } => GraphPattern::Slice {
+            inner: Box::new(join_with_values(*inner, values)?),
+            start,
+            length,
+        },
+        GraphPattern::Distinct { inner } => GraphPattern::Distinct {
+            inner: Box::new(join_with_values(*inner, values)?),
+        },
+        GraphPattern::Reduced { inner } => GraphPattern::Reduced {
+            inner: Box::new(join_with_values(*inner, values)?),
+        },
+        GraphPattern::Project { inner, variables } => GraphPattern::Project {
+            inner: Box::new(join_with_values(*inner, values)?),
+            variables,
+        },
+        GraphPattern::OrderBy { inner, expression } => GraphPattern::OrderBy {
+            inner: Box::new(join_with_values(*inner, values)?),
+            expression,
+        },
+        GraphPattern::TopK {
+            inner,
+            expression,
+            limit,
+        } => GraphPattern::TopK {
+            inner: Box::new(join_with_values(*inner, values)?),
+            expression,
+            limit,
+        },
+        GraphPattern::Filter { expression, inner } => GraphPattern::Filter {
+            expression,
+            inner: Box::new(join_with_values(*inner, values)?),
+        },
+        GraphPattern::Extend {
+            inner,
+            variable,
+            expression,
+        } => {
+            // The BIND would fail or silently drop the solutions if its variable is already bound
+            if matches!(&values, GraphPattern::Values { variables, .. } if variables.contains(&variable))
+            {
+                return Err(EvaluationError::InvalidBinding(variable));
+            }
+            GraphPattern::Extend {
+                inner: Box::new(join_with_values(*inner, values)?),
+                variable,
+                expression,
+            }
+        }
+        GraphPattern::Group {
+            inner,
+            variables,
+            aggregates,
+        } => GraphPattern::Group {
+            inner: Box::new(join_with_values(*inner, values)?),
+            variables,
+            aggregates,
+        },
+        pattern => GraphPattern::join(values, pattern, JoinAlgorithm::default()),
+    })
+}
+
 /// Options for SPARQL update evaluation.
 #[derive(Clone, Default)]
 pub struct UpdateOptions {
     query_options: QueryOptions,
+    max_recorded_changes: Option<usize>,
+}
+
+impl UpdateOptions {
+    /// Keeps the inserted and deleted quads in the [`UpdateSummary`] returned by [`Store::update_with_summary`](crate::store::Store::update_with_summary).
+    ///
+    /// At most `limit` inserted quads and `limit` deleted quads are kept, the following ones are only counted.
+    #[inline]
+    #[must_use]
+    pub fn with_recorded_changes(mut self, limit: usize) -> Self {
+        self.max_recorded_changes = Some(limit);
+        self
+    }
 }
 
 impl From<QueryOptions> for UpdateOptions {
     #[inline]
     fn from(query_options: QueryOptions) -> Self {
-        Self { query_options }
+        Self {
+            query_options,
+            max_recorded_changes: None,
+        }
     }
 }
 
@@ -305,6 +925,49 @@ impl QueryExplanation {
         self.inner.json_node(&mut writer, self.with_stats)?;
         writer.write_event(JsonEvent::EndObject)
     }
+
+    /// Writes the explanation as a human-readable tree, one plan node per line.
+    ///
+    /// ```
+    /// use oxigraph::sparql::QueryOptions;
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let (_, explanation) = store.explain_query_opt(
+    ///     "SELECT ?s WHERE { ?s ?p ?o }",
+    ///     QueryOptions::default(),
+    ///     false,
+    /// )?;
+    /// let mut buf = Vec::new();
+    /// explanation.write_in_text(&mut buf)?;
+    /// assert!(String::from_utf8(buf)?.contains("QuadPattern"));
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn write_in_text(&self, mut write: impl io::Write) -> io::Result<()> {
+        if let Some(parsing_duration) = self.parsing_duration {
+            writeln!(
+                write,
+                "parsing duration: {}s",
+                parsing_duration.as_seconds()
+            )?;
+        }
+        if let Some(planning_duration) = self.planning_duration {
+            writeln!(
+                write,
+                "planning duration: {}s",
+                planning_duration.as_seconds()
+            )?;
+        }
+        self.inner.text_node(&mut write, "", self.with_stats)
+    }
+
+    /// Writes the plan as a [Graphviz DOT](https://graphviz.org/doc/info/lang.html) directed graph.
+    pub fn write_in_dot(&self, mut write: impl io::Write) -> io::Result<()> {
+        writeln!(write, "digraph plan {{")?;
+        writeln!(write, "  node [shape=box];")?;
+        self.inner.dot_node(&mut write, "n", 0, self.with_stats)?;
+        writeln!(write, "}}")
+    }
 }
 
 impl fmt::Debug for QueryExplanation {
@@ -1,12 +1,16 @@
 use crate::io::{RdfFormat, RdfSerializer};
+use crate::metrics::EndHookIterator;
 use crate::model::*;
 use crate::sparql::error::EvaluationError;
 use crate::sparql::results::{
     FromReadQueryResultsReader, FromReadSolutionsReader, QueryResultsFormat,
     QueryResultsParseError, QueryResultsParser, QueryResultsSerializer,
 };
+use crate::sparql::RowLimitPolicy;
 pub use sparesults::QuerySolution;
+use std::cell::Cell;
 use std::io::{Read, Write};
+use std::rc::Rc;
 use std::sync::Arc;
 
 /// Results of a [SPARQL query](https://www.w3.org/TR/sparql11-query/).
@@ -20,6 +24,63 @@ pub enum QueryResults {
 }
 
 impl QueryResults {
+    /// Calls `on_end` with the success status once the results have been consumed or dropped.
+    pub(crate) fn on_end(self, on_end: impl FnOnce(bool) + 'static) -> Self {
+        match self {
+            Self::Solutions(QuerySolutionIter {
+                variables,
+                iter,
+                truncated,
+            }) => Self::Solutions(QuerySolutionIter {
+                variables,
+                iter: Box::new(EndHookIterator::new(iter, on_end)),
+                truncated,
+            }),
+            Self::Boolean(value) => {
+                on_end(true);
+                Self::Boolean(value)
+            }
+            Self::Graph(QueryTripleIter { iter, truncated }) => Self::Graph(QueryTripleIter {
+                iter: Box::new(EndHookIterator::new(iter, on_end)),
+                truncated,
+            }),
+        }
+    }
+
+    /// Stops the solutions or the triples after `max_rows` of them following `policy`
+    pub(crate) fn with_row_limit(self, max_rows: usize, policy: RowLimitPolicy) -> Self {
+        match self {
+            Self::Solutions(QuerySolutionIter {
+                variables,
+                iter,
+                truncated,
+            }) => Self::Solutions(QuerySolutionIter {
+                variables,
+                iter: Box::new(RowLimitIterator {
+                    inner: iter,
+                    remaining: max_rows,
+                    max_rows,
+                    policy,
+                    done: false,
+                    truncated: Rc::clone(&truncated),
+                }),
+                truncated,
+            }),
+            Self::Boolean(value) => Self::Boolean(value),
+            Self::Graph(QueryTripleIter { iter, truncated }) => Self::Graph(QueryTripleIter {
+                iter: Box::new(RowLimitIterator {
+                    inner: iter,
+                    remaining: max_rows,
+                    max_rows,
+                    policy,
+                    done: false,
+                    truncated: Rc::clone(&truncated),
+                }),
+                truncated,
+            }),
+        }
+    }
+
     /// Reads a SPARQL query results serialization.
     pub fn read(
         read: impl Read + 'static,
@@ -175,6 +236,7 @@ impl<R: Read + 'static> From<FromReadQueryResultsReader<R>> for QueryResults {
 pub struct QuerySolutionIter {
     variables: Arc<[Variable]>,
     iter: Box<dyn Iterator<Item = Result<QuerySolution, EvaluationError>>>,
+    truncated: Rc<Cell<bool>>,
 }
 
 impl QuerySolutionIter {
@@ -189,6 +251,7 @@ impl QuerySolutionIter {
             iter: Box::new(
                 iter.map(move |t| t.map(|values| (Arc::clone(&variables), values).into())),
             ),
+            truncated: Rc::default(),
         }
     }
 
@@ -211,6 +274,19 @@ impl QuerySolutionIter {
     pub fn variables(&self) -> &[Variable] {
         &self.variables
     }
+
+    /// Returns `true` if some solutions have been dropped because of the [`RowLimitPolicy::Truncate`] limit
+    /// set with [`QueryOptions::with_max_result_rows`](super::QueryOptions::with_max_result_rows).
+    ///
+    /// It is only known once all the returned solutions have been consumed.
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated.get()
+    }
+
+    pub(crate) fn mark_truncated(&self) {
+        self.truncated.set(true);
+    }
 }
 
 impl<R: Read + 'static> From<FromReadSolutionsReader<R>> for QuerySolutionIter {
@@ -218,6 +294,7 @@ impl<R: Read + 'static> From<FromReadSolutionsReader<R>> for QuerySolutionIter {
         Self {
             variables: reader.variables().into(),
             iter: Box::new(reader.map(|t| t.map_err(EvaluationError::from))),
+            truncated: Rc::default(),
         }
     }
 }
@@ -251,7 +328,32 @@ impl Iterator for QuerySolutionIter {
 /// # Result::<_,Box<dyn std::error::Error>>::Ok(())
 /// ```
 pub struct QueryTripleIter {
-    pub(crate) iter: Box<dyn Iterator<Item = Result<Triple, EvaluationError>>>,
+    iter: Box<dyn Iterator<Item = Result<Triple, EvaluationError>>>,
+    truncated: Rc<Cell<bool>>,
+}
+
+impl QueryTripleIter {
+    pub(crate) fn new(
+        iter: impl Iterator<Item = Result<Triple, EvaluationError>> + 'static,
+    ) -> Self {
+        Self {
+            iter: Box::new(iter),
+            truncated: Rc::default(),
+        }
+    }
+
+    /// Returns `true` if some triples have been dropped because of the [`RowLimitPolicy::Truncate`] limit
+    /// set with [`QueryOptions::with_max_result_rows`](super::QueryOptions::with_max_result_rows).
+    ///
+    /// It is only known once all the returned triples have been consumed.
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated.get()
+    }
+
+    pub(crate) fn mark_truncated(&self) {
+        self.truncated.set(true);
+    }
 }
 
 impl Iterator for QueryTripleIter {
@@ -276,6 +378,54 @@ impl Iterator for QueryTripleIter {
     }
 }
 
+/// Stops after `max_rows` results and then either flags the results as truncated or returns an error
+struct RowLimitIterator<I> {
+    inner: I,
+    remaining: usize,
+    max_rows: usize,
+    policy: RowLimitPolicy,
+    done: bool,
+    truncated: Rc<Cell<bool>>,
+}
+
+impl<T, I: Iterator<Item = Result<T, EvaluationError>>> Iterator for RowLimitIterator<I> {
+    type Item = Result<T, EvaluationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining > 0 {
+            let result = self.inner.next()?;
+            if result.is_ok() {
+                self.remaining -= 1;
+            }
+            return Some(result);
+        }
+        if self.done {
+            return None;
+        }
+        // We check if there is an other result without returning it
+        match self.inner.next()? {
+            Ok(_) => {
+                self.done = true;
+                match self.policy {
+                    RowLimitPolicy::Truncate => {
+                        self.truncated.set(true);
+                        None
+                    }
+                    RowLimitPolicy::Error => {
+                        Some(Err(EvaluationError::ResultRowsLimitExceeded(self.max_rows)))
+                    }
+                }
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (min, max) = self.inner.size_hint();
+        (min.min(self.remaining), max)
+    }
+}
+
 #[cfg(test)]
 #[allow(clippy::panic_in_result_fn)]
 mod tests {
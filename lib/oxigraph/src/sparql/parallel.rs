@@ -0,0 +1,273 @@
+//! Building blocks to evaluate parts of a query on multiple threads.
+//!
+//! The work is done by a pool of threads shared by all the queries.
+//! Tasks are only given to idle threads, the calling thread does the rest of the work itself.
+//! So a task never waits behind the tasks of another query, even if that query consumer stopped reading its results.
+
+use std::mem::take;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread::{self, available_parallelism, Builder};
+use std::vec;
+
+/// Number of elements sent at once from a thread to another
+pub const CHUNK_SIZE: usize = 1024;
+
+/// Minimal number of elements to process before it becomes worth using multiple threads
+pub const MIN_PARALLEL_SIZE: usize = 16 * CHUNK_SIZE;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// The threads shared by the parallel evaluation of all queries
+struct WorkerPool {
+    /// Senders to the threads not running any task
+    idle: Mutex<Vec<Sender<Task>>>,
+    /// Number of started threads, the pool is grown lazily
+    started: Mutex<usize>,
+    max_threads: usize,
+}
+
+fn pool() -> &'static WorkerPool {
+    static POOL: OnceLock<WorkerPool> = OnceLock::new();
+    POOL.get_or_init(|| WorkerPool {
+        idle: Mutex::new(Vec::new()),
+        started: Mutex::new(0),
+        max_threads: available_parallelism().map_or(1, usize::from),
+    })
+}
+
+impl WorkerPool {
+    fn release(&self, worker: Sender<Task>) {
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(worker);
+    }
+
+    fn start_thread(&'static self) -> Option<Sender<Task>> {
+        let mut started = self.started.lock().unwrap_or_else(PoisonError::into_inner);
+        if *started >= self.max_threads {
+            return None;
+        }
+        let (sender, receiver) = channel::<Task>();
+        let own_sender = sender.clone();
+        Builder::new()
+            .name("oxigraph-worker".into())
+            .spawn(move || {
+                for task in receiver {
+                    task();
+                    self.release(own_sender.clone());
+                }
+            })
+            .ok()?;
+        *started += 1;
+        Some(sender)
+    }
+}
+
+/// Returns up to `count` workers not running any task, starting new threads if the pool is not full yet
+pub fn idle_workers(count: usize) -> Vec<Worker> {
+    let pool = pool();
+    let mut workers = {
+        let mut idle = pool.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        let split = idle.len().saturating_sub(count);
+        idle.split_off(split)
+    };
+    while workers.len() < count {
+        let Some(worker) = pool.start_thread() else {
+            break;
+        };
+        workers.push(worker);
+    }
+    workers
+        .into_iter()
+        .map(|sender| Worker {
+            sender: Some(sender),
+        })
+        .collect()
+}
+
+/// A pool thread reserved to run a task
+///
+/// It goes back to the pool if dropped without running a task.
+pub struct Worker {
+    /// `None` once the task is sent, the thread then goes back to the pool by itself
+    sender: Option<Sender<Task>>,
+}
+
+impl Worker {
+    pub fn spawn<T: Send + 'static>(
+        mut self,
+        task: impl FnOnce() -> T + Send + 'static,
+    ) -> TaskHandle<T> {
+        let (result_sender, result_receiver) = sync_channel(1);
+        let task: Task = Box::new(move || {
+            result_sender
+                .send(catch_unwind(AssertUnwindSafe(task)))
+                .ok();
+        });
+        match self.sender.take() {
+            Some(sender) => {
+                if let Err(error) = sender.send(task) {
+                    // The thread is gone, the task is run on the current thread
+                    (error.0)();
+                }
+            }
+            None => task(),
+        }
+        TaskHandle {
+            receiver: result_receiver,
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            pool().release(sender);
+        }
+    }
+}
+
+/// The result of a task run by a [`Worker`]
+pub struct TaskHandle<T> {
+    receiver: Receiver<thread::Result<T>>,
+}
+
+impl<T> TaskHandle<T> {
+    /// Waits for the end of the task and returns its result, resuming its panic if it panicked
+    pub fn join(self) -> T {
+        match self.receiver.recv() {
+            Ok(Ok(result)) => result,
+            Ok(Err(panic)) => resume_unwind(panic),
+            Err(_) => resume_unwind(Box::new("The worker thread has stopped")),
+        }
+    }
+}
+
+/// Applies `f` to all the inputs on the idle workers and on the current thread and returns the outputs in the inputs order
+pub fn map_in_parallel<I: Send + 'static, O: Send + 'static>(
+    inputs: Vec<I>,
+    f: impl Fn(I) -> O + Send + Sync + 'static,
+) -> Vec<O> {
+    let f = Arc::new(f);
+    let mut inputs = inputs.into_iter();
+    let tasks = idle_workers(inputs.len().saturating_sub(1))
+        .into_iter()
+        .zip(inputs.by_ref())
+        .map(|(worker, input)| {
+            let f = Arc::clone(&f);
+            worker.spawn(move || f(input))
+        })
+        .collect::<Vec<_>>();
+    let local = inputs.map(&*f).collect::<Vec<_>>();
+    tasks
+        .into_iter()
+        .map(TaskHandle::join)
+        .chain(local)
+        .collect()
+}
+
+/// Consumes the iterators on the given workers and outputs all their elements.
+///
+/// Each worker consumes its share of the iterators one after the other.
+/// The elements of the same iterator are returned in order but the iterators are interleaved.
+pub struct ParallelIterator<T> {
+    receiver: Receiver<Vec<T>>,
+    current: vec::IntoIter<T>,
+    tasks: Vec<TaskHandle<()>>,
+}
+
+impl<T: Send + 'static> ParallelIterator<T> {
+    /// `workers` must not be empty
+    pub fn new(iters: Vec<Box<dyn Iterator<Item = T> + Send>>, workers: Vec<Worker>) -> Self {
+        let (sender, receiver) = sync_channel(2 * workers.len());
+        let mut shares = workers.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        for (i, iter) in iters.into_iter().enumerate() {
+            shares[i % workers.len()].push(iter);
+        }
+        let tasks = workers
+            .into_iter()
+            .zip(shares)
+            .map(|(worker, share)| {
+                let sender = sender.clone();
+                worker.spawn(move || {
+                    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+                    for element in share.into_iter().flatten() {
+                        chunk.push(element);
+                        if chunk.len() >= CHUNK_SIZE && sender.send(take(&mut chunk)).is_err() {
+                            return; // The consumer is gone
+                        }
+                    }
+                    if !chunk.is_empty() {
+                        sender.send(chunk).ok();
+                    }
+                })
+            })
+            .collect();
+        Self {
+            receiver,
+            current: Vec::new().into_iter(),
+            tasks,
+        }
+    }
+}
+
+impl<T> Iterator for ParallelIterator<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            if let Some(element) = self.current.next() {
+                return Some(element);
+            }
+            if let Ok(chunk) = self.receiver.recv() {
+                self.current = chunk.into_iter();
+            } else {
+                // All the tasks are done, we make sure none of them panicked
+                for task in take(&mut self.tasks) {
+                    task.join();
+                }
+                return None;
+            }
+        }
+    }
+}
+
+/// The partition among `partitions` a hash belongs to
+pub fn partition_of(hash: u64, partitions: usize) -> usize {
+    usize::try_from(hash % u64::try_from(partitions).unwrap_or(u64::MAX)).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_in_parallel() {
+        assert_eq!(
+            map_in_parallel((0..100).collect(), |i: usize| i * 2),
+            (0..100).map(|i| i * 2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parallel_iterator() {
+        let workers = idle_workers(2);
+        if workers.is_empty() {
+            return; // Single CPU
+        }
+        let mut elements = ParallelIterator::new(
+            (0..4)
+                .map(|i| {
+                    Box::new(i * 10_000..(i + 1) * 10_000) as Box<dyn Iterator<Item = _> + Send>
+                })
+                .collect(),
+            workers,
+        )
+        .collect::<Vec<_>>();
+        elements.sort_unstable();
+        assert_eq!(elements, (0..40_000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,349 @@
+//! Path search services returning the paths matching a property path and not only their endpoints.
+//!
+//! `SERVICE <http://oxigraph.org/path#shortest> { ... }` returns the shortest path from the start node to each reachable end node
+//! and `SERVICE <http://oxigraph.org/path#all> { ... }` returns all the simple paths i.e. the paths without repeated nodes.
+//! They are evaluated locally against the queried dataset.
+
+use crate::sparql::error::EvaluationError;
+use crate::storage::numeric_encoder::EncodedTerm;
+use oxrdf::{NamedNode, Variable};
+use rustc_hash::{FxBuildHasher, FxHashSet};
+use spargebra::algebra::PropertyPathExpression;
+use spargebra::term::{GroundTermPattern, NamedNodePattern};
+use sparopt::algebra::GraphPattern;
+
+const PATH_NAMESPACE: &str = "http://oxigraph.org/path#";
+
+type EdgesIterator =
+    Box<dyn Iterator<Item = Result<(Option<EncodedTerm>, EncodedTerm), EvaluationError>>>;
+
+#[derive(Clone, Copy)]
+pub enum PathSearchMode {
+    Shortest,
+    All,
+}
+
+/// The content of a path search `SERVICE` call.
+///
+/// It must contain a single triple pattern whose predicate is the searched property path.
+/// Its subject is the start node and must be bound, its object is the end node.
+/// If the path is `p+`, `p*`, `p?` or `p{n,m}`, `p` is a step of the returned paths, else the path is a single step.
+///
+/// The other triple patterns configure the search, their subjects are ignored:
+/// * `path:index ?i` binds the index of the path in the results.
+/// * `path:length ?l` binds the number of steps of the path.
+/// * `path:step ?s`, `path:node ?n` and `path:edge ?e` return a solution per node of the path
+///   with its position, the node itself and the predicate of the edge leading to it (unbound for the start node).
+/// * `path:maxLength n` limits the number of steps of the path, it is required with `path:all` if the path is not bounded.
+pub struct PathSearch {
+    pub mode: PathSearchMode,
+    pub subject: GroundTermPattern,
+    pub step: PropertyPathExpression,
+    pub object: GroundTermPattern,
+    pub graph_name: Option<NamedNodePattern>,
+    pub min_length: usize,
+    pub max_length: Option<usize>,
+    pub index: Option<Variable>,
+    pub length: Option<Variable>,
+    pub position: Option<Variable>,
+    pub node: Option<Variable>,
+    pub edge: Option<Variable>,
+}
+
+impl PathSearch {
+    /// Returns `None` if `service` is not a path search service
+    pub fn parse(service: &NamedNode, inner: &GraphPattern) -> Option<Result<Self, String>> {
+        let mode = match service.as_str().strip_prefix(PATH_NAMESPACE)? {
+            "shortest" => PathSearchMode::Shortest,
+            "all" => PathSearchMode::All,
+            _ => return None,
+        };
+        Some(Self::parse_pattern(mode, inner))
+    }
+
+    fn parse_pattern(mode: PathSearchMode, inner: &GraphPattern) -> Result<Self, String> {
+        let mut patterns = Vec::new();
+        flatten_joins(inner, &mut patterns);
+        let mut path = None;
+        let mut max_length = None;
+        let mut index = None;
+        let mut length = None;
+        let mut position = None;
+        let mut node = None;
+        let mut edge = None;
+        for pattern in patterns {
+            match pattern {
+                GraphPattern::QuadPattern {
+                    predicate: NamedNodePattern::NamedNode(predicate),
+                    object,
+                    ..
+                } if predicate.as_str().starts_with(PATH_NAMESPACE) => {
+                    let name = predicate
+                        .as_str()
+                        .strip_prefix(PATH_NAMESPACE)
+                        .unwrap_or_default();
+                    if name == "maxLength" {
+                        let GroundTermPattern::Literal(value) = object else {
+                            return Err("path:maxLength value should be an integer".into());
+                        };
+                        max_length = Some(value.value().parse::<usize>().map_err(|_| {
+                            "path:maxLength value should be a non negative integer".to_owned()
+                        })?);
+                        continue;
+                    }
+                    let target = match name {
+                        "index" => &mut index,
+                        "length" => &mut length,
+                        "step" => &mut position,
+                        "node" => &mut node,
+                        "edge" => &mut edge,
+                        _ => return Err(format!("{predicate} is not a path search parameter")),
+                    };
+                    let GroundTermPattern::Variable(variable) = object else {
+                        return Err(format!("The object of {predicate} should be a variable"));
+                    };
+                    *target = Some(variable.clone());
+                }
+                GraphPattern::QuadPattern {
+                    subject,
+                    predicate: NamedNodePattern::NamedNode(predicate),
+                    object,
+                    graph_name,
+                } => {
+                    if path.is_some() {
+                        return Err("A path search should contain a single path".into());
+                    }
+                    path = Some((
+                        subject,
+                        PropertyPathExpression::from(predicate.clone()),
+                        object,
+                        graph_name,
+                    ));
+                }
+                GraphPattern::Path {
+                    subject,
+                    path: p,
+                    object,
+                    graph_name,
+                } => {
+                    if path.is_some() {
+                        return Err("A path search should contain a single path".into());
+                    }
+                    path = Some((subject, p.clone(), object, graph_name));
+                }
+                _ => {
+                    return Err(
+                        "A path search should only contain a path and path:* triple patterns"
+                            .into(),
+                    )
+                }
+            }
+        }
+        let Some((subject, path, object, graph_name)) = path else {
+            return Err("A path search should contain a path".into());
+        };
+        let (step, min_length, max) = match path {
+            PropertyPathExpression::OneOrMore(p) => (*p, 1, None),
+            PropertyPathExpression::ZeroOrMore(p) => (*p, 0, None),
+            PropertyPathExpression::ZeroOrOne(p) => (*p, 0, Some(1)),
+            PropertyPathExpression::Repeat(p, min, max) => (*p, min, max),
+            p => (p, 1, Some(1)),
+        };
+        let max_length = match (max, max_length) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if matches!(mode, PathSearchMode::All) && max_length.is_none() {
+            return Err(
+                "path:all requires a maximal path length, use p{n,m} or path:maxLength".into(),
+            );
+        }
+        Ok(Self {
+            mode,
+            subject: subject.clone(),
+            step,
+            object: object.clone(),
+            graph_name: graph_name.clone(),
+            min_length,
+            max_length,
+            index,
+            length,
+            position,
+            node,
+            edge,
+        })
+    }
+}
+
+fn flatten_joins<'a>(pattern: &'a GraphPattern, output: &mut Vec<&'a GraphPattern>) {
+    if let GraphPattern::Join { left, right, .. } = pattern {
+        flatten_joins(left, output);
+        flatten_joins(right, output);
+    } else {
+        output.push(pattern);
+    }
+}
+
+/// A path as its nodes and the predicates of the edges between them if they are known
+pub struct FoundPath {
+    pub nodes: Vec<EncodedTerm>,
+    pub edges: Vec<Option<EncodedTerm>>,
+}
+
+/// Returns the shortest path from `start` to each reachable node (or only to `end` if set)
+/// with a length between `min_length` and `max_length`
+///
+/// It is a breadth-first search on the (node, number of steps up to `min_length`) pairs.
+pub fn shortest_paths(
+    steps: impl Fn(&EncodedTerm) -> EdgesIterator,
+    start: &EncodedTerm,
+    end: Option<&EncodedTerm>,
+    min_length: usize,
+    max_length: Option<usize>,
+) -> Result<Vec<FoundPath>, EvaluationError> {
+    // node, length, parent state and edge
+    let mut states = vec![(start.clone(), 0, None)];
+    let mut seen = FxHashSet::with_hasher(FxBuildHasher);
+    seen.insert((start.clone(), 0));
+    let mut found_ends = FxHashSet::with_hasher(FxBuildHasher);
+    let mut paths = Vec::new();
+    let mut current = 0;
+    while let Some((state_node, state_length, _)) = states.get(current) {
+        let (node, length) = (state_node.clone(), *state_length);
+        if length >= min_length
+            && end.map_or(true, |end| *end == node)
+            && found_ends.insert(node.clone())
+        {
+            paths.push(build_path(&states, current));
+            if end.is_some() {
+                break;
+            }
+        }
+        if max_length.map_or(true, |max_length| length < max_length) {
+            for edge in steps(&node) {
+                let (edge, next) = edge?;
+                if seen.insert((next.clone(), min_length.min(length + 1))) {
+                    states.push((next, length + 1, Some((current, edge))));
+                }
+            }
+        }
+        current += 1;
+    }
+    Ok(paths)
+}
+
+fn build_path(
+    states: &[(EncodedTerm, usize, Option<(usize, Option<EncodedTerm>)>)],
+    mut current: usize,
+) -> FoundPath {
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    loop {
+        let (node, _, parent) = &states[current];
+        nodes.push(node.clone());
+        let Some((parent, edge)) = parent else {
+            break;
+        };
+        edges.push(edge.clone());
+        current = *parent;
+    }
+    nodes.reverse();
+    edges.reverse();
+    FoundPath { nodes, edges }
+}
+
+/// Returns the paths without repeated nodes from `start` (to `end` if set)
+/// with a length between `min_length` and `max_length`
+///
+/// It is a depth-first search keeping a stack of the not yet explored edges of the current path nodes.
+pub struct SimplePathsIterator<S: Fn(&EncodedTerm) -> EdgesIterator> {
+    steps: S,
+    end: Option<EncodedTerm>,
+    min_length: usize,
+    max_length: usize,
+    nodes: Vec<EncodedTerm>,
+    edges: Vec<Option<EncodedTerm>>,
+    stack: Vec<EdgesIterator>,
+    start_pending: bool,
+}
+
+impl<S: Fn(&EncodedTerm) -> EdgesIterator> SimplePathsIterator<S> {
+    pub fn new(
+        steps: S,
+        start: EncodedTerm,
+        end: Option<EncodedTerm>,
+        min_length: usize,
+        max_length: usize,
+    ) -> Self {
+        Self {
+            steps,
+            end,
+            min_length,
+            max_length,
+            nodes: vec![start],
+            edges: Vec::new(),
+            stack: Vec::new(),
+            start_pending: true,
+        }
+    }
+
+    fn current_path(&self) -> FoundPath {
+        FoundPath {
+            nodes: self.nodes.clone(),
+            edges: self.edges.clone(),
+        }
+    }
+}
+
+impl<S: Fn(&EncodedTerm) -> EdgesIterator> Iterator for SimplePathsIterator<S> {
+    type Item = Result<FoundPath, EvaluationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start_pending {
+            self.start_pending = false;
+            let start = self.nodes.first()?.clone();
+            if self.max_length > 0 {
+                self.stack.push((self.steps)(&start));
+            }
+            if self.min_length == 0 && self.end.as_ref().map_or(true, |end| *end == start) {
+                return Some(Ok(FoundPath {
+                    nodes: vec![start],
+                    edges: Vec::new(),
+                }));
+            }
+        }
+        loop {
+            let edges = self.stack.last_mut()?;
+            let Some(edge) = edges.next() else {
+                self.stack.pop();
+                self.nodes.pop();
+                self.edges.pop();
+                continue;
+            };
+            let (edge, next) = match edge {
+                Ok(edge) => edge,
+                Err(e) => return Some(Err(e)),
+            };
+            if self.nodes.contains(&next) {
+                continue;
+            }
+            let length = self.nodes.len();
+            let is_end = self.end.as_ref().map_or(true, |end| *end == next);
+            let can_continue = length < self.max_length
+                // A simple path can't go through its end twice
+                && self.end.as_ref().map_or(true, |end| *end != next);
+            self.nodes.push(next);
+            self.edges.push(edge);
+            let path = (is_end && length >= self.min_length).then(|| self.current_path());
+            if can_continue {
+                self.stack.push((self.steps)(self.nodes.last()?));
+            } else {
+                self.nodes.pop();
+                self.edges.pop();
+            }
+            if let Some(path) = path {
+                return Some(Ok(path));
+            }
+        }
+    }
+}
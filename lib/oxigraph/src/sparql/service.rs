@@ -2,9 +2,12 @@ use crate::model::NamedNode;
 use crate::sparql::algebra::Query;
 use crate::sparql::error::EvaluationError;
 use crate::sparql::http::Client;
-use crate::sparql::model::QueryResults;
+use crate::sparql::model::{QueryResults, QuerySolution};
 use crate::sparql::results::QueryResultsFormat;
+use spargebra::algebra::GraphPattern;
+use spargebra::term::GroundTerm;
 use std::error::Error;
+use std::mem::replace;
 use std::time::Duration;
 
 /// Handler for [SPARQL 1.1 Federated Query](https://www.w3.org/TR/sparql11-federated-query/) SERVICE.
@@ -60,6 +63,60 @@ pub trait ServiceHandler: Send + Sync {
 
     /// Evaluates a [`Query`] against a given service identified by a [`NamedNode`].
     fn handle(&self, service_name: NamedNode, query: Query) -> Result<QueryResults, Self::Error>;
+
+    /// Evaluates a [`Query`] against a given service for a batch of input solutions.
+    ///
+    /// It is used for bind joins: `bindings` are the solutions computed so far restricted to the join variables,
+    /// the ones bound by both the solutions and the `SERVICE` pattern.
+    /// The returned solutions are joined back with the bindings they are compatible with
+    /// so returning solutions that are not compatible with any binding is not an error, they are just ignored.
+    /// The bind joins are enabled and the size of the batches is set with [`QueryOptions::with_service_batch_size`](super::QueryOptions::with_service_batch_size).
+    ///
+    /// By default, the bindings are added to the query as a `VALUES` clause
+    /// (blank nodes are not allowed in `VALUES` and are left unbound)
+    /// and [`handle`](Self::handle) is called.
+    fn handle_batch(
+        &self,
+        service_name: NamedNode,
+        query: Query,
+        bindings: Vec<QuerySolution>,
+    ) -> Result<QueryResults, Self::Error> {
+        self.handle(service_name, add_values_clause(query, bindings))
+    }
+}
+
+/// Joins the query pattern with a `VALUES` clause containing the given bindings
+fn add_values_clause(mut query: Query, bindings: Vec<QuerySolution>) -> Query {
+    let variables = bindings
+        .first()
+        .map(|solution| solution.variables().to_vec())
+        .unwrap_or_default();
+    let bindings = bindings
+        .into_iter()
+        .map(|solution| {
+            solution
+                .values()
+                .iter()
+                .map(|value| GroundTerm::try_from(value.clone()?).ok())
+                .collect()
+        })
+        .collect();
+    if let spargebra::Query::Select { pattern, .. } = &mut query.inner {
+        let inner = replace(
+            pattern,
+            GraphPattern::Bgp {
+                patterns: Vec::new(),
+            },
+        );
+        *pattern = GraphPattern::Join {
+            left: Box::new(GraphPattern::Values {
+                variables,
+                bindings,
+            }),
+            right: Box::new(inner),
+        };
+    }
+    query
 }
 
 pub struct EmptyServiceHandler;
@@ -90,6 +147,17 @@ impl<S: ServiceHandler> ServiceHandler for ErrorConversionServiceHandler<S> {
             .handle(service_name, query)
             .map_err(|e| EvaluationError::Service(Box::new(e)))
     }
+
+    fn handle_batch(
+        &self,
+        service_name: NamedNode,
+        query: Query,
+        bindings: Vec<QuerySolution>,
+    ) -> Result<QueryResults, Self::Error> {
+        self.handler
+            .handle_batch(service_name, query, bindings)
+            .map_err(|e| EvaluationError::Service(Box::new(e)))
+    }
 }
 
 pub struct SimpleServiceHandler {
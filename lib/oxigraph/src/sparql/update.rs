@@ -1,12 +1,13 @@
 use crate::io::{RdfFormat, RdfParser};
-use crate::model::{GraphName as OxGraphName, GraphNameRef, Quad as OxQuad};
+use crate::model::{GraphName as OxGraphName, GraphNameRef, Quad as OxQuad, QuadRef};
 use crate::sparql::algebra::QueryDataset;
 use crate::sparql::dataset::DatasetView;
-use crate::sparql::eval::{EncodedTuple, SimpleEvaluator};
+use crate::sparql::eval::{EncodedTuple, SimpleEvaluator, Timer};
 use crate::sparql::http::Client;
-use crate::sparql::{EvaluationError, Update, UpdateOptions};
+use crate::sparql::{EvaluationError, QueryExplanation, Update, UpdateOptions};
 use crate::storage::numeric_encoder::{Decoder, EncodedTerm};
 use crate::storage::StorageWriter;
+use json_event_parser::{JsonEvent, ToWriteJsonWriter};
 use oxiri::Iri;
 use rustc_hash::FxHashMap;
 use spargebra::algebra::{GraphPattern, GraphTarget};
@@ -17,25 +18,224 @@ use spargebra::term::{
 };
 use spargebra::GraphUpdateOperation;
 use sparopt::Optimizer;
-use std::io;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::{fmt, io};
 
+/// Evaluates the update
+///
+/// If `summarize` is false the counts of the returned summary do not include the quads removed by `CLEAR` and `DROP`
+/// that would require to read the cleared graphs.
 pub fn evaluate_update<'a, 'b: 'a>(
     transaction: &'a mut StorageWriter<'b>,
     update: &Update,
     options: &UpdateOptions,
-) -> Result<(), EvaluationError> {
-    SimpleUpdateEvaluator {
-        transaction,
-        base_iri: update.inner.base_iri.clone().map(Rc::new),
-        options: options.clone(),
-        client: Client::new(
-            options.query_options.http_timeout,
-            options.query_options.http_redirection_limit,
-        ),
-    }
-    .eval_all(&update.inner.operations, &update.using_datasets)
+    summarize: bool,
+) -> Result<UpdateSummary, EvaluationError> {
+    let mut evaluator = SimpleUpdateEvaluator::new(transaction, update, options, summarize, None);
+    evaluator.eval_all(&update.inner.operations, &update.using_datasets)?;
+    Ok(evaluator.summary)
+}
+
+/// Evaluates the update and returns the explanation of each of its operations
+pub fn explain_update<'a, 'b: 'a>(
+    transaction: &'a mut StorageWriter<'b>,
+    update: &Update,
+    options: &UpdateOptions,
+    with_stats: bool,
+) -> Result<UpdateExplanation, EvaluationError> {
+    let mut evaluator =
+        SimpleUpdateEvaluator::new(transaction, update, options, true, Some(with_stats));
+    evaluator.eval_all(&update.inner.operations, &update.using_datasets)?;
+    Ok(UpdateExplanation {
+        operations: evaluator.explanations,
+    })
+}
+
+/// The changes done by a SPARQL update.
+///
+/// Returned by [`Store::update_with_summary`](crate::store::Store::update_with_summary).
+#[derive(Clone, Debug, Default)]
+pub struct UpdateSummary {
+    inserted_count: usize,
+    deleted_count: usize,
+    inserted: Option<Vec<OxQuad>>,
+    deleted: Option<Vec<OxQuad>>,
+}
+
+impl UpdateSummary {
+    /// The number of quads that were not in the store and have been inserted.
+    #[inline]
+    pub fn inserted_count(&self) -> usize {
+        self.inserted_count
+    }
+
+    /// The number of quads that were in the store and have been deleted.
+    #[inline]
+    pub fn deleted_count(&self) -> usize {
+        self.deleted_count
+    }
+
+    /// The inserted quads, only available if [`UpdateOptions::with_recorded_changes`] is set.
+    ///
+    /// It is truncated to the limit given to [`UpdateOptions::with_recorded_changes`], [`inserted_count`](Self::inserted_count) is always exact.
+    #[inline]
+    pub fn inserted_quads(&self) -> Option<&[OxQuad]> {
+        self.inserted.as_deref()
+    }
+
+    /// The deleted quads, only available if [`UpdateOptions::with_recorded_changes`] is set.
+    ///
+    /// It is truncated to the limit given to [`UpdateOptions::with_recorded_changes`], [`deleted_count`](Self::deleted_count) is always exact.
+    #[inline]
+    pub fn deleted_quads(&self) -> Option<&[OxQuad]> {
+        self.deleted.as_deref()
+    }
+}
+
+/// The explanation of a SPARQL update evaluation.
+///
+/// Returned by [`Store::explain_update_opt`](crate::store::Store::explain_update_opt).
+#[derive(Clone)]
+pub struct UpdateExplanation {
+    operations: Vec<UpdateOperationExplanation>,
+}
+
+impl UpdateExplanation {
+    /// The explanations of the update operations, in their evaluation order.
+    #[inline]
+    pub fn operations(&self) -> &[UpdateOperationExplanation] {
+        &self.operations
+    }
+
+    /// Writes the explanation as JSON.
+    pub fn write_in_json(&self, write: impl io::Write) -> io::Result<()> {
+        let mut writer = ToWriteJsonWriter::new(write);
+        writer.write_event(JsonEvent::StartArray)?;
+        for operation in &self.operations {
+            writer.write_event(JsonEvent::StartObject)?;
+            writer.write_event(JsonEvent::ObjectKey("operation".into()))?;
+            writer.write_event(JsonEvent::String(operation.name.into()))?;
+            writer.write_event(JsonEvent::ObjectKey("inserted quads".into()))?;
+            writer.write_event(JsonEvent::Number(
+                operation.inserted_count.to_string().into(),
+            ))?;
+            writer.write_event(JsonEvent::ObjectKey("deleted quads".into()))?;
+            writer.write_event(JsonEvent::Number(
+                operation.deleted_count.to_string().into(),
+            ))?;
+            if let Some(plan) = &operation.plan {
+                if let Some(planning_duration) = plan.planning_duration {
+                    writer
+                        .write_event(JsonEvent::ObjectKey("planning duration in seconds".into()))?;
+                    writer.write_event(JsonEvent::Number(
+                        planning_duration.as_seconds().to_string().into(),
+                    ))?;
+                }
+                writer.write_event(JsonEvent::ObjectKey("plan".into()))?;
+                plan.inner.json_node(&mut writer, plan.with_stats)?;
+            }
+            writer.write_event(JsonEvent::EndObject)?;
+        }
+        writer.write_event(JsonEvent::EndArray)
+    }
+
+    /// Writes the explanation in a human-readable form, one block per operation.
+    pub fn write_in_text(&self, mut write: impl io::Write) -> io::Result<()> {
+        for operation in &self.operations {
+            writeln!(
+                write,
+                "{}: {} inserted quads, {} deleted quads",
+                operation.name, operation.inserted_count, operation.deleted_count
+            )?;
+            if let Some(plan) = &operation.plan {
+                plan.write_in_text(&mut write)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the plans of the operations as a [Graphviz DOT](https://graphviz.org/doc/info/lang.html) directed graph
+    /// with one cluster per operation.
+    pub fn write_in_dot(&self, mut write: impl io::Write) -> io::Result<()> {
+        writeln!(write, "digraph plan {{")?;
+        writeln!(write, "  node [shape=box];")?;
+        for (i, operation) in self.operations.iter().enumerate() {
+            writeln!(write, "  subgraph cluster_{i} {{")?;
+            writeln!(
+                write,
+                "  label=\"{}: {} inserted quads, {} deleted quads\";",
+                operation.name, operation.inserted_count, operation.deleted_count
+            )?;
+            if let Some(plan) = &operation.plan {
+                plan.inner
+                    .dot_node(&mut write, &format!("o{i}n"), 0, plan.with_stats)?;
+            } else {
+                // Graphviz does not draw empty clusters
+                writeln!(
+                    write,
+                    "  o{i} [label=\"{}\" shape=plaintext];",
+                    operation.name
+                )?;
+            }
+            writeln!(write, "  }}")?;
+        }
+        writeln!(write, "}}")
+    }
+}
+
+impl fmt::Debug for UpdateExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.operations).finish()
+    }
+}
+
+/// The explanation of an operation of a SPARQL update.
+#[derive(Clone)]
+pub struct UpdateOperationExplanation {
+    name: &'static str,
+    inserted_count: usize,
+    deleted_count: usize,
+    plan: Option<QueryExplanation>,
+}
+
+impl UpdateOperationExplanation {
+    /// The kind of the operation like `INSERT DATA` or `DELETE/INSERT`.
+    #[inline]
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// The number of quads that were not in the store and have been inserted by the operation.
+    #[inline]
+    pub fn inserted_count(&self) -> usize {
+        self.inserted_count
+    }
+
+    /// The number of quads that were in the store and have been deleted by the operation.
+    #[inline]
+    pub fn deleted_count(&self) -> usize {
+        self.deleted_count
+    }
+
+    /// The plan of the `WHERE` clause, only set for `DELETE/INSERT` operations.
+    #[inline]
+    pub fn plan(&self) -> Option<&QueryExplanation> {
+        self.plan.as_ref()
+    }
+}
+
+impl fmt::Debug for UpdateOperationExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut obj = f.debug_struct("Operation");
+        obj.field("name", &self.name)
+            .field("inserted quads", &self.inserted_count)
+            .field("deleted quads", &self.deleted_count);
+        if let Some(plan) = &self.plan {
+            obj.field("plan", plan);
+        }
+        obj.finish()
+    }
 }
 
 struct SimpleUpdateEvaluator<'a, 'b> {
@@ -43,16 +243,60 @@ struct SimpleUpdateEvaluator<'a, 'b> {
     base_iri: Option<Rc<Iri<String>>>,
     options: UpdateOptions,
     client: Client,
+    summarize: bool,
+    summary: UpdateSummary,
+    /// If the operations should be explained and then if statistics should be computed
+    explain: Option<bool>,
+    explanations: Vec<UpdateOperationExplanation>,
+    last_plan: Option<QueryExplanation>,
 }
 
 impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
+    fn new(
+        transaction: &'a mut StorageWriter<'b>,
+        update: &Update,
+        options: &UpdateOptions,
+        summarize: bool,
+        explain: Option<bool>,
+    ) -> Self {
+        Self {
+            transaction,
+            base_iri: update.inner.base_iri.clone().map(Rc::new),
+            options: options.clone(),
+            client: Client::new(
+                options.query_options.http_timeout,
+                options.query_options.http_redirection_limit,
+            ),
+            summarize,
+            summary: UpdateSummary {
+                inserted_count: 0,
+                deleted_count: 0,
+                inserted: options.max_recorded_changes.map(|_| Vec::new()),
+                deleted: options.max_recorded_changes.map(|_| Vec::new()),
+            },
+            explain,
+            explanations: Vec::new(),
+            last_plan: None,
+        }
+    }
+
     fn eval_all(
         &mut self,
         updates: &[GraphUpdateOperation],
         using_datasets: &[Option<QueryDataset>],
     ) -> Result<(), EvaluationError> {
         for (update, using_dataset) in updates.iter().zip(using_datasets) {
+            let (inserted_count, deleted_count) =
+                (self.summary.inserted_count, self.summary.deleted_count);
             self.eval(update, using_dataset)?;
+            if self.explain.is_some() {
+                self.explanations.push(UpdateOperationExplanation {
+                    name: operation_name(update),
+                    inserted_count: self.summary.inserted_count - inserted_count,
+                    deleted_count: self.summary.deleted_count - deleted_count,
+                    plan: self.last_plan.take(),
+                });
+            }
         }
         Ok(())
     }
@@ -101,7 +345,7 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
         let mut bnodes = FxHashMap::default();
         for quad in data {
             let quad = Self::convert_quad(quad, &mut bnodes);
-            self.transaction.insert(quad.as_ref())?;
+            self.insert(quad.as_ref())?;
         }
         Ok(())
     }
@@ -109,7 +353,7 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
     fn eval_delete_data(&mut self, data: &[GroundQuad]) -> Result<(), EvaluationError> {
         for quad in data {
             let quad = Self::convert_ground_quad(quad);
-            self.transaction.remove(quad.as_ref())?;
+            self.remove(quad.as_ref())?;
         }
         Ok(())
     }
@@ -121,8 +365,12 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
         using: &QueryDataset,
         algebra: &GraphPattern,
     ) -> Result<(), EvaluationError> {
+        let start_planning = Timer::now();
         let dataset = Rc::new(DatasetView::new(self.transaction.reader(), using));
         let mut pattern = sparopt::algebra::GraphPattern::from(algebra);
+        self.options
+            .query_options
+            .replace_property_functions(&mut pattern);
         if !self.options.query_options.without_optimizations {
             pattern = Optimizer::optimize_graph_pattern(sparopt::algebra::GraphPattern::Reduced {
                 inner: Box::new(pattern),
@@ -132,27 +380,40 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
             Rc::clone(&dataset),
             self.base_iri.clone(),
             self.options.query_options.service_handler(),
+            self.options.query_options.service_batch_size(),
+            self.options.query_options.parallelism(),
             Arc::new(self.options.query_options.custom_functions.clone()),
-            false,
+            Arc::new(self.options.query_options.property_functions.clone()),
+            self.explain == Some(true),
         );
         let mut variables = Vec::new();
         let mut bnodes = FxHashMap::default();
-        let (eval, _) = evaluator.graph_pattern_evaluator(&pattern, &mut variables);
+        let (eval, stats) = evaluator.graph_pattern_evaluator(&pattern, &mut variables);
+        let planning_duration = start_planning.elapsed();
         let tuples =
-            eval(EncodedTuple::with_capacity(variables.len())).collect::<Result<Vec<_>, _>>()?; // TODO: would be much better to stream
+            eval(EncodedTuple::with_capacity(variables.len())).collect::<Result<Vec<_>, _>>();
+        if let Some(with_stats) = self.explain {
+            self.last_plan = Some(QueryExplanation {
+                inner: stats,
+                with_stats,
+                parsing_duration: None,
+                planning_duration,
+            });
+        }
+        let tuples = tuples?; // TODO: would be much better to stream
         for tuple in tuples {
             for quad in delete {
                 if let Some(quad) =
                     Self::convert_ground_quad_pattern(quad, &variables, &tuple, &dataset)?
                 {
-                    self.transaction.remove(quad.as_ref())?;
+                    self.remove(quad.as_ref())?;
                 }
             }
             for quad in insert {
                 if let Some(quad) =
                     Self::convert_quad_pattern(quad, &variables, &tuple, &dataset, &mut bnodes)?
                 {
-                    self.transaction.insert(quad.as_ref())?;
+                    self.insert(quad.as_ref())?;
                 }
             }
             bnodes.clear();
@@ -185,7 +446,7 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
             )))
         })?;
         for q in parser.parse_read(body) {
-            self.transaction.insert(q?.as_ref())?;
+            self.insert(q?.as_ref())?;
         }
         Ok(())
     }
@@ -206,6 +467,7 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
                     .reader()
                     .contains_named_graph(&graph_name.as_ref().into())?
                 {
+                    self.summarize_cleared(&GraphTarget::NamedNode(graph_name.clone()))?;
                     Ok(self.transaction.clear_graph(graph_name.into())?)
                 } else if silent {
                     Ok(())
@@ -214,17 +476,25 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
                 }
             }
             GraphTarget::DefaultGraph => {
+                self.summarize_cleared(graph)?;
                 self.transaction.clear_graph(GraphNameRef::DefaultGraph)?;
                 Ok(())
             }
-            GraphTarget::NamedGraphs => Ok(self.transaction.clear_all_named_graphs()?),
-            GraphTarget::AllGraphs => Ok(self.transaction.clear_all_graphs()?),
+            GraphTarget::NamedGraphs => {
+                self.summarize_cleared(graph)?;
+                Ok(self.transaction.clear_all_named_graphs()?)
+            }
+            GraphTarget::AllGraphs => {
+                self.summarize_cleared(graph)?;
+                Ok(self.transaction.clear_all_graphs()?)
+            }
         }
     }
 
     fn eval_drop(&mut self, graph: &GraphTarget, silent: bool) -> Result<(), EvaluationError> {
         match graph {
             GraphTarget::NamedNode(graph_name) => {
+                self.summarize_cleared(graph)?;
                 if self.transaction.remove_named_graph(graph_name.into())? || silent {
                     Ok(())
                 } else {
@@ -232,13 +502,75 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
                 }
             }
             GraphTarget::DefaultGraph => {
+                self.summarize_cleared(graph)?;
                 Ok(self.transaction.clear_graph(GraphNameRef::DefaultGraph)?)
             }
-            GraphTarget::NamedGraphs => Ok(self.transaction.remove_all_named_graphs()?),
-            GraphTarget::AllGraphs => Ok(self.transaction.clear()?),
+            GraphTarget::NamedGraphs => {
+                self.summarize_cleared(graph)?;
+                Ok(self.transaction.remove_all_named_graphs()?)
+            }
+            GraphTarget::AllGraphs => {
+                self.summarize_cleared(graph)?;
+                Ok(self.transaction.clear()?)
+            }
         }
     }
 
+    fn insert(&mut self, quad: QuadRef<'_>) -> Result<(), EvaluationError> {
+        if self.transaction.insert(quad)? {
+            self.summary.inserted_count += 1;
+            if let Some(inserted) = self.recorded_changes(false) {
+                inserted.push(quad.into_owned());
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, quad: QuadRef<'_>) -> Result<(), EvaluationError> {
+        if self.transaction.remove(quad)? {
+            self.summary.deleted_count += 1;
+            if let Some(deleted) = self.recorded_changes(true) {
+                deleted.push(quad.into_owned());
+            }
+        }
+        Ok(())
+    }
+
+    /// The recorded inserted or deleted quads if they are recorded and the limit is not reached yet
+    fn recorded_changes(&mut self, deleted: bool) -> Option<&mut Vec<OxQuad>> {
+        let limit = self.options.max_recorded_changes?;
+        let changes = if deleted {
+            self.summary.deleted.as_mut()
+        } else {
+            self.summary.inserted.as_mut()
+        }?;
+        (changes.len() < limit).then_some(changes)
+    }
+
+    /// Adds the quads of the graphs that are going to be cleared to the summary
+    fn summarize_cleared(&mut self, graph: &GraphTarget) -> Result<(), EvaluationError> {
+        if !self.summarize {
+            return Ok(());
+        }
+        let graph_name = match graph {
+            GraphTarget::NamedNode(graph_name) => Some(graph_name.as_ref().into()),
+            GraphTarget::DefaultGraph => Some(EncodedTerm::DefaultGraph),
+            GraphTarget::NamedGraphs | GraphTarget::AllGraphs => None,
+        };
+        let reader = self.transaction.reader();
+        for quad in reader.quads_for_pattern(None, None, None, graph_name.as_ref()) {
+            let quad = quad?;
+            if matches!(graph, GraphTarget::NamedGraphs) && quad.graph_name.is_default_graph() {
+                continue;
+            }
+            self.summary.deleted_count += 1;
+            if let Some(deleted) = self.recorded_changes(true) {
+                deleted.push(reader.decode_quad(&quad)?);
+            }
+        }
+        Ok(())
+    }
+
     fn convert_quad(quad: &Quad, bnodes: &mut FxHashMap<BlankNode, BlankNode>) -> OxQuad {
         OxQuad {
             subject: match &quad.subject {
@@ -563,3 +895,15 @@ impl<'a, 'b: 'a> SimpleUpdateEvaluator<'a, 'b> {
             .cloned()
     }
 }
+
+fn operation_name(operation: &GraphUpdateOperation) -> &'static str {
+    match operation {
+        GraphUpdateOperation::InsertData { .. } => "INSERT DATA",
+        GraphUpdateOperation::DeleteData { .. } => "DELETE DATA",
+        GraphUpdateOperation::DeleteInsert { .. } => "DELETE/INSERT",
+        GraphUpdateOperation::Load { .. } => "LOAD",
+        GraphUpdateOperation::Clear { .. } => "CLEAR",
+        GraphUpdateOperation::Create { .. } => "CREATE",
+        GraphUpdateOperation::Drop { .. } => "DROP",
+    }
+}
@@ -0,0 +1,187 @@
+use crate::model::{BlankNode, NamedNode, NamedNodeRef, Quad};
+use crate::sparql::error::EvaluationError;
+use crate::sparql::model::QueryResults;
+use crate::sparql::PreparedQuery;
+use crate::storage::numeric_encoder::StrHash;
+use crate::storage::CorruptionError;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+const VIEW_NAMESPACE: &str = "http://oxigraph.org/view#";
+
+/// When a materialized view created with [`Store::create_view`](crate::store::Store::create_view) is refreshed.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+#[non_exhaustive]
+pub enum ViewRefresh {
+    /// Only when [`Store::refresh_view`](crate::store::Store::refresh_view) is called.
+    OnDemand,
+    /// Before the evaluation of a query if the store has been changed since the last refresh.
+    OnChange,
+}
+
+pub(crate) struct MaterializedView {
+    pub name: NamedNode,
+    pub query: PreparedQuery,
+    pub refresh: ViewRefresh,
+    /// The store version the view content is computed from, locked during the view refreshes
+    version: Mutex<Option<u64>>,
+}
+
+impl MaterializedView {
+    pub fn new(name: NamedNode, query: PreparedQuery, refresh: ViewRefresh) -> Self {
+        Self {
+            name,
+            query,
+            refresh,
+            version: Mutex::new(None),
+        }
+    }
+
+    pub fn version(&self) -> MutexGuard<'_, Option<u64>> {
+        self.version.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The materialized views of a store, in their creation order
+///
+/// The list is only locked to be read or modified, each view has its own lock for its refreshes.
+#[derive(Default)]
+pub(crate) struct ViewRegistry {
+    views: Mutex<Vec<Arc<MaterializedView>>>,
+}
+
+impl ViewRegistry {
+    /// Loads the definitions serialized with [`ViewRegistry::encode_definitions`]
+    pub fn decode_definitions(
+        definitions: &[u8],
+        prepare: impl Fn(&str) -> Result<PreparedQuery, EvaluationError>,
+    ) -> Result<Self, CorruptionError> {
+        let mut views = Vec::new();
+        let mut input = definitions;
+        while let Some((&refresh, rest)) = input.split_first() {
+            let refresh = match refresh {
+                0 => ViewRefresh::OnDemand,
+                1 => ViewRefresh::OnChange,
+                _ => return Err(CorruptionError::new("Invalid view refresh policy")),
+            };
+            let (name, rest) = read_str(rest)?;
+            let (query, rest) = read_str(rest)?;
+            input = rest;
+            let name = NamedNode::new(name).map_err(CorruptionError::new)?;
+            let query = prepare(query).map_err(|e| {
+                CorruptionError::new(format!("Invalid query of the view {name}: {e}"))
+            })?;
+            views.push(Arc::new(MaterializedView::new(name, query, refresh)));
+        }
+        Ok(Self {
+            views: Mutex::new(views),
+        })
+    }
+
+    /// Serializes the definitions of the views to save them in the storage
+    pub fn encode_definitions(views: &[Arc<MaterializedView>]) -> Vec<u8> {
+        let mut output = Vec::new();
+        for view in views {
+            output.push(match view.refresh {
+                ViewRefresh::OnDemand => 0,
+                ViewRefresh::OnChange => 1,
+            });
+            write_str(&mut output, view.name.as_str());
+            write_str(&mut output, view.query.text());
+        }
+        output
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Vec<Arc<MaterializedView>>> {
+        self.views.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The current views, the list is not locked anymore once returned
+    pub fn list(&self) -> Vec<Arc<MaterializedView>> {
+        self.lock().clone()
+    }
+
+    /// Checks if the view is still registered, i.e. it has not been dropped
+    pub fn contains(&self, view: &Arc<MaterializedView>) -> bool {
+        self.lock().iter().any(|v| Arc::ptr_eq(v, view))
+    }
+}
+
+fn write_str(output: &mut Vec<u8>, value: &str) {
+    output.extend_from_slice(&(value.len() as u64).to_be_bytes());
+    output.extend_from_slice(value.as_bytes());
+}
+
+fn read_str(input: &[u8]) -> Result<(&str, &[u8]), CorruptionError> {
+    if input.len() < 8 {
+        return Err(CorruptionError::new("Truncated view definition"));
+    }
+    let (len, rest) = input.split_at(8);
+    let mut len_bytes = [0; 8];
+    len_bytes.copy_from_slice(len);
+    let len = usize::try_from(u64::from_be_bytes(len_bytes)).map_err(CorruptionError::new)?;
+    if rest.len() < len {
+        return Err(CorruptionError::new("Truncated view definition"));
+    }
+    let (value, rest) = rest.split_at(len);
+    Ok((str::from_utf8(value).map_err(CorruptionError::new)?, rest))
+}
+
+/// Converts the results of the view query into the quads of the view graph
+///
+/// Graph results are stored as is and each solution is stored as a blank node
+/// with a `<http://oxigraph.org/view#{variable}>` property per bound variable.
+/// The blank node identifiers are derived from the solution values
+/// so that the solutions that are still returned keep the same quads after a refresh.
+pub(crate) fn view_quads(
+    name: NamedNodeRef<'_>,
+    results: QueryResults,
+) -> Result<Vec<Quad>, EvaluationError> {
+    match results {
+        QueryResults::Graph(triples) => triples
+            .map(|triple| Ok(triple?.in_graph(name.into_owned())))
+            .collect(),
+        QueryResults::Solutions(solutions) => {
+            let properties = solutions
+                .variables()
+                .iter()
+                .map(|variable| {
+                    NamedNode::new_unchecked(format!("{VIEW_NAMESPACE}{}", variable.as_str()))
+                })
+                .collect::<Vec<_>>();
+            let mut quads = Vec::new();
+            let mut occurrences = HashMap::<String, usize>::new();
+            for solution in solutions {
+                let solution = solution?;
+                let mut key = name.as_str().to_owned();
+                for value in solution.values() {
+                    key.push(' ');
+                    if let Some(value) = value {
+                        write!(key, "{value}").ok();
+                    }
+                }
+                // The duplicated solutions get their own blank node
+                let occurrence = occurrences.entry(key.clone()).or_default();
+                write!(key, " {occurrence}").ok();
+                *occurrence += 1;
+                let row = BlankNode::new_from_unique_id(u128::from_be_bytes(
+                    StrHash::new(&key).to_be_bytes(),
+                ));
+                for (property, value) in properties.iter().zip(solution.values()) {
+                    if let Some(value) = value {
+                        quads.push(Quad::new(
+                            row.clone(),
+                            property.clone(),
+                            value.clone(),
+                            name.into_owned(),
+                        ));
+                    }
+                }
+            }
+            Ok(quads)
+        }
+        QueryResults::Boolean(_) => Err(EvaluationError::NotAGraph),
+    }
+}
@@ -1,12 +1,12 @@
 use crate::storage::error::{CorruptionError, StorageError};
 use crate::storage::numeric_encoder::{EncodedQuad, EncodedTerm, EncodedTriple, StrHash};
-use crate::storage::small_string::SmallString;
+use crate::storage::small_string::{MediumString, SmallString};
 use oxsdatatypes::*;
 use std::io::Read;
 use std::mem::size_of;
 
 #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-pub const LATEST_STORAGE_VERSION: u64 = 1;
+pub const LATEST_STORAGE_VERSION: u64 = 3;
 pub const WRITTEN_TERM_MAX_SIZE: usize = size_of::<u8>() + 2 * size_of::<StrHash>();
 
 // Encoded term type blocks
@@ -18,17 +18,20 @@ pub const WRITTEN_TERM_MAX_SIZE: usize = size_of::<u8>() + 2 * size_of::<StrHash
 // 64-127: default named node prefixes
 // 128-255: custom named node prefixes
 const TYPE_NAMED_NODE_ID: u8 = 1;
+const TYPE_PREFIXED_NAMED_NODE_ID: u8 = 2;
 const TYPE_NUMERICAL_BLANK_NODE_ID: u8 = 8;
 const TYPE_SMALL_BLANK_NODE_ID: u8 = 9;
 const TYPE_BIG_BLANK_NODE_ID: u8 = 10;
 const TYPE_SMALL_STRING_LITERAL: u8 = 16;
 const TYPE_BIG_STRING_LITERAL: u8 = 17;
+const TYPE_MEDIUM_LANG_STRING_LITERAL: u8 = 18;
 const TYPE_SMALL_SMALL_LANG_STRING_LITERAL: u8 = 20;
 const TYPE_SMALL_BIG_LANG_STRING_LITERAL: u8 = 21;
 const TYPE_BIG_SMALL_LANG_STRING_LITERAL: u8 = 22;
 const TYPE_BIG_BIG_LANG_STRING_LITERAL: u8 = 23;
 const TYPE_SMALL_TYPED_LITERAL: u8 = 24;
 const TYPE_BIG_TYPED_LITERAL: u8 = 25;
+const TYPE_MEDIUM_ANY_URI_LITERAL: u8 = 26;
 const TYPE_BOOLEAN_LITERAL_TRUE: u8 = 28;
 const TYPE_BOOLEAN_LITERAL_FALSE: u8 = 29;
 const TYPE_FLOAT_LITERAL: u8 = 30;
@@ -212,6 +215,17 @@ impl<R: Read> TermReader for R {
                     iri_id: StrHash::from_be_bytes(buffer),
                 })
             }
+            TYPE_PREFIXED_NAMED_NODE_ID => {
+                let mut prefix_buffer = [0; 16];
+                self.read_exact(&mut prefix_buffer)?;
+                let mut local_name_buffer = [0; 16];
+                self.read_exact(&mut local_name_buffer)?;
+                Ok(EncodedTerm::PrefixedNamedNode {
+                    prefix_id: StrHash::from_be_bytes(prefix_buffer),
+                    local_name: SmallString::from_be_bytes(local_name_buffer)
+                        .map_err(CorruptionError::new)?,
+                })
+            }
             TYPE_NUMERICAL_BLANK_NODE_ID => {
                 let mut id = [0; 16];
                 self.read_exact(&mut id)?;
@@ -275,6 +289,13 @@ impl<R: Read> TermReader for R {
                     language_id: StrHash::from_be_bytes(language_buffer),
                 })
             }
+            TYPE_MEDIUM_LANG_STRING_LITERAL => {
+                let mut buffer = [0; 32];
+                self.read_exact(&mut buffer)?;
+                Ok(EncodedTerm::MediumLangStringLiteral(
+                    MediumString::from_be_bytes(buffer).map_err(CorruptionError::new)?,
+                ))
+            }
             TYPE_SMALL_TYPED_LITERAL => {
                 let mut datatype_buffer = [0; 16];
                 self.read_exact(&mut datatype_buffer)?;
@@ -296,6 +317,13 @@ impl<R: Read> TermReader for R {
                     value_id: StrHash::from_be_bytes(value_buffer),
                 })
             }
+            TYPE_MEDIUM_ANY_URI_LITERAL => {
+                let mut buffer = [0; 32];
+                self.read_exact(&mut buffer)?;
+                Ok(EncodedTerm::MediumAnyUriLiteral(
+                    MediumString::from_be_bytes(buffer).map_err(CorruptionError::new)?,
+                ))
+            }
             TYPE_SMALL_STRING_LITERAL => {
                 let mut buffer = [0; 16];
                 self.read_exact(&mut buffer)?;
@@ -464,13 +492,6 @@ pub fn encode_term(t: &EncodedTerm) -> Vec<u8> {
     vec
 }
 
-pub fn encode_term_pair(t1: &EncodedTerm, t2: &EncodedTerm) -> Vec<u8> {
-    let mut vec = Vec::with_capacity(2 * WRITTEN_TERM_MAX_SIZE);
-    write_term(&mut vec, t1);
-    write_term(&mut vec, t2);
-    vec
-}
-
 pub fn encode_term_triple(t1: &EncodedTerm, t2: &EncodedTerm, t3: &EncodedTerm) -> Vec<u8> {
     let mut vec = Vec::with_capacity(3 * WRITTEN_TERM_MAX_SIZE);
     write_term(&mut vec, t1);
@@ -500,6 +521,14 @@ pub fn write_term(sink: &mut Vec<u8>, term: &EncodedTerm) {
             sink.push(TYPE_NAMED_NODE_ID);
             sink.extend_from_slice(&iri_id.to_be_bytes());
         }
+        EncodedTerm::PrefixedNamedNode {
+            prefix_id,
+            local_name,
+        } => {
+            sink.push(TYPE_PREFIXED_NAMED_NODE_ID);
+            sink.extend_from_slice(&prefix_id.to_be_bytes());
+            sink.extend_from_slice(&local_name.to_be_bytes());
+        }
         EncodedTerm::NumericalBlankNode { id } => {
             sink.push(TYPE_NUMERICAL_BLANK_NODE_ID);
             sink.extend_from_slice(id)
@@ -543,6 +572,10 @@ pub fn write_term(sink: &mut Vec<u8>, term: &EncodedTerm) {
             sink.extend_from_slice(&language_id.to_be_bytes());
             sink.extend_from_slice(&value_id.to_be_bytes());
         }
+        EncodedTerm::MediumLangStringLiteral(value) => {
+            sink.push(TYPE_MEDIUM_LANG_STRING_LITERAL);
+            sink.extend_from_slice(&value.to_be_bytes())
+        }
         EncodedTerm::SmallTypedLiteral { value, datatype_id } => {
             sink.push(TYPE_SMALL_TYPED_LITERAL);
             sink.extend_from_slice(&datatype_id.to_be_bytes());
@@ -556,6 +589,10 @@ pub fn write_term(sink: &mut Vec<u8>, term: &EncodedTerm) {
             sink.extend_from_slice(&datatype_id.to_be_bytes());
             sink.extend_from_slice(&value_id.to_be_bytes());
         }
+        EncodedTerm::MediumAnyUriLiteral(value) => {
+            sink.push(TYPE_MEDIUM_ANY_URI_LITERAL);
+            sink.extend_from_slice(&value.to_be_bytes())
+        }
         EncodedTerm::BooleanLiteral(value) => sink.push(if bool::from(*value) {
             TYPE_BOOLEAN_LITERAL_TRUE
         } else {
@@ -677,6 +714,9 @@ mod tests {
             NamedNode::new_unchecked("http://foo.com").into(),
             NamedNode::new_unchecked("http://bar.com").into(),
             NamedNode::new_unchecked("http://foo.com").into(),
+            NamedNode::new_unchecked("http://example.com/ns#local").into(),
+            NamedNode::new_unchecked("http://example.com/this-is-a-very-long-local-name").into(),
+            NamedNode::new_unchecked("urn:isbn:0451450523").into(),
             BlankNode::default().into(),
             BlankNode::new_unchecked("1234567890").into(),
             BlankNode::new_unchecked("bnode").into(),
@@ -688,6 +728,7 @@ mod tests {
             Literal::from(1).into(),
             Literal::from("foo-string").into(),
             Literal::new_language_tagged_literal_unchecked("foo-fr", "fr").into(),
+            Literal::new_language_tagged_literal_unchecked("foo-fr-medium-literal@", "fr").into(),
             Literal::new_language_tagged_literal_unchecked(
                 "foo-fr-literal-thisisaverylargelanguagetaggedstringliteral",
                 "fr",
@@ -715,6 +756,12 @@ mod tests {
             Literal::new_typed_literal("PT1S", xsd::DURATION).into(),
             Literal::new_typed_literal("PT1S", xsd::DAY_TIME_DURATION).into(),
             Literal::new_typed_literal("P1Y", xsd::YEAR_MONTH_DURATION).into(),
+            Literal::new_typed_literal("http://example.com/", xsd::ANY_URI).into(),
+            Literal::new_typed_literal(
+                "http://example.com/this-is-a-very-long-uri-literal",
+                xsd::ANY_URI,
+            )
+            .into(),
             Literal::new_typed_literal("-foo", NamedNode::new_unchecked("http://foo.com")).into(),
             Literal::new_typed_literal(
                 "-foo-thisisaverybigtypedliteralwiththefoodatatype",
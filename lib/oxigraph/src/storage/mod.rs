@@ -1,19 +1,32 @@
+use crate::metrics::StorageStatistics;
 use crate::model::{GraphNameRef, NamedOrBlankNodeRef, QuadRef};
 pub use crate::storage::error::{CorruptionError, LoaderError, SerializerError, StorageError};
 use crate::storage::memory::{
     MemoryDecodingGraphIterator, MemoryStorage, MemoryStorageBulkLoader, MemoryStorageReader,
     MemoryStorageWriter, QuadIterator,
 };
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+use crate::storage::numeric_encoder::StrHashHasher;
 use crate::storage::numeric_encoder::{EncodedQuad, EncodedTerm, StrHash, StrLookup};
 #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+pub use crate::storage::rocksdb::{Compression, QuadIndex, StoreOptions};
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
 use crate::storage::rocksdb::{
     RocksDbChainedDecodingQuadIterator, RocksDbDecodingGraphIterator, RocksDbStorage,
     RocksDbStorageBulkLoader, RocksDbStorageReader, RocksDbStorageWriter,
 };
 use oxrdf::Quad;
+use std::cell::RefCell;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+use std::hash::BuildHasherDefault;
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
 use std::path::Path;
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+use std::sync::PoisonError;
+use std::sync::{Arc, Mutex};
 
 #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
 mod binary_encoder;
@@ -30,6 +43,8 @@ pub mod small_string;
 #[derive(Clone)]
 pub struct Storage {
     kind: StorageKind,
+    /// `None` if the storage might be modified by an other process
+    versions: Option<Arc<Mutex<StorageVersions>>>,
 }
 
 #[derive(Clone)]
@@ -44,6 +59,7 @@ impl Storage {
     pub fn new() -> Result<Self, StorageError> {
         Ok(Self {
             kind: StorageKind::Memory(MemoryStorage::new()),
+            versions: Some(Arc::default()),
         })
     }
 
@@ -51,13 +67,26 @@ impl Storage {
     pub fn open(path: &Path) -> Result<Self, StorageError> {
         Ok(Self {
             kind: StorageKind::RocksDb(RocksDbStorage::open(path)?),
+            versions: Some(Arc::default()),
+        })
+    }
+
+    #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+    pub fn open_with_options(path: &Path, options: &StoreOptions) -> Result<Self, StorageError> {
+        Ok(Self {
+            kind: StorageKind::RocksDb(RocksDbStorage::open_with_options(path, options)?),
+            versions: Some(Arc::default()),
         })
     }
 
     #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-    pub fn open_secondary(primary_path: &Path) -> Result<Self, StorageError> {
+    pub fn open_secondary(
+        primary_path: &Path,
+        options: &StoreOptions,
+    ) -> Result<Self, StorageError> {
         Ok(Self {
-            kind: StorageKind::RocksDb(RocksDbStorage::open_secondary(primary_path)?),
+            kind: StorageKind::RocksDb(RocksDbStorage::open_secondary(primary_path, options)?),
+            versions: None,
         })
     }
 
@@ -65,31 +94,43 @@ impl Storage {
     pub fn open_persistent_secondary(
         primary_path: &Path,
         secondary_path: &Path,
+        options: &StoreOptions,
     ) -> Result<Self, StorageError> {
         Ok(Self {
             kind: StorageKind::RocksDb(RocksDbStorage::open_persistent_secondary(
                 primary_path,
                 secondary_path,
+                options,
             )?),
+            versions: None,
         })
     }
 
     #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-    pub fn open_read_only(path: &Path) -> Result<Self, StorageError> {
+    pub fn open_read_only(path: &Path, options: &StoreOptions) -> Result<Self, StorageError> {
         Ok(Self {
-            kind: StorageKind::RocksDb(RocksDbStorage::open_read_only(path)?),
+            kind: StorageKind::RocksDb(RocksDbStorage::open_read_only(path, options)?),
+            versions: Some(Arc::default()),
         })
     }
 
+    pub fn statistics(&self) -> StorageStatistics {
+        match &self.kind {
+            #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+            StorageKind::RocksDb(storage) => storage.statistics(),
+            StorageKind::Memory(_) => StorageStatistics::default(),
+        }
+    }
+
     pub fn snapshot(&self) -> StorageReader {
         match &self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-            StorageKind::RocksDb(storage) => StorageReader {
-                kind: StorageReaderKind::RocksDb(storage.snapshot()),
-            },
-            StorageKind::Memory(storage) => StorageReader {
-                kind: StorageReaderKind::Memory(storage.snapshot()),
-            },
+            StorageKind::RocksDb(storage) => {
+                StorageReader::new(StorageReaderKind::RocksDb(storage.snapshot()))
+            }
+            StorageKind::Memory(storage) => {
+                StorageReader::new(StorageReaderKind::Memory(storage.snapshot()))
+            }
         }
     }
 
@@ -97,18 +138,78 @@ impl Storage {
         &self,
         f: impl for<'a> Fn(StorageWriter<'a>) -> Result<T, E>,
     ) -> Result<T, E> {
-        match &self.kind {
+        let modified = RefCell::new(ModifiedGraphs::default());
+        let result = match &self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageKind::RocksDb(storage) => storage.transaction(|transaction| {
+                modified.replace(ModifiedGraphs::default());
                 f(StorageWriter {
                     kind: StorageWriterKind::RocksDb(transaction),
+                    modified: &modified,
                 })
             }),
             StorageKind::Memory(storage) => storage.transaction(|transaction| {
+                modified.replace(ModifiedGraphs::default());
                 f(StorageWriter {
                     kind: StorageWriterKind::Memory(transaction),
+                    modified: &modified,
                 })
             }),
+        };
+        if result.is_ok() {
+            self.record_modifications(modified.into_inner());
+        }
+        result
+    }
+
+    /// Version of the storage content, incremented after each committed change done through this process
+    ///
+    /// Returns `None` if the storage might be modified by an other process.
+    pub fn version(&self) -> Option<u64> {
+        Some(self.versions.as_ref()?.lock().ok()?.current)
+    }
+
+    /// Version of the last committed change to the given graph done through this process
+    ///
+    /// Returns `None` if the storage might be modified by an other process.
+    pub fn graph_version(&self, graph_name: &EncodedTerm) -> Option<u64> {
+        let versions = self.versions.as_ref()?.lock().ok()?;
+        Some(max(
+            versions.graphs.get(graph_name).copied().unwrap_or(0),
+            versions.all_graphs,
+        ))
+    }
+
+    /// Checks if a graph that is not in `ignored` has been modified through this process after `version`
+    ///
+    /// Returns `None` if the storage might be modified by an other process.
+    pub fn is_modified_since(&self, version: u64, ignored: &[EncodedTerm]) -> Option<bool> {
+        let versions = self.versions.as_ref()?.lock().ok()?;
+        Some(
+            versions.all_graphs > version
+                || versions.graphs.iter().any(|(graph_name, graph_version)| {
+                    *graph_version > version && !ignored.contains(graph_name)
+                }),
+        )
+    }
+
+    /// The materialized view definitions saved with [`StorageWriter::set_view_definitions`]
+    ///
+    /// Always `None` for in-memory storages: they can't be reopened.
+    #[allow(clippy::unnecessary_wraps)]
+    pub fn view_definitions(&self) -> Result<Option<Vec<u8>>, StorageError> {
+        match &self.kind {
+            #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+            StorageKind::RocksDb(storage) => storage.view_definitions(),
+            StorageKind::Memory(_) => Ok(None),
+        }
+    }
+
+    fn record_modifications(&self, modified: ModifiedGraphs) {
+        if let Some(versions) = &self.versions {
+            if let Ok(mut versions) = versions.lock() {
+                versions.record(modified);
+            }
         }
     }
 
@@ -146,16 +247,75 @@ impl Storage {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageKind::RocksDb(storage) => StorageBulkLoader {
                 kind: StorageBulkLoaderKind::RocksDb(storage.bulk_loader()),
+                storage: self.clone(),
             },
             StorageKind::Memory(storage) => StorageBulkLoader {
                 kind: StorageBulkLoaderKind::Memory(storage.bulk_loader()),
+                storage: self.clone(),
             },
         }
     }
 }
 
+/// Counts the changes committed to the storage
+#[derive(Default)]
+struct StorageVersions {
+    current: u64,
+    /// Version of the last change that might have modified all graphs
+    all_graphs: u64,
+    graphs: HashMap<EncodedTerm, u64>,
+}
+
+impl StorageVersions {
+    fn record(&mut self, modified: ModifiedGraphs) {
+        if !modified.all && modified.graphs.is_empty() {
+            return; // Nothing changed
+        }
+        self.current += 1;
+        if modified.all {
+            self.all_graphs = self.current;
+            // The previous graph versions are now useless
+            self.graphs.clear();
+        } else {
+            for graph_name in modified.graphs {
+                self.graphs.insert(graph_name, self.current);
+            }
+        }
+    }
+}
+
+/// The graphs modified by a transaction
+#[derive(Default)]
+struct ModifiedGraphs {
+    all: bool,
+    graphs: HashSet<EncodedTerm>,
+}
+
+impl ModifiedGraphs {
+    fn add(&mut self, graph_name: impl Into<EncodedTerm>) {
+        if !self.all {
+            self.graphs.insert(graph_name.into());
+        }
+    }
+
+    fn add_all(&mut self) {
+        self.all = true;
+        self.graphs.clear();
+    }
+}
+
+/// Maximal number of decoded strings kept in the cache of a [`StorageReader`]
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+const STR_CACHE_CAPACITY: usize = 4096;
+
 pub struct StorageReader {
     kind: StorageReaderKind,
+    /// Cache of the strings already fetched from `id2str`
+    ///
+    /// Result-heavy queries decode the same IRIs over and over again.
+    /// It is behind a mutex so that the reader can be shared between threads.
+    #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+    str_cache: Mutex<HashMap<StrHash, String, BuildHasherDefault<StrHashHasher>>>,
 }
 
 enum StorageReaderKind {
@@ -166,6 +326,14 @@ enum StorageReaderKind {
 
 #[allow(clippy::unnecessary_wraps)]
 impl StorageReader {
+    fn new(kind: StorageReaderKind) -> Self {
+        Self {
+            kind,
+            #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+            str_cache: Mutex::default(),
+        }
+    }
+
     pub fn len(&self) -> Result<usize, StorageError> {
         match &self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
@@ -210,6 +378,43 @@ impl StorageReader {
         }
     }
 
+    /// Returns disjoint iterators covering together the quads matching the pattern in the given graph.
+    ///
+    /// The iterators can be consumed from different threads.
+    /// Only the on-disk storage is partitioned, a single iterator is returned for the in-memory storage
+    /// and if at most `min_partitioned_size` quads match.
+    #[allow(unused_variables)]
+    pub fn quads_for_pattern_partitioned(
+        &self,
+        subject: Option<&EncodedTerm>,
+        predicate: Option<&EncodedTerm>,
+        object: Option<&EncodedTerm>,
+        graph_name: &EncodedTerm,
+        partitions: usize,
+        min_partitioned_size: usize,
+    ) -> Result<Vec<DecodingQuadIterator>, StorageError> {
+        Ok(match &self.kind {
+            #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+            StorageReaderKind::RocksDb(reader) => reader
+                .quads_for_pattern_partitioned(
+                    subject,
+                    predicate,
+                    object,
+                    graph_name,
+                    partitions,
+                    min_partitioned_size,
+                )?
+                .into_iter()
+                .map(|iter| DecodingQuadIterator {
+                    kind: DecodingQuadIteratorKind::RocksDb(iter),
+                })
+                .collect(),
+            StorageReaderKind::Memory(_) => {
+                vec![self.quads_for_pattern(subject, predicate, object, Some(graph_name))]
+            }
+        })
+    }
+
     pub fn named_graphs(&self) -> DecodingGraphIterator {
         match &self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
@@ -296,7 +501,30 @@ impl StrLookup for StorageReader {
     fn get_str(&self, key: &StrHash) -> Result<Option<String>, StorageError> {
         match &self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-            StorageReaderKind::RocksDb(reader) => reader.get_str(key),
+            StorageReaderKind::RocksDb(reader) => {
+                if let Some(value) = self
+                    .str_cache
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get(key)
+                {
+                    return Ok(Some(value.clone()));
+                }
+                // The lock is not held while reading from RocksDB
+                let value = reader.get_str(key)?;
+                if let Some(value) = &value {
+                    let mut cache = self
+                        .str_cache
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    if cache.len() >= STR_CACHE_CAPACITY {
+                        cache.clear();
+                    }
+                    cache.insert(*key, value.clone());
+                }
+                Ok(value)
+            }
+            // The in-memory storage already keeps the decoded strings around
             StorageReaderKind::Memory(reader) => reader.get_str(key),
         }
     }
@@ -304,6 +532,7 @@ impl StrLookup for StorageReader {
 
 pub struct StorageWriter<'a> {
     kind: StorageWriterKind<'a>,
+    modified: &'a RefCell<ModifiedGraphs>,
 }
 
 enum StorageWriterKind<'a> {
@@ -317,43 +546,68 @@ impl<'a> StorageWriter<'a> {
     pub fn reader(&self) -> StorageReader {
         match &self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-            StorageWriterKind::RocksDb(writer) => StorageReader {
-                kind: StorageReaderKind::RocksDb(writer.reader()),
-            },
-            StorageWriterKind::Memory(writer) => StorageReader {
-                kind: StorageReaderKind::Memory(writer.reader()),
-            },
+            StorageWriterKind::RocksDb(writer) => {
+                StorageReader::new(StorageReaderKind::RocksDb(writer.reader()))
+            }
+            StorageWriterKind::Memory(writer) => {
+                StorageReader::new(StorageReaderKind::Memory(writer.reader()))
+            }
         }
     }
 
     pub fn insert(&mut self, quad: QuadRef<'_>) -> Result<bool, StorageError> {
-        match &mut self.kind {
+        let inserted = match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-            StorageWriterKind::RocksDb(writer) => writer.insert(quad),
-            StorageWriterKind::Memory(writer) => Ok(writer.insert(quad)),
+            StorageWriterKind::RocksDb(writer) => writer.insert(quad)?,
+            StorageWriterKind::Memory(writer) => writer.insert(quad),
+        };
+        if inserted {
+            self.modified.borrow_mut().add(quad.graph_name);
         }
+        Ok(inserted)
     }
 
     pub fn insert_named_graph(
         &mut self,
         graph_name: NamedOrBlankNodeRef<'_>,
     ) -> Result<bool, StorageError> {
-        match &mut self.kind {
+        let inserted = match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-            StorageWriterKind::RocksDb(writer) => writer.insert_named_graph(graph_name),
-            StorageWriterKind::Memory(writer) => Ok(writer.insert_named_graph(graph_name)),
+            StorageWriterKind::RocksDb(writer) => writer.insert_named_graph(graph_name)?,
+            StorageWriterKind::Memory(writer) => writer.insert_named_graph(graph_name),
+        };
+        if inserted {
+            self.modified.borrow_mut().add(graph_name);
         }
+        Ok(inserted)
     }
 
     pub fn remove(&mut self, quad: QuadRef<'_>) -> Result<bool, StorageError> {
+        let removed = match &mut self.kind {
+            #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+            StorageWriterKind::RocksDb(writer) => writer.remove(quad)?,
+            StorageWriterKind::Memory(writer) => writer.remove(quad),
+        };
+        if removed {
+            self.modified.borrow_mut().add(quad.graph_name);
+        }
+        Ok(removed)
+    }
+
+    /// Saves the materialized view definitions so that they are available when the storage is reopened
+    pub fn set_view_definitions(&mut self, definitions: &[u8]) -> Result<(), StorageError> {
         match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
-            StorageWriterKind::RocksDb(writer) => writer.remove(quad),
-            StorageWriterKind::Memory(writer) => Ok(writer.remove(quad)),
+            StorageWriterKind::RocksDb(writer) => writer.set_view_definitions(definitions),
+            StorageWriterKind::Memory(_) => {
+                let _ = definitions; // The in-memory storages are never reopened
+                Ok(())
+            }
         }
     }
 
     pub fn clear_graph(&mut self, graph_name: GraphNameRef<'_>) -> Result<(), StorageError> {
+        self.modified.borrow_mut().add(graph_name);
         match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageWriterKind::RocksDb(writer) => writer.clear_graph(graph_name),
@@ -365,6 +619,7 @@ impl<'a> StorageWriter<'a> {
     }
 
     pub fn clear_all_named_graphs(&mut self) -> Result<(), StorageError> {
+        self.modified.borrow_mut().add_all();
         match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageWriterKind::RocksDb(writer) => writer.clear_all_named_graphs(),
@@ -376,6 +631,7 @@ impl<'a> StorageWriter<'a> {
     }
 
     pub fn clear_all_graphs(&mut self) -> Result<(), StorageError> {
+        self.modified.borrow_mut().add_all();
         match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageWriterKind::RocksDb(writer) => writer.clear_all_graphs(),
@@ -390,6 +646,7 @@ impl<'a> StorageWriter<'a> {
         &mut self,
         graph_name: NamedOrBlankNodeRef<'_>,
     ) -> Result<bool, StorageError> {
+        self.modified.borrow_mut().add(graph_name);
         match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageWriterKind::RocksDb(writer) => writer.remove_named_graph(graph_name),
@@ -398,6 +655,7 @@ impl<'a> StorageWriter<'a> {
     }
 
     pub fn remove_all_named_graphs(&mut self) -> Result<(), StorageError> {
+        self.modified.borrow_mut().add_all();
         match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageWriterKind::RocksDb(writer) => writer.remove_all_named_graphs(),
@@ -409,6 +667,7 @@ impl<'a> StorageWriter<'a> {
     }
 
     pub fn clear(&mut self) -> Result<(), StorageError> {
+        self.modified.borrow_mut().add_all();
         match &mut self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageWriterKind::RocksDb(writer) => writer.clear(),
@@ -423,6 +682,7 @@ impl<'a> StorageWriter<'a> {
 #[must_use]
 pub struct StorageBulkLoader {
     kind: StorageBulkLoaderKind,
+    storage: Storage,
 }
 
 enum StorageBulkLoaderKind {
@@ -437,9 +697,11 @@ impl StorageBulkLoader {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageBulkLoaderKind::RocksDb(loader) => Self {
                 kind: StorageBulkLoaderKind::RocksDb(loader.with_num_threads(num_threads)),
+                storage: self.storage,
             },
             StorageBulkLoaderKind::Memory(loader) => Self {
                 kind: StorageBulkLoaderKind::Memory(loader),
+                storage: self.storage,
             },
         }
     }
@@ -452,9 +714,11 @@ impl StorageBulkLoader {
                 kind: StorageBulkLoaderKind::RocksDb(
                     loader.with_max_memory_size_in_megabytes(max_memory_size),
                 ),
+                storage: self.storage,
             },
             StorageBulkLoaderKind::Memory(loader) => Self {
                 kind: StorageBulkLoaderKind::Memory(loader),
+                storage: self.storage,
             },
         }
     }
@@ -464,9 +728,11 @@ impl StorageBulkLoader {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageBulkLoaderKind::RocksDb(loader) => Self {
                 kind: StorageBulkLoaderKind::RocksDb(loader.on_progress(callback)),
+                storage: self.storage,
             },
             StorageBulkLoaderKind::Memory(loader) => Self {
                 kind: StorageBulkLoaderKind::Memory(loader.on_progress(callback)),
+                storage: self.storage,
             },
         }
     }
@@ -476,10 +742,19 @@ impl StorageBulkLoader {
         &self,
         quads: impl IntoIterator<Item = Result<Quad, EI>>,
     ) -> Result<(), EO> {
-        match &self.kind {
+        let modified = RefCell::new(ModifiedGraphs::default());
+        let quads = quads.into_iter().inspect(|quad| {
+            if let Ok(quad) = quad {
+                modified.borrow_mut().add(quad.graph_name.as_ref());
+            }
+        });
+        let result = match &self.kind {
             #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
             StorageBulkLoaderKind::RocksDb(loader) => loader.load(quads),
             StorageBulkLoaderKind::Memory(loader) => loader.load(quads),
-        }
+        };
+        // Some quads might have been written even if the load failed
+        self.storage.record_modifications(modified.into_inner());
+        result
     }
 }
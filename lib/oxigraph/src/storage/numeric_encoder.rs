@@ -1,8 +1,9 @@
 #![allow(clippy::unreadable_literal)]
 
+use crate::model::vocab::xsd;
 use crate::model::*;
 use crate::storage::error::{CorruptionError, StorageError};
-use crate::storage::small_string::SmallString;
+use crate::storage::small_string::{MediumString, SmallString};
 use oxsdatatypes::*;
 use siphasher::sip128::{Hasher128, SipHasher24};
 use std::fmt::Debug;
@@ -49,6 +50,11 @@ pub enum EncodedTerm {
     NamedNode {
         iri_id: StrHash,
     },
+    /// A named node split into a namespace stored in the string store and an inline local name
+    PrefixedNamedNode {
+        prefix_id: StrHash,
+        local_name: SmallString,
+    },
     NumericalBlankNode {
         id: [u8; 16],
     },
@@ -76,6 +82,8 @@ pub enum EncodedTerm {
         value_id: StrHash,
         language_id: StrHash,
     },
+    /// A language-tagged string stored inline as `{language}@{value}`
+    MediumLangStringLiteral(MediumString),
     SmallTypedLiteral {
         value: SmallString,
         datatype_id: StrHash,
@@ -84,6 +92,7 @@ pub enum EncodedTerm {
         value_id: StrHash,
         datatype_id: StrHash,
     },
+    MediumAnyUriLiteral(MediumString),
     BooleanLiteral(Boolean),
     FloatLiteral(Float),
     DoubleLiteral(Double),
@@ -110,6 +119,16 @@ impl PartialEq for EncodedTerm {
             (Self::NamedNode { iri_id: iri_id_a }, Self::NamedNode { iri_id: iri_id_b }) => {
                 iri_id_a == iri_id_b
             }
+            (
+                Self::PrefixedNamedNode {
+                    prefix_id: prefix_id_a,
+                    local_name: local_name_a,
+                },
+                Self::PrefixedNamedNode {
+                    prefix_id: prefix_id_b,
+                    local_name: local_name_b,
+                },
+            ) => prefix_id_a == prefix_id_b && local_name_a == local_name_b,
             (Self::NumericalBlankNode { id: id_a }, Self::NumericalBlankNode { id: id_b }) => {
                 id_a == id_b
             }
@@ -166,6 +185,7 @@ impl PartialEq for EncodedTerm {
                     language_id: language_id_b,
                 },
             ) => value_id_a == value_id_b && language_id_a == language_id_b,
+            (Self::MediumLangStringLiteral(a), Self::MediumLangStringLiteral(b)) => a == b,
             (
                 Self::SmallTypedLiteral {
                     value: value_a,
@@ -186,6 +206,7 @@ impl PartialEq for EncodedTerm {
                     datatype_id: datatype_id_b,
                 },
             ) => value_id_a == value_id_b && datatype_id_a == datatype_id_b,
+            (Self::MediumAnyUriLiteral(a), Self::MediumAnyUriLiteral(b)) => a == b,
             (Self::BooleanLiteral(a), Self::BooleanLiteral(b)) => a == b,
             (Self::FloatLiteral(a), Self::FloatLiteral(b)) => a.is_identical_with(*b),
             (Self::DoubleLiteral(a), Self::DoubleLiteral(b)) => a.is_identical_with(*b),
@@ -218,6 +239,13 @@ impl Hash for EncodedTerm {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
             Self::NamedNode { iri_id } => iri_id.hash(state),
+            Self::PrefixedNamedNode {
+                prefix_id,
+                local_name,
+            } => {
+                prefix_id.hash(state);
+                local_name.hash(state);
+            }
             Self::NumericalBlankNode { id } => id.hash(state),
             Self::SmallBlankNode(id) => id.hash(state),
             Self::BigBlankNode { id_id } => id_id.hash(state),
@@ -243,6 +271,7 @@ impl Hash for EncodedTerm {
                 value_id.hash(state);
                 language_id.hash(state);
             }
+            Self::MediumLangStringLiteral(value) => value.hash(state),
             Self::SmallTypedLiteral { value, datatype_id } => {
                 value.hash(state);
                 datatype_id.hash(state);
@@ -254,6 +283,7 @@ impl Hash for EncodedTerm {
                 value_id.hash(state);
                 datatype_id.hash(state);
             }
+            Self::MediumAnyUriLiteral(value) => value.hash(state),
             Self::BooleanLiteral(value) => value.hash(state),
             Self::FloatLiteral(value) => value.to_be_bytes().hash(state),
             Self::DoubleLiteral(value) => value.to_be_bytes().hash(state),
@@ -277,7 +307,10 @@ impl Hash for EncodedTerm {
 
 impl EncodedTerm {
     pub fn is_named_node(&self) -> bool {
-        matches!(self, Self::NamedNode { .. })
+        matches!(
+            self,
+            Self::NamedNode { .. } | Self::PrefixedNamedNode { .. }
+        )
     }
 
     pub fn is_blank_node(&self) -> bool {
@@ -298,8 +331,10 @@ impl EncodedTerm {
                 | Self::SmallBigLangStringLiteral { .. }
                 | Self::BigSmallLangStringLiteral { .. }
                 | Self::BigBigLangStringLiteral { .. }
+                | Self::MediumLangStringLiteral(_)
                 | Self::SmallTypedLiteral { .. }
                 | Self::BigTypedLiteral { .. }
+                | Self::MediumAnyUriLiteral(_)
                 | Self::BooleanLiteral(_)
                 | Self::FloatLiteral(_)
                 | Self::DoubleLiteral(_)
@@ -322,7 +357,9 @@ impl EncodedTerm {
     pub fn is_unknown_typed_literal(&self) -> bool {
         matches!(
             self,
-            Self::SmallTypedLiteral { .. } | Self::BigTypedLiteral { .. }
+            Self::SmallTypedLiteral { .. }
+                | Self::BigTypedLiteral { .. }
+                | Self::MediumAnyUriLiteral(_)
         )
     }
 
@@ -333,6 +370,68 @@ impl EncodedTerm {
     pub fn is_triple(&self) -> bool {
         matches!(self, Self::Triple { .. })
     }
+
+    /// Calls `f` on the ids of the strings of the string store used by the term
+    pub fn for_each_str_id(&self, f: &mut impl FnMut(&StrHash)) {
+        match self {
+            Self::NamedNode { iri_id: id }
+            | Self::PrefixedNamedNode { prefix_id: id, .. }
+            | Self::BigBlankNode { id_id: id }
+            | Self::BigStringLiteral { value_id: id }
+            | Self::SmallBigLangStringLiteral {
+                language_id: id, ..
+            }
+            | Self::BigSmallLangStringLiteral { value_id: id, .. }
+            | Self::SmallTypedLiteral {
+                datatype_id: id, ..
+            } => f(id),
+            Self::BigBigLangStringLiteral {
+                value_id,
+                language_id,
+            } => {
+                f(value_id);
+                f(language_id);
+            }
+            Self::BigTypedLiteral {
+                value_id,
+                datatype_id,
+            } => {
+                f(value_id);
+                f(datatype_id);
+            }
+            Self::Triple(triple) => {
+                triple.subject.for_each_str_id(f);
+                triple.predicate.for_each_str_id(f);
+                triple.object.for_each_str_id(f);
+            }
+            _ => (),
+        }
+    }
+
+    /// Builds the inline encoding of a language-tagged string if it fits into a [`MediumString`]
+    pub fn medium_lang_string_literal(value: &str, language: &str) -> Option<Self> {
+        if language.len() + value.len() >= 31 {
+            return None;
+        }
+        Some(Self::MediumLangStringLiteral(
+            format!("{language}@{value}").as_str().try_into().ok()?,
+        ))
+    }
+}
+
+/// Returns the value and the language tag of an inline language-tagged string
+///
+/// Language tags never contain `@` so we split at the first `@`.
+pub fn split_medium_lang_string(value: &MediumString) -> (&str, &str) {
+    value
+        .split_once('@')
+        .map_or((value.as_str(), ""), |(language, value)| (value, language))
+}
+
+/// Splits an IRI into a namespace and a local name small enough to be inlined
+fn split_iri(iri: &str) -> Option<(&str, SmallString)> {
+    let (namespace, local_name) = iri.split_at(iri.rfind(|c| c == '/' || c == '#')? + 1);
+    Some((namespace, local_name.try_into().ok()?))
 }
 
 impl From<bool> for EncodedTerm {
@@ -481,8 +580,15 @@ impl From<EncodedTriple> for EncodedTerm {
 
 impl From<NamedNodeRef<'_>> for EncodedTerm {
     fn from(named_node: NamedNodeRef<'_>) -> Self {
-        Self::NamedNode {
-            iri_id: StrHash::new(named_node.as_str()),
+        if let Some((prefix, local_name)) = split_iri(named_node.as_str()) {
+            Self::PrefixedNamedNode {
+                prefix_id: StrHash::new(prefix),
+                local_name,
+            }
+        } else {
+            Self::NamedNode {
+                iri_id: StrHash::new(named_node.as_str()),
+            }
         }
     }
 }
@@ -513,14 +619,18 @@ impl From<LiteralRef<'_>> for EncodedTerm {
         let native_encoding = match datatype {
             "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString" => {
                 literal.language().map(|language| {
-                    if let Ok(value) = SmallString::try_from(value) {
-                        if let Ok(language) = SmallString::try_from(language) {
-                            Self::SmallSmallLangStringLiteral { value, language }
-                        } else {
-                            Self::SmallBigLangStringLiteral {
-                                value,
-                                language_id: StrHash::new(language),
-                            }
+                    if let (Ok(value), Ok(language)) = (
+                        SmallString::try_from(value),
+                        SmallString::try_from(language),
+                    ) {
+                        Self::SmallSmallLangStringLiteral { value, language }
+                    } else if let Some(encoded) = Self::medium_lang_string_literal(value, language)
+                    {
+                        encoded
+                    } else if let Ok(value) = SmallString::try_from(value) {
+                        Self::SmallBigLangStringLiteral {
+                            value,
+                            language_id: StrHash::new(language),
                         }
                     } else if let Ok(language) = SmallString::try_from(language) {
                         Self::BigSmallLangStringLiteral {
@@ -545,6 +655,9 @@ impl From<LiteralRef<'_>> for EncodedTerm {
                     }
                 })
             }
+            "http://www.w3.org/2001/XMLSchema#anyURI" => MediumString::try_from(value)
+                .ok()
+                .map(Self::MediumAnyUriLiteral),
             "http://www.w3.org/2001/XMLSchema#float" => parse_float_str(value),
             "http://www.w3.org/2001/XMLSchema#double" => parse_double_str(value),
             "http://www.w3.org/2001/XMLSchema#integer"
@@ -716,13 +829,20 @@ pub fn insert_term<F: FnMut(&StrHash, &str) -> Result<(), StorageError>>(
     insert_str: &mut F,
 ) -> Result<(), StorageError> {
     match term {
-        TermRef::NamedNode(node) => {
-            if let EncodedTerm::NamedNode { iri_id } = encoded {
-                insert_str(iri_id, node.as_str())
-            } else {
-                Err(CorruptionError::from_encoded_term(encoded, &term).into())
+        TermRef::NamedNode(node) => match encoded {
+            EncodedTerm::NamedNode { iri_id } => insert_str(iri_id, node.as_str()),
+            EncodedTerm::PrefixedNamedNode {
+                prefix_id,
+                local_name,
+            } => {
+                if let Some(prefix) = node.as_str().strip_suffix(local_name.as_str()) {
+                    insert_str(prefix_id, prefix)
+                } else {
+                    Err(CorruptionError::from_encoded_term(encoded, &term).into())
+                }
             }
-        }
+            _ => Err(CorruptionError::from_encoded_term(encoded, &term).into()),
+        },
         TermRef::BlankNode(node) => match encoded {
             EncodedTerm::BigBlankNode { id_id } => insert_str(id_id, node.as_str()),
             EncodedTerm::SmallBlankNode(..) | EncodedTerm::NumericalBlankNode { .. } => Ok(()),
@@ -763,6 +883,8 @@ pub fn insert_term<F: FnMut(&StrHash, &str) -> Result<(), StorageError>>(
             }
             EncodedTerm::SmallStringLiteral(..)
             | EncodedTerm::SmallSmallLangStringLiteral { .. }
+            | EncodedTerm::MediumLangStringLiteral(..)
+            | EncodedTerm::MediumAnyUriLiteral(..)
             | EncodedTerm::BooleanLiteral(..)
             | EncodedTerm::FloatLiteral(..)
             | EncodedTerm::DoubleLiteral(..)
@@ -957,6 +1079,14 @@ impl<S: StrLookup> Decoder for S {
             EncodedTerm::NamedNode { iri_id } => {
                 Ok(NamedNode::new_unchecked(get_required_str(self, iri_id)?).into())
             }
+            EncodedTerm::PrefixedNamedNode {
+                prefix_id,
+                local_name,
+            } => {
+                let mut iri = get_required_str(self, prefix_id)?;
+                iri.push_str(local_name);
+                Ok(NamedNode::new_unchecked(iri).into())
+            }
             EncodedTerm::NumericalBlankNode { id } => {
                 Ok(BlankNode::new_from_unique_id(u128::from_be_bytes(*id)).into())
             }
@@ -995,6 +1125,10 @@ impl<S: StrLookup> Decoder for S {
                 get_required_str(self, language_id)?,
             )
             .into()),
+            EncodedTerm::MediumLangStringLiteral(value) => {
+                let (value, language) = split_medium_lang_string(value);
+                Ok(Literal::new_language_tagged_literal_unchecked(value, language).into())
+            }
             EncodedTerm::SmallTypedLiteral { value, datatype_id } => {
                 Ok(Literal::new_typed_literal(
                     *value,
@@ -1010,6 +1144,9 @@ impl<S: StrLookup> Decoder for S {
                 NamedNode::new_unchecked(get_required_str(self, datatype_id)?),
             )
             .into()),
+            EncodedTerm::MediumAnyUriLiteral(value) => {
+                Ok(Literal::new_typed_literal(value.as_str(), xsd::ANY_URI).into())
+            }
             EncodedTerm::BooleanLiteral(value) => Ok(Literal::from(*value).into()),
             EncodedTerm::FloatLiteral(value) => Ok(Literal::from(*value).into()),
             EncodedTerm::DoubleLiteral(value) => Ok(Literal::from(*value).into()),
@@ -1,8 +1,9 @@
+use crate::metrics::StorageStatistics;
 use crate::model::{GraphNameRef, NamedOrBlankNodeRef, Quad, QuadRef, TermRef};
 use crate::storage::binary_encoder::{
-    decode_term, encode_term, encode_term_pair, encode_term_quad, encode_term_triple,
-    write_gosp_quad, write_gpos_quad, write_gspo_quad, write_osp_quad, write_ospg_quad,
-    write_pos_quad, write_posg_quad, write_spo_quad, write_spog_quad, write_term, QuadEncoding,
+    decode_term, encode_term, encode_term_quad, encode_term_triple, write_gosp_quad,
+    write_gpos_quad, write_gspo_quad, write_osp_quad, write_ospg_quad, write_pos_quad,
+    write_posg_quad, write_spo_quad, write_spog_quad, write_term, QuadEncoding,
     LATEST_STORAGE_VERSION, WRITTEN_TERM_MAX_SIZE,
 };
 pub use crate::storage::error::{CorruptionError, StorageError};
@@ -19,7 +20,7 @@ use std::hash::BuildHasherDefault;
 use std::mem::{swap, take};
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
-use std::{io, thread};
+use std::{fmt, io, str, thread};
 
 const ID2STR_CF: &str = "id2str";
 const SPOG_CF: &str = "spog";
@@ -34,6 +35,343 @@ const DOSP_CF: &str = "dosp";
 const GRAPHS_CF: &str = "graphs";
 const DEFAULT_CF: &str = "default";
 const DEFAULT_BULK_LOAD_BATCH_SIZE: usize = 1_000_000;
+const MIGRATION_BATCH_SIZE: usize = 100_000;
+
+/// A quad index that might be maintained by the on-disk storage.
+///
+/// The name gives the order of the terms in the index keys.
+/// The `D*` indexes only contain the triples of the default graph and the other ones only the quads of the named graphs.
+///
+/// [`QuadIndex::Dspo`] and [`QuadIndex::Gspo`] are always maintained.
+/// Patterns that are not covered by the maintained indexes are evaluated by scanning an index and filtering the quads.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+pub enum QuadIndex {
+    Spog,
+    Posg,
+    Ospg,
+    Gspo,
+    Gpos,
+    Gosp,
+    Dspo,
+    Dpos,
+    Dosp,
+}
+
+impl QuadIndex {
+    /// All the indexes. This is the default configuration.
+    pub const ALL: [Self; 9] = [
+        Self::Spog,
+        Self::Posg,
+        Self::Ospg,
+        Self::Gspo,
+        Self::Gpos,
+        Self::Gosp,
+        Self::Dspo,
+        Self::Dpos,
+        Self::Dosp,
+    ];
+    /// The default graph indexes, the always maintained one first
+    const DEFAULT_GRAPH: [Self; 3] = [Self::Dspo, Self::Dpos, Self::Dosp];
+    /// The named graphs indexes, the always maintained one first
+    const NAMED_GRAPHS: [Self; 6] = [
+        Self::Gspo,
+        Self::Gpos,
+        Self::Gosp,
+        Self::Spog,
+        Self::Posg,
+        Self::Ospg,
+    ];
+
+    /// The index name, e.g. `spog`.
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Spog => SPOG_CF,
+            Self::Posg => POSG_CF,
+            Self::Ospg => OSPG_CF,
+            Self::Gspo => GSPO_CF,
+            Self::Gpos => GPOS_CF,
+            Self::Gosp => GOSP_CF,
+            Self::Dspo => DSPO_CF,
+            Self::Dpos => DPOS_CF,
+            Self::Dosp => DOSP_CF,
+        }
+    }
+
+    /// Positions of the terms in the index keys (0 for subject, 1 for predicate, 2 for object and 3 for graph name)
+    fn term_positions(self) -> &'static [usize] {
+        match self {
+            Self::Spog => &[0, 1, 2, 3],
+            Self::Posg => &[1, 2, 0, 3],
+            Self::Ospg => &[2, 0, 1, 3],
+            Self::Gspo => &[3, 0, 1, 2],
+            Self::Gpos => &[3, 1, 2, 0],
+            Self::Gosp => &[3, 2, 0, 1],
+            Self::Dspo => &[0, 1, 2],
+            Self::Dpos => &[1, 2, 0],
+            Self::Dosp => &[2, 0, 1],
+        }
+    }
+
+    fn encoding(self) -> QuadEncoding {
+        match self {
+            Self::Spog => QuadEncoding::Spog,
+            Self::Posg => QuadEncoding::Posg,
+            Self::Ospg => QuadEncoding::Ospg,
+            Self::Gspo => QuadEncoding::Gspo,
+            Self::Gpos => QuadEncoding::Gpos,
+            Self::Gosp => QuadEncoding::Gosp,
+            Self::Dspo => QuadEncoding::Dspo,
+            Self::Dpos => QuadEncoding::Dpos,
+            Self::Dosp => QuadEncoding::Dosp,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|index| index.as_str() == name)
+    }
+}
+
+impl fmt::Display for QuadIndex {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The set of the indexes maintained by a storage
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+struct QuadIndexSet(u16);
+
+impl QuadIndexSet {
+    const ALL: Self = Self(0b1_1111_1111);
+
+    fn new(indexes: impl IntoIterator<Item = QuadIndex>) -> Self {
+        let mut set = Self(0);
+        for index in [QuadIndex::Dspo, QuadIndex::Gspo]
+            .into_iter()
+            .chain(indexes)
+        {
+            set.0 |= Self::flag(index);
+        }
+        set
+    }
+
+    fn contains(self, index: QuadIndex) -> bool {
+        self.0 & Self::flag(index) != 0
+    }
+
+    fn flag(index: QuadIndex) -> u16 {
+        1 << (index as u16)
+    }
+
+    fn iter(self) -> impl Iterator<Item = QuadIndex> {
+        QuadIndex::ALL
+            .into_iter()
+            .filter(move |index| self.contains(*index))
+    }
+
+    fn decode(value: &[u8]) -> Result<Self, StorageError> {
+        let value = str::from_utf8(value).map_err(CorruptionError::new)?;
+        let mut indexes = Vec::new();
+        for name in value.split(',') {
+            indexes.push(QuadIndex::from_name(name).ok_or_else(|| {
+                CorruptionError::new(format!(
+                    "Unknown quad index {name} in the store configuration"
+                ))
+            })?);
+        }
+        Ok(Self::new(indexes))
+    }
+}
+
+impl fmt::Display for QuadIndexSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, index) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(index.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// A compression algorithm applied to the on-disk data files.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
+pub enum Compression {
+    /// No compression.
+    None,
+    /// [LZ4](https://lz4.org/), the default.
+    Lz4,
+    /// LZ4 high compression mode: slower writes but smaller files.
+    Lz4hc,
+}
+
+impl Compression {
+    /// The compression name, e.g. `lz4`.
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Lz4 => "lz4",
+            Self::Lz4hc => "lz4hc",
+        }
+    }
+
+    /// Looks for a compression from its name, e.g. `lz4`.
+    #[inline]
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::None, Self::Lz4, Self::Lz4hc]
+            .into_iter()
+            .find(|compression| compression.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Compression {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Options used when opening a read-write on-disk store.
+///
+/// The options left unset keep the values used by [`Store::open`](crate::store::Store::open).
+/// Only the quad indexes are saved in the store, the tuning options might be changed each time the store is opened.
+///
+/// ```
+/// use oxigraph::store::{Compression, StoreOptions};
+///
+/// let options = StoreOptions::read_heavy()
+///     .with_block_cache_size(4 * 1024 * 1024 * 1024)
+///     .with_compression_per_level([Compression::None, Compression::Lz4]);
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct StoreOptions {
+    indexes: Option<QuadIndexSet>,
+    pub(super) block_cache_size: Option<usize>,
+    pub(super) bloom_filter_bits_per_key: Option<f64>,
+    pub(super) compression_per_level: Option<Vec<Compression>>,
+    pub(super) max_open_files: Option<usize>,
+    pub(super) write_buffer_size: Option<usize>,
+    pub(super) max_write_buffer_number: Option<usize>,
+    pub(super) statistics: bool,
+}
+
+impl StoreOptions {
+    /// Options for small stores on machines with little memory, e.g. laptops or embedded devices.
+    pub fn small() -> Self {
+        Self::default()
+            .with_block_cache_size(8 * 1024 * 1024)
+            .with_write_buffer_size(4 * 1024 * 1024)
+            .with_max_write_buffer_number(2)
+            .with_max_open_files(256)
+    }
+
+    /// Options for stores that are mostly queried: a large block cache and bloom filters to avoid useless disk reads.
+    pub fn read_heavy() -> Self {
+        Self::default()
+            .with_block_cache_size(1024 * 1024 * 1024)
+            .with_bloom_filter(10.)
+    }
+
+    /// Options for stores in which large amounts of data are written: large write buffers and no compression of the first levels.
+    pub fn bulk_import() -> Self {
+        Self::default()
+            .with_write_buffer_size(256 * 1024 * 1024)
+            .with_max_write_buffer_number(6)
+            .with_compression_per_level([Compression::None, Compression::None, Compression::Lz4])
+    }
+
+    /// Looks for a preset from its name: `small`, `read-heavy` or `bulk-import`.
+    pub fn from_preset_name(name: &str) -> Option<Self> {
+        match name {
+            "small" => Some(Self::small()),
+            "read-heavy" => Some(Self::read_heavy()),
+            "bulk-import" => Some(Self::bulk_import()),
+            _ => None,
+        }
+    }
+
+    /// Sets the quad indexes to maintain (see [`Store::open_with_indexes`](crate::store::Store::open_with_indexes)).
+    ///
+    /// [`QuadIndex::Dspo`] and [`QuadIndex::Gspo`] are always maintained.
+    #[inline]
+    pub fn with_indexes(mut self, indexes: impl IntoIterator<Item = QuadIndex>) -> Self {
+        self.indexes = Some(QuadIndexSet::new(indexes));
+        self
+    }
+
+    /// Sets the size in bytes of the LRU cache of uncompressed data blocks shared by the quad indexes.
+    #[inline]
+    pub fn with_block_cache_size(mut self, size: usize) -> Self {
+        self.block_cache_size = Some(size);
+        self
+    }
+
+    /// Enables bloom filters with the given number of bits per key.
+    ///
+    /// They allow to skip reading data files that do not contain a key at the cost of some memory.
+    /// 10 bits per key gives a false positive rate of around 1%.
+    #[inline]
+    pub fn with_bloom_filter(mut self, bits_per_key: f64) -> Self {
+        self.bloom_filter_bits_per_key = Some(bits_per_key);
+        self
+    }
+
+    /// Sets the compression used for all the levels of the data files.
+    #[inline]
+    pub fn with_compression(self, compression: Compression) -> Self {
+        self.with_compression_per_level([compression])
+    }
+
+    /// Sets the compression used for each level of the data files, the first value being for the level 0.
+    ///
+    /// The last value is used for the levels that are not listed.
+    #[inline]
+    pub fn with_compression_per_level(
+        mut self,
+        compression: impl IntoIterator<Item = Compression>,
+    ) -> Self {
+        let compression = compression.into_iter().collect::<Vec<_>>();
+        self.compression_per_level = (!compression.is_empty()).then_some(compression);
+        self
+    }
+
+    /// Sets the maximal number of files kept open.
+    ///
+    /// It is capped by the number of file descriptors the process is allowed to open.
+    #[inline]
+    pub fn with_max_open_files(mut self, max_open_files: usize) -> Self {
+        self.max_open_files = Some(max_open_files);
+        self
+    }
+
+    /// Sets the size in bytes of each in-memory write buffer before it is flushed to disk.
+    #[inline]
+    pub fn with_write_buffer_size(mut self, size: usize) -> Self {
+        self.write_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the maximal number of in-memory write buffers per index.
+    #[inline]
+    pub fn with_max_write_buffer_number(mut self, max_write_buffer_number: usize) -> Self {
+        self.max_write_buffer_number = Some(max_write_buffer_number);
+        self
+    }
+
+    /// Enables the collection of RocksDB statistics like the block cache hits and misses.
+    ///
+    /// It has a small performance cost.
+    #[inline]
+    pub fn with_statistics(mut self) -> Self {
+        self.statistics = true;
+        self
+    }
+}
 
 /// Low level storage primitives
 #[derive(Clone)]
@@ -51,34 +389,56 @@ pub struct RocksDbStorage {
     dpos_cf: ColumnFamily,
     dosp_cf: ColumnFamily,
     graphs_cf: ColumnFamily,
+    indexes: QuadIndexSet,
 }
 
 impl RocksDbStorage {
     pub fn open(path: &Path) -> Result<Self, StorageError> {
-        Self::setup(Db::open_read_write(path, Self::column_families())?)
+        Self::open_with_options(path, &StoreOptions::default())
+    }
+
+    pub fn open_with_options(path: &Path, options: &StoreOptions) -> Result<Self, StorageError> {
+        Self::setup(
+            Db::open_read_write(path, Self::column_families(), options)?,
+            options.indexes,
+            true,
+        )
     }
 
-    pub fn open_secondary(primary_path: &Path) -> Result<Self, StorageError> {
-        Self::setup(Db::open_secondary(
-            primary_path,
-            None,
-            Self::column_families(),
-        )?)
+    pub fn open_secondary(
+        primary_path: &Path,
+        options: &StoreOptions,
+    ) -> Result<Self, StorageError> {
+        Self::setup(
+            Db::open_secondary(primary_path, None, Self::column_families(), options)?,
+            options.indexes,
+            false,
+        )
     }
 
     pub fn open_persistent_secondary(
         primary_path: &Path,
         secondary_path: &Path,
+        options: &StoreOptions,
     ) -> Result<Self, StorageError> {
-        Self::setup(Db::open_secondary(
-            primary_path,
-            Some(secondary_path),
-            Self::column_families(),
-        )?)
+        Self::setup(
+            Db::open_secondary(
+                primary_path,
+                Some(secondary_path),
+                Self::column_families(),
+                options,
+            )?,
+            options.indexes,
+            false,
+        )
     }
 
-    pub fn open_read_only(path: &Path) -> Result<Self, StorageError> {
-        Self::setup(Db::open_read_only(path, Self::column_families())?)
+    pub fn open_read_only(path: &Path, options: &StoreOptions) -> Result<Self, StorageError> {
+        Self::setup(
+            Db::open_read_only(path, Self::column_families(), options)?,
+            options.indexes,
+            false,
+        )
     }
 
     fn column_families() -> Vec<ColumnFamilyDefinition> {
@@ -152,8 +512,9 @@ impl RocksDbStorage {
         ]
     }
 
-    fn setup(db: Db) -> Result<Self, StorageError> {
-        let this = Self {
+    /// `writable` is false for the read-only and secondary instances that must not write to the database
+    fn setup(db: Db, indexes: Option<QuadIndexSet>, writable: bool) -> Result<Self, StorageError> {
+        let mut this = Self {
             default_cf: db.column_family(DEFAULT_CF)?,
             id2str_cf: db.column_family(ID2STR_CF)?,
             spog_cf: db.column_family(SPOG_CF)?,
@@ -167,8 +528,10 @@ impl RocksDbStorage {
             dosp_cf: db.column_family(DOSP_CF)?,
             graphs_cf: db.column_family(GRAPHS_CF)?,
             db,
+            indexes: QuadIndexSet::ALL,
         };
         this.migrate()?;
+        this.indexes = this.ensure_indexes(indexes, writable)?;
         Ok(this)
     }
 
@@ -197,6 +560,64 @@ impl RocksDbStorage {
             version = 1;
             self.update_version(version)?;
         }
+        if version == 1 {
+            // We migrate to v2: some IRIs and literals are now inlined in the encoded terms
+            let snapshot = self.snapshot();
+            let mut to_migrate = Vec::new();
+            // The strings that might only be used by the previous encodings
+            let mut unused_strs = FxHashSet::default();
+            for quad in snapshot.quads() {
+                let encoded = quad?;
+                let quad = snapshot.decode_quad(&encoded)?;
+                if EncodedQuad::from(quad.as_ref()) != encoded {
+                    for term in [
+                        &encoded.subject,
+                        &encoded.predicate,
+                        &encoded.object,
+                        &encoded.graph_name,
+                    ] {
+                        term.for_each_str_id(&mut |id| {
+                            unused_strs.insert(*id);
+                        });
+                    }
+                    to_migrate.push((encoded, quad));
+                    if to_migrate.len() >= MIGRATION_BATCH_SIZE {
+                        self.migrate_quads(&to_migrate)?;
+                        to_migrate.clear();
+                    }
+                }
+            }
+            self.migrate_quads(&to_migrate)?;
+            let mut graph_names_to_migrate = Vec::new();
+            for graph_name in snapshot.named_graphs() {
+                let encoded = graph_name?;
+                let graph_name = snapshot.decode_named_or_blank_node(&encoded)?;
+                if EncodedTerm::from(graph_name.as_ref()) != encoded {
+                    encoded.for_each_str_id(&mut |id| {
+                        unused_strs.insert(*id);
+                    });
+                    graph_names_to_migrate.push((encoded, graph_name));
+                }
+            }
+            self.transaction(|mut writer| {
+                for (encoded, graph_name) in &graph_names_to_migrate {
+                    writer.buffer.clear();
+                    write_term(&mut writer.buffer, encoded);
+                    writer.transaction.remove(&self.graphs_cf, &writer.buffer)?;
+                    writer.insert_named_graph(graph_name.as_ref())?;
+                }
+                Ok::<_, StorageError>(())
+            })?;
+            self.remove_unused_strs(unused_strs)?;
+            version = 2;
+            self.update_version(version)?;
+        }
+        if version == 2 {
+            // We migrate to v3: the set of maintained indexes is persisted, the older stores have all of them
+            self.update_indexes(QuadIndexSet::ALL)?;
+            version = 3;
+            self.update_version(version)?;
+        }
 
         match version {
             _ if version < LATEST_STORAGE_VERSION => Err(CorruptionError::msg(format!(
@@ -211,6 +632,54 @@ impl RocksDbStorage {
         }
     }
 
+    fn migrate_quads(&self, quads: &[(EncodedQuad, Quad)]) -> Result<(), StorageError> {
+        self.transaction(|mut writer| {
+            for (encoded, quad) in quads {
+                writer.remove_encoded(encoded)?;
+                writer.insert(quad.as_ref())?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Removes from `id2str` the given strings that are not used by any quad or named graph anymore
+    fn remove_unused_strs(&self, mut candidates: FxHashSet<StrHash>) -> Result<(), StorageError> {
+        if candidates.is_empty() {
+            return Ok(());
+        }
+        let snapshot = self.snapshot();
+        for quad in snapshot.quads() {
+            let quad = quad?;
+            for term in [
+                &quad.subject,
+                &quad.predicate,
+                &quad.object,
+                &quad.graph_name,
+            ] {
+                term.for_each_str_id(&mut |id| {
+                    candidates.remove(id);
+                });
+            }
+        }
+        for graph_name in snapshot.named_graphs() {
+            graph_name?.for_each_str_id(&mut |id| {
+                candidates.remove(id);
+            });
+        }
+        let unused = candidates.into_iter().collect::<Vec<_>>();
+        for batch in unused.chunks(MIGRATION_BATCH_SIZE) {
+            self.transaction(|mut writer| {
+                for id in batch {
+                    writer
+                        .transaction
+                        .remove(&self.id2str_cf, &id.to_be_bytes())?;
+                }
+                Ok::<_, StorageError>(())
+            })?;
+        }
+        Ok(())
+    }
+
     fn ensure_version(&self) -> Result<u64, StorageError> {
         Ok(
             if let Some(version) = self.db.get(&self.default_cf, b"oxversion")? {
@@ -230,6 +699,94 @@ impl RocksDbStorage {
         self.db.flush()
     }
 
+    pub fn view_definitions(&self) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self
+            .db
+            .get(&self.default_cf, b"oxviews")?
+            .map(|definitions| definitions.as_ref().to_vec()))
+    }
+
+    fn ensure_indexes(
+        &self,
+        requested: Option<QuadIndexSet>,
+        writable: bool,
+    ) -> Result<QuadIndexSet, StorageError> {
+        if let Some(indexes) = self.db.get(&self.default_cf, b"oxindexes")? {
+            let indexes = QuadIndexSet::decode(indexes.as_ref())?;
+            if let Some(requested) = requested {
+                if requested != indexes {
+                    return Err(StorageError::Other(format!(
+                        "The store maintains the indexes {indexes} but the indexes {requested} have been requested. The indexes can only be chosen when the store is created"
+                    ).into()));
+                }
+            }
+            Ok(indexes)
+        } else {
+            // The store has just been created
+            let indexes = requested.unwrap_or(QuadIndexSet::ALL);
+            if writable {
+                self.update_indexes(indexes)?;
+            }
+            Ok(indexes)
+        }
+    }
+
+    fn update_indexes(&self, indexes: QuadIndexSet) -> Result<(), StorageError> {
+        self.db.insert(
+            &self.default_cf,
+            b"oxindexes",
+            indexes.to_string().as_bytes(),
+        )?;
+        self.db.flush()
+    }
+
+    fn column_family(&self, index: QuadIndex) -> &ColumnFamily {
+        match index {
+            QuadIndex::Spog => &self.spog_cf,
+            QuadIndex::Posg => &self.posg_cf,
+            QuadIndex::Ospg => &self.ospg_cf,
+            QuadIndex::Gspo => &self.gspo_cf,
+            QuadIndex::Gpos => &self.gpos_cf,
+            QuadIndex::Gosp => &self.gosp_cf,
+            QuadIndex::Dspo => &self.dspo_cf,
+            QuadIndex::Dpos => &self.dpos_cf,
+            QuadIndex::Dosp => &self.dosp_cf,
+        }
+    }
+
+    pub fn statistics(&self) -> StorageStatistics {
+        let sum = |name| {
+            self.indexes
+                .iter()
+                .map(|index| self.column_family(index))
+                .chain([&self.id2str_cf, &self.graphs_cf, &self.default_cf])
+                .map(|cf| self.db.int_property(cf, name))
+                .sum::<Option<u64>>()
+        };
+        StorageStatistics {
+            estimated_quads: [&self.dspo_cf, &self.gspo_cf]
+                .into_iter()
+                .map(|cf| self.db.int_property(cf, "rocksdb.estimate-num-keys"))
+                .sum(),
+            sst_files_size: sum("rocksdb.total-sst-files-size"),
+            live_data_size: sum("rocksdb.estimate-live-data-size"),
+            memtables_size: sum("rocksdb.cur-size-all-mem-tables"),
+            pending_compaction_size: sum("rocksdb.estimate-pending-compaction-bytes"),
+            running_compactions: self
+                .db
+                .int_property(&self.default_cf, "rocksdb.num-running-compactions"),
+            // The quad indexes share the same block cache
+            block_cache_usage: self
+                .db
+                .int_property(&self.dspo_cf, "rocksdb.block-cache-usage"),
+            block_cache_capacity: self
+                .db
+                .int_property(&self.dspo_cf, "rocksdb.block-cache-capacity"),
+            block_cache_hits: self.db.ticker_count("rocksdb.block.cache.hit"),
+            block_cache_misses: self.db.ticker_count("rocksdb.block.cache.miss"),
+        }
+    }
+
     pub fn snapshot(&self) -> RocksDbStorageReader {
         RocksDbStorageReader {
             reader: self.db.snapshot(),
@@ -315,134 +872,133 @@ impl RocksDbStorageReader {
         object: Option<&EncodedTerm>,
         graph_name: Option<&EncodedTerm>,
     ) -> RocksDbChainedDecodingQuadIterator {
-        match subject {
-            Some(subject) => match predicate {
-                Some(predicate) => match object {
-                    Some(object) => match graph_name {
-                        Some(graph_name) => self.quads_for_subject_predicate_object_graph(
-                            subject, predicate, object, graph_name,
-                        ),
-                        None => self.quads_for_subject_predicate_object(subject, predicate, object),
-                    },
-                    None => match graph_name {
-                        Some(graph_name) => {
-                            self.quads_for_subject_predicate_graph(subject, predicate, graph_name)
-                        }
-                        None => self.quads_for_subject_predicate(subject, predicate),
-                    },
-                },
-                None => match object {
-                    Some(object) => match graph_name {
-                        Some(graph_name) => {
-                            self.quads_for_subject_object_graph(subject, object, graph_name)
-                        }
-                        None => self.quads_for_subject_object(subject, object),
-                    },
-                    None => match graph_name {
-                        Some(graph_name) => self.quads_for_subject_graph(subject, graph_name),
-                        None => self.quads_for_subject(subject),
-                    },
-                },
-            },
-            None => match predicate {
-                Some(predicate) => match object {
-                    Some(object) => match graph_name {
-                        Some(graph_name) => {
-                            self.quads_for_predicate_object_graph(predicate, object, graph_name)
-                        }
-                        None => self.quads_for_predicate_object(predicate, object),
-                    },
-                    None => match graph_name {
-                        Some(graph_name) => self.quads_for_predicate_graph(predicate, graph_name),
-                        None => self.quads_for_predicate(predicate),
-                    },
-                },
-                None => match object {
-                    Some(object) => match graph_name {
-                        Some(graph_name) => self.quads_for_object_graph(object, graph_name),
-                        None => self.quads_for_object(object),
-                    },
-                    None => match graph_name {
-                        Some(graph_name) => self.quads_for_graph(graph_name),
-                        None => self.quads(),
-                    },
-                },
-            },
+        match graph_name {
+            Some(graph_name) if graph_name.is_default_graph() => {
+                RocksDbChainedDecodingQuadIterator::new(self.quads_for_pattern_in_indexes(
+                    &QuadIndex::DEFAULT_GRAPH,
+                    [subject, predicate, object, None],
+                ))
+            }
+            Some(graph_name) => {
+                RocksDbChainedDecodingQuadIterator::new(self.quads_for_pattern_in_indexes(
+                    &QuadIndex::NAMED_GRAPHS,
+                    [subject, predicate, object, Some(graph_name)],
+                ))
+            }
+            None => RocksDbChainedDecodingQuadIterator::pair(
+                self.quads_for_pattern_in_indexes(
+                    &QuadIndex::DEFAULT_GRAPH,
+                    [subject, predicate, object, None],
+                ),
+                self.quads_for_pattern_in_indexes(
+                    &QuadIndex::NAMED_GRAPHS,
+                    [subject, predicate, object, None],
+                ),
+            ),
         }
     }
 
-    pub fn quads(&self) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::pair(self.dspo_quads(&[]), self.gspo_quads(&[]))
-    }
-
-    fn quads_in_named_graph(&self) -> RocksDbDecodingQuadIterator {
-        self.gspo_quads(&[])
-    }
-
-    fn quads_for_subject(&self, subject: &EncodedTerm) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::pair(
-            self.dspo_quads(&encode_term(subject)),
-            self.spog_quads(&encode_term(subject)),
-        )
-    }
-
-    fn quads_for_subject_predicate(
+    /// Picks among the maintained `indexes` the one with the longest key prefix bound by the pattern.
+    ///
+    /// The pattern terms that are not in the prefix are checked by filtering the scanned quads.
+    fn quads_for_pattern_in_indexes(
         &self,
-        subject: &EncodedTerm,
-        predicate: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::pair(
-            self.dspo_quads(&encode_term_pair(subject, predicate)),
-            self.spog_quads(&encode_term_pair(subject, predicate)),
-        )
+        indexes: &[QuadIndex],
+        pattern: [Option<&EncodedTerm>; 4],
+    ) -> RocksDbDecodingQuadIterator {
+        let (index, prefix, filter) = self.index_scan(indexes, pattern);
+        let mut iter =
+            self.inner_quads(self.storage.column_family(index), &prefix, index.encoding());
+        iter.filter = filter;
+        iter
     }
 
-    fn quads_for_subject_predicate_object(
+    /// Returns disjoint iterators covering together the quads matching the pattern in the given graph.
+    ///
+    /// The iterators can be consumed from different threads.
+    pub fn quads_for_pattern_partitioned(
         &self,
-        subject: &EncodedTerm,
-        predicate: &EncodedTerm,
-        object: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::pair(
-            self.dspo_quads(&encode_term_triple(subject, predicate, object)),
-            self.spog_quads(&encode_term_triple(subject, predicate, object)),
-        )
+        subject: Option<&EncodedTerm>,
+        predicate: Option<&EncodedTerm>,
+        object: Option<&EncodedTerm>,
+        graph_name: &EncodedTerm,
+        partitions: usize,
+        min_partitioned_size: usize,
+    ) -> Result<Vec<RocksDbChainedDecodingQuadIterator>, StorageError> {
+        let (index, prefix, filter) = if graph_name.is_default_graph() {
+            self.index_scan(
+                &QuadIndex::DEFAULT_GRAPH,
+                [subject, predicate, object, None],
+            )
+        } else {
+            self.index_scan(
+                &QuadIndex::NAMED_GRAPHS,
+                [subject, predicate, object, Some(graph_name)],
+            )
+        };
+        Ok(self
+            .reader
+            .scan_prefix_partitioned(
+                self.storage.column_family(index),
+                &prefix,
+                partitions,
+                min_partitioned_size,
+            )?
+            .into_iter()
+            .map(|iter| {
+                RocksDbChainedDecodingQuadIterator::new(RocksDbDecodingQuadIterator {
+                    iter,
+                    encoding: index.encoding(),
+                    filter: filter.clone(),
+                })
+            })
+            .collect())
     }
 
-    fn quads_for_subject_object(
+    /// Picks the index to scan, the key prefix to scan and the filter to apply on the scanned quads
+    fn index_scan(
         &self,
-        subject: &EncodedTerm,
-        object: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::pair(
-            self.dosp_quads(&encode_term_pair(object, subject)),
-            self.ospg_quads(&encode_term_pair(object, subject)),
-        )
-    }
-
-    fn quads_for_predicate(&self, predicate: &EncodedTerm) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::pair(
-            self.dpos_quads(&encode_term(predicate)),
-            self.posg_quads(&encode_term(predicate)),
-        )
+        indexes: &[QuadIndex],
+        pattern: [Option<&EncodedTerm>; 4],
+    ) -> (QuadIndex, Vec<u8>, Option<[Option<EncodedTerm>; 4]>) {
+        let mut best_index = indexes[0]; // Always maintained
+        let mut best_prefix_len = 0;
+        for index in indexes {
+            if !self.storage.indexes.contains(*index) {
+                continue;
+            }
+            let prefix_len = index
+                .term_positions()
+                .iter()
+                .take_while(|position| pattern[**position].is_some())
+                .count();
+            if prefix_len > best_prefix_len {
+                best_index = *index;
+                best_prefix_len = prefix_len;
+            }
+        }
+        let (prefix_positions, filter_positions) =
+            best_index.term_positions().split_at(best_prefix_len);
+        let mut prefix = Vec::with_capacity(best_prefix_len * WRITTEN_TERM_MAX_SIZE);
+        for term in prefix_positions
+            .iter()
+            .filter_map(|position| pattern[*position])
+        {
+            write_term(&mut prefix, term);
+        }
+        let filter = filter_positions
+            .iter()
+            .any(|position| pattern[*position].is_some())
+            .then(|| pattern.map(|term| term.cloned()));
+        (best_index, prefix, filter)
     }
 
-    fn quads_for_predicate_object(
-        &self,
-        predicate: &EncodedTerm,
-        object: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::pair(
-            self.dpos_quads(&encode_term_pair(predicate, object)),
-            self.posg_quads(&encode_term_pair(predicate, object)),
-        )
+    pub fn quads(&self) -> RocksDbChainedDecodingQuadIterator {
+        RocksDbChainedDecodingQuadIterator::pair(self.dspo_quads(&[]), self.gspo_quads(&[]))
     }
 
-    fn quads_for_object(&self, object: &EncodedTerm) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::pair(
-            self.dosp_quads(&encode_term(object)),
-            self.ospg_quads(&encode_term(object)),
-        )
+    fn quads_in_named_graph(&self) -> RocksDbDecodingQuadIterator {
+        self.gspo_quads(&[])
     }
 
     fn quads_for_graph(&self, graph_name: &EncodedTerm) -> RocksDbChainedDecodingQuadIterator {
@@ -453,95 +1009,6 @@ impl RocksDbStorageReader {
         })
     }
 
-    fn quads_for_subject_graph(
-        &self,
-        subject: &EncodedTerm,
-        graph_name: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::new(if graph_name.is_default_graph() {
-            self.dspo_quads(&encode_term(subject))
-        } else {
-            self.gspo_quads(&encode_term_pair(graph_name, subject))
-        })
-    }
-
-    fn quads_for_subject_predicate_graph(
-        &self,
-        subject: &EncodedTerm,
-        predicate: &EncodedTerm,
-        graph_name: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::new(if graph_name.is_default_graph() {
-            self.dspo_quads(&encode_term_pair(subject, predicate))
-        } else {
-            self.gspo_quads(&encode_term_triple(graph_name, subject, predicate))
-        })
-    }
-
-    fn quads_for_subject_predicate_object_graph(
-        &self,
-        subject: &EncodedTerm,
-        predicate: &EncodedTerm,
-        object: &EncodedTerm,
-        graph_name: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::new(if graph_name.is_default_graph() {
-            self.dspo_quads(&encode_term_triple(subject, predicate, object))
-        } else {
-            self.gspo_quads(&encode_term_quad(graph_name, subject, predicate, object))
-        })
-    }
-
-    fn quads_for_subject_object_graph(
-        &self,
-        subject: &EncodedTerm,
-        object: &EncodedTerm,
-        graph_name: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::new(if graph_name.is_default_graph() {
-            self.dosp_quads(&encode_term_pair(object, subject))
-        } else {
-            self.gosp_quads(&encode_term_triple(graph_name, object, subject))
-        })
-    }
-
-    fn quads_for_predicate_graph(
-        &self,
-        predicate: &EncodedTerm,
-        graph_name: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::new(if graph_name.is_default_graph() {
-            self.dpos_quads(&encode_term(predicate))
-        } else {
-            self.gpos_quads(&encode_term_pair(graph_name, predicate))
-        })
-    }
-
-    fn quads_for_predicate_object_graph(
-        &self,
-        predicate: &EncodedTerm,
-        object: &EncodedTerm,
-        graph_name: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::new(if graph_name.is_default_graph() {
-            self.dpos_quads(&encode_term_pair(predicate, object))
-        } else {
-            self.gpos_quads(&encode_term_triple(graph_name, predicate, object))
-        })
-    }
-
-    fn quads_for_object_graph(
-        &self,
-        object: &EncodedTerm,
-        graph_name: &EncodedTerm,
-    ) -> RocksDbChainedDecodingQuadIterator {
-        RocksDbChainedDecodingQuadIterator::new(if graph_name.is_default_graph() {
-            self.dosp_quads(&encode_term(object))
-        } else {
-            self.gosp_quads(&encode_term_pair(graph_name, object))
-        })
-    }
-
     pub fn named_graphs(&self) -> RocksDbDecodingGraphIterator {
         RocksDbDecodingGraphIterator {
             iter: self.reader.iter(&self.storage.graphs_cf).unwrap(), // TODO: propagate error?
@@ -598,6 +1065,7 @@ impl RocksDbStorageReader {
         RocksDbDecodingQuadIterator {
             iter: self.reader.scan_prefix(column_family, prefix).unwrap(), // TODO: propagate error?
             encoding,
+            filter: None,
         }
     }
 
@@ -611,7 +1079,11 @@ impl RocksDbStorageReader {
     pub fn validate(&self) -> Result<(), StorageError> {
         // triples
         let dspo_size = self.dspo_quads(&[]).count();
-        if dspo_size != self.dpos_quads(&[]).count() || dspo_size != self.dosp_quads(&[]).count() {
+        if (self.storage.indexes.contains(QuadIndex::Dpos)
+            && dspo_size != self.dpos_quads(&[]).count())
+            || (self.storage.indexes.contains(QuadIndex::Dosp)
+                && dspo_size != self.dosp_quads(&[]).count())
+        {
             return Err(CorruptionError::new(
                 "Not the same number of triples in dspo, dpos and dosp",
             )
@@ -620,27 +1092,35 @@ impl RocksDbStorageReader {
         for spo in self.dspo_quads(&[]) {
             let spo = spo?;
             self.decode_quad(&spo)?; // We ensure that the quad is readable
-            if !self.storage.db.contains_key(
-                &self.storage.dpos_cf,
-                &encode_term_triple(&spo.predicate, &spo.object, &spo.subject),
-            )? {
+            if self.storage.indexes.contains(QuadIndex::Dpos)
+                && !self.storage.db.contains_key(
+                    &self.storage.dpos_cf,
+                    &encode_term_triple(&spo.predicate, &spo.object, &spo.subject),
+                )?
+            {
                 return Err(CorruptionError::new("Quad in dspo and not in dpos").into());
             }
-            if !self.storage.db.contains_key(
-                &self.storage.dosp_cf,
-                &encode_term_triple(&spo.object, &spo.subject, &spo.predicate),
-            )? {
+            if self.storage.indexes.contains(QuadIndex::Dosp)
+                && !self.storage.db.contains_key(
+                    &self.storage.dosp_cf,
+                    &encode_term_triple(&spo.object, &spo.subject, &spo.predicate),
+                )?
+            {
                 return Err(CorruptionError::new("Quad in dspo and not in dosp").into());
             }
         }
 
         // quads
         let gspo_size = self.gspo_quads(&[]).count();
-        if gspo_size != self.gpos_quads(&[]).count()
-            || gspo_size != self.gosp_quads(&[]).count()
-            || gspo_size != self.spog_quads(&[]).count()
-            || gspo_size != self.posg_quads(&[]).count()
-            || gspo_size != self.ospg_quads(&[]).count()
+        if [
+            (QuadIndex::Gpos, self.gpos_quads(&[])),
+            (QuadIndex::Gosp, self.gosp_quads(&[])),
+            (QuadIndex::Spog, self.spog_quads(&[])),
+            (QuadIndex::Posg, self.posg_quads(&[])),
+            (QuadIndex::Ospg, self.ospg_quads(&[])),
+        ]
+        .into_iter()
+        .any(|(index, quads)| self.storage.indexes.contains(index) && gspo_size != quads.count())
         {
             return Err(CorruptionError::new(
                 "Not the same number of triples in dspo, dpos and dosp",
@@ -650,59 +1130,69 @@ impl RocksDbStorageReader {
         for gspo in self.gspo_quads(&[]) {
             let gspo = gspo?;
             self.decode_quad(&gspo)?; // We ensure that the quad is readable
-            if !self.storage.db.contains_key(
-                &self.storage.gpos_cf,
-                &encode_term_quad(
-                    &gspo.graph_name,
-                    &gspo.predicate,
-                    &gspo.object,
-                    &gspo.subject,
-                ),
-            )? {
+            if self.storage.indexes.contains(QuadIndex::Gpos)
+                && !self.storage.db.contains_key(
+                    &self.storage.gpos_cf,
+                    &encode_term_quad(
+                        &gspo.graph_name,
+                        &gspo.predicate,
+                        &gspo.object,
+                        &gspo.subject,
+                    ),
+                )?
+            {
                 return Err(CorruptionError::new("Quad in gspo and not in gpos").into());
             }
-            if !self.storage.db.contains_key(
-                &self.storage.gosp_cf,
-                &encode_term_quad(
-                    &gspo.graph_name,
-                    &gspo.object,
-                    &gspo.subject,
-                    &gspo.predicate,
-                ),
-            )? {
+            if self.storage.indexes.contains(QuadIndex::Gosp)
+                && !self.storage.db.contains_key(
+                    &self.storage.gosp_cf,
+                    &encode_term_quad(
+                        &gspo.graph_name,
+                        &gspo.object,
+                        &gspo.subject,
+                        &gspo.predicate,
+                    ),
+                )?
+            {
                 return Err(CorruptionError::new("Quad in gspo and not in gosp").into());
             }
-            if !self.storage.db.contains_key(
-                &self.storage.spog_cf,
-                &encode_term_quad(
-                    &gspo.subject,
-                    &gspo.predicate,
-                    &gspo.object,
-                    &gspo.graph_name,
-                ),
-            )? {
+            if self.storage.indexes.contains(QuadIndex::Spog)
+                && !self.storage.db.contains_key(
+                    &self.storage.spog_cf,
+                    &encode_term_quad(
+                        &gspo.subject,
+                        &gspo.predicate,
+                        &gspo.object,
+                        &gspo.graph_name,
+                    ),
+                )?
+            {
                 return Err(CorruptionError::new("Quad in gspo and not in spog").into());
             }
-            if !self.storage.db.contains_key(
-                &self.storage.posg_cf,
-                &encode_term_quad(
-                    &gspo.predicate,
-                    &gspo.object,
-                    &gspo.subject,
-                    &gspo.graph_name,
-                ),
-            )? {
+            if self.storage.indexes.contains(QuadIndex::Posg)
+                && !self.storage.db.contains_key(
+                    &self.storage.posg_cf,
+                    &encode_term_quad(
+                        &gspo.predicate,
+                        &gspo.object,
+                        &gspo.subject,
+                        &gspo.graph_name,
+                    ),
+                )?
+            {
                 return Err(CorruptionError::new("Quad in gspo and not in posg").into());
             }
-            if !self.storage.db.contains_key(
-                &self.storage.ospg_cf,
-                &encode_term_quad(
-                    &gspo.object,
-                    &gspo.subject,
-                    &gspo.predicate,
-                    &gspo.graph_name,
-                ),
-            )? {
+            if self.storage.indexes.contains(QuadIndex::Ospg)
+                && !self.storage.db.contains_key(
+                    &self.storage.ospg_cf,
+                    &encode_term_quad(
+                        &gspo.object,
+                        &gspo.subject,
+                        &gspo.predicate,
+                        &gspo.graph_name,
+                    ),
+                )?
+            {
                 return Err(CorruptionError::new("Quad in gspo and not in ospg").into());
             }
             if !self
@@ -757,18 +1247,33 @@ impl Iterator for RocksDbChainedDecodingQuadIterator {
 struct RocksDbDecodingQuadIterator {
     iter: Iter,
     encoding: QuadEncoding,
+    /// Subject, predicate, object and graph name the quads must match if they are not already ensured by the scanned prefix
+    filter: Option<[Option<EncodedTerm>; 4]>,
 }
 
 impl Iterator for RocksDbDecodingQuadIterator {
     type Item = Result<EncodedQuad, StorageError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Err(e) = self.iter.status() {
-            return Some(Err(e));
+        loop {
+            if let Err(e) = self.iter.status() {
+                return Some(Err(e));
+            }
+            let quad = self.encoding.decode(self.iter.key()?);
+            self.iter.next();
+            if let (Ok(quad), Some([subject, predicate, object, graph_name])) =
+                (&quad, &self.filter)
+            {
+                if subject.as_ref().is_some_and(|t| *t != quad.subject)
+                    || predicate.as_ref().is_some_and(|t| *t != quad.predicate)
+                    || object.as_ref().is_some_and(|t| *t != quad.object)
+                    || graph_name.as_ref().is_some_and(|t| *t != quad.graph_name)
+                {
+                    continue;
+                }
+            }
+            return Some(quad);
         }
-        let term = self.encoding.decode(self.iter.key()?);
-        self.iter.next();
-        Some(term)
     }
 }
 
@@ -816,10 +1321,18 @@ impl<'a> RocksDbStorageWriter<'a> {
     }
 
     pub fn insert(&mut self, quad: QuadRef<'_>) -> Result<bool, StorageError> {
-        let encoded = quad.into();
+        self.insert_encoded(quad, &quad.into())
+    }
+
+    /// Inserts the quad using the given encoding of its terms
+    fn insert_encoded(
+        &mut self,
+        quad: QuadRef<'_>,
+        encoded: &EncodedQuad,
+    ) -> Result<bool, StorageError> {
         self.buffer.clear();
         let result = if quad.graph_name.is_default_graph() {
-            write_spo_quad(&mut self.buffer, &encoded);
+            write_spo_quad(&mut self.buffer, encoded);
             if self
                 .transaction
                 .contains_key_for_update(&self.storage.dspo_cf, &self.buffer)?
@@ -829,15 +1342,19 @@ impl<'a> RocksDbStorageWriter<'a> {
                 self.transaction
                     .insert_empty(&self.storage.dspo_cf, &self.buffer)?;
 
-                self.buffer.clear();
-                write_pos_quad(&mut self.buffer, &encoded);
-                self.transaction
-                    .insert_empty(&self.storage.dpos_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Dpos) {
+                    self.buffer.clear();
+                    write_pos_quad(&mut self.buffer, encoded);
+                    self.transaction
+                        .insert_empty(&self.storage.dpos_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_osp_quad(&mut self.buffer, &encoded);
-                self.transaction
-                    .insert_empty(&self.storage.dosp_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Dosp) {
+                    self.buffer.clear();
+                    write_osp_quad(&mut self.buffer, encoded);
+                    self.transaction
+                        .insert_empty(&self.storage.dosp_cf, &self.buffer)?;
+                }
 
                 self.insert_term(quad.subject.into(), &encoded.subject)?;
                 self.insert_term(quad.predicate.into(), &encoded.predicate)?;
@@ -845,40 +1362,50 @@ impl<'a> RocksDbStorageWriter<'a> {
                 true
             }
         } else {
-            write_spog_quad(&mut self.buffer, &encoded);
+            write_gspo_quad(&mut self.buffer, encoded);
             if self
                 .transaction
-                .contains_key_for_update(&self.storage.spog_cf, &self.buffer)?
+                .contains_key_for_update(&self.storage.gspo_cf, &self.buffer)?
             {
                 false
             } else {
                 self.transaction
-                    .insert_empty(&self.storage.spog_cf, &self.buffer)?;
+                    .insert_empty(&self.storage.gspo_cf, &self.buffer)?;
 
-                self.buffer.clear();
-                write_posg_quad(&mut self.buffer, &encoded);
-                self.transaction
-                    .insert_empty(&self.storage.posg_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Posg) {
+                    self.buffer.clear();
+                    write_posg_quad(&mut self.buffer, encoded);
+                    self.transaction
+                        .insert_empty(&self.storage.posg_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_ospg_quad(&mut self.buffer, &encoded);
-                self.transaction
-                    .insert_empty(&self.storage.ospg_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Ospg) {
+                    self.buffer.clear();
+                    write_ospg_quad(&mut self.buffer, encoded);
+                    self.transaction
+                        .insert_empty(&self.storage.ospg_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_gspo_quad(&mut self.buffer, &encoded);
-                self.transaction
-                    .insert_empty(&self.storage.gspo_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Spog) {
+                    self.buffer.clear();
+                    write_spog_quad(&mut self.buffer, encoded);
+                    self.transaction
+                        .insert_empty(&self.storage.spog_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_gpos_quad(&mut self.buffer, &encoded);
-                self.transaction
-                    .insert_empty(&self.storage.gpos_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Gpos) {
+                    self.buffer.clear();
+                    write_gpos_quad(&mut self.buffer, encoded);
+                    self.transaction
+                        .insert_empty(&self.storage.gpos_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_gosp_quad(&mut self.buffer, &encoded);
-                self.transaction
-                    .insert_empty(&self.storage.gosp_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Gosp) {
+                    self.buffer.clear();
+                    write_gosp_quad(&mut self.buffer, encoded);
+                    self.transaction
+                        .insert_empty(&self.storage.gosp_cf, &self.buffer)?;
+                }
 
                 self.insert_term(quad.subject.into(), &encoded.subject)?;
                 self.insert_term(quad.predicate.into(), &encoded.predicate)?;
@@ -900,6 +1427,11 @@ impl<'a> RocksDbStorageWriter<'a> {
         Ok(result)
     }
 
+    pub fn set_view_definitions(&mut self, definitions: &[u8]) -> Result<(), StorageError> {
+        self.transaction
+            .insert(&self.storage.default_cf, b"oxviews", definitions)
+    }
+
     pub fn insert_named_graph(
         &mut self,
         graph_name: NamedOrBlankNodeRef<'_>,
@@ -973,53 +1505,67 @@ impl<'a> RocksDbStorageWriter<'a> {
                 self.transaction
                     .remove(&self.storage.dspo_cf, &self.buffer)?;
 
-                self.buffer.clear();
-                write_pos_quad(&mut self.buffer, quad);
-                self.transaction
-                    .remove(&self.storage.dpos_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Dpos) {
+                    self.buffer.clear();
+                    write_pos_quad(&mut self.buffer, quad);
+                    self.transaction
+                        .remove(&self.storage.dpos_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_osp_quad(&mut self.buffer, quad);
-                self.transaction
-                    .remove(&self.storage.dosp_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Dosp) {
+                    self.buffer.clear();
+                    write_osp_quad(&mut self.buffer, quad);
+                    self.transaction
+                        .remove(&self.storage.dosp_cf, &self.buffer)?;
+                }
                 true
             } else {
                 false
             }
         } else {
-            write_spog_quad(&mut self.buffer, quad);
+            write_gspo_quad(&mut self.buffer, quad);
 
             if self
                 .transaction
-                .contains_key_for_update(&self.storage.spog_cf, &self.buffer)?
+                .contains_key_for_update(&self.storage.gspo_cf, &self.buffer)?
             {
                 self.transaction
-                    .remove(&self.storage.spog_cf, &self.buffer)?;
+                    .remove(&self.storage.gspo_cf, &self.buffer)?;
 
-                self.buffer.clear();
-                write_posg_quad(&mut self.buffer, quad);
-                self.transaction
-                    .remove(&self.storage.posg_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Posg) {
+                    self.buffer.clear();
+                    write_posg_quad(&mut self.buffer, quad);
+                    self.transaction
+                        .remove(&self.storage.posg_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_ospg_quad(&mut self.buffer, quad);
-                self.transaction
-                    .remove(&self.storage.ospg_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Ospg) {
+                    self.buffer.clear();
+                    write_ospg_quad(&mut self.buffer, quad);
+                    self.transaction
+                        .remove(&self.storage.ospg_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_gspo_quad(&mut self.buffer, quad);
-                self.transaction
-                    .remove(&self.storage.gspo_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Spog) {
+                    self.buffer.clear();
+                    write_spog_quad(&mut self.buffer, quad);
+                    self.transaction
+                        .remove(&self.storage.spog_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_gpos_quad(&mut self.buffer, quad);
-                self.transaction
-                    .remove(&self.storage.gpos_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Gpos) {
+                    self.buffer.clear();
+                    write_gpos_quad(&mut self.buffer, quad);
+                    self.transaction
+                        .remove(&self.storage.gpos_cf, &self.buffer)?;
+                }
 
-                self.buffer.clear();
-                write_gosp_quad(&mut self.buffer, quad);
-                self.transaction
-                    .remove(&self.storage.gosp_cf, &self.buffer)?;
+                if self.storage.indexes.contains(QuadIndex::Gosp) {
+                    self.buffer.clear();
+                    write_gosp_quad(&mut self.buffer, quad);
+                    self.transaction
+                        .remove(&self.storage.gosp_cf, &self.buffer)?;
+                }
                 true
             } else {
                 false
@@ -1335,22 +1881,22 @@ impl<'a> FileBulkLoader<'a> {
                     }),
                 )?,
             ));
-            to_load.push((
-                &self.storage.dpos_cf,
-                self.build_sst_for_keys(
-                    self.triples.iter().map(|quad| {
+            if self.storage.indexes.contains(QuadIndex::Dpos) {
+                to_load.push((
+                    &self.storage.dpos_cf,
+                    self.build_sst_for_keys(self.triples.iter().map(|quad| {
                         encode_term_triple(&quad.predicate, &quad.object, &quad.subject)
-                    }),
-                )?,
-            ));
-            to_load.push((
-                &self.storage.dosp_cf,
-                self.build_sst_for_keys(
-                    self.triples.iter().map(|quad| {
+                    }))?,
+                ));
+            }
+            if self.storage.indexes.contains(QuadIndex::Dosp) {
+                to_load.push((
+                    &self.storage.dosp_cf,
+                    self.build_sst_for_keys(self.triples.iter().map(|quad| {
                         encode_term_triple(&quad.object, &quad.subject, &quad.predicate)
-                    }),
-                )?,
-            ));
+                    }))?,
+                ));
+            }
             self.triples.clear();
         }
 
@@ -1372,61 +1918,71 @@ impl<'a> FileBulkLoader<'a> {
                     )
                 }))?,
             ));
-            to_load.push((
-                &self.storage.gpos_cf,
-                self.build_sst_for_keys(self.quads.iter().map(|quad| {
-                    encode_term_quad(
-                        &quad.graph_name,
-                        &quad.predicate,
-                        &quad.object,
-                        &quad.subject,
-                    )
-                }))?,
-            ));
-            to_load.push((
-                &self.storage.gosp_cf,
-                self.build_sst_for_keys(self.quads.iter().map(|quad| {
-                    encode_term_quad(
-                        &quad.graph_name,
-                        &quad.object,
-                        &quad.subject,
-                        &quad.predicate,
-                    )
-                }))?,
-            ));
-            to_load.push((
-                &self.storage.spog_cf,
-                self.build_sst_for_keys(self.quads.iter().map(|quad| {
-                    encode_term_quad(
-                        &quad.subject,
-                        &quad.predicate,
-                        &quad.object,
-                        &quad.graph_name,
-                    )
-                }))?,
-            ));
-            to_load.push((
-                &self.storage.posg_cf,
-                self.build_sst_for_keys(self.quads.iter().map(|quad| {
-                    encode_term_quad(
-                        &quad.predicate,
-                        &quad.object,
-                        &quad.subject,
-                        &quad.graph_name,
-                    )
-                }))?,
-            ));
-            to_load.push((
-                &self.storage.ospg_cf,
-                self.build_sst_for_keys(self.quads.iter().map(|quad| {
-                    encode_term_quad(
-                        &quad.object,
-                        &quad.subject,
-                        &quad.predicate,
-                        &quad.graph_name,
-                    )
-                }))?,
-            ));
+            if self.storage.indexes.contains(QuadIndex::Gpos) {
+                to_load.push((
+                    &self.storage.gpos_cf,
+                    self.build_sst_for_keys(self.quads.iter().map(|quad| {
+                        encode_term_quad(
+                            &quad.graph_name,
+                            &quad.predicate,
+                            &quad.object,
+                            &quad.subject,
+                        )
+                    }))?,
+                ));
+            }
+            if self.storage.indexes.contains(QuadIndex::Gosp) {
+                to_load.push((
+                    &self.storage.gosp_cf,
+                    self.build_sst_for_keys(self.quads.iter().map(|quad| {
+                        encode_term_quad(
+                            &quad.graph_name,
+                            &quad.object,
+                            &quad.subject,
+                            &quad.predicate,
+                        )
+                    }))?,
+                ));
+            }
+            if self.storage.indexes.contains(QuadIndex::Spog) {
+                to_load.push((
+                    &self.storage.spog_cf,
+                    self.build_sst_for_keys(self.quads.iter().map(|quad| {
+                        encode_term_quad(
+                            &quad.subject,
+                            &quad.predicate,
+                            &quad.object,
+                            &quad.graph_name,
+                        )
+                    }))?,
+                ));
+            }
+            if self.storage.indexes.contains(QuadIndex::Posg) {
+                to_load.push((
+                    &self.storage.posg_cf,
+                    self.build_sst_for_keys(self.quads.iter().map(|quad| {
+                        encode_term_quad(
+                            &quad.predicate,
+                            &quad.object,
+                            &quad.subject,
+                            &quad.graph_name,
+                        )
+                    }))?,
+                ));
+            }
+            if self.storage.indexes.contains(QuadIndex::Ospg) {
+                to_load.push((
+                    &self.storage.ospg_cf,
+                    self.build_sst_for_keys(self.quads.iter().map(|quad| {
+                        encode_term_quad(
+                            &quad.object,
+                            &quad.subject,
+                            &quad.predicate,
+                            &quad.graph_name,
+                        )
+                    }))?,
+                ));
+            }
             self.quads.clear();
         }
 
@@ -1467,3 +2023,106 @@ fn map_thread_result<R>(result: thread::Result<R>) -> io::Result<R> {
         })
     })
 }
+
+#[cfg(test)]
+#[allow(clippy::panic_in_result_fn)]
+mod tests {
+    use super::*;
+    use crate::model::{LiteralRef, NamedNodeRef};
+    use rand::random;
+    use std::env::temp_dir;
+    use std::fs::remove_dir_all;
+
+    #[test]
+    fn test_v1_migration() -> Result<(), Box<dyn Error>> {
+        let s = NamedNodeRef::new("http://example.com/s")?;
+        let p = NamedNodeRef::new("http://example.com/p")?;
+        let g = NamedNodeRef::new("http://example.com/g")?;
+        let long = LiteralRef::new_language_tagged_literal_unchecked("la ville lumi\u{E8}re", "fr");
+        let short = LiteralRef::new_language_tagged_literal_unchecked("Paris", "fr");
+        let quads = [
+            QuadRef::new(s, p, long, GraphNameRef::DefaultGraph),
+            QuadRef::new(s, p, short, g),
+        ];
+        // The version 1 encoding stores all IRIs and all the values longer than 15 bytes in id2str
+        let v1_named_node = |node: NamedNodeRef<'_>| EncodedTerm::NamedNode {
+            iri_id: StrHash::new(node.as_str()),
+        };
+        let v1_quads = [
+            EncodedQuad::new(
+                v1_named_node(s),
+                v1_named_node(p),
+                EncodedTerm::BigSmallLangStringLiteral {
+                    value_id: StrHash::new(long.value()),
+                    language: "fr".try_into()?,
+                },
+                EncodedTerm::DefaultGraph,
+            ),
+            EncodedQuad::new(
+                v1_named_node(s),
+                v1_named_node(p),
+                short.into(),
+                v1_named_node(g),
+            ),
+        ];
+
+        let path = temp_dir().join(format!("oxigraph-test-{}", random::<u128>()));
+        let storage = RocksDbStorage::open(&path)?;
+        storage.transaction(|mut writer| {
+            for (quad, encoded) in quads.iter().zip(&v1_quads) {
+                writer.insert_encoded(*quad, encoded)?;
+            }
+            Ok::<_, StorageError>(())
+        })?;
+        storage.update_version(1)?;
+        drop(storage);
+
+        let storage = RocksDbStorage::open(&path)?;
+        assert_eq!(storage.ensure_version()?, LATEST_STORAGE_VERSION);
+        let reader = storage.snapshot();
+        reader.validate()?;
+        let mut migrated = reader.quads().collect::<Result<Vec<_>, _>>()?;
+        migrated.sort_by_key(|quad| quad.graph_name.is_default_graph());
+        assert_eq!(
+            migrated,
+            [EncodedQuad::from(quads[1]), EncodedQuad::from(quads[0])]
+        );
+        assert_eq!(
+            reader.named_graphs().collect::<Result<Vec<_>, _>>()?,
+            [EncodedTerm::from(g)]
+        );
+        // Only the IRI prefix is still in id2str
+        assert!(reader.contains_str(&StrHash::new("http://example.com/"))?);
+        for removed in [s.as_str(), p.as_str(), g.as_str(), long.value()] {
+            assert!(!reader.contains_str(&StrHash::new(removed))?);
+        }
+        drop(reader);
+        drop(storage);
+        remove_dir_all(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_does_not_write_indexes() -> Result<(), Box<dyn Error>> {
+        let path = temp_dir().join(format!("oxigraph-test-{}", random::<u128>()));
+        let storage = RocksDbStorage::open(&path)?;
+        // A store created by a primary that has not written its index set yet
+        storage.transaction(|mut writer| {
+            writer
+                .transaction
+                .remove(&writer.storage.default_cf, b"oxindexes")
+        })?;
+        storage.db.flush()?;
+        drop(storage);
+
+        let storage = RocksDbStorage::open_read_only(&path, &StoreOptions::default())?;
+        assert_eq!(storage.indexes, QuadIndexSet::ALL);
+        assert!(storage.db.get(&storage.default_cf, b"oxindexes")?.is_none());
+        drop(storage);
+        let storage = RocksDbStorage::open_secondary(&path, &StoreOptions::default())?;
+        assert!(storage.db.get(&storage.default_cf, b"oxindexes")?.is_none());
+        drop(storage);
+        remove_dir_all(&path)?;
+        Ok(())
+    }
+}
@@ -9,7 +9,8 @@
 )]
 
 use crate::storage::error::{CorruptionError, StorageError};
-use libc::c_void;
+use crate::storage::rocksdb::{Compression, StoreOptions};
+use libc::{c_int, c_void};
 use oxrocksdb_sys::*;
 use rand::random;
 use std::borrow::Borrow;
@@ -75,6 +76,7 @@ struct RwDbHandler {
     ingest_external_file_options: *mut rocksdb_ingestexternalfileoptions_t,
     compaction_options: *mut rocksdb_compactoptions_t,
     block_based_table_options: *mut rocksdb_block_based_table_options_t,
+    block_cache: *mut rocksdb_cache_t,
     column_family_names: Vec<&'static str>,
     cf_handles: Vec<*mut rocksdb_column_family_handle_t>,
     cf_options: Vec<*mut rocksdb_options_t>,
@@ -105,6 +107,9 @@ impl Drop for RwDbHandler {
             rocksdb_transactiondb_options_destroy(self.transactiondb_options);
             rocksdb_options_destroy(self.options);
             rocksdb_block_based_options_destroy(self.block_based_table_options);
+            if !self.block_cache.is_null() {
+                rocksdb_cache_destroy(self.block_cache);
+            }
         }
     }
 }
@@ -116,6 +121,8 @@ struct RoDbHandler {
     column_family_names: Vec<&'static str>,
     cf_handles: Vec<*mut rocksdb_column_family_handle_t>,
     cf_options: Vec<*mut rocksdb_options_t>,
+    block_based_table_options: *mut rocksdb_block_based_table_options_t,
+    block_cache: *mut rocksdb_cache_t,
     is_secondary: bool,
     path_to_remove: Option<PathBuf>,
 }
@@ -136,6 +143,10 @@ impl Drop for RoDbHandler {
             }
             rocksdb_readoptions_destroy(self.read_options);
             rocksdb_options_destroy(self.options);
+            rocksdb_block_based_options_destroy(self.block_based_table_options);
+            if !self.block_cache.is_null() {
+                rocksdb_cache_destroy(self.block_cache);
+            }
         }
         if let Some(path) = &self.path_to_remove {
             drop(remove_dir_all(path));
@@ -147,24 +158,36 @@ impl Db {
     pub fn open_read_write(
         path: &Path,
         column_families: Vec<ColumnFamilyDefinition>,
+        store_options: &StoreOptions,
     ) -> Result<Self, StorageError> {
         let c_path = path_to_cstring(path)?;
         unsafe {
-            let options = Self::db_options(true)?;
+            let options = Self::db_options(true, store_options.max_open_files)?;
             rocksdb_options_set_create_if_missing(options, 1);
             rocksdb_options_set_create_missing_column_families(options, 1);
             rocksdb_options_set_compression(options, rocksdb_lz4_compression.try_into().unwrap());
-            let block_based_table_options = rocksdb_block_based_options_create();
-            assert!(
-                !block_based_table_options.is_null(),
-                "rocksdb_block_based_options_create returned null"
-            );
-            rocksdb_block_based_options_set_format_version(block_based_table_options, 5);
-            rocksdb_block_based_options_set_index_block_restart_interval(
-                block_based_table_options,
-                16,
-            );
-            rocksdb_options_set_block_based_table_factory(options, block_based_table_options);
+            if let Some(compression_per_level) = &store_options.compression_per_level {
+                let compression_per_level = compression_per_level
+                    .iter()
+                    .map(|compression| compression_type(*compression))
+                    .collect::<Vec<_>>();
+                rocksdb_options_set_compression_per_level(
+                    options,
+                    compression_per_level.as_ptr(),
+                    compression_per_level.len(),
+                );
+            }
+            if let Some(write_buffer_size) = store_options.write_buffer_size {
+                rocksdb_options_set_write_buffer_size(options, write_buffer_size);
+            }
+            if let Some(max_write_buffer_number) = store_options.max_write_buffer_number {
+                rocksdb_options_set_max_write_buffer_number(
+                    options,
+                    max_write_buffer_number.try_into().unwrap_or(c_int::MAX),
+                );
+            }
+            let (block_based_table_options, block_cache) =
+                Self::set_read_options(options, store_options);
             #[cfg(feature = "rocksdb-debug")]
             {
                 rocksdb_options_set_info_log_level(options, 0);
@@ -204,6 +227,9 @@ impl Db {
                 }
                 rocksdb_options_destroy(options);
                 rocksdb_block_based_options_destroy(block_based_table_options);
+                if !block_cache.is_null() {
+                    rocksdb_cache_destroy(block_cache);
+                }
                 e
             })?;
             assert!(!db.is_null(), "rocksdb_create returned null");
@@ -270,6 +296,7 @@ impl Db {
                     ingest_external_file_options,
                     compaction_options,
                     block_based_table_options,
+                    block_cache,
                     column_family_names,
                     cf_handles,
                     cf_options,
@@ -279,10 +306,12 @@ impl Db {
         }
     }
 
+    /// Only the block cache, bloom filter and statistics options of `store_options` are used
     pub fn open_secondary(
         primary_path: &Path,
         secondary_path: Option<&Path>,
         column_families: Vec<ColumnFamilyDefinition>,
+        store_options: &StoreOptions,
     ) -> Result<Self, StorageError> {
         let c_primary_path = path_to_cstring(primary_path)?;
         let (secondary_path, in_memory) = if let Some(path) = secondary_path {
@@ -292,7 +321,10 @@ impl Db {
         };
         let c_secondary_path = path_to_cstring(&secondary_path)?;
         unsafe {
-            let options = Self::db_options(false)?;
+            // Secondary instances require max_open_files to be -1
+            let options = Self::db_options(false, None)?;
+            let (block_based_table_options, block_cache) =
+                Self::set_read_options(options, store_options);
             let (column_family_names, c_column_family_names, cf_options) =
                 Self::column_families_names_and_options(column_families, options);
             let mut cf_handles: Vec<*mut rocksdb_column_family_handle_t> =
@@ -316,6 +348,10 @@ impl Db {
                     rocksdb_options_destroy(*cf_option);
                 }
                 rocksdb_options_destroy(options);
+                rocksdb_block_based_options_destroy(block_based_table_options);
+                if !block_cache.is_null() {
+                    rocksdb_cache_destroy(block_cache);
+                }
                 e
             })?;
             assert!(
@@ -341,6 +377,8 @@ impl Db {
                     column_family_names,
                     cf_handles,
                     cf_options,
+                    block_based_table_options,
+                    block_cache,
                     is_secondary: true,
                     path_to_remove: in_memory.then_some(secondary_path),
                 })),
@@ -348,13 +386,17 @@ impl Db {
         }
     }
 
+    /// Only the block cache, bloom filter, maximal number of open files and statistics options of `store_options` are used
     pub fn open_read_only(
         path: &Path,
         column_families: Vec<ColumnFamilyDefinition>,
+        store_options: &StoreOptions,
     ) -> Result<Self, StorageError> {
         unsafe {
             let c_path = path_to_cstring(path)?;
-            let options = Self::db_options(true)?;
+            let options = Self::db_options(true, store_options.max_open_files)?;
+            let (block_based_table_options, block_cache) =
+                Self::set_read_options(options, store_options);
             let (column_family_names, c_column_family_names, cf_options) =
                 Self::column_families_names_and_options(column_families, options);
             let mut cf_handles: Vec<*mut rocksdb_column_family_handle_t> =
@@ -378,6 +420,10 @@ impl Db {
                     rocksdb_options_destroy(*cf_option);
                 }
                 rocksdb_options_destroy(options);
+                rocksdb_block_based_options_destroy(block_based_table_options);
+                if !block_cache.is_null() {
+                    rocksdb_cache_destroy(block_cache);
+                }
                 e
             })?;
             assert!(
@@ -404,6 +450,8 @@ impl Db {
                     column_family_names,
                     cf_handles,
                     cf_options,
+                    block_based_table_options,
+                    block_cache,
                     is_secondary: false,
                     path_to_remove: None,
                 })),
@@ -411,7 +459,52 @@ impl Db {
         }
     }
 
-    fn db_options(limit_max_open_files: bool) -> Result<*mut rocksdb_options_t, StorageError> {
+    /// Sets the options used when reading the data files: block cache, bloom filters and statistics
+    ///
+    /// Returns the table options and the block cache (null if none) that must be destroyed after the database.
+    unsafe fn set_read_options(
+        options: *mut rocksdb_options_t,
+        store_options: &StoreOptions,
+    ) -> (
+        *mut rocksdb_block_based_table_options_t,
+        *mut rocksdb_cache_t,
+    ) {
+        if store_options.statistics {
+            rocksdb_options_enable_statistics(options);
+        }
+        let block_based_table_options = rocksdb_block_based_options_create();
+        assert!(
+            !block_based_table_options.is_null(),
+            "rocksdb_block_based_options_create returned null"
+        );
+        rocksdb_block_based_options_set_format_version(block_based_table_options, 5);
+        rocksdb_block_based_options_set_index_block_restart_interval(block_based_table_options, 16);
+        let block_cache = if let Some(block_cache_size) = store_options.block_cache_size {
+            let block_cache = rocksdb_cache_create_lru(block_cache_size);
+            assert!(
+                !block_cache.is_null(),
+                "rocksdb_cache_create_lru returned null"
+            );
+            rocksdb_block_based_options_set_block_cache(block_based_table_options, block_cache);
+            block_cache
+        } else {
+            ptr::null_mut()
+        };
+        if let Some(bits_per_key) = store_options.bloom_filter_bits_per_key {
+            // The table options take the ownership of the filter policy
+            rocksdb_block_based_options_set_filter_policy(
+                block_based_table_options,
+                rocksdb_filterpolicy_create_bloom(bits_per_key),
+            );
+        }
+        rocksdb_options_set_block_based_table_factory(options, block_based_table_options);
+        (block_based_table_options, block_cache)
+    }
+
+    fn db_options(
+        limit_max_open_files: bool,
+        max_open_files: Option<usize>,
+    ) -> Result<*mut rocksdb_options_t, StorageError> {
         static ROCKSDB_ENV: OnceLock<UnsafeEnv> = OnceLock::new();
         unsafe {
             let options = rocksdb_options_create();
@@ -422,6 +515,7 @@ impl Db {
                 available_parallelism()?.get().try_into().unwrap(),
             );
             if limit_max_open_files {
+                let mut max_open_files = max_open_files;
                 if let Some(available_fd) = available_file_descriptors()? {
                     if available_fd < 96 {
                         rocksdb_options_destroy(options);
@@ -432,9 +526,17 @@ impl Db {
                         ))
                         .into());
                     }
+                    let available_fd = (available_fd - 48).try_into().unwrap();
+                    max_open_files = Some(
+                        max_open_files.map_or(available_fd, |max_open_files: usize| {
+                            max_open_files.min(available_fd)
+                        }),
+                    );
+                }
+                if let Some(max_open_files) = max_open_files {
                     rocksdb_options_set_max_open_files(
                         options,
-                        (available_fd - 48).try_into().unwrap(),
+                        max_open_files.try_into().unwrap_or(c_int::MAX),
                     )
                 }
             } else {
@@ -536,10 +638,12 @@ impl Db {
                     );
                     rocksdb_readoptions_set_snapshot(options, snapshot);
                     Reader {
-                        inner: InnerReader::TransactionalSnapshot(Rc::new(TransactionalSnapshot {
-                            db: Arc::clone(db),
-                            snapshot,
-                        })),
+                        inner: InnerReader::TransactionalSnapshot(Arc::new(
+                            TransactionalSnapshot {
+                                db: Arc::clone(db),
+                                snapshot,
+                            },
+                        )),
                         options,
                     }
                 }
@@ -728,6 +832,42 @@ impl Db {
         Ok(())
     }
 
+    /// Returns the value of an integer [RocksDB property](https://github.com/facebook/rocksdb/blob/main/include/rocksdb/db.h) of a column family
+    pub fn int_property(&self, column_family: &ColumnFamily, name: &str) -> Option<u64> {
+        let c_name = CString::new(name).unwrap();
+        let mut value = 0;
+        let found = unsafe {
+            match &self.inner {
+                DbKind::ReadOnly(db) => {
+                    rocksdb_property_int_cf(db.db, column_family.0, c_name.as_ptr(), &mut value)
+                        == 0
+                }
+                DbKind::ReadWrite(db) => {
+                    rocksdb_transactiondb_get_int_property_cf(
+                        db.db,
+                        column_family.0,
+                        c_name.as_ptr(),
+                        &mut value,
+                    ) != 0
+                }
+            }
+        };
+        found.then_some(value)
+    }
+
+    /// Returns the value of a statistics ticker like `rocksdb.block.cache.hit` if the statistics are enabled
+    pub fn ticker_count(&self, name: &str) -> Option<u64> {
+        let c_name = CString::new(name).unwrap();
+        let mut value = 0;
+        let options = match &self.inner {
+            DbKind::ReadOnly(db) => db.options,
+            DbKind::ReadWrite(db) => db.options,
+        };
+        let found =
+            unsafe { rocksdb_options_get_ticker_count(options, c_name.as_ptr(), &mut value) };
+        (found != 0).then_some(value)
+    }
+
     pub fn new_sst_file(&self) -> Result<SstFileWriter, StorageError> {
         let DbKind::ReadWrite(db) = &self.inner else {
             return Err(StorageError::Other(
@@ -826,7 +966,7 @@ pub struct Reader {
 
 #[derive(Clone)]
 enum InnerReader {
-    TransactionalSnapshot(Rc<TransactionalSnapshot>),
+    TransactionalSnapshot(Arc<TransactionalSnapshot>),
     Transaction(Weak<*mut rocksdb_transaction_t>),
     PlainDb(Arc<RoDbHandler>),
 }
@@ -836,6 +976,11 @@ struct TransactionalSnapshot {
     snapshot: *const rocksdb_snapshot_t,
 }
 
+// RocksDB snapshots can be read from multiple threads
+unsafe impl Send for TransactionalSnapshot {}
+
+unsafe impl Sync for TransactionalSnapshot {}
+
 impl Drop for TransactionalSnapshot {
     fn drop(&mut self) {
         unsafe { rocksdb_transactiondb_release_snapshot(self.db.db, self.snapshot) }
@@ -924,20 +1069,66 @@ impl Reader {
         column_family: &ColumnFamily,
         prefix: &[u8],
     ) -> Result<Iter, StorageError> {
-        // We generate the upper bound
-        let upper_bound = {
-            let mut bound = prefix.to_vec();
-            let mut found = false;
-            for c in bound.iter_mut().rev() {
-                if *c < u8::MAX {
-                    *c += 1;
-                    found = true;
-                    break;
-                }
+        self.scan_range(column_family, prefix, prefix_upper_bound(prefix))
+    }
+
+    /// Splits the keys starting with `prefix` into at most `partitions` contiguous ranges and returns an iterator on each of them.
+    ///
+    /// The keys are not split if there are at most `min_partitioned_size` of them.
+    /// The ranges are cut evenly in the key space between the first and the last key of the prefix.
+    /// The returned iterators can be sent to other threads,
+    /// a single iterator is returned if the reader is bound to a transaction.
+    pub fn scan_prefix_partitioned(
+        &self,
+        column_family: &ColumnFamily,
+        prefix: &[u8],
+        partitions: usize,
+        min_partitioned_size: usize,
+    ) -> Result<Vec<Iter>, StorageError> {
+        let first = self.scan_prefix(column_family, prefix)?;
+        if partitions <= 1 || matches!(self.inner, InnerReader::Transaction(_)) {
+            return Ok(vec![first]);
+        }
+        // Small ranges are read on the current thread, counting their first keys is cheap compared to their evaluation
+        let mut probe = self.scan_prefix(column_family, prefix)?;
+        for _ in 0..=min_partitioned_size {
+            if !probe.is_valid() {
+                probe.status()?;
+                return Ok(vec![first]);
             }
-            found.then_some(bound)
+            probe.next();
+        }
+        let Some(first_key) = first.key().map(<[u8]>::to_vec) else {
+            return Ok(vec![first]);
+        };
+        let upper_bound = prefix_upper_bound(prefix);
+        let mut last = self.scan_range(column_family, prefix, upper_bound.clone())?;
+        last.seek_to_last();
+        last.status()?;
+        let Some(last_key) = last.key().map(<[u8]>::to_vec) else {
+            return Ok(vec![first]);
         };
+        let split_keys = split_keys(&first_key, &last_key, partitions);
+        if split_keys.is_empty() {
+            return Ok(vec![first]);
+        }
+        let mut iters = Vec::with_capacity(split_keys.len() + 1);
+        let mut lower_bound = prefix.to_vec();
+        for split_key in split_keys {
+            iters.push(self.scan_range(column_family, &lower_bound, Some(split_key.clone()))?);
+            lower_bound = split_key;
+        }
+        iters.push(self.scan_range(column_family, &lower_bound, upper_bound)?);
+        Ok(iters)
+    }
 
+    /// Iterates on the keys greater or equal to `lower_bound` and strictly lower than `upper_bound`
+    fn scan_range(
+        &self,
+        column_family: &ColumnFamily,
+        lower_bound: &[u8],
+        upper_bound: Option<Vec<u8>>,
+    ) -> Result<Iter, StorageError> {
         unsafe {
             let options = rocksdb_readoptions_create_copy(self.options);
             assert!(
@@ -968,10 +1159,10 @@ impl Reader {
                 }
             };
             assert!(!iter.is_null(), "rocksdb_create_iterator returned null");
-            if prefix.is_empty() {
+            if lower_bound.is_empty() {
                 rocksdb_iter_seek_to_first(iter);
             } else {
-                rocksdb_iter_seek(iter, prefix.as_ptr().cast(), prefix.len());
+                rocksdb_iter_seek(iter, lower_bound.as_ptr().cast(), lower_bound.len());
             }
             let is_currently_valid = rocksdb_iter_valid(iter) != 0;
             Ok(Iter {
@@ -1002,6 +1193,52 @@ impl Reader {
     }
 }
 
+/// The smallest key greater than all the keys starting with `prefix`, if it exists
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut bound = prefix.to_vec();
+    while let Some(last) = bound.pop() {
+        if last < u8::MAX {
+            bound.push(last + 1);
+            return Some(bound);
+        }
+    }
+    None
+}
+
+/// Picks keys evenly spread between `first` and `last` to cut the range into `partitions` parts
+///
+/// The 8 bytes following the common prefix of `first` and `last` are interpolated.
+fn split_keys(first: &[u8], last: &[u8], partitions: usize) -> Vec<Vec<u8>> {
+    let common_len = first
+        .iter()
+        .zip(last)
+        .take_while(|(first, last)| first == last)
+        .count();
+    let position = |key: &[u8]| {
+        let mut buffer = [0; 8];
+        for (b, k) in buffer.iter_mut().zip(&key[common_len..]) {
+            *b = *k;
+        }
+        u128::from(u64::from_be_bytes(buffer))
+    };
+    let start = position(first);
+    let end = position(last);
+    let partitions = u64::try_from(partitions).unwrap_or(u64::MAX);
+    let mut keys = Vec::<Vec<u8>>::new();
+    for i in 1..partitions {
+        let split = start + (end - start) * u128::from(i) / u128::from(partitions);
+        let mut key = first[..common_len].to_vec();
+        key.extend_from_slice(&u64::try_from(split).unwrap_or(u64::MAX).to_be_bytes());
+        if key.as_slice() > first
+            && key.as_slice() <= last
+            && keys.last().map_or(true, |previous| *previous < key)
+        {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
 pub struct Transaction<'a> {
     inner: Rc<*mut rocksdb_transaction_t>,
     read_options: *mut rocksdb_readoptions_t,
@@ -1205,6 +1442,14 @@ impl Iter {
         }
     }
 
+    /// Moves to the last key before the upper bound
+    pub fn seek_to_last(&mut self) {
+        unsafe {
+            rocksdb_iter_seek_to_last(self.inner);
+            self.is_currently_valid = rocksdb_iter_valid(self.inner) != 0;
+        }
+    }
+
     pub fn key(&self) -> Option<&[u8]> {
         if self.is_valid() {
             unsafe {
@@ -1363,6 +1608,16 @@ fn available_file_descriptors() -> io::Result<Option<libc::c_int>> {
     Ok(None)
 }
 
+fn compression_type(compression: Compression) -> c_int {
+    match compression {
+        Compression::None => rocksdb_no_compression,
+        Compression::Lz4 => rocksdb_lz4_compression,
+        Compression::Lz4hc => rocksdb_lz4hc_compression,
+    }
+    .try_into()
+    .unwrap()
+}
+
 fn tmp_path() -> PathBuf {
     if cfg!(target_os = "linux") {
         "/dev/shm/".into()
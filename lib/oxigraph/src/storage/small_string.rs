@@ -5,16 +5,24 @@ use std::ops::Deref;
 use std::str::{FromStr, Utf8Error};
 use std::{fmt, str};
 
-/// A small inline string
-#[derive(Clone, Copy, Default)]
-pub struct SmallString {
-    inner: [u8; 16],
+/// A small inline string of at most 15 bytes
+pub type SmallString = InlineString<16>;
+
+/// A medium inline string of at most 31 bytes
+pub type MediumString = InlineString<32>;
+
+/// An inline string stored in a fixed size buffer of `N` bytes
+///
+/// The last byte of the buffer stores the string length.
+#[derive(Clone, Copy)]
+pub struct InlineString<const N: usize> {
+    inner: [u8; N],
 }
 
-impl SmallString {
+impl<const N: usize> InlineString<N> {
     #[inline]
     pub const fn new() -> Self {
-        Self { inner: [0; 16] }
+        Self { inner: [0; N] }
     }
 
     #[inline]
@@ -23,16 +31,16 @@ impl SmallString {
     }
 
     #[inline]
-    pub fn from_be_bytes(bytes: [u8; 16]) -> Result<Self, BadSmallStringError> {
+    pub fn from_be_bytes(bytes: [u8; N]) -> Result<Self, BadSmallStringError> {
         // We check that it is valid UTF-8
-        str::from_utf8(&bytes.as_ref()[..bytes[15].into()])
+        str::from_utf8(&bytes.as_ref()[..bytes[N - 1].into()])
             .map_err(BadSmallStringError::BadUtf8)?;
         Ok(Self { inner: bytes })
     }
 
     #[inline]
     pub fn len(&self) -> usize {
-        self.inner[15].into()
+        self.inner[N - 1].into()
     }
 
     #[inline]
@@ -53,12 +61,19 @@ impl SmallString {
     }
 
     #[inline]
-    pub fn to_be_bytes(self) -> [u8; 16] {
+    pub fn to_be_bytes(self) -> [u8; N] {
         self.inner
     }
 }
 
-impl Deref for SmallString {
+impl<const N: usize> Default for InlineString<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Deref for InlineString<N> {
     type Target = str;
 
     #[inline]
@@ -67,98 +82,101 @@ impl Deref for SmallString {
     }
 }
 
-impl AsRef<str> for SmallString {
+impl<const N: usize> AsRef<str> for InlineString<N> {
     #[inline]
     fn as_ref(&self) -> &str {
         self.as_str()
     }
 }
 
-impl Borrow<str> for SmallString {
+impl<const N: usize> Borrow<str> for InlineString<N> {
     #[inline]
     fn borrow(&self) -> &str {
         self.as_str()
     }
 }
 
-impl fmt::Debug for SmallString {
+impl<const N: usize> fmt::Debug for InlineString<N> {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.as_str().fmt(f)
     }
 }
 
-impl fmt::Display for SmallString {
+impl<const N: usize> fmt::Display for InlineString<N> {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.as_str().fmt(f)
     }
 }
 
-impl PartialEq for SmallString {
+impl<const N: usize> PartialEq for InlineString<N> {
     #[inline]
     fn eq(&self, other: &Self) -> bool {
         self.as_str() == other.as_str()
     }
 }
 
-impl Eq for SmallString {}
+impl<const N: usize> Eq for InlineString<N> {}
 
-impl PartialOrd for SmallString {
+impl<const N: usize> PartialOrd for InlineString<N> {
     #[inline]
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for SmallString {
+impl<const N: usize> Ord for InlineString<N> {
     #[inline]
     fn cmp(&self, other: &Self) -> Ordering {
         self.as_str().cmp(other.as_str())
     }
 }
 
-impl Hash for SmallString {
+impl<const N: usize> Hash for InlineString<N> {
     #[inline]
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.as_str().hash(state)
     }
 }
 
-impl From<SmallString> for String {
+impl<const N: usize> From<InlineString<N>> for String {
     #[inline]
-    fn from(value: SmallString) -> Self {
+    fn from(value: InlineString<N>) -> Self {
         value.as_str().into()
     }
 }
 
-impl<'a> From<&'a SmallString> for &'a str {
+impl<'a, const N: usize> From<&'a InlineString<N>> for &'a str {
     #[inline]
-    fn from(value: &'a SmallString) -> Self {
+    fn from(value: &'a InlineString<N>) -> Self {
         value.as_str()
     }
 }
 
-impl FromStr for SmallString {
+impl<const N: usize> FromStr for InlineString<N> {
     type Err = BadSmallStringError;
 
     #[inline]
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        if value.len() <= 15 {
-            let mut inner = [0; 16];
+        if value.len() < N {
+            let mut inner = [0; N];
             inner[..value.len()].copy_from_slice(value.as_bytes());
-            inner[15] = value
-                .len()
-                .try_into()
-                .map_err(|_| Self::Err::TooLong(value.len()))?;
+            inner[N - 1] = value.len().try_into().map_err(|_| Self::Err::TooLong {
+                len: value.len(),
+                max: N - 1,
+            })?;
             Ok(Self { inner })
         } else {
-            Err(Self::Err::TooLong(value.len()))
+            Err(Self::Err::TooLong {
+                len: value.len(),
+                max: N - 1,
+            })
         }
     }
 }
 
-impl<'a> TryFrom<&'a str> for SmallString {
+impl<'a, const N: usize> TryFrom<&'a str> for InlineString<N> {
     type Error = BadSmallStringError;
 
     #[inline]
@@ -169,8 +187,8 @@ impl<'a> TryFrom<&'a str> for SmallString {
 
 #[derive(Debug, Clone, Copy, thiserror::Error)]
 pub enum BadSmallStringError {
-    #[error("small strings could only contain at most 15 characters, found {0}")]
-    TooLong(usize),
+    #[error("inline strings could only contain at most {max} bytes, found {len}")]
+    TooLong { len: usize, max: usize },
     #[error(transparent)]
     BadUtf8(#[from] Utf8Error),
 }
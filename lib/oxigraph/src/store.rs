@@ -26,21 +26,29 @@
 //! # Result::<_, Box<dyn std::error::Error>>::Ok(())
 //! ```
 use crate::io::{RdfFormat, RdfParseError, RdfParser, RdfSerializer};
+use crate::metrics::{MetricsRegistry, StoreMetrics};
 use crate::model::*;
 use crate::sparql::{
-    evaluate_query, evaluate_update, EvaluationError, Query, QueryExplanation, QueryOptions,
-    QueryResults, Update, UpdateOptions,
+    evaluate_prepared_query, evaluate_query, evaluate_update, explain_update, prepare_query,
+    view_quads, EvaluationError, MaterializedView, PreparedQuery, Query, QueryExplanation,
+    QueryOptions, QueryResults, Timer, Update, UpdateExplanation, UpdateOptions, UpdateSummary,
+    Variable, ViewRefresh, ViewRegistry,
 };
 use crate::storage::numeric_encoder::{Decoder, EncodedQuad, EncodedTerm};
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+pub use crate::storage::{Compression, QuadIndex, StoreOptions};
 pub use crate::storage::{CorruptionError, LoaderError, SerializerError, StorageError};
 use crate::storage::{
     DecodingGraphIterator, DecodingQuadIterator, Storage, StorageBulkLoader, StorageReader,
     StorageWriter,
 };
+use std::collections::HashSet;
 use std::error::Error;
 use std::io::{Read, Write};
 #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::{fmt, str};
 
 /// An on-disk [RDF dataset](https://www.w3.org/TR/rdf11-concepts/#dfn-rdf-dataset).
@@ -81,15 +89,19 @@ use std::{fmt, str};
 /// ```
 #[derive(Clone)]
 pub struct Store {
+    /// Identifies the store in the caches shared between stores
+    id: u64,
     storage: Storage,
+    metrics: Arc<MetricsRegistry>,
+    views: Arc<ViewRegistry>,
 }
 
+static NEXT_STORE_ID: AtomicU64 = AtomicU64::new(0);
+
 impl Store {
     /// New in-memory [`Store`] without RocksDB.
     pub fn new() -> Result<Self, StorageError> {
-        Ok(Self {
-            storage: Storage::new()?,
-        })
+        Ok(Self::from_storage(Storage::new()?, ViewRegistry::default()))
     }
 
     /// Opens a read-write [`Store`] and creates it if it does not exist yet.
@@ -99,9 +111,83 @@ impl Store {
     /// use [`Store::open_secondary`] or [`Store::open_read_only`].
     #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
     pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
-        Ok(Self {
-            storage: Storage::open(path.as_ref())?,
-        })
+        let storage = Storage::open(path.as_ref())?;
+        let views = load_views(&storage)?;
+        Ok(Self::from_storage(storage, views))
+    }
+
+    /// Opens a read-write [`Store`] that only maintains the given quad indexes and creates it if it does not exist yet.
+    ///
+    /// Fewer indexes mean less write amplification but the quad patterns that are not covered by an index are evaluated by scanning and filtering another index.
+    /// [`QuadIndex::Dspo`] and [`QuadIndex::Gspo`] are always maintained.
+    ///
+    /// The set of indexes is chosen when the store is created and saved in it.
+    /// Opening an existing store with a different set of indexes returns an error.
+    /// [`Store::open`] opens the store with the indexes it has been created with.
+    ///
+    /// Usage example:
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::store::{QuadIndex, Store};
+    /// # use std::fs::remove_dir_all;
+    ///
+    /// # {
+    /// let store = Store::open_with_indexes("example-indexes.db", &[QuadIndex::Spog])?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// store.insert(QuadRef::new(ex, ex, ex, ex))?;
+    ///
+    /// // Pattern not covered by an index
+    /// assert_eq!(
+    ///     store
+    ///         .quads_for_pattern(None, None, Some(ex.into()), None)
+    ///         .count(),
+    ///     1
+    /// );
+    /// # };
+    /// # remove_dir_all("example-indexes.db")?;
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+    pub fn open_with_indexes(
+        path: impl AsRef<Path>,
+        indexes: &[QuadIndex],
+    ) -> Result<Self, StorageError> {
+        Self::open_with_options(
+            path,
+            &StoreOptions::default().with_indexes(indexes.iter().copied()),
+        )
+    }
+
+    /// Opens a read-write [`Store`] with some tuning of the on-disk storage and creates it if it does not exist yet.
+    ///
+    /// See [`StoreOptions`] for the available options and presets.
+    ///
+    /// Usage example:
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::store::{Store, StoreOptions};
+    /// # use std::fs::remove_dir_all;
+    ///
+    /// # {
+    /// let store = Store::open_with_options(
+    ///     "example-options.db",
+    ///     &StoreOptions::read_heavy().with_block_cache_size(64 * 1024 * 1024),
+    /// )?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    /// assert_eq!(store.len()?, 1);
+    /// # };
+    /// # remove_dir_all("example-options.db")?;
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+    pub fn open_with_options(
+        path: impl AsRef<Path>,
+        options: &StoreOptions,
+    ) -> Result<Self, StorageError> {
+        let storage = Storage::open_with_options(path.as_ref(), options)?;
+        let views = load_views(&storage)?;
+        Ok(Self::from_storage(storage, views))
     }
 
     /// Opens a read-only clone of a running read-write [`Store`].
@@ -116,9 +202,22 @@ impl Store {
     /// If you want to simple read-only [`Store`] use [`Store::open_read_only`].
     #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
     pub fn open_secondary(primary_path: impl AsRef<Path>) -> Result<Self, StorageError> {
-        Ok(Self {
-            storage: Storage::open_secondary(primary_path.as_ref())?,
-        })
+        Self::open_secondary_with_options(primary_path, &StoreOptions::default())
+    }
+
+    /// Opens a read-only clone of a running read-write [`Store`] with some tuning of the on-disk storage.
+    ///
+    /// Only the block cache, bloom filter and statistics options are used, see [`Store::open_secondary`].
+    #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+    pub fn open_secondary_with_options(
+        primary_path: impl AsRef<Path>,
+        options: &StoreOptions,
+    ) -> Result<Self, StorageError> {
+        // The views are only refreshed by the read-write instance
+        Ok(Self::from_storage(
+            Storage::open_secondary(primary_path.as_ref(), options)?,
+            ViewRegistry::default(),
+        ))
     }
 
     /// Opens a read-only clone of a running read-write [`Store`] with persistence of the secondary instance cache.
@@ -134,12 +233,31 @@ impl Store {
         primary_path: impl AsRef<Path>,
         secondary_path: impl AsRef<Path>,
     ) -> Result<Self, StorageError> {
-        Ok(Self {
-            storage: Storage::open_persistent_secondary(
+        Self::open_persistent_secondary_with_options(
+            primary_path,
+            secondary_path,
+            &StoreOptions::default(),
+        )
+    }
+
+    /// Opens a read-only clone of a running read-write [`Store`] with persistence of the secondary instance cache and some tuning of the on-disk storage.
+    ///
+    /// Only the block cache, bloom filter and statistics options are used, see [`Store::open_persistent_secondary`].
+    #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+    pub fn open_persistent_secondary_with_options(
+        primary_path: impl AsRef<Path>,
+        secondary_path: impl AsRef<Path>,
+        options: &StoreOptions,
+    ) -> Result<Self, StorageError> {
+        // The views are only refreshed by the read-write instance
+        Ok(Self::from_storage(
+            Storage::open_persistent_secondary(
                 primary_path.as_ref(),
                 secondary_path.as_ref(),
+                options,
             )?,
-        })
+            ViewRegistry::default(),
+        ))
     }
 
     /// Opens a read-only [`Store`] from disk.
@@ -148,9 +266,31 @@ impl Store {
     /// [`Store::open_secondary`] should be used in this case.
     #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
     pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self, StorageError> {
-        Ok(Self {
-            storage: Storage::open_read_only(path.as_ref())?,
-        })
+        Self::open_read_only_with_options(path, &StoreOptions::default())
+    }
+
+    /// Opens a read-only [`Store`] from disk with some tuning of the on-disk storage.
+    ///
+    /// Only the block cache, bloom filter, maximal number of open files and statistics options are used, see [`Store::open_read_only`].
+    #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+    pub fn open_read_only_with_options(
+        path: impl AsRef<Path>,
+        options: &StoreOptions,
+    ) -> Result<Self, StorageError> {
+        // The views are only refreshed by the read-write instance
+        Ok(Self::from_storage(
+            Storage::open_read_only(path.as_ref(), options)?,
+            ViewRegistry::default(),
+        ))
+    }
+
+    fn from_storage(storage: Storage, views: ViewRegistry) -> Self {
+        Self {
+            id: NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed),
+            storage,
+            metrics: Arc::default(),
+            views: Arc::new(views),
+        }
     }
 
     /// Executes a [SPARQL 1.1 query](https://www.w3.org/TR/sparql11-query/).
@@ -211,8 +351,22 @@ impl Store {
         query: impl TryInto<Query, Error = impl Into<EvaluationError>>,
         options: QueryOptions,
     ) -> Result<QueryResults, EvaluationError> {
-        let (results, _) = self.explain_query_opt(query, options, false)?;
-        results
+        self.refresh_stale_views()?;
+        let start = Timer::now();
+        // The query is prepared once, its serialization is only computed if it is a cache key
+        let query = prepare_query(query, &options).map_err(|e| {
+            self.metrics.queries.record(&start, false);
+            e
+        })?;
+        if let (Some((cache, key)), Some(version)) = (
+            options.results_cache_entry(self.id, query.text().to_owned(), query.dataset()),
+            self.storage.version(),
+        ) {
+            return cache.get_or_evaluate(key, version, || {
+                self.evaluate_prepared_since(&query, options, start)
+            });
+        }
+        self.evaluate_prepared_since(&query, options, start)
     }
 
     /// Executes a [SPARQL 1.1 query](https://www.w3.org/TR/sparql11-query/) with some options and
@@ -244,7 +398,356 @@ impl Store {
         options: QueryOptions,
         with_stats: bool,
     ) -> Result<(Result<QueryResults, EvaluationError>, QueryExplanation), EvaluationError> {
-        evaluate_query(self.storage.snapshot(), query, options, with_stats)
+        self.refresh_stale_views()?;
+        self.evaluate_query(query, options, with_stats)
+    }
+
+    fn evaluate_query(
+        &self,
+        query: impl TryInto<Query, Error = impl Into<EvaluationError>>,
+        options: QueryOptions,
+        with_stats: bool,
+    ) -> Result<(Result<QueryResults, EvaluationError>, QueryExplanation), EvaluationError> {
+        let start = Timer::now();
+        let (results, explanation) =
+            evaluate_query(self.storage.snapshot(), query, options, with_stats).map_err(|e| {
+                self.metrics.queries.record(&start, false);
+                e
+            })?;
+        Ok((
+            record_query_results(&self.metrics, start, results),
+            explanation,
+        ))
+    }
+
+    /// Parses and optimizes a [SPARQL 1.1 query](https://www.w3.org/TR/sparql11-query/) once
+    /// so that it can be evaluated many times with [`Store::query_prepared`].
+    ///
+    /// Usage example:
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::sparql::{QueryOptions, QueryResults};
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    ///
+    /// let query = store.prepare("ASK { ?s ?p ?o }")?;
+    /// for _ in 0..10 {
+    ///     assert!(matches!(
+    ///         store.query_prepared(&query, QueryOptions::default())?,
+    ///         QueryResults::Boolean(true)
+    ///     ));
+    /// }
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn prepare(
+        &self,
+        query: impl TryInto<Query, Error = impl Into<EvaluationError>>,
+    ) -> Result<PreparedQuery, EvaluationError> {
+        self.prepare_with_parameters(query, [])
+    }
+
+    /// Parses and optimizes a [SPARQL 1.1 query](https://www.w3.org/TR/sparql11-query/) with parameters.
+    ///
+    /// The values of the parameters are given at each evaluation with [`QueryOptions::with_binding`]
+    /// and all of them must be bound.
+    /// Fails with [`EvaluationError::InvalidBinding`] if a parameter is assigned by a `BIND`.
+    /// The query is optimized knowing that the parameters are bound, leading to the same plans as regular bindings.
+    /// Bindings of variables that are not parameters are still allowed but are joined with the already optimized plan.
+    ///
+    /// Usage example:
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::sparql::{QueryOptions, QueryResults, Variable};
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    ///
+    /// let s = Variable::new("s")?;
+    /// let query = store.prepare_with_parameters("SELECT ?o WHERE { ?s ?p ?o }", [s.clone()])?;
+    /// if let QueryResults::Solutions(mut solutions) =
+    ///     store.query_prepared(&query, QueryOptions::default().with_binding(s, ex.into_owned()))?
+    /// {
+    ///     assert_eq!(solutions.next().unwrap()?.get("o"), Some(&ex.into()));
+    /// }
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    #[allow(clippy::unused_self)]
+    pub fn prepare_with_parameters(
+        &self,
+        query: impl TryInto<Query, Error = impl Into<EvaluationError>>,
+        parameters: impl IntoIterator<Item = Variable>,
+    ) -> Result<PreparedQuery, EvaluationError> {
+        let mut unique_parameters = Vec::new();
+        for parameter in parameters {
+            if !unique_parameters.contains(&parameter) {
+                unique_parameters.push(parameter);
+            }
+        }
+        PreparedQuery::new(
+            query.try_into().map_err(Into::into)?,
+            unique_parameters,
+            &QueryOptions::default(),
+        )
+    }
+
+    /// Evaluates a query prepared with [`Store::prepare`] or [`Store::prepare_with_parameters`].
+    ///
+    /// The parameter values are given with [`QueryOptions::with_binding`].
+    pub fn query_prepared(
+        &self,
+        query: &PreparedQuery,
+        options: QueryOptions,
+    ) -> Result<QueryResults, EvaluationError> {
+        self.refresh_stale_views()?;
+        if let (Some((cache, key)), Some(version)) = (
+            options.results_cache_entry(self.id, query.text().to_owned(), query.dataset()),
+            self.storage.version(),
+        ) {
+            return cache.get_or_evaluate(key, version, || self.evaluate_prepared(query, options));
+        }
+        self.evaluate_prepared(query, options)
+    }
+
+    fn evaluate_prepared(
+        &self,
+        query: &PreparedQuery,
+        options: QueryOptions,
+    ) -> Result<QueryResults, EvaluationError> {
+        self.evaluate_prepared_since(query, options, Timer::now())
+    }
+
+    /// Evaluates the prepared query, `start` being the start of the query processing recorded in the metrics
+    fn evaluate_prepared_since(
+        &self,
+        query: &PreparedQuery,
+        options: QueryOptions,
+        start: Timer,
+    ) -> Result<QueryResults, EvaluationError> {
+        let (results, _) = evaluate_prepared_query(self.storage.snapshot(), query, options, false)
+            .map_err(|e| {
+                self.metrics.queries.record(&start, false);
+                e
+            })?;
+        record_query_results(&self.metrics, start, results)
+    }
+
+    /// Creates a materialized view named `name`: the results of `query` are stored
+    /// in the named graph `name` and can be queried like any other graph, e.g. with `GRAPH <name> { ... }`.
+    ///
+    /// `CONSTRUCT` and `DESCRIBE` results are stored as is.
+    /// Each `SELECT` solution is stored as a blank node with a `<http://oxigraph.org/view#{variable}>` property per bound variable.
+    /// `ASK` queries are not allowed.
+    ///
+    /// The view content is computed when the view is created and then on each refresh, as configured by `refresh`.
+    /// A refresh evaluates the query again but only writes the quads that have changed.
+    ///
+    /// The view definitions are saved in the store: the views are available again when the store is reopened
+    /// with [`Store::open`] and their graph is refreshed before the first query if needed.
+    /// Read-only and secondary stores do not refresh the views.
+    ///
+    /// An error is returned if a view or a named graph called `name` already exists.
+    ///
+    /// Usage example:
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::sparql::{QueryResults, ViewRefresh};
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let view = NamedNode::new("http://example.com/count")?;
+    /// store.create_view(
+    ///     view.clone(),
+    ///     "SELECT (COUNT(*) AS ?c) WHERE { ?s ?p ?o }",
+    ///     ViewRefresh::OnChange,
+    /// )?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    ///
+    /// // The view is refreshed before the query evaluation
+    /// if let QueryResults::Solutions(mut solutions) = store.query(
+    ///     "SELECT ?c WHERE { GRAPH <http://example.com/count> { ?r <http://oxigraph.org/view#c> ?c } }",
+    /// )? {
+    ///     assert_eq!(solutions.next().unwrap()?.get("c"), Some(&Literal::from(1).into()));
+    /// }
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn create_view(
+        &self,
+        name: impl Into<NamedNode>,
+        query: impl TryInto<Query, Error = impl Into<EvaluationError>>,
+        refresh: ViewRefresh,
+    ) -> Result<(), EvaluationError> {
+        let name = name.into();
+        let view = Arc::new(MaterializedView::new(name, self.prepare(query)?, refresh));
+        // The view is locked until its first refresh so that the queries wait for its content
+        let mut version = view.version();
+        {
+            let mut views = self.views.lock();
+            if views.iter().any(|v| v.name == view.name) || self.contains_named_graph(&view.name)? {
+                return Err(EvaluationError::GraphAlreadyExists(view.name.clone()));
+            }
+            views.push(Arc::clone(&view));
+        }
+        if let Err(error) = self.materialize_view(&view, &mut version) {
+            self.views.lock().retain(|v| !Arc::ptr_eq(v, &view));
+            return Err(error);
+        }
+        drop(version);
+        self.save_view_definitions()?;
+        Ok(())
+    }
+
+    /// Recomputes the content of the materialized view `name` created with [`Store::create_view`].
+    ///
+    /// Returns `false` if there is no such view.
+    ///
+    /// Usage example:
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::sparql::ViewRefresh;
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let view = NamedNodeRef::new("http://example.com/view")?;
+    /// store.create_view(view, "CONSTRUCT WHERE { ?s ?p ?o }", ViewRefresh::OnDemand)?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    /// assert!(!store.contains(QuadRef::new(ex, ex, ex, view))?);
+    ///
+    /// assert!(store.refresh_view(view)?);
+    /// assert!(store.contains(QuadRef::new(ex, ex, ex, view))?);
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn refresh_view<'a>(
+        &self,
+        name: impl Into<NamedNodeRef<'a>>,
+    ) -> Result<bool, EvaluationError> {
+        let name = name.into();
+        let Some(view) = self.views.list().into_iter().find(|view| view.name == name) else {
+            return Ok(false);
+        };
+        let mut version = view.version();
+        if !self.views.contains(&view) {
+            return Ok(false); // Dropped in the meantime
+        }
+        self.materialize_view(&view, &mut version)?;
+        Ok(true)
+    }
+
+    /// Removes the materialized view `name` created with [`Store::create_view`] and its graph.
+    ///
+    /// Returns `false` if there is no such view.
+    ///
+    /// Usage example:
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::sparql::ViewRefresh;
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let view = NamedNodeRef::new("http://example.com/view")?;
+    /// store.create_view(view, "CONSTRUCT WHERE { ?s ?p ?o }", ViewRefresh::OnChange)?;
+    /// assert!(store.contains_named_graph(view)?);
+    ///
+    /// assert!(store.drop_view(view)?);
+    /// assert!(!store.contains_named_graph(view)?);
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn drop_view<'a>(&self, name: impl Into<NamedNodeRef<'a>>) -> Result<bool, StorageError> {
+        let name = name.into();
+        let view = {
+            let mut views = self.views.lock();
+            let Some(position) = views.iter().position(|view| view.name == name) else {
+                return Ok(false);
+            };
+            views.remove(position)
+        };
+        // We wait for the running refreshes, the next ones will see that the view is not registered anymore
+        let _version = view.version();
+        self.save_view_definitions()?;
+        self.remove_named_graph(name)?;
+        Ok(true)
+    }
+
+    /// Recomputes the [`ViewRefresh::OnChange`] views if the store has changed since their last refresh
+    ///
+    /// Changes to the view graphs themselves are ignored.
+    /// Only the view being refreshed is locked: queries wait for the refreshes they need but not for each other.
+    fn refresh_stale_views(&self) -> Result<(), EvaluationError> {
+        let views = self.views.list();
+        if views.is_empty() {
+            return Ok(());
+        }
+        let view_graphs = views
+            .iter()
+            .map(|view| EncodedTerm::from(view.name.as_ref()))
+            .collect::<Vec<_>>();
+        for view in views {
+            if view.refresh != ViewRefresh::OnChange {
+                continue;
+            }
+            // If an other thread is refreshing the view, we wait for it and check again
+            let mut version = view.version();
+            if !self.views.contains(&view) {
+                continue; // Dropped in the meantime
+            }
+            if version.map_or(true, |version| {
+                self.storage
+                    .is_modified_since(version, &view_graphs)
+                    .unwrap_or(true)
+            }) {
+                self.materialize_view(&view, &mut version)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the view query and only applies the differences with the current view content
+    fn materialize_view(
+        &self,
+        view: &MaterializedView,
+        version: &mut Option<u64>,
+    ) -> Result<(), EvaluationError> {
+        // The version is read before the evaluation so that concurrent changes trigger a new refresh
+        let new_version = self.storage.version();
+        let quads = view_quads(
+            view.name.as_ref(),
+            self.evaluate_prepared(&view.query, QueryOptions::default())?,
+        )?;
+        let new_content = quads.iter().collect::<HashSet<_>>();
+        self.transaction(|mut transaction| {
+            transaction.insert_named_graph(&view.name)?;
+            let outdated = transaction
+                .quads_for_pattern(None, None, None, Some(view.name.as_ref().into()))
+                .filter(|quad| {
+                    quad.as_ref()
+                        .map_or(true, |quad| !new_content.contains(quad))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            for quad in &outdated {
+                transaction.remove(quad)?;
+            }
+            for quad in &quads {
+                transaction.insert(quad)?;
+            }
+            Result::<_, StorageError>::Ok(())
+        })?;
+        *version = new_version;
+        Ok(())
+    }
+
+    /// Saves the view definitions in the storage so that they are restored when the store is reopened
+    fn save_view_definitions(&self) -> Result<(), StorageError> {
+        // The list is locked so that concurrent changes are saved in order
+        let views = self.views.lock();
+        let definitions = ViewRegistry::encode_definitions(&views);
+        self.storage
+            .transaction(|mut writer| writer.set_view_definitions(&definitions))
     }
 
     /// Retrieves quads with a filter on each quad component
@@ -400,7 +903,12 @@ impl Store {
         &self,
         f: impl for<'a> Fn(Transaction<'a>) -> Result<T, E>,
     ) -> Result<T, E> {
-        self.storage.transaction(|writer| f(Transaction { writer }))
+        self.storage.transaction(|writer| {
+            f(Transaction {
+                writer,
+                metrics: &self.metrics,
+            })
+        })
     }
 
     /// Executes a [SPARQL 1.1 update](https://www.w3.org/TR/sparql11-update/).
@@ -450,10 +958,91 @@ impl Store {
         update: impl TryInto<Update, Error = impl Into<EvaluationError>>,
         options: impl Into<UpdateOptions>,
     ) -> Result<(), EvaluationError> {
-        let update = update.try_into().map_err(Into::into)?;
-        let options = options.into();
-        self.storage
-            .transaction(|mut t| evaluate_update(&mut t, &update, &options))
+        self.refresh_stale_views()?;
+        let start = Timer::now();
+        let result = update.try_into().map_err(Into::into).and_then(|update| {
+            let options = options.into();
+            self.storage
+                .transaction(|mut t| evaluate_update(&mut t, &update, &options, false))
+        });
+        self.metrics.updates.record(&start, result.is_ok());
+        result.map(|_| ())
+    }
+
+    /// Executes a [SPARQL 1.1 update](https://www.w3.org/TR/sparql11-update/) and returns what it changed.
+    ///
+    /// The quads removed by `CLEAR` and `DROP` are counted by reading the cleared graphs before removing them,
+    /// making these operations slower than with [`update_opt`](Self::update_opt).
+    ///
+    /// ```
+    /// use oxigraph::store::Store;
+    /// use oxigraph::sparql::UpdateOptions;
+    ///
+    /// let store = Store::new()?;
+    /// let summary = store.update_with_summary(
+    ///     "INSERT DATA { <http://example.com/s> <http://example.com/p> <http://example.com/o> }",
+    ///     UpdateOptions::default().with_recorded_changes(1000),
+    /// )?;
+    /// assert_eq!(summary.inserted_count(), 1);
+    /// assert_eq!(summary.deleted_count(), 0);
+    /// assert_eq!(summary.inserted_quads().map(<[_]>::len), Some(1));
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn update_with_summary(
+        &self,
+        update: impl TryInto<Update, Error = impl Into<EvaluationError>>,
+        options: impl Into<UpdateOptions>,
+    ) -> Result<UpdateSummary, EvaluationError> {
+        let start = Timer::now();
+        let result = update.try_into().map_err(Into::into).and_then(|update| {
+            let options = options.into();
+            self.storage
+                .transaction(|mut t| evaluate_update(&mut t, &update, &options, true))
+        });
+        self.metrics.updates.record(&start, result.is_ok());
+        result
+    }
+
+    /// Executes a [SPARQL 1.1 update](https://www.w3.org/TR/sparql11-update/) and returns an explanation of each of its operations
+    /// with the number of quads it inserted and deleted and, for `DELETE/INSERT` operations, the plan of the `WHERE` clause.
+    ///
+    /// The plans include evaluation statistics if enabled with the `with_stats` parameter.
+    /// Like with [`update_with_summary`](Self::update_with_summary), the quads removed by `CLEAR` and `DROP` are counted.
+    ///
+    /// Usage example printing the plans:
+    /// ```
+    /// use oxigraph::sparql::UpdateOptions;
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let explanation = store.explain_update_opt(
+    ///     "INSERT DATA { <http://example.com/s> <http://example.com/p> <http://example.com/o> } ;
+    ///      DELETE { ?s ?p ?o } WHERE { ?s ?p ?o }",
+    ///     UpdateOptions::default(),
+    ///     true,
+    /// )?;
+    /// assert_eq!(explanation.operations()[0].inserted_count(), 1);
+    /// assert_eq!(explanation.operations()[1].deleted_count(), 1);
+    /// assert!(explanation.operations()[1].plan().is_some());
+    /// let mut buf = Vec::new();
+    /// explanation.write_in_text(&mut buf)?;
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn explain_update_opt(
+        &self,
+        update: impl TryInto<Update, Error = impl Into<EvaluationError>>,
+        options: impl Into<UpdateOptions>,
+        with_stats: bool,
+    ) -> Result<UpdateExplanation, EvaluationError> {
+        self.refresh_stale_views()?;
+        let start = Timer::now();
+        let result = update.try_into().map_err(Into::into).and_then(|update| {
+            let options = options.into();
+            self.storage
+                .transaction(|mut t| explain_update(&mut t, &update, &options, with_stats))
+        });
+        self.metrics.updates.record(&start, result.is_ok());
+        result
     }
 
     /// Loads a RDF file under into the store.
@@ -494,17 +1083,29 @@ impl Store {
         parser: impl Into<RdfParser>,
         read: impl Read,
     ) -> Result<(), LoaderError> {
-        let quads = parser
+        let start = Timer::now();
+        let result = parser
             .into()
             .rename_blank_nodes()
             .parse_read(read)
-            .collect::<Result<Vec<_>, _>>()?;
-        self.storage.transaction(move |mut t| {
-            for quad in &quads {
-                t.insert(quad.as_ref())?;
-            }
-            Ok(())
-        })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LoaderError::from)
+            .and_then(|quads| {
+                self.storage.transaction(|mut t| {
+                    for quad in &quads {
+                        t.insert(quad.as_ref())?;
+                    }
+                    Ok::<_, StorageError>(())
+                })?;
+                Ok(quads.len())
+            });
+        self.metrics.loads.record(&start, result.is_ok());
+        let loaded_quads = result?;
+        self.metrics.loaded_quads.fetch_add(
+            loaded_quads.try_into().unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        Ok(())
     }
 
     /// Loads a graph file (i.e. triples) into the store.
@@ -991,9 +1592,58 @@ impl Store {
         BulkLoader {
             storage: self.storage.bulk_loader(),
             on_parse_error: None,
+            metrics: Arc::clone(&self.metrics),
         }
     }
 
+    /// Returns the metrics collected by this store and its clones since it has been opened.
+    ///
+    /// See the [`metrics`](crate::metrics) module for an example.
+    pub fn metrics(&self) -> StoreMetrics {
+        self.metrics.snapshot(self.storage.statistics())
+    }
+
+    /// Returns a number that is incremented each time a change to the store content is committed by this process.
+    ///
+    /// It starts from 0 when the store is opened, hence it should be combined with some other identifier to identify a state of the store across processes.
+    /// It returns `None` for secondary stores, whose content is modified by an other process.
+    ///
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// let version = store.version();
+    /// store.insert(QuadRef::new(ex, ex, ex, ex))?;
+    /// assert!(store.version() > version);
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn version(&self) -> Option<u64> {
+        self.storage.version()
+    }
+
+    /// Returns the [`version`](Self::version) of the store when the given graph was last modified by this process.
+    ///
+    /// It returns `None` for secondary stores, whose content is modified by an other process.
+    ///
+    /// ```
+    /// use oxigraph::model::*;
+    /// use oxigraph::store::Store;
+    ///
+    /// let store = Store::new()?;
+    /// let ex = NamedNodeRef::new("http://example.com")?;
+    /// let version = store.graph_version(GraphNameRef::DefaultGraph);
+    /// store.insert(QuadRef::new(ex, ex, ex, ex))?;
+    /// assert_eq!(store.graph_version(GraphNameRef::DefaultGraph), version);
+    /// assert!(store.graph_version(ex) > version);
+    /// # Result::<_, Box<dyn std::error::Error>>::Ok(())
+    /// ```
+    pub fn graph_version<'a>(&self, graph_name: impl Into<GraphNameRef<'a>>) -> Option<u64> {
+        self.storage
+            .graph_version(&EncodedTerm::from(graph_name.into()))
+    }
+
     /// Validates that all the store invariants held in the data
     #[doc(hidden)]
     pub fn validate(&self) -> Result<(), StorageError> {
@@ -1020,11 +1670,45 @@ impl IntoIterator for &Store {
     }
 }
 
+/// Loads the materialized views saved in the storage by [`Store::create_view`]
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+fn load_views(storage: &Storage) -> Result<ViewRegistry, StorageError> {
+    let Some(definitions) = storage.view_definitions()? else {
+        return Ok(ViewRegistry::default());
+    };
+    Ok(ViewRegistry::decode_definitions(&definitions, |query| {
+        PreparedQuery::new(
+            Query::parse(query, None)?,
+            Vec::new(),
+            &QueryOptions::default(),
+        )
+    })?)
+}
+
+/// Records the query in the metrics when its results are exhausted or dropped
+fn record_query_results(
+    metrics: &Arc<MetricsRegistry>,
+    start: Timer,
+    results: Result<QueryResults, EvaluationError>,
+) -> Result<QueryResults, EvaluationError> {
+    match results {
+        Ok(results) => {
+            let metrics = Arc::clone(metrics);
+            Ok(results.on_end(move |success| metrics.queries.record(&start, success)))
+        }
+        Err(e) => {
+            metrics.queries.record(&start, false);
+            Err(e)
+        }
+    }
+}
+
 /// An object to do operations during a transaction.
 ///
 /// See [`Store::transaction`] for a more detailed description.
 pub struct Transaction<'a> {
     writer: StorageWriter<'a>,
+    metrics: &'a Arc<MetricsRegistry>,
 }
 
 impl<'a> Transaction<'a> {
@@ -1103,8 +1787,13 @@ impl<'a> Transaction<'a> {
         query: impl TryInto<Query, Error = impl Into<EvaluationError>>,
         options: QueryOptions,
     ) -> Result<QueryResults, EvaluationError> {
-        let (results, _) = evaluate_query(self.writer.reader(), query, options, false)?;
-        results
+        let start = Timer::now();
+        let (results, _) =
+            evaluate_query(self.writer.reader(), query, options, false).map_err(|e| {
+                self.metrics.queries.record(&start, false);
+                e
+            })?;
+        record_query_results(self.metrics, start, results)
     }
 
     /// Retrieves quads with a filter on each quad component.
@@ -1205,11 +1894,13 @@ impl<'a> Transaction<'a> {
         update: impl TryInto<Update, Error = impl Into<EvaluationError>>,
         options: impl Into<UpdateOptions>,
     ) -> Result<(), EvaluationError> {
-        evaluate_update(
-            &mut self.writer,
-            &update.try_into().map_err(Into::into)?,
-            &options.into(),
-        )
+        let start = Timer::now();
+        let result = update
+            .try_into()
+            .map_err(Into::into)
+            .and_then(|update| evaluate_update(&mut self.writer, &update, &options.into(), false));
+        self.metrics.updates.record(&start, result.is_ok());
+        result.map(|_| ())
     }
 
     /// Loads a RDF file into the store.
@@ -1608,6 +2299,7 @@ impl Iterator for GraphNameIter {
 pub struct BulkLoader {
     storage: StorageBulkLoader,
     on_parse_error: Option<Box<dyn Fn(RdfParseError) -> Result<(), RdfParseError>>>,
+    metrics: Arc<MetricsRegistry>,
 }
 
 impl BulkLoader {
@@ -1902,8 +2594,16 @@ impl BulkLoader {
         &self,
         quads: impl IntoIterator<Item = Result<impl Into<Quad>, EI>>,
     ) -> Result<(), EO> {
-        self.storage
-            .load(quads.into_iter().map(|q| q.map(Into::into)))
+        let start = Timer::now();
+        let result = self.storage.load(quads.into_iter().map(|q| {
+            let q = q.map(Into::into);
+            if q.is_ok() {
+                self.metrics.loaded_quads.fetch_add(1, Ordering::Relaxed);
+            }
+            q
+        }));
+        self.metrics.loads.record(&start, result.is_ok());
+        result
     }
 }
 
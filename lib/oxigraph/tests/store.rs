@@ -4,8 +4,14 @@
 use oxigraph::io::RdfFormat;
 use oxigraph::model::vocab::{rdf, xsd};
 use oxigraph::model::*;
+use oxigraph::sparql::{
+    EvaluationError, Query, QueryOptions, QueryResults, QueryResultsCache, RowLimitPolicy,
+    ServiceHandler, UpdateOptions, Variable, ViewRefresh,
+};
 use oxigraph::store::Store;
 #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+use oxigraph::store::{Compression, QuadIndex, StoreOptions};
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
 use rand::random;
 #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
 use std::env::temp_dir;
@@ -21,6 +27,8 @@ use std::iter::once;
 use std::path::{Path, PathBuf};
 #[cfg(all(target_os = "linux", feature = "rocksdb"))]
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 #[allow(clippy::non_ascii_literal)]
 const DATA: &str = r#"
@@ -461,6 +469,7 @@ fn test_secondary() -> Result<(), Box<dyn Error>> {
     // We open the store
     let primary = Store::open(&primary_dir)?;
     let secondary = Store::open_secondary(&primary_dir)?;
+    assert_eq!(secondary.version(), None);
 
     // We insert a quad
     primary.insert(quad)?;
@@ -576,6 +585,1104 @@ fn reset_dir(dir: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+fn test_open_with_indexes() -> Result<(), Box<dyn Error>> {
+    let dir = TempDir::default();
+    let graph_name =
+        NamedNodeRef::new_unchecked("http://www.wikidata.org/wiki/Special:EntityData/Q90");
+    {
+        let store = Store::open_with_indexes(&dir, &[QuadIndex::Posg])?;
+        store.load_from_read(RdfFormat::Turtle, DATA.as_bytes())?;
+        store
+            .bulk_loader()
+            .load_from_read(RdfFormat::TriG, GRAPH_DATA.as_bytes())?;
+        store.validate()?;
+        store.flush()?;
+    }
+
+    // The indexes are persisted
+    assert!(Store::open_with_indexes(&dir, &QuadIndex::ALL).is_err());
+    let store = Store::open(&dir)?;
+    store.validate()?;
+
+    // All patterns are still supported
+    for q in quads(GraphNameRef::DefaultGraph)
+        .into_iter()
+        .chain(quads(graph_name))
+    {
+        assert!(store.contains(q)?);
+        for (subject, predicate, object) in [
+            (Some(q.subject), None, None),
+            (None, Some(q.predicate), None),
+            (None, None, Some(q.object)),
+            (Some(q.subject), None, Some(q.object)),
+            (None, Some(q.predicate), Some(q.object)),
+        ] {
+            for graph_name in [None, Some(q.graph_name)] {
+                let results = store
+                    .quads_for_pattern(subject, predicate, object, graph_name)
+                    .collect::<Result<Vec<_>, _>>()?;
+                assert!(results.contains(&q.into_owned()));
+                assert!(results
+                    .iter()
+                    .all(|r| subject.map_or(true, |s| r.subject.as_ref() == s)
+                        && predicate.map_or(true, |p| r.predicate.as_ref() == p)
+                        && object.map_or(true, |o| r.object.as_ref() == o)
+                        && graph_name.map_or(true, |g| r.graph_name.as_ref() == g)));
+            }
+        }
+    }
+    assert_eq!(
+        store
+            .quads_for_pattern(None, None, None, Some(graph_name.into()))
+            .count(),
+        NUMBER_OF_TRIPLES
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+fn test_metrics() -> Result<(), Box<dyn Error>> {
+    let dir = TempDir::default();
+    let store = Store::open_with_options(&dir, &StoreOptions::default().with_statistics())?;
+    store.load_from_read(RdfFormat::Turtle, DATA.as_bytes())?;
+    store
+        .bulk_loader()
+        .load_from_read(RdfFormat::TriG, GRAPH_DATA.as_bytes())?;
+    store.update("DELETE WHERE { ?s ?p ?o }")?;
+    assert!(store.update("INSERT DATA {").is_err());
+    if let QueryResults::Solutions(solutions) = store.query("SELECT * WHERE { ?s ?p ?o }")? {
+        for solution in solutions {
+            solution?;
+        }
+    }
+    store.transaction(|mut transaction| {
+        transaction.query("ASK { ?s ?p ?o }")?;
+        transaction.update("INSERT DATA { <http://example.com/s> <http://example.com/p> 1 }")
+    })?;
+    store.flush()?;
+
+    let metrics = store.metrics();
+    assert_eq!(metrics.queries().count(), 2);
+    assert_eq!(metrics.queries().errors(), 0);
+    assert_eq!(metrics.updates().count(), 3);
+    assert_eq!(metrics.updates().errors(), 1);
+    assert_eq!(metrics.loads().count(), 2);
+    assert_eq!(
+        metrics.loaded_quads(),
+        u64::try_from(2 * NUMBER_OF_TRIPLES)?
+    );
+    assert!(metrics.storage().sst_files_size().is_some());
+    assert!(metrics.storage().block_cache_hits().is_some());
+
+    let mut prometheus = Vec::new();
+    metrics.write_in_prometheus(&mut prometheus)?;
+    let prometheus = String::from_utf8(prometheus)?;
+    assert!(prometheus.contains("oxigraph_update_errors_total 1\n"));
+    assert!(prometheus.contains("oxigraph_storage_sst_files_bytes "));
+    Ok(())
+}
+
+#[test]
+fn test_versions() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let g1 = NamedNodeRef::new("http://example.com/g1")?;
+    let g2 = NamedNodeRef::new("http://example.com/g2")?;
+    let initial = store.version();
+    assert_eq!(initial, Some(0));
+
+    store.insert(QuadRef::new(g1, g1, g1, g1))?;
+    let after_insert = store.version();
+    assert!(after_insert > initial);
+    assert_eq!(store.graph_version(g1), after_insert);
+    assert_eq!(store.graph_version(g2), initial);
+
+    // No change: no new version
+    store.insert(QuadRef::new(g1, g1, g1, g1))?;
+    store.update("DELETE DATA { GRAPH <http://example.com/g2> { <http://example.com/s> <http://example.com/p> <http://example.com/o> } }")?;
+    assert_eq!(store.version(), after_insert);
+
+    store.update("INSERT DATA { GRAPH <http://example.com/g2> { <http://example.com/s> <http://example.com/p> <http://example.com/o> } }")?;
+    assert!(store.graph_version(g2) > after_insert);
+    assert_eq!(store.graph_version(g1), after_insert);
+
+    store.clear()?;
+    assert_eq!(store.graph_version(g1), store.version());
+    assert_eq!(
+        store.graph_version(GraphNameRef::DefaultGraph),
+        store.version()
+    );
+
+    let before_load = store.version();
+    store
+        .bulk_loader()
+        .load_from_read(RdfFormat::TriG, GRAPH_DATA.as_bytes())?;
+    assert!(store.version() > before_load);
+    assert!(
+        store.graph_version(NamedNodeRef::new(
+            "http://www.wikidata.org/wiki/Special:EntityData/Q90"
+        )?) > before_load
+    );
+    assert_eq!(store.graph_version(g2), before_load);
+    Ok(())
+}
+
+#[test]
+fn test_query_with_bindings() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let ex = NamedNodeRef::new("http://example.com/s")?;
+    let p = NamedNodeRef::new("http://example.com/p")?;
+    store.insert(QuadRef::new(
+        ex,
+        p,
+        &Literal::from(1),
+        GraphNameRef::DefaultGraph,
+    ))?;
+    store.insert(QuadRef::new(
+        ex,
+        p,
+        &Literal::from(3),
+        GraphNameRef::DefaultGraph,
+    ))?;
+    store.insert(QuadRef::new(
+        p,
+        p,
+        &Literal::from(2),
+        GraphNameRef::DefaultGraph,
+    ))?;
+    let count = |query: &str, options: QueryOptions| -> Result<usize, Box<dyn Error>> {
+        let QueryResults::Solutions(solutions) = store.query_opt(query, options)? else {
+            return Err("SELECT queries return solutions".into());
+        };
+        Ok(solutions.collect::<Result<Vec<_>, _>>()?.len())
+    };
+
+    // The bound variable does not need to be projected
+    let options = QueryOptions::default().with_binding(Variable::new("s")?, ex.into_owned());
+    assert_eq!(count("SELECT ?o WHERE { ?s ?p ?o }", options.clone())?, 2);
+    assert_eq!(
+        count(
+            "SELECT DISTINCT ?o WHERE { ?s ?p ?o } ORDER BY ?o LIMIT 10",
+            options.clone()
+        )?,
+        2
+    );
+    // The bound variables are visible from filters and aggregations
+    let options = options.with_binding(Variable::new("min")?, Literal::from(2));
+    assert_eq!(
+        count(
+            "SELECT ?o WHERE { ?s ?p ?o FILTER(?o > ?min) }",
+            options.clone()
+        )?,
+        1
+    );
+    let QueryResults::Solutions(mut solutions) = store.query_opt(
+        "SELECT (COUNT(*) AS ?c) WHERE { ?s ?p ?o } GROUP BY ?s",
+        options.clone(),
+    )?
+    else {
+        return Err("SELECT queries return solutions".into());
+    };
+    assert_eq!(
+        solutions
+            .next()
+            .transpose()?
+            .and_then(|s| s.get("c").cloned()),
+        Some(Literal::from(2).into())
+    );
+    assert!(matches!(
+        store.query_opt("ASK { ?s ?p 2 }", options.clone())?,
+        QueryResults::Boolean(false)
+    ));
+    // Binding again a variable replaces its value
+    assert_eq!(
+        count(
+            "SELECT ?o WHERE { ?s ?p ?o }",
+            options.with_binding(Variable::new("s")?, p.into_owned())
+        )?,
+        1
+    );
+    assert!(store
+        .query_opt(
+            "SELECT ?o WHERE { ?s ?p ?o }",
+            QueryOptions::default().with_binding(Variable::new("s")?, BlankNode::default()),
+        )
+        .is_err());
+    // The variables assigned by a BIND can't be bound
+    let x = Variable::new("x")?;
+    assert!(matches!(
+        store.query_opt(
+            "SELECT ?x WHERE { ?s ?p ?o BIND(?o AS ?x) }",
+            QueryOptions::default().with_binding(x.clone(), ex.into_owned()),
+        ),
+        Err(EvaluationError::InvalidBinding(v)) if v == x
+    ));
+    assert!(matches!(
+        store.prepare_with_parameters("SELECT ?x WHERE { BIND(1 AS ?x) }", [x.clone()]),
+        Err(EvaluationError::InvalidBinding(v)) if v == x
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_service_bind_join() -> Result<(), Box<dyn Error>> {
+    struct CountingServiceHandler {
+        store: Store,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl ServiceHandler for CountingServiceHandler {
+        type Error = EvaluationError;
+
+        fn handle(&self, _: NamedNode, query: Query) -> Result<QueryResults, EvaluationError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.store.query(query)
+        }
+    }
+
+    let store = Store::new()?;
+    let service_store = Store::new()?;
+    let p = NamedNodeRef::new("http://example.com/p")?;
+    let q = NamedNodeRef::new("http://example.com/q")?;
+    for i in 0..5 {
+        let s = NamedNode::new(format!("http://example.com/s{i}"))?;
+        let o = NamedNode::new(format!("http://example.com/o{i}"))?;
+        store.insert(QuadRef::new(&s, p, &o, GraphNameRef::DefaultGraph))?;
+        service_store.insert(QuadRef::new(
+            &o,
+            q,
+            &Literal::from(i),
+            GraphNameRef::DefaultGraph,
+        ))?;
+    }
+    // Not reachable from the local data
+    service_store.insert(QuadRef::new(
+        NamedNodeRef::new("http://example.com/o5")?,
+        q,
+        &Literal::from(5),
+        GraphNameRef::DefaultGraph,
+    ))?;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let count = |options: QueryOptions| -> Result<usize, Box<dyn Error>> {
+        let QueryResults::Solutions(solutions) = store.query_opt(
+            "SELECT ?s ?v WHERE { ?s <http://example.com/p> ?o SERVICE <http://example.com/service> { ?o <http://example.com/q> ?v } }",
+            options.with_service_handler(CountingServiceHandler {
+                store: service_store.clone(),
+                calls: Arc::clone(&calls),
+            }),
+        )?
+        else {
+            return Err("SELECT queries return solutions".into());
+        };
+        Ok(solutions.collect::<Result<Vec<_>, _>>()?.len())
+    };
+    assert_eq!(count(QueryOptions::default())?, 5);
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 1);
+    assert_eq!(
+        count(QueryOptions::default().with_service_batch_size(2))?,
+        5
+    );
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 3);
+    assert_eq!(
+        count(QueryOptions::default().with_service_batch_size(1))?,
+        5
+    );
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 5);
+
+    // The optional variables are not sent: the service solutions without them are only joined once
+    let x = NamedNodeRef::new("http://example.com/x")?;
+    let o0 = NamedNodeRef::new("http://example.com/o0")?;
+    store.insert(QuadRef::new(
+        NamedNodeRef::new("http://example.com/s5")?,
+        p,
+        o0,
+        GraphNameRef::DefaultGraph,
+    ))?;
+    store.insert(QuadRef::new(
+        NamedNodeRef::new("http://example.com/s0")?,
+        x,
+        &Literal::from(1),
+        GraphNameRef::DefaultGraph,
+    ))?;
+    let QueryResults::Solutions(solutions) = store.query_opt(
+        "SELECT * WHERE { ?s <http://example.com/p> ?o OPTIONAL { ?s <http://example.com/x> ?x } SERVICE <http://example.com/service> { ?o <http://example.com/q> ?v OPTIONAL { ?o <http://example.com/x> ?x } } }",
+        QueryOptions::default()
+            .with_service_batch_size(10)
+            .with_service_handler(CountingServiceHandler {
+                store: service_store.clone(),
+                calls: Arc::clone(&calls),
+            }),
+    )?
+    else {
+        return Err("SELECT queries return solutions".into());
+    };
+    assert_eq!(solutions.collect::<Result<Vec<_>, _>>()?.len(), 6);
+    Ok(())
+}
+
+#[test]
+fn test_parallel_query() -> Result<(), Box<dyn Error>> {
+    check_parallel_query(&Store::new()?)
+}
+
+#[test]
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+fn test_parallel_query_on_disk() -> Result<(), Box<dyn Error>> {
+    let dir = TempDir::default();
+    check_parallel_query(&Store::open(&dir.0)?)
+}
+
+fn check_parallel_query(store: &Store) -> Result<(), Box<dyn Error>> {
+    let p = NamedNodeRef::new("http://example.com/p")?;
+    let q = NamedNodeRef::new("http://example.com/q")?;
+    let mut quads = Vec::new();
+    for i in 0..20_000 {
+        let s = NamedNode::new(format!("http://example.com/s{i}"))?;
+        quads.push(Quad::new(
+            s.clone(),
+            p,
+            Literal::from(i),
+            GraphName::DefaultGraph,
+        ));
+        quads.push(Quad::new(
+            s,
+            q,
+            NamedNode::new(format!("http://example.com/o{}", i % 10))?,
+            GraphName::DefaultGraph,
+        ));
+    }
+    store.extend(quads)?;
+    let evaluate = |query: &str, parallelism: usize| -> Result<Vec<String>, Box<dyn Error>> {
+        let QueryResults::Solutions(solutions) =
+            store.query_opt(query, QueryOptions::default().with_parallelism(parallelism))?
+        else {
+            return Err("SELECT queries return solutions".into());
+        };
+        let mut solutions = solutions
+            .map(|s| Ok(format!("{:?}", s?.values())))
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        solutions.sort();
+        Ok(solutions)
+    };
+    for query in [
+        "SELECT ?o (COUNT(*) AS ?c) (SUM(?v) AS ?sum) (AVG(?v) AS ?avg) WHERE { ?s <http://example.com/p> ?v . ?s <http://example.com/q> ?o } GROUP BY ?o",
+        "SELECT (COUNT(*) AS ?c) WHERE { ?s ?p ?o }",
+        "SELECT ?s ?o WHERE { ?s <http://example.com/q> ?o . ?s <http://example.com/p> ?v FILTER(?v < 100) }",
+    ] {
+        assert_eq!(evaluate(query, 4)?, evaluate(query, 1)?);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_leapfrog_join() -> Result<(), Box<dyn Error>> {
+    check_leapfrog_join(&Store::new()?)
+}
+
+#[test]
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+fn test_leapfrog_join_on_disk() -> Result<(), Box<dyn Error>> {
+    let dir = TempDir::default();
+    check_leapfrog_join(&Store::open(&dir.0)?)
+}
+
+fn check_leapfrog_join(store: &Store) -> Result<(), Box<dyn Error>> {
+    let knows = NamedNodeRef::new("http://example.com/knows")?;
+    let name = NamedNodeRef::new("http://example.com/name")?;
+    let edges = (0..40)
+        .flat_map(|i| (0..40).map(move |j| (i, j)))
+        .filter(|(i, j)| i != j && (i * j + i) % 3 == 0)
+        .collect::<Vec<_>>();
+    let person = |i: usize| NamedNode::new(format!("http://example.com/p{i}"));
+    for (i, j) in &edges {
+        store.insert(QuadRef::new(
+            &person(*i)?,
+            knows,
+            &person(*j)?,
+            GraphNameRef::DefaultGraph,
+        ))?;
+    }
+    for i in 0..40 {
+        store.insert(QuadRef::new(
+            &person(i)?,
+            name,
+            LiteralRef::new_simple_literal(&format!("p{i}")),
+            GraphNameRef::DefaultGraph,
+        ))?;
+    }
+    let triangles = edges
+        .iter()
+        .flat_map(|(a, b)| edges.iter().map(move |(b2, c)| (a, b, b2, c)))
+        .filter(|(a, b, b2, c)| b == b2 && edges.contains(&(**a, **c)))
+        .count();
+
+    let query = "SELECT ?a ?b ?c ?n WHERE { ?a <http://example.com/knows> ?b . ?b <http://example.com/knows> ?c . ?a <http://example.com/knows> ?c . ?a <http://example.com/name> ?n FILTER(?b != ?n) }";
+    let (results, explanation) = store.explain_query_opt(query, QueryOptions::default(), false)?;
+    assert!(format!("{explanation:?}").contains("LeapfrogJoin"));
+    let QueryResults::Solutions(solutions) = results? else {
+        return Err("SELECT queries return solutions".into());
+    };
+    let mut solutions = solutions
+        .map(|s| Ok(format!("{:?}", s?.values())))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    solutions.sort();
+    assert_eq!(solutions.len(), triangles);
+
+    let QueryResults::Solutions(expected) =
+        store.query_opt(query, QueryOptions::default().without_optimizations())?
+    else {
+        return Err("SELECT queries return solutions".into());
+    };
+    let mut expected = expected
+        .map(|s| Ok(format!("{:?}", s?.values())))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    expected.sort();
+    assert_eq!(solutions, expected);
+
+    let QueryResults::Solutions(limited) = store.query(format!("{query} LIMIT 3"))? else {
+        return Err("SELECT queries return solutions".into());
+    };
+    assert_eq!(limited.count(), 3.min(triangles));
+    Ok(())
+}
+
+#[test]
+fn test_prepared_query() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let name = NamedNode::new("http://example.com/name")?;
+    for person in ["a", "b"] {
+        store.insert(QuadRef::new(
+            &NamedNode::new(format!("http://example.com/{person}"))?,
+            &name,
+            &Literal::from(person.to_uppercase()),
+            GraphNameRef::DefaultGraph,
+        ))?;
+    }
+    let prepared = store.prepare_with_parameters(
+        "SELECT ?name WHERE { ?s <http://example.com/name> ?name }",
+        [Variable::new("s")?],
+    )?;
+    assert_eq!(prepared.parameters(), [Variable::new("s")?]);
+
+    // The prepared query is executed with different bindings, including from other threads
+    let handle = {
+        let store = store.clone();
+        let prepared = prepared.clone();
+        std::thread::spawn(move || -> Result<usize, EvaluationError> {
+            let QueryResults::Solutions(solutions) = store.query_prepared(
+                &prepared,
+                QueryOptions::default().with_binding(
+                    Variable::new_unchecked("s"),
+                    NamedNode::new_unchecked("http://example.com/b"),
+                ),
+            )?
+            else {
+                return Ok(0);
+            };
+            Ok(solutions.count())
+        })
+    };
+    for person in ["a", "b"] {
+        let QueryResults::Solutions(mut solutions) = store.query_prepared(
+            &prepared,
+            QueryOptions::default().with_binding(
+                Variable::new("s")?,
+                NamedNode::new(format!("http://example.com/{person}"))?,
+            ),
+        )?
+        else {
+            return Err("SELECT queries return solutions".into());
+        };
+        assert_eq!(
+            solutions
+                .next()
+                .transpose()?
+                .and_then(|s| s.get("name").cloned()),
+            Some(Literal::from(person.to_uppercase()).into())
+        );
+        assert!(solutions.next().is_none());
+    }
+    assert_eq!(handle.join().map_err(|_| "the thread panicked")??, 1);
+
+    // A parameter must be bound
+    assert!(matches!(
+        store.query_prepared(&prepared, QueryOptions::default()),
+        Err(EvaluationError::UnboundParameter(_))
+    ));
+    Ok(())
+}
+
+fn split_into_words_options() -> Result<QueryOptions, Box<dyn Error>> {
+    Ok(QueryOptions::default().with_property_function(
+        NamedNode::new("http://example.com/splitIntoWords")?,
+        10,
+        |subject, _| {
+            let Some(Term::Literal(text)) = subject else {
+                return Err("The subject must be a bound literal".into());
+            };
+            let subject = Term::from(text.clone());
+            Ok(text
+                .value()
+                .split_whitespace()
+                .map(|word| Ok((subject.clone(), Literal::from(word).into())))
+                .collect::<Vec<_>>())
+        },
+    ))
+}
+
+fn words(results: QueryResults) -> Result<Vec<String>, Box<dyn Error>> {
+    let QueryResults::Solutions(solutions) = results else {
+        return Err("SELECT queries return solutions".into());
+    };
+    let mut words = solutions
+        .map(|s| match s?.get("word") {
+            Some(Term::Literal(word)) => Ok(word.value().to_owned()),
+            _ => Err("?word must be bound to a literal".into()),
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    words.sort();
+    Ok(words)
+}
+
+#[test]
+fn test_property_function() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    store.insert(QuadRef::new(
+        NamedNodeRef::new("http://example.com/doc")?,
+        NamedNodeRef::new("http://example.com/text")?,
+        LiteralRef::new_simple_literal("hello  big world"),
+        GraphNameRef::DefaultGraph,
+    ))?;
+
+    // The optimizer binds the subject before calling the function
+    let query = "SELECT ?word WHERE { ?text <http://example.com/splitIntoWords> ?word . ?doc <http://example.com/text> ?text }";
+    let (results, explanation) =
+        store.explain_query_opt(query, split_into_words_options()?, false)?;
+    assert!(format!("{explanation:?}").contains("PropertyFunction"));
+    assert_eq!(words(results?)?, ["big", "hello", "world"]);
+
+    // Bound objects filter the returned pairs
+    let results = store.query_opt(
+        "SELECT ?word WHERE { ?doc <http://example.com/text> ?text . ?text <http://example.com/splitIntoWords> ?word . ?text <http://example.com/splitIntoWords> \"big\" }",
+        split_into_words_options()?,
+    )?;
+    assert_eq!(words(results)?, ["big", "hello", "world"]);
+    let results = store.query_opt(
+        "SELECT ?word WHERE { ?doc <http://example.com/text> ?text . ?text <http://example.com/splitIntoWords> ?word . ?text <http://example.com/splitIntoWords> \"small\" }",
+        split_into_words_options()?,
+    )?;
+    assert_eq!(words(results)?, Vec::<String>::new());
+
+    // Queries prepared without the function still call it
+    let prepared = store.prepare("SELECT ?word WHERE { ?text <http://example.com/splitIntoWords> ?word . <http://example.com/doc> <http://example.com/text> ?text }")?;
+    assert_eq!(
+        words(store.query_prepared(&prepared, split_into_words_options()?)?)?,
+        ["big", "hello", "world"]
+    );
+
+    // The errors of the function are returned
+    let QueryResults::Solutions(mut solutions) = store.query_opt(
+        "SELECT ?word WHERE { ?text <http://example.com/splitIntoWords> ?word }",
+        split_into_words_options()?,
+    )?
+    else {
+        return Err("SELECT queries return solutions".into());
+    };
+    assert!(matches!(
+        solutions.next(),
+        Some(Err(EvaluationError::PropertyFunction(_)))
+    ));
+    Ok(())
+}
+
+fn column(results: QueryResults, variable: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let QueryResults::Solutions(solutions) = results else {
+        return Err("SELECT queries return solutions".into());
+    };
+    solutions
+        .map(|s| Ok(s?.get(variable).map(Term::to_string).unwrap_or_default()))
+        .collect()
+}
+
+#[test]
+fn test_path_search() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    store.update(
+        "PREFIX ex: <http://example.com/>
+        INSERT DATA { ex:a ex:knows ex:b . ex:b ex:knows ex:c . ex:c ex:knows ex:d . ex:a ex:likes ex:c }",
+    )?;
+
+    // Bounded repetitions
+    assert_eq!(
+        column(
+            store.query("PREFIX ex: <http://example.com/> SELECT ?x WHERE { ex:a ex:knows{2,3} ?x } ORDER BY ?x")?,
+            "x"
+        )?,
+        ["<http://example.com/c>", "<http://example.com/d>"]
+    );
+    assert_eq!(
+        column(
+            store.query("PREFIX ex: <http://example.com/> SELECT ?x WHERE { ex:a ex:knows{,1} ?x } ORDER BY ?x")?,
+            "x"
+        )?,
+        ["<http://example.com/a>", "<http://example.com/b>"]
+    );
+    assert_eq!(
+        column(
+            store.query("PREFIX ex: <http://example.com/> SELECT ?x WHERE { ?x ex:knows{0,1000000} ex:c } ORDER BY ?x")?,
+            "x"
+        )?,
+        [
+            "<http://example.com/a>",
+            "<http://example.com/b>",
+            "<http://example.com/c>"
+        ]
+    );
+    assert_eq!(
+        column(
+            store.query("PREFIX ex: <http://example.com/> SELECT ?x WHERE { ?x ex:knows{3} ?y } ORDER BY ?x")?,
+            "x"
+        )?,
+        ["<http://example.com/a>"]
+    );
+    assert_eq!(
+        column(
+            store.query(
+                "PREFIX ex: <http://example.com/> SELECT ?x WHERE { ex:a ex:knows{0} ?x }"
+            )?,
+            "x"
+        )?,
+        ["<http://example.com/a>"]
+    );
+    assert!(store
+        .query("SELECT * WHERE { ?s <http://example.com/knows>{3,2} ?o }")
+        .is_err());
+
+    // Shortest path with a row per node
+    let query = "PREFIX ex: <http://example.com/> PREFIX path: <http://oxigraph.org/path#>
+        SELECT ?step ?node ?edge WHERE {
+            SERVICE path:shortest { ex:a (ex:knows|ex:likes)+ ex:d . [] path:step ?step ; path:node ?node ; path:edge ?edge }
+        } ORDER BY ?step";
+    assert_eq!(
+        column(store.query(query)?, "node")?,
+        [
+            "<http://example.com/a>",
+            "<http://example.com/c>",
+            "<http://example.com/d>"
+        ]
+    );
+    assert_eq!(
+        column(store.query(query)?, "edge")?,
+        [
+            "",
+            "<http://example.com/likes>",
+            "<http://example.com/knows>"
+        ]
+    );
+
+    // All simple paths
+    assert_eq!(
+        column(
+            store.query("PREFIX ex: <http://example.com/> PREFIX path: <http://oxigraph.org/path#>
+                SELECT ?length WHERE {
+                    SERVICE path:all { ex:a (ex:knows|ex:likes)+ ex:d . [] path:length ?length ; path:maxLength 5 }
+                } ORDER BY ?length")?,
+            "length"
+        )?,
+        [
+            Literal::from(2).to_string(),
+            Literal::from(3).to_string()
+        ]
+    );
+
+    // The start node can be given by LATERAL
+    assert_eq!(
+        column(
+            store.query(
+                "PREFIX ex: <http://example.com/> PREFIX path: <http://oxigraph.org/path#>
+                SELECT ?end WHERE {
+                    VALUES ?start { ex:b }
+                    LATERAL { SERVICE path:shortest { ?start ex:knows{1,2} ?end } }
+                } ORDER BY ?end"
+            )?,
+            "end"
+        )?,
+        ["<http://example.com/c>", "<http://example.com/d>"]
+    );
+
+    // The number of simple paths must be bounded
+    let QueryResults::Solutions(mut solutions) = store.query(
+        "SELECT * WHERE { SERVICE <http://oxigraph.org/path#all> { <http://example.com/a> <http://example.com/knows>+ ?end } }",
+    )?
+    else {
+        return Err("SELECT queries return solutions".into());
+    };
+    assert!(matches!(
+        solutions.next(),
+        Some(Err(EvaluationError::InvalidPathSearch(_)))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_top_k() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let value = NamedNodeRef::new("http://example.com/value")?;
+    for i in 0..100 {
+        store.insert(QuadRef::new(
+            &NamedNode::new(format!("http://example.com/s{i}"))?,
+            value,
+            &Literal::from((i * 37) % 100),
+            GraphNameRef::DefaultGraph,
+        ))?;
+    }
+
+    let query =
+        "SELECT ?v WHERE { ?s <http://example.com/value> ?v } ORDER BY DESC(?v) LIMIT 3 OFFSET 2";
+    let (results, explanation) = store.explain_query_opt(query, QueryOptions::default(), false)?;
+    assert!(format!("{explanation:?}").contains("TopK"));
+    assert_eq!(
+        column(results?, "v")?,
+        [
+            Literal::from(97).to_string(),
+            Literal::from(96).to_string(),
+            Literal::from(95).to_string()
+        ]
+    );
+
+    // Without LIMIT all the solutions are sorted
+    let (results, explanation) = store.explain_query_opt(
+        "SELECT ?v WHERE { ?s <http://example.com/value> ?v } ORDER BY ?v OFFSET 98",
+        QueryOptions::default(),
+        false,
+    )?;
+    assert!(!format!("{explanation:?}").contains("TopK"));
+    assert_eq!(
+        column(results?, "v")?,
+        [Literal::from(98).to_string(), Literal::from(99).to_string()]
+    );
+
+    // Solutions already in order and solutions that are only partially in order
+    assert_eq!(
+        column(
+            store.query("SELECT ?v WHERE { VALUES ?v { 1 2 3 4 5 } } ORDER BY ?v LIMIT 2")?,
+            "v"
+        )?,
+        [Literal::from(1).to_string(), Literal::from(2).to_string()]
+    );
+    assert_eq!(
+        column(
+            store.query("SELECT ?v WHERE { VALUES ?v { 1 2 5 3 -1 4 } } ORDER BY ?v LIMIT 2")?,
+            "v"
+        )?,
+        [Literal::from(-1).to_string(), Literal::from(1).to_string()]
+    );
+    assert_eq!(
+        column(
+            store.query("SELECT ?v WHERE { VALUES ?v { 1 2 5 3 -1 4 } } ORDER BY ?v OFFSET 4")?,
+            "v"
+        )?,
+        [Literal::from(4).to_string(), Literal::from(5).to_string()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_materialized_views() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let ex = NamedNodeRef::new("http://example.com")?;
+    let count = NamedNodeRef::new("http://example.com/count")?;
+    let copy = NamedNodeRef::new("http://example.com/copy")?;
+    store.create_view(
+        count,
+        "SELECT (COUNT(*) AS ?c) WHERE { ?s ?p ?o }",
+        ViewRefresh::OnChange,
+    )?;
+    store.create_view(copy, "CONSTRUCT WHERE { ?s ?p ?o }", ViewRefresh::OnDemand)?;
+    assert!(matches!(
+        store.create_view(copy, "CONSTRUCT WHERE { ?s ?p ?o }", ViewRefresh::OnDemand),
+        Err(EvaluationError::GraphAlreadyExists(_))
+    ));
+    assert!(store
+        .create_view(ex, "ASK {}", ViewRefresh::OnDemand)
+        .is_err());
+
+    let count_query =
+        "SELECT ?c WHERE { GRAPH <http://example.com/count> { ?r <http://oxigraph.org/view#c> ?c } }";
+    assert_eq!(
+        column(store.query(count_query)?, "c")?,
+        [Literal::from(0).to_string()]
+    );
+    store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    assert_eq!(
+        column(store.query(count_query)?, "c")?,
+        [Literal::from(1).to_string()]
+    );
+
+    // On demand views are only refreshed explicitly
+    let in_copy = QuadRef::new(ex, ex, ex, copy);
+    assert!(!store.contains(in_copy)?);
+    assert!(store.refresh_view(copy)?);
+    assert!(store.contains(in_copy)?);
+
+    assert!(store.drop_view(copy)?);
+    assert!(!store.drop_view(copy)?);
+    assert!(!store.refresh_view(copy)?);
+    assert!(!store.contains_named_graph(copy)?);
+
+    // The solutions still returned keep their quads
+    let rows = NamedNodeRef::new("http://example.com/rows")?;
+    store.create_view(rows, "SELECT ?s WHERE { ?s ?p ?o }", ViewRefresh::OnChange)?;
+    let row_of_ex = store
+        .quads_for_pattern(None, None, Some(ex.into()), Some(rows.into()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(row_of_ex.len(), 1);
+    let other = NamedNodeRef::new("http://example.com/other")?;
+    store.insert(QuadRef::new(other, ex, ex, GraphNameRef::DefaultGraph))?;
+    store.query("ASK {}")?;
+    assert!(store.contains(&row_of_ex[0])?);
+    assert_eq!(
+        store
+            .quads_for_pattern(None, None, None, Some(rows.into()))
+            .count(),
+        2
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+fn test_materialized_views_on_disk() -> Result<(), Box<dyn Error>> {
+    let dir = TempDir::default();
+    let ex = NamedNodeRef::new("http://example.com")?;
+    let copy = NamedNodeRef::new("http://example.com/copy")?;
+    {
+        let store = Store::open(&dir.0)?;
+        store.create_view(copy, "CONSTRUCT WHERE { ?s ?p ?o }", ViewRefresh::OnChange)?;
+    }
+    let store = Store::open(&dir.0)?;
+    // The view is restored
+    assert!(matches!(
+        store.create_view(copy, "CONSTRUCT WHERE { ?s ?p ?o }", ViewRefresh::OnDemand),
+        Err(EvaluationError::GraphAlreadyExists(_))
+    ));
+    store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    store.query("ASK {}")?;
+    assert!(store.contains(QuadRef::new(ex, ex, ex, copy))?);
+    assert!(store.drop_view(copy)?);
+    drop(store);
+
+    let store = Store::open(&dir.0)?;
+    assert!(!store.refresh_view(copy)?);
+    Ok(())
+}
+
+#[test]
+fn test_max_result_rows() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let query = "SELECT ?i WHERE { VALUES ?i { 1 2 3 } }";
+
+    let QueryResults::Solutions(mut solutions) = store.query_opt(
+        query,
+        QueryOptions::default().with_max_result_rows(2, RowLimitPolicy::Truncate),
+    )?
+    else {
+        return Err("SELECT returns solutions".into());
+    };
+    assert_eq!(solutions.by_ref().collect::<Result<Vec<_>, _>>()?.len(), 2);
+    assert!(solutions.is_truncated());
+
+    let QueryResults::Solutions(mut solutions) = store.query_opt(
+        query,
+        QueryOptions::default().with_max_result_rows(3, RowLimitPolicy::Truncate),
+    )?
+    else {
+        return Err("SELECT returns solutions".into());
+    };
+    assert_eq!(solutions.by_ref().collect::<Result<Vec<_>, _>>()?.len(), 3);
+    assert!(!solutions.is_truncated());
+
+    let QueryResults::Solutions(solutions) = store.query_opt(
+        query,
+        QueryOptions::default().with_max_result_rows(2, RowLimitPolicy::Error),
+    )?
+    else {
+        return Err("SELECT returns solutions".into());
+    };
+    assert!(matches!(
+        solutions.collect::<Result<Vec<_>, _>>(),
+        Err(EvaluationError::ResultRowsLimitExceeded(2))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_query_results_cache() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let ex = NamedNodeRef::new("http://example.com")?;
+    let cache = QueryResultsCache::new(10);
+    let query = "SELECT ?s WHERE { ?s ?p ?o }";
+    let prepared = store.prepare(query)?;
+    let options = QueryOptions::default().with_results_cache(cache);
+    assert!(column(store.query_opt(query, options.clone())?, "s")?.is_empty());
+    assert!(column(store.query_prepared(&prepared, options.clone())?, "s")?.is_empty());
+
+    store.insert(QuadRef::new(ex, ex, ex, GraphNameRef::DefaultGraph))?;
+    for _ in 0..2 {
+        assert_eq!(
+            column(store.query_opt(query, options.clone())?, "s")?,
+            [ex.to_string()]
+        );
+        assert_eq!(
+            column(store.query_prepared(&prepared, options.clone())?, "s")?,
+            [ex.to_string()]
+        );
+    }
+
+    // Bindings are part of the cache key
+    let other = NamedNode::new("http://example.com/other")?;
+    assert_eq!(
+        column(
+            store.query_opt(
+                query,
+                options
+                    .clone()
+                    .with_binding(Variable::new("s")?, other.clone())
+            )?,
+            "s"
+        )?,
+        Vec::<String>::new()
+    );
+
+    // The cache can be shared between stores
+    let other_store = Store::new()?;
+    assert!(column(other_store.query_opt(query, options.clone())?, "s")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_explain_update() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let explanation = store.explain_update_opt(
+        "INSERT DATA { <http://example.com/s> <http://example.com/p> 1 , 2 } ;
+         DELETE { ?s ?p 1 } INSERT { ?s ?p 3 } WHERE { ?s ?p 1 } ;
+         CLEAR DEFAULT",
+        UpdateOptions::default(),
+        true,
+    )?;
+    let operations = explanation.operations();
+    assert_eq!(operations.len(), 3);
+    assert_eq!(
+        operations
+            .iter()
+            .map(|o| (o.name(), o.inserted_count(), o.deleted_count()))
+            .collect::<Vec<_>>(),
+        [
+            ("INSERT DATA", 2, 0),
+            ("DELETE/INSERT", 1, 1),
+            ("CLEAR", 0, 2)
+        ]
+    );
+    assert!(operations[0].plan().is_none());
+    let Some(plan) = operations[1].plan() else {
+        return Err("DELETE/INSERT should have a plan".into());
+    };
+
+    let mut text = Vec::new();
+    plan.write_in_text(&mut text)?;
+    assert!(String::from_utf8(text)?.contains("QuadPattern"));
+    let mut dot = Vec::new();
+    explanation.write_in_dot(&mut dot)?;
+    let dot = String::from_utf8(dot)?;
+    assert!(dot.starts_with("digraph plan {"));
+    assert!(dot.contains("subgraph cluster_1"));
+    let mut json = Vec::new();
+    explanation.write_in_json(&mut json)?;
+    assert!(json.starts_with(b"[{"));
+    Ok(())
+}
+
+#[test]
+fn test_update_with_summary() -> Result<(), Box<dyn Error>> {
+    let store = Store::new()?;
+    let summary = store.update_with_summary(
+        "INSERT DATA { <http://example.com/s> <http://example.com/p> 1, 2 . GRAPH <http://example.com/g> { <http://example.com/s> <http://example.com/p> 3 } }",
+        UpdateOptions::default(),
+    )?;
+    assert_eq!(summary.inserted_count(), 3);
+    assert_eq!(summary.deleted_count(), 0);
+    assert_eq!(summary.inserted_quads(), None);
+
+    // Already existing quads are not counted
+    let summary = store.update_with_summary(
+        "DELETE { ?s ?p 1 } INSERT { ?s ?p 2 } WHERE { ?s ?p 1 }",
+        UpdateOptions::default().with_recorded_changes(10),
+    )?;
+    assert_eq!(summary.inserted_count(), 0);
+    assert_eq!(summary.deleted_count(), 1);
+    assert_eq!(summary.inserted_quads(), Some([].as_slice()));
+    assert_eq!(
+        summary.deleted_quads(),
+        Some(
+            [Quad::new(
+                NamedNode::new("http://example.com/s")?,
+                NamedNode::new("http://example.com/p")?,
+                Literal::from(1),
+                GraphName::DefaultGraph
+            )]
+            .as_slice()
+        )
+    );
+
+    // The recorded quads are truncated but the counts are exact
+    let summary = store.update_with_summary(
+        "INSERT DATA { <http://example.com/s> <http://example.com/p> 4, 5, 6 }",
+        UpdateOptions::default().with_recorded_changes(2),
+    )?;
+    assert_eq!(summary.inserted_count(), 3);
+    assert_eq!(summary.inserted_quads().map(<[_]>::len), Some(2));
+
+    let summary = store.update_with_summary("CLEAR NAMED", UpdateOptions::default())?;
+    assert_eq!(summary.deleted_count(), 1);
+    let summary = store.update_with_summary("DROP ALL", UpdateOptions::default())?;
+    assert_eq!(summary.deleted_count(), 4);
+    assert!(store.is_empty()?);
+    Ok(())
+}
+
+#[test]
+#[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
+fn test_open_with_options() -> Result<(), Box<dyn Error>> {
+    let dir = TempDir::default();
+    for options in [
+        StoreOptions::small(),
+        StoreOptions::read_heavy(),
+        StoreOptions::bulk_import(),
+        StoreOptions::default()
+            .with_block_cache_size(1024 * 1024)
+            .with_bloom_filter(5.)
+            .with_compression(Compression::Lz4hc)
+            .with_max_open_files(100)
+            .with_write_buffer_size(1024 * 1024)
+            .with_max_write_buffer_number(3),
+    ] {
+        let store = Store::open_with_options(&dir, &options)?;
+        store.load_from_read(RdfFormat::Turtle, DATA.as_bytes())?;
+        store
+            .bulk_loader()
+            .load_from_read(RdfFormat::TriG, GRAPH_DATA.as_bytes())?;
+        store.optimize()?;
+        store.validate()?;
+        assert_eq!(store.len()?, NUMBER_OF_TRIPLES * 2);
+    }
+    assert!(StoreOptions::from_preset_name("read-heavy").is_some());
+    assert!(StoreOptions::from_preset_name("foo").is_none());
+    Ok(())
+}
+
 #[cfg(all(not(target_family = "wasm"), feature = "rocksdb"))]
 struct TempDir(PathBuf);
 
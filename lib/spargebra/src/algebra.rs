@@ -15,6 +15,11 @@ pub enum PropertyPathExpression {
     OneOrMore(Box<Self>),
     ZeroOrOne(Box<Self>),
     NegatedPropertySet(Vec<NamedNode>),
+    /// Between `min` and `max` (unbounded if `None`) repetitions of the path, written `p{min,max}`.
+    ///
+    /// It is an extension of SPARQL 1.1 from its [2010 working draft](https://www.w3.org/TR/2010/WD-sparql11-property-paths-20100126/).
+    /// The `path-repetition` feature only enables its syntax in the parser.
+    Repeat(Box<Self>, usize, Option<usize>),
 }
 
 impl PropertyPathExpression {
@@ -63,6 +68,16 @@ impl PropertyPathExpression {
                 }
                 f.write_str(")")
             }
+            Self::Repeat(p, min, max) => {
+                write!(f, "(mod {min} ")?;
+                if let Some(max) = max {
+                    write!(f, "{max} ")?;
+                } else {
+                    f.write_str("* ")?;
+                }
+                p.fmt_sse(f)?;
+                f.write_str(")")
+            }
         }
     }
 }
@@ -87,6 +102,9 @@ impl fmt::Display for PropertyPathExpression {
                 }
                 f.write_str(")")
             }
+            Self::Repeat(p, min, Some(max)) if min == max => write!(f, "({p}){{{min}}}"),
+            Self::Repeat(p, min, Some(max)) => write!(f, "({p}){{{min},{max}}}"),
+            Self::Repeat(p, min, None) => write!(f, "({p}){{{min},}}"),
         }
     }
 }
@@ -1522,17 +1522,33 @@ parser! {
 
         rule PathElt() -> PropertyPathExpression = p:PathPrimary() _ o:PathElt_op()? {
             match o {
-                Some('?') => PropertyPathExpression::ZeroOrOne(Box::new(p)),
-                Some('*') => PropertyPathExpression::ZeroOrMore(Box::new(p)),
-                Some('+') => PropertyPathExpression::OneOrMore(Box::new(p)),
-                Some(_) => unreachable!(),
+                Some(Either::Left('?')) => PropertyPathExpression::ZeroOrOne(Box::new(p)),
+                Some(Either::Left('*')) => PropertyPathExpression::ZeroOrMore(Box::new(p)),
+                Some(Either::Left('+')) => PropertyPathExpression::OneOrMore(Box::new(p)),
+                Some(Either::Left(_)) => unreachable!(),
+                Some(Either::Right((min, max))) => PropertyPathExpression::Repeat(Box::new(p), min, max),
                 None => p
             }
         }
-        rule PathElt_op() -> char =
-            "*" { '*' } /
-            "+" { '+' } /
-            "?" !(['0'..='9'] / PN_CHARS_U()) { '?' } // We mandate that this is not a variable
+        rule PathElt_op() -> Either<char, (usize, Option<usize>)> =
+            "*" { Either::Left('*') } /
+            "+" { Either::Left('+') } /
+            "?" !(['0'..='9'] / PN_CHARS_U()) { Either::Left('?') } / // We mandate that this is not a variable
+            "{" _ r:PathElt_range() _ "}" {?
+                #[cfg(feature = "path-repetition")]{if r.1.is_some_and(|max| max < r.0) {
+                    Err("The maximal number of repetitions of a path should not be lower than the minimal one")
+                } else {
+                    Ok(Either::Right(r))
+                }}
+                #[cfg(not(feature = "path-repetition"))]{let _ = r; Err("Path repetitions p{n,m} are not supported")}
+            }
+        rule PathElt_range() -> (usize, Option<usize>) =
+            min:PathElt_bound() _ "," _ max:PathElt_bound()? { (min, max) } /
+            "," _ max:PathElt_bound() { (0, Some(max)) } /
+            n:PathElt_bound() { (n, Some(n)) }
+        rule PathElt_bound() -> usize = n:$(INTEGER()) {?
+            usize::from_str(n).map_err(|_| "The number of repetitions of a path should be a non negative integer")
+        }
 
         rule PathEltOrInverse() -> PropertyPathExpression =
             "^" _ p:PathElt() { PropertyPathExpression::Reverse(Box::new(p)) } /
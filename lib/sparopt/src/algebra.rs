@@ -252,6 +252,42 @@ impl Expression {
         }
     }
 
+    fn replace_property_functions(&mut self, cost: &impl Fn(&NamedNode) -> Option<usize>) {
+        match self {
+            Self::NamedNode(_) | Self::Literal(_) | Self::Variable(_) | Self::Bound(_) => {}
+            Self::Or(inner)
+            | Self::And(inner)
+            | Self::Coalesce(inner)
+            | Self::FunctionCall(_, inner) => {
+                for i in inner {
+                    i.replace_property_functions(cost);
+                }
+            }
+            Self::Equal(a, b)
+            | Self::SameTerm(a, b)
+            | Self::Greater(a, b)
+            | Self::GreaterOrEqual(a, b)
+            | Self::Less(a, b)
+            | Self::LessOrEqual(a, b)
+            | Self::Add(a, b)
+            | Self::Subtract(a, b)
+            | Self::Multiply(a, b)
+            | Self::Divide(a, b) => {
+                a.replace_property_functions(cost);
+                b.replace_property_functions(cost);
+            }
+            Self::UnaryPlus(i) | Self::UnaryMinus(i) | Self::Not(i) => {
+                i.replace_property_functions(cost)
+            }
+            Self::Exists(e) => e.replace_property_functions(cost),
+            Self::If(a, b, c) => {
+                a.replace_property_functions(cost);
+                b.replace_property_functions(cost);
+                c.replace_property_functions(cost);
+            }
+        }
+    }
+
     fn from_sparql_algebra(
         expression: &AlExpression,
         graph_name: Option<&NamedNodePattern>,
@@ -649,6 +685,17 @@ pub enum GraphPattern {
         object: GroundTermPattern,
         graph_name: Option<NamedNodePattern>,
     },
+    /// A property function call i.e. a triple pattern whose solutions are computed by the function `name`
+    /// from the subject and the object values.
+    ///
+    /// The function is opaque to the optimizer that only uses `cost`,
+    /// the estimated number of solutions returned by a call with a bound subject or object.
+    PropertyFunction {
+        subject: GroundTermPattern,
+        name: NamedNode,
+        object: GroundTermPattern,
+        cost: usize,
+    },
     /// [Join](https://www.w3.org/TR/sparql11-query/#defn_algJoin).
     Join {
         left: Box<Self>,
@@ -665,6 +712,16 @@ pub enum GraphPattern {
     /// Lateral join i.e. evaluate right for all result row of left
     #[cfg(feature = "sep-0006")]
     Lateral { left: Box<Self>, right: Box<Self> },
+    /// Worst-case optimal [join](https://www.w3.org/TR/sparql11-query/#defn_algJoin) of all the inner patterns
+    /// in the spirit of the [leapfrog triejoin](https://arxiv.org/abs/1210.0481) and [generic join](https://arxiv.org/abs/1310.3314) algorithms.
+    ///
+    /// The variables are bound one after the other in the order of `variables`,
+    /// the values of each variable being read from the inner pattern with the fewest matches for the values bound so far.
+    /// The inner patterns must always bind all their in-scope variables.
+    LeapfrogJoin {
+        inner: Vec<Self>,
+        variables: Vec<Variable>,
+    },
     /// [Filter](https://www.w3.org/TR/sparql11-query/#defn_algFilter).
     Filter {
         expression: Expression,
@@ -694,6 +751,15 @@ pub enum GraphPattern {
         inner: Box<Self>,
         expression: Vec<OrderExpression>,
     },
+    /// [OrderBy](https://www.w3.org/TR/sparql11-query/#defn_algOrdered) only returning the first `limit` solutions.
+    ///
+    /// It is used in place of a [`Slice`](Self::Slice) over an [`OrderBy`](Self::OrderBy)
+    /// to only keep in memory the solutions that might be returned.
+    TopK {
+        inner: Box<Self>,
+        expression: Vec<OrderExpression>,
+        limit: usize,
+    },
     /// [Project](https://www.w3.org/TR/sparql11-query/#defn_algProjection).
     Project {
         inner: Box<Self>,
@@ -772,6 +838,21 @@ impl GraphPattern {
         }
     }
 
+    pub fn leapfrog_join(inner: Vec<Self>, variables: Vec<Variable>) -> Self {
+        if inner.iter().any(Self::is_empty) {
+            return Self::empty();
+        }
+        let mut inner = inner
+            .into_iter()
+            .filter(|p| !p.is_empty_singleton())
+            .collect::<Vec<_>>();
+        match inner.len() {
+            0 => Self::empty_singleton(),
+            1 => inner.pop().unwrap(),
+            _ => Self::LeapfrogJoin { inner, variables },
+        }
+    }
+
     #[cfg(feature = "sep-0006")]
     pub fn lateral(left: Self, right: Self) -> Self {
         if left.is_empty() || right.is_empty() {
@@ -950,6 +1031,20 @@ impl GraphPattern {
         }
     }
 
+    pub fn top_k(inner: Self, expression: Vec<OrderExpression>, limit: usize) -> Self {
+        if inner.is_empty() || limit == 0 {
+            return Self::empty();
+        }
+        if expression.is_empty() {
+            return Self::slice(inner, 0, Some(limit));
+        }
+        Self::TopK {
+            inner: Box::new(inner),
+            expression,
+            limit,
+        }
+    }
+
     pub fn project(inner: Self, variables: Vec<Variable>) -> Self {
         Self::Project {
             inner: Box::new(inner),
@@ -1049,11 +1144,17 @@ impl GraphPattern {
                     callback(v);
                 }
             }
+            Self::PropertyFunction {
+                subject, object, ..
+            } => {
+                lookup_term_pattern_variables(subject, callback);
+                lookup_term_pattern_variables(object, callback);
+            }
             Self::Filter { inner, expression } => {
                 expression.lookup_used_variables(callback);
                 inner.lookup_used_variables(callback);
             }
-            Self::Union { inner } => {
+            Self::Union { inner } | Self::LeapfrogJoin { inner, .. } => {
                 for child in inner {
                     child.lookup_used_variables(callback);
                 }
@@ -1087,6 +1188,7 @@ impl GraphPattern {
                 inner.lookup_used_variables(callback);
             }
             Self::OrderBy { inner, .. }
+            | Self::TopK { inner, .. }
             | Self::Distinct { inner }
             | Self::Reduced { inner }
             | Self::Slice { inner, .. } => inner.lookup_used_variables(callback),
@@ -1111,6 +1213,93 @@ impl GraphPattern {
         }
     }
 
+    /// Replaces the triple patterns whose predicate is a property function by [`GraphPattern::PropertyFunction`].
+    ///
+    /// `cost` returns the cost hint of the property functions and `None` for the other predicates.
+    /// The graph name of the triple patterns is ignored and the patterns inside of `SERVICE` are left untouched.
+    pub fn replace_property_functions(&mut self, cost: &impl Fn(&NamedNode) -> Option<usize>) {
+        match self {
+            Self::QuadPattern {
+                subject,
+                predicate: NamedNodePattern::NamedNode(name),
+                object,
+                ..
+            } => {
+                if let Some(cost) = cost(name) {
+                    *self = Self::PropertyFunction {
+                        subject: subject.clone(),
+                        name: name.clone(),
+                        object: object.clone(),
+                        cost,
+                    };
+                }
+            }
+            Self::QuadPattern { .. }
+            | Self::Path { .. }
+            | Self::PropertyFunction { .. }
+            | Self::Values { .. }
+            | Self::Service { .. } => {}
+            Self::Filter { inner, expression }
+            | Self::Extend {
+                inner, expression, ..
+            } => {
+                expression.replace_property_functions(cost);
+                inner.replace_property_functions(cost);
+            }
+            Self::Union { inner } | Self::LeapfrogJoin { inner, .. } => {
+                for child in inner {
+                    child.replace_property_functions(cost);
+                }
+            }
+            Self::Join { left, right, .. } | Self::Minus { left, right, .. } => {
+                left.replace_property_functions(cost);
+                right.replace_property_functions(cost);
+            }
+            #[cfg(feature = "sep-0006")]
+            Self::Lateral { left, right } => {
+                left.replace_property_functions(cost);
+                right.replace_property_functions(cost);
+            }
+            Self::LeftJoin {
+                left,
+                right,
+                expression,
+                ..
+            } => {
+                expression.replace_property_functions(cost);
+                left.replace_property_functions(cost);
+                right.replace_property_functions(cost);
+            }
+            Self::OrderBy { inner, expression }
+            | Self::TopK {
+                inner, expression, ..
+            } => {
+                for e in expression {
+                    match e {
+                        OrderExpression::Asc(e) | OrderExpression::Desc(e) => {
+                            e.replace_property_functions(cost)
+                        }
+                    }
+                }
+                inner.replace_property_functions(cost);
+            }
+            Self::Project { inner, .. }
+            | Self::Distinct { inner }
+            | Self::Reduced { inner }
+            | Self::Slice { inner, .. } => inner.replace_property_functions(cost),
+            Self::Group {
+                inner, aggregates, ..
+            } => {
+                for (_, aggregate) in aggregates {
+                    if let AggregateExpression::FunctionCall { expr, .. } = aggregate {
+                        expr.replace_property_functions(cost);
+                    }
+                }
+                inner.replace_property_functions(cost);
+            }
+        }
+    }
+
     fn from_sparql_algebra(
         pattern: &AlGraphPattern,
         graph_name: Option<&NamedNodePattern>,
@@ -1364,6 +1553,18 @@ impl From<&GraphPattern> for AlGraphPattern {
                     pattern
                 }
             }
+            GraphPattern::PropertyFunction {
+                subject,
+                name,
+                object,
+                ..
+            } => Self::Bgp {
+                patterns: vec![TriplePattern {
+                    subject: subject.clone().into(),
+                    predicate: name.clone().into(),
+                    object: object.clone().into(),
+                }],
+            },
             GraphPattern::Join { left, right, .. } => {
                 match (left.as_ref().into(), right.as_ref().into()) {
                     (Self::Bgp { patterns: mut left }, Self::Bgp { patterns: right }) => {
@@ -1376,6 +1577,22 @@ impl From<&GraphPattern> for AlGraphPattern {
                     },
                 }
             }
+            GraphPattern::LeapfrogJoin { inner, .. } => inner
+                .iter()
+                .map(Into::into)
+                .reduce(|left, right| match (left, right) {
+                    (Self::Bgp { patterns: mut left }, Self::Bgp { patterns: right }) => {
+                        left.extend(right);
+                        Self::Bgp { patterns: left }
+                    }
+                    (left, right) => Self::Join {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                })
+                .unwrap_or_else(|| Self::Bgp {
+                    patterns: Vec::new(),
+                }),
             GraphPattern::LeftJoin {
                 left,
                 right,
@@ -1449,6 +1666,18 @@ impl From<&GraphPattern> for AlGraphPattern {
                 inner: Box::new(inner.as_ref().into()),
                 expression: expression.iter().map(Into::into).collect(),
             },
+            GraphPattern::TopK {
+                inner,
+                expression,
+                limit,
+            } => Self::Slice {
+                inner: Box::new(Self::OrderBy {
+                    inner: Box::new(inner.as_ref().into()),
+                    expression: expression.iter().map(Into::into).collect(),
+                }),
+                start: 0,
+                length: Some(*limit),
+            },
             GraphPattern::Project { inner, variables } => Self::Project {
                 inner: Box::new(inner.as_ref().into()),
                 variables: variables.clone(),
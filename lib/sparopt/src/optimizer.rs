@@ -8,6 +8,7 @@ use oxrdf::Variable;
 use spargebra::algebra::PropertyPathExpression;
 use spargebra::term::{GroundTermPattern, NamedNodePattern};
 use std::cmp::{max, min};
+use std::collections::HashMap;
 
 pub struct Optimizer;
 
@@ -43,6 +44,17 @@ impl Optimizer {
                 object,
                 graph_name,
             },
+            GraphPattern::PropertyFunction {
+                subject,
+                name,
+                object,
+                cost,
+            } => GraphPattern::PropertyFunction {
+                subject,
+                name,
+                object,
+                cost,
+            },
             GraphPattern::Join {
                 left,
                 right,
@@ -52,6 +64,11 @@ impl Optimizer {
                 Self::normalize_pattern(*right, input_types),
                 algorithm,
             ),
+            GraphPattern::LeapfrogJoin { inner, .. } => inner
+                .into_iter()
+                .map(|e| Self::normalize_pattern(e, input_types))
+                .reduce(|a, b| GraphPattern::join(a, b, JoinAlgorithm::default()))
+                .unwrap_or_else(GraphPattern::empty_singleton),
             GraphPattern::LeftJoin {
                 left,
                 right,
@@ -139,6 +156,14 @@ impl Optimizer {
                         .collect(),
                 )
             }
+            GraphPattern::TopK {
+                inner,
+                expression,
+                limit,
+            } => Self::normalize_pattern(
+                GraphPattern::slice(GraphPattern::order_by(*inner, expression), 0, Some(limit)),
+                input_types,
+            ),
             GraphPattern::Project { inner, variables } => {
                 GraphPattern::project(Self::normalize_pattern(*inner, input_types), variables)
             }
@@ -281,6 +306,7 @@ impl Optimizer {
         match pattern {
             GraphPattern::QuadPattern { .. }
             | GraphPattern::Path { .. }
+            | GraphPattern::PropertyFunction { .. }
             | GraphPattern::Values { .. } => {
                 GraphPattern::filter(pattern, Expression::and_all(filters))
             }
@@ -319,6 +345,37 @@ impl Optimizer {
                     Expression::and_all(final_filters),
                 )
             }
+            GraphPattern::LeapfrogJoin { inner, variables } => {
+                let inner_types = inner
+                    .iter()
+                    .map(|p| infer_graph_pattern_types(p, input_types.clone()))
+                    .collect::<Vec<_>>();
+                let mut inner_filters = vec![Vec::new(); inner.len()];
+                let mut final_filters = Vec::new();
+                for filter in filters {
+                    let mut pushed = false;
+                    for (types, inner_filters) in inner_types.iter().zip(&mut inner_filters) {
+                        if are_all_expression_variables_bound(&filter, types) {
+                            inner_filters.push(filter.clone());
+                            pushed = true;
+                        }
+                    }
+                    if !pushed {
+                        final_filters.push(filter);
+                    }
+                }
+                GraphPattern::filter(
+                    GraphPattern::leapfrog_join(
+                        inner
+                            .into_iter()
+                            .zip(inner_filters)
+                            .map(|(p, filters)| Self::push_filters(p, filters, input_types))
+                            .collect(),
+                        variables,
+                    ),
+                    Expression::and_all(final_filters),
+                )
+            }
             #[cfg(feature = "sep-0006")]
             GraphPattern::Lateral { left, right } => {
                 let left_types = infer_graph_pattern_types(&left, input_types.clone());
@@ -455,6 +512,18 @@ impl Optimizer {
             GraphPattern::OrderBy { inner, expression } => {
                 GraphPattern::order_by(Self::push_filters(*inner, filters, input_types), expression)
             }
+            GraphPattern::TopK {
+                inner,
+                expression,
+                limit,
+            } => GraphPattern::filter(
+                GraphPattern::top_k(
+                    Self::push_filters(*inner, Vec::new(), input_types),
+                    expression,
+                    limit,
+                ),
+                Expression::and_all(filters),
+            ),
             GraphPattern::Service {
                 inner,
                 name,
@@ -483,6 +552,7 @@ impl Optimizer {
         match pattern {
             GraphPattern::QuadPattern { .. }
             | GraphPattern::Path { .. }
+            | GraphPattern::PropertyFunction { .. }
             | GraphPattern::Values { .. } => pattern,
             GraphPattern::Join { left, right, .. } => {
                 // We flatten the join operation
@@ -497,6 +567,11 @@ impl Optimizer {
                     }
                 }
 
+                // Cyclic sets of quad patterns blow up with binary joins, we use a worst-case optimal join for them
+                if let Some(leapfrog_join) = extract_leapfrog_join(&mut to_reorder, input_types) {
+                    to_reorder.push(leapfrog_join);
+                }
+
                 // We do first type inference
                 let to_reorder_types = to_reorder
                     .iter()
@@ -622,6 +697,7 @@ impl Optimizer {
                     })
                     .unwrap()
             }
+            GraphPattern::LeapfrogJoin { .. } => pattern,
             #[cfg(feature = "sep-0006")]
             GraphPattern::Lateral { left, right } => {
                 let left_types = infer_graph_pattern_types(&left, input_types.clone());
@@ -699,7 +775,16 @@ impl Optimizer {
                 inner,
                 start,
                 length,
-            } => GraphPattern::slice(Self::reorder_joins(*inner, input_types), start, length),
+            } => {
+                let inner = Self::reorder_joins(*inner, input_types);
+                let inner = if let Some(limit) = length.and_then(|length| start.checked_add(length))
+                {
+                    Self::select_top_k(inner, limit)
+                } else {
+                    inner
+                };
+                GraphPattern::slice(inner, start, length)
+            }
             GraphPattern::Distinct { inner } => {
                 GraphPattern::distinct(Self::reorder_joins(*inner, input_types))
             }
@@ -712,6 +797,11 @@ impl Optimizer {
             GraphPattern::OrderBy { inner, expression } => {
                 GraphPattern::order_by(Self::reorder_joins(*inner, input_types), expression)
             }
+            GraphPattern::TopK {
+                inner,
+                expression,
+                limit,
+            } => GraphPattern::top_k(Self::reorder_joins(*inner, input_types), expression, limit),
             service @ GraphPattern::Service { .. } => {
                 // We don't do join reordering inside of SERVICE calls, we don't know about cardinalities
                 service
@@ -727,6 +817,29 @@ impl Optimizer {
             ),
         }
     }
+
+    /// Replaces an `OrderBy` only read up to its `limit` first solutions by a `TopK`
+    ///
+    /// The indexes are sorted by encoded terms (hashes for IRIs and strings) and not by the SPARQL ordering
+    /// so the evaluator does not rely on the leading key of a scan:
+    /// it checks at runtime if the solutions come in order and then skips the sort and keeps only `limit` solutions.
+    fn select_top_k(pattern: GraphPattern, limit: usize) -> GraphPattern {
+        match pattern {
+            GraphPattern::OrderBy { inner, expression } => {
+                GraphPattern::top_k(*inner, expression, limit)
+            }
+            // Projection does not change the number of solutions
+            GraphPattern::Project { inner, variables } => {
+                GraphPattern::project(Self::select_top_k(*inner, limit), variables)
+            }
+            GraphPattern::TopK {
+                inner,
+                expression,
+                limit: inner_limit,
+            } => GraphPattern::top_k(*inner, expression, min(limit, inner_limit)),
+            pattern => pattern,
+        }
+    }
 }
 
 fn is_fit_for_for_loop_join(
@@ -738,7 +851,8 @@ fn is_fit_for_for_loop_join(
     match pattern {
         GraphPattern::Values { .. }
         | GraphPattern::QuadPattern { .. }
-        | GraphPattern::Path { .. } => true,
+        | GraphPattern::Path { .. }
+        | GraphPattern::PropertyFunction { .. } => true,
         #[cfg(feature = "sep-0006")]
         GraphPattern::Lateral { left, right } => {
             is_fit_for_for_loop_join(left, global_input_types, entry_types)
@@ -794,9 +908,11 @@ fn is_fit_for_for_loop_join(
                 )
         }
         GraphPattern::Join { .. }
+        | GraphPattern::LeapfrogJoin { .. }
         | GraphPattern::Minus { .. }
         | GraphPattern::Service { .. }
         | GraphPattern::OrderBy { .. }
+        | GraphPattern::TopK { .. }
         | GraphPattern::Distinct { .. }
         | GraphPattern::Reduced { .. }
         | GraphPattern::Slice { .. }
@@ -877,6 +993,121 @@ fn has_common_variables(
     })
 }
 
+/// Removes from `patterns` the quad patterns that form a cycle and returns a [`GraphPattern::LeapfrogJoin`] of them.
+///
+/// The cyclic core is computed with the GYO reduction:
+/// variables only used in a single pattern and patterns whose variables are all contained in an other pattern are removed until nothing changes.
+/// If the patterns are acyclic nothing remains.
+fn extract_leapfrog_join(
+    patterns: &mut Vec<GraphPattern>,
+    input_types: &VariableTypes,
+) -> Option<GraphPattern> {
+    let mut edges = patterns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, pattern)| {
+            let mut variables = leapfrog_join_variables(pattern)?;
+            variables.retain(|v| input_types.get(v).undef);
+            Some((i, variables))
+        })
+        .collect::<Vec<_>>();
+    loop {
+        let mut changed = false;
+        let mut occurrences = HashMap::<&Variable, usize>::new();
+        for (_, variables) in &edges {
+            for variable in variables {
+                *occurrences.entry(variable).or_default() += 1;
+            }
+        }
+        let occurrences = occurrences
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(v, _)| v.clone())
+            .collect::<Vec<_>>();
+        for (_, variables) in &mut edges {
+            let len = variables.len();
+            variables.retain(|v| occurrences.contains(v));
+            changed |= variables.len() != len;
+        }
+        let mut i = 0;
+        while i < edges.len() {
+            let is_ear = edges
+                .iter()
+                .enumerate()
+                .any(|(j, (_, other))| i != j && edges[i].1.iter().all(|v| other.contains(v)));
+            if edges[i].1.is_empty() || is_ear {
+                edges.remove(i);
+                changed = true;
+            } else {
+                i += 1;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    if edges.len() < 3 {
+        return None; // No cycle
+    }
+
+    let mut inner = Vec::with_capacity(edges.len());
+    for (i, _) in edges.into_iter().rev() {
+        inner.push(patterns.remove(i));
+    }
+    inner.reverse();
+
+    // We bind first the variables shared by the most patterns
+    let mut variables = Vec::<(Variable, usize)>::new();
+    for pattern in &inner {
+        for variable in leapfrog_join_variables(pattern).unwrap_or_default() {
+            if let Some((_, count)) = variables.iter_mut().find(|(v, _)| *v == variable) {
+                *count += 1;
+            } else {
+                variables.push((variable, 1));
+            }
+        }
+    }
+    variables.sort_by(|(_, a), (_, b)| b.cmp(a));
+    Some(GraphPattern::leapfrog_join(
+        inner,
+        variables.into_iter().map(|(v, _)| v).collect(),
+    ))
+}
+
+/// The variables of a quad pattern that can be evaluated by a leapfrog join
+fn leapfrog_join_variables(pattern: &GraphPattern) -> Option<Vec<Variable>> {
+    let GraphPattern::QuadPattern {
+        subject,
+        predicate,
+        object,
+        graph_name,
+    } = pattern
+    else {
+        return None;
+    };
+    let mut variables = Vec::new();
+    for term in [subject, object] {
+        match term {
+            GroundTermPattern::Variable(v) => variables.push(v),
+            GroundTermPattern::NamedNode(_) | GroundTermPattern::Literal(_) => (),
+            #[cfg(feature = "rdf-star")]
+            GroundTermPattern::Triple(_) => return None,
+        }
+    }
+    for term in [Some(predicate), graph_name.as_ref()].into_iter().flatten() {
+        if let NamedNodePattern::Variable(v) = term {
+            variables.push(v);
+        }
+    }
+    let mut output = Vec::<Variable>::with_capacity(variables.len());
+    for variable in variables {
+        if !output.contains(variable) {
+            output.push(variable.clone());
+        }
+    }
+    Some(output)
+}
+
 fn join_key_variables(
     left: &VariableTypes,
     right: &VariableTypes,
@@ -913,11 +1144,34 @@ fn estimate_graph_pattern_size(pattern: &GraphPattern, input_types: &VariableTyp
             path,
             is_term_pattern_bound(object, input_types),
         ),
+        GraphPattern::PropertyFunction {
+            subject,
+            object,
+            cost,
+            ..
+        } => {
+            if is_term_pattern_bound(subject, input_types)
+                || is_term_pattern_bound(object, input_types)
+            {
+                *cost
+            } else {
+                // Property functions are usually expensive or even failing without input
+                cost.saturating_mul(1_000_000)
+            }
+        }
         GraphPattern::Join {
             left,
             right,
             algorithm,
         } => estimate_join_cost(left, right, algorithm, input_types),
+        GraphPattern::LeapfrogJoin { inner, .. } => {
+            // The output is bounded by the smallest input with the same variables, we use the smallest input as a rough estimation
+            inner
+                .iter()
+                .map(|inner| estimate_graph_pattern_size(inner, input_types))
+                .min()
+                .unwrap_or(1)
+        }
         GraphPattern::LeftJoin {
             left,
             right,
@@ -957,6 +1211,9 @@ fn estimate_graph_pattern_size(pattern: &GraphPattern, input_types: &VariableTyp
         | GraphPattern::Reduced { inner, .. }
         | GraphPattern::Group { inner, .. }
         | GraphPattern::Service { inner, .. } => estimate_graph_pattern_size(inner, input_types),
+        GraphPattern::TopK { inner, limit, .. } => {
+            min(estimate_graph_pattern_size(inner, input_types), *limit)
+        }
         GraphPattern::Slice {
             inner,
             start,
@@ -1058,6 +1315,16 @@ fn estimate_path_size(start_bound: bool, path: &PropertyPathExpression, end_boun
         PropertyPathExpression::NegatedPropertySet(_) => {
             estimate_triple_pattern_size(start_bound, false, end_bound)
         }
+        PropertyPathExpression::Repeat(p, _, max) => {
+            if start_bound && end_bound {
+                1
+            } else if start_bound || end_bound {
+                estimate_path_size(start_bound, p, end_bound)
+                    .saturating_mul(max.map_or(1000, |max| max.min(1000)))
+            } else {
+                1_000_000_000
+            }
+        }
     }
 }
 
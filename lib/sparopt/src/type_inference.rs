@@ -39,11 +39,27 @@ pub fn infer_graph_pattern_types(
             }
             types
         }
+        GraphPattern::PropertyFunction {
+            subject, object, ..
+        } => {
+            // The subject of a property function might be a literal
+            add_ground_term_pattern_types(subject, &mut types, true);
+            add_ground_term_pattern_types(object, &mut types, true);
+            types
+        }
         GraphPattern::Join { left, right, .. } => {
             let mut output_types = infer_graph_pattern_types(left, types.clone());
             output_types.intersect_with(infer_graph_pattern_types(right, types));
             output_types
         }
+        GraphPattern::LeapfrogJoin { inner, .. } => inner
+            .iter()
+            .map(|inner| infer_graph_pattern_types(inner, types.clone()))
+            .reduce(|mut a, b| {
+                a.intersect_with(b);
+                a
+            })
+            .unwrap_or(types),
         #[cfg(feature = "sep-0006")]
         GraphPattern::Lateral { left, right } => {
             infer_graph_pattern_types(right, infer_graph_pattern_types(left, types))
@@ -89,6 +105,7 @@ pub fn infer_graph_pattern_types(
         GraphPattern::Distinct { inner }
         | GraphPattern::Reduced { inner }
         | GraphPattern::OrderBy { inner, .. }
+        | GraphPattern::TopK { inner, .. }
         | GraphPattern::Slice { inner, .. } => infer_graph_pattern_types(inner, types),
         GraphPattern::Group {
             inner,
@@ -1,6 +1,7 @@
 #include "c.h"
 
 #include <rocksdb/db.h>
+#include <rocksdb/statistics.h>
 #include <rocksdb/utilities/checkpoint.h>
 #include <rocksdb/utilities/transaction_db.h>
 
@@ -22,6 +23,7 @@ using ROCKSDB_NAMESPACE::ReadOptions;
 using ROCKSDB_NAMESPACE::Slice;
 using ROCKSDB_NAMESPACE::SstFileWriter;
 using ROCKSDB_NAMESPACE::Status;
+using ROCKSDB_NAMESPACE::TickersNameMap;
 using ROCKSDB_NAMESPACE::Transaction;
 using ROCKSDB_NAMESPACE::TransactionDB;
 using ROCKSDB_NAMESPACE::TransactionDBOptions;
@@ -414,4 +416,25 @@ rocksdb_readoptions_t* rocksdb_readoptions_create_copy(
     rocksdb_readoptions_t* options) {
   return new rocksdb_readoptions_t(*options);
 }
+
+unsigned char rocksdb_transactiondb_get_int_property_cf(
+    rocksdb_transactiondb_t* db, rocksdb_column_family_handle_t* column_family,
+    const char* propname, uint64_t* out_val) {
+  return db->rep->GetIntProperty(column_family->rep, Slice(propname), out_val);
+}
+
+unsigned char rocksdb_options_get_ticker_count(const rocksdb_options_t* options,
+                                               const char* ticker_name,
+                                               uint64_t* out_val) {
+  if (!options->rep.statistics) {
+    return 0;
+  }
+  for (const auto& ticker : TickersNameMap) {
+    if (ticker.second == ticker_name) {
+      *out_val = options->rep.statistics->getTickerCount(ticker.first);
+      return 1;
+    }
+  }
+  return 0;
+}
 }
@@ -179,6 +179,15 @@ extern ROCKSDB_LIBRARY_API void rocksdb_iter_get_status(
 extern ROCKSDB_LIBRARY_API rocksdb_readoptions_t*
 rocksdb_readoptions_create_copy(rocksdb_readoptions_t*);
 
+extern ROCKSDB_LIBRARY_API unsigned char
+rocksdb_transactiondb_get_int_property_cf(
+    rocksdb_transactiondb_t* db, rocksdb_column_family_handle_t* column_family,
+    const char* propname, uint64_t* out_val);
+
+extern ROCKSDB_LIBRARY_API unsigned char rocksdb_options_get_ticker_count(
+    const rocksdb_options_t* options, const char* ticker_name,
+    uint64_t* out_val);
+
 #ifdef __cplusplus
 }
 #endif
@@ -730,6 +730,7 @@ pub fn map_evaluation_error(error: EvaluationError) -> PyErr {
             Ok(error) => (*error).into(),
             Err(error) => PyRuntimeError::new_err(error.to_string()),
         },
+        EvaluationError::InvalidBinding(_) => PyValueError::new_err(error.to_string()),
         _ => PyRuntimeError::new_err(error.to_string()),
     }
 }
@@ -10,6 +10,8 @@ use oxigraph::io::RdfParser;
 use oxigraph::model::GraphNameRef;
 use oxigraph::sparql::{QueryResults, Update};
 use oxigraph::store::{self, LoaderError, SerializerError, StorageError, Store};
+#[cfg(not(target_family = "wasm"))]
+use oxigraph::store::{Compression, StoreOptions};
 use pyo3::exceptions::{PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::PyBytes;
@@ -33,7 +35,22 @@ use std::path::PathBuf;
 ///              If no directory is provided a temporary one is created and removed when the Python garbage collector removes the store.
 ///              In this case, the store data are kept in memory and never written on disk.
 /// :type path: str or os.PathLike[str] or None, optional
+/// :param preset: a preset of storage options: ``"small"`` for machines with little memory, ``"read-heavy"`` for stores that are mostly queried and ``"bulk-import"`` for stores in which large amounts of data are written. The other storage options override the preset values. The storage options can only be set if a path is given.
+/// :type preset: str or None, optional
+/// :param block_cache_size: the size in bytes of the block cache.
+/// :type block_cache_size: int or None, optional
+/// :param bloom_filter_bits_per_key: the number of bits per key of the bloom filters. Bloom filters are disabled by default.
+/// :type bloom_filter_bits_per_key: float or None, optional
+/// :param compression: the compression of each level of the data files (``"none"``, ``"lz4"`` or ``"lz4hc"``), the last value being used for the remaining levels.
+/// :type compression: list[str] or None, optional
+/// :param max_open_files: the maximal number of files kept open.
+/// :type max_open_files: int or None, optional
+/// :param write_buffer_size: the size in bytes of each write buffer.
+/// :type write_buffer_size: int or None, optional
+/// :param max_write_buffer_number: the maximal number of write buffers per index.
+/// :type max_write_buffer_number: int or None, optional
 /// :raises OSError: if the target directory contains invalid data or could not be accessed.
+/// :raises ValueError: if the storage options are invalid or are set without path.
 ///
 /// The :py:class:`str` function provides a serialization of the store in NQuads:
 ///
@@ -51,12 +68,74 @@ pub struct PyStore {
 impl PyStore {
     #[cfg(not(target_family = "wasm"))]
     #[new]
-    #[pyo3(signature = (path = None))]
-    fn new(path: Option<PathBuf>, py: Python<'_>) -> PyResult<Self> {
+    #[pyo3(signature = (path = None, *, preset = None, block_cache_size = None, bloom_filter_bits_per_key = None, compression = None, max_open_files = None, write_buffer_size = None, max_write_buffer_number = None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        path: Option<PathBuf>,
+        preset: Option<&str>,
+        block_cache_size: Option<usize>,
+        bloom_filter_bits_per_key: Option<f64>,
+        compression: Option<Vec<String>>,
+        max_open_files: Option<usize>,
+        write_buffer_size: Option<usize>,
+        max_write_buffer_number: Option<usize>,
+        py: Python<'_>,
+    ) -> PyResult<Self> {
+        if path.is_none()
+            && (preset.is_some()
+                || block_cache_size.is_some()
+                || bloom_filter_bits_per_key.is_some()
+                || compression.is_some()
+                || max_open_files.is_some()
+                || write_buffer_size.is_some()
+                || max_write_buffer_number.is_some())
+        {
+            return Err(PyValueError::new_err(
+                "The storage options can only be set if a path is given",
+            ));
+        }
+        let mut options = if let Some(preset) = preset {
+            StoreOptions::from_preset_name(preset).ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "Not supported storage preset: {preset}. Supported values are small, read-heavy and bulk-import"
+                ))
+            })?
+        } else {
+            StoreOptions::default()
+        };
+        if let Some(block_cache_size) = block_cache_size {
+            options = options.with_block_cache_size(block_cache_size);
+        }
+        if let Some(bits_per_key) = bloom_filter_bits_per_key {
+            options = options.with_bloom_filter(bits_per_key);
+        }
+        if let Some(compression) = compression {
+            options = options.with_compression_per_level(
+                compression
+                    .iter()
+                    .map(|name| {
+                        Compression::from_name(name).ok_or_else(|| {
+                            PyValueError::new_err(format!(
+                                "Not supported compression: {name}. Supported values are none, lz4 and lz4hc"
+                            ))
+                        })
+                    })
+                    .collect::<PyResult<Vec<_>>>()?,
+            );
+        }
+        if let Some(max_open_files) = max_open_files {
+            options = options.with_max_open_files(max_open_files);
+        }
+        if let Some(write_buffer_size) = write_buffer_size {
+            options = options.with_write_buffer_size(write_buffer_size);
+        }
+        if let Some(max_write_buffer_number) = max_write_buffer_number {
+            options = options.with_max_write_buffer_number(max_write_buffer_number);
+        }
         py.allow_threads(|| {
             Ok(Self {
                 inner: if let Some(path) = path {
-                    Store::open(path)
+                    Store::open_with_options(path, &options)
                 } else {
                     Store::new()
                 }
@@ -268,6 +347,8 @@ impl PyStore {
     /// :type named_graphs: list[NamedNode or BlankNode] or None, optional
     /// :param custom_functions: dictionary of custom functions mapping function names to their definition. Custom functions takes for input some :py:class:`Term`s and return a :py:class:`Term` or :py:const:`None`.
     /// :type custom_functions: dict[NamedNode, typing.Callable[[NamedNode or BlankNode or Literal or Triple, ...], NamedNode or BlankNode or Literal or Triple or None]] or None, optional
+    /// :param bindings: dictionary of the initial values of some variables. The query is evaluated as if it was joined with a ``VALUES`` clause binding them, which is safer than inserting the values in the query string. Blank nodes are not allowed.
+    /// :type bindings: dict[Variable, NamedNode or Literal or Triple] or None, optional
     /// :return: a :py:class:`bool` for ``ASK`` queries, an iterator of :py:class:`Triple` for ``CONSTRUCT`` and ``DESCRIBE`` queries and an iterator of :py:class:`QuerySolution` for ``SELECT`` queries.
     /// :rtype: QuerySolutions or QueryBoolean or QueryTriples
     /// :raises SyntaxError: if the provided query is invalid.
@@ -293,7 +374,14 @@ impl PyStore {
     /// >>> store.add(Quad(NamedNode('http://example.com'), NamedNode('http://example.com/p'), Literal('1')))
     /// >>> bool(store.query('ASK { ?s ?p ?o }'))
     /// True
-    #[pyo3(signature = (query, *, base_iri = None, use_default_graph_as_union = false, default_graph = None, named_graphs = None, custom_functions = None))]
+    ///
+    /// ``SELECT`` query with a bound variable:
+    ///
+    /// >>> store = Store()
+    /// >>> store.add(Quad(NamedNode('http://example.com'), NamedNode('http://example.com/p'), Literal('1')))
+    /// >>> [solution['o'] for solution in store.query('SELECT ?o WHERE { ?s ?p ?o }', bindings={Variable('s'): NamedNode('http://example.com')})]
+    /// [<Literal value=1 datatype=<NamedNode value=http://www.w3.org/2001/XMLSchema#string>>]
+    #[pyo3(signature = (query, *, base_iri = None, use_default_graph_as_union = false, default_graph = None, named_graphs = None, custom_functions = None, bindings = None))]
     fn query(
         &self,
         query: &str,
@@ -302,6 +390,7 @@ impl PyStore {
         default_graph: Option<&Bound<'_, PyAny>>,
         named_graphs: Option<&Bound<'_, PyAny>>,
         custom_functions: Option<HashMap<PyNamedNode, PyObject>>,
+        bindings: Option<HashMap<PyVariable, PyTerm>>,
         py: Python<'_>,
     ) -> PyResult<PyObject> {
         pub struct UngilQueryResults(QueryResults);
@@ -318,7 +407,10 @@ impl PyStore {
             named_graphs,
             py,
         )?;
-        let options = query_options_from_python(custom_functions);
+        let mut options = query_options_from_python(custom_functions);
+        for (variable, value) in bindings.unwrap_or_default() {
+            options = options.with_binding(variable.into(), value);
+        }
         let results = py
             .allow_threads(|| Ok(UngilQueryResults(self.inner.query_opt(query, options)?)))
             .map_err(map_evaluation_error)?
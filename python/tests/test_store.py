@@ -211,6 +211,18 @@ class TestStore(unittest.TestCase):
         self.assertEqual(solution["c"], Literal("http://foohttp://bar"))
         self.assertIsNone(solution["f"], None)
 
+    def test_select_query_with_bindings(self) -> None:
+        store = Store()
+        store.add(Quad(foo, bar, baz))
+        store.add(Quad(baz, bar, foo))
+        results: Any = store.query(
+            "SELECT ?o WHERE { ?s ?p ?o }",
+            bindings={Variable("s"): foo},
+        )
+        self.assertEqual([solution["o"] for solution in results], [baz])
+        with self.assertRaises(ValueError):
+            store.query("SELECT ?o WHERE { ?s ?p ?o }", bindings={Variable("s"): BlankNode()})
+
     def test_select_query_dump(self) -> None:
         store = Store()
         store.add(Quad(foo, bar, baz))
@@ -402,6 +414,28 @@ class TestStore(unittest.TestCase):
         self.assertEqual(list(store.named_graphs()), [])
         self.assertEqual(list(store), [])
 
+    @unittest.skipIf(is_wasm, "Not supported with WASM")
+    def test_options(self) -> None:
+        quad = Quad(foo, bar, baz, graph)
+        with TemporaryDirectory() as dir:
+            store = Store(
+                dir,
+                preset="read-heavy",
+                block_cache_size=1024 * 1024,
+                compression=["none", "lz4"],
+                max_open_files=128,
+            )
+            store.add(quad)
+            self.assertEqual(list(store), [quad])
+            del store
+            gc.collect()
+            with self.assertRaises(ValueError):
+                Store(dir, preset="foo")
+            with self.assertRaises(ValueError):
+                Store(dir, compression=["foo"])
+        with self.assertRaises(ValueError):
+            Store(block_cache_size=1024 * 1024)
+
     @unittest.skipIf(is_wasm, "Not supported with WASM")
     def test_read_only(self) -> None:
         quad = Quad(foo, bar, baz, graph)